@@ -0,0 +1,183 @@
+use crate::residue::ResidueKey;
+use anyhow::{bail, Result};
+use nalgebra::{Matrix3, Vector3};
+use pdbtbx::{Atom, StrictnessLevel, PDB};
+use serde::Serialize;
+use std::collections::HashMap;
+
+
+/// Atoms used to compute a superposition.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AlignAtoms {
+    /// Alpha carbons only
+    Ca,
+    /// Backbone atoms (N, CA, C, O)
+    Backbone,
+    /// Every atom with a counterpart in the reference
+    All,
+}
+
+impl AlignAtoms {
+    fn selects(&self, atom: &Atom) -> bool {
+        match self {
+            AlignAtoms::Ca => atom.name() == "CA",
+            AlignAtoms::Backbone => atom.is_backbone(),
+            AlignAtoms::All => true,
+        }
+    }
+}
+
+
+type AtomKey = (ResidueKey, String);
+
+
+/// Atoms of the first model of `pdb` selected by `selection`, keyed by
+/// residue and atom name so they can be paired across related structures.
+fn keyed_atoms(pdb: &PDB, selection: AlignAtoms) -> HashMap<AtomKey, Vector3<f64>> {
+    let mut atoms = HashMap::new();
+    let Some(model) = pdb.model(0) else {
+        return atoms;
+    };
+    for chain in model.chains() {
+        for residue in chain.residues() {
+            for atom in residue.atoms().filter(|a| selection.selects(a)) {
+                let (x, y, z) = atom.pos();
+                atoms.entry((ResidueKey::new(chain, residue), atom.name().to_string()))
+                    .or_insert(Vector3::new(x, y, z));
+            }
+        }
+    }
+    atoms
+}
+
+
+/// Structure every input is superposed onto.
+pub struct Reference {
+    source: String,
+    selection: AlignAtoms,
+    atoms: HashMap<AtomKey, Vector3<f64>>,
+}
+
+impl Reference {
+    pub fn load(fname: &str, selection: AlignAtoms) -> Result<Reference> {
+        let (pdb, _) = match crate::input::open(fname, StrictnessLevel::Loose) {
+            Ok(pdb) => pdb,
+            Err(e) => bail!("Error parsing reference structure {} - {:?}", fname, e),
+        };
+        Ok(Reference { source: fname.to_string(), selection, atoms: keyed_atoms(&pdb, selection) })
+    }
+}
+
+
+/// Result of superposing a structure onto the reference, stored in the graph metadata.
+#[derive(Serialize, Debug, Clone)]
+pub struct AlignmentInfo {
+    pub reference: String,
+    pub atoms: AlignAtoms,
+    pub matched_atoms: usize,
+    /// RMSD over the matched atoms after superposition, in Å
+    pub rmsd: f64,
+}
+
+
+/// Superposes `pdb` onto `reference` with the Kabsch algorithm, using atoms
+/// paired by chain, residue and atom name, and transforms all its atoms.
+pub fn align(pdb: &mut PDB, reference: &Reference) -> Result<AlignmentInfo> {
+    let mobile = keyed_atoms(pdb, reference.selection);
+    let mut keys: Vec<&AtomKey> = mobile.keys().filter(|k| reference.atoms.contains_key(*k)).collect();
+    if keys.len() < 3 {
+        bail!("Only {} atoms could be paired with reference {}, at least 3 are needed", keys.len(), reference.source);
+    }
+    keys.sort();
+    let p: Vec<Vector3<f64>> = keys.iter().map(|k| mobile[*k]).collect();
+    let q: Vec<Vector3<f64>> = keys.iter().map(|k| reference.atoms[*k]).collect();
+    let n = keys.len() as f64;
+    let p_center = p.iter().sum::<Vector3<f64>>() / n;
+    let q_center = q.iter().sum::<Vector3<f64>>() / n;
+
+    let h: Matrix3<f64> = p.iter().zip(&q)
+        .map(|(a, b)| (a - p_center) * (b - q_center).transpose())
+        .sum();
+    let svd = h.svd(true, true);
+    let (Some(u), Some(v_t)) = (svd.u, svd.v_t) else {
+        bail!("Superposition onto {} failed", reference.source);
+    };
+    let v = v_t.transpose();
+    let d = (v * u.transpose()).determinant().signum();
+    let rotation = v * Matrix3::from_diagonal(&Vector3::new(1.0, 1.0, d)) * u.transpose();
+    let transform = |x: Vector3<f64>| rotation * (x - p_center) + q_center;
+
+    let rmsd = (p.iter().zip(&q).map(|(a, b)| (transform(*a) - b).norm_squared()).sum::<f64>() / n).sqrt();
+    for atom in pdb.atoms_mut() {
+        let (x, y, z) = atom.pos();
+        let t = transform(Vector3::new(x, y, z));
+        atom.set_pos((t.x, t.y, t.z)).map_err(anyhow::Error::msg)?;
+    }
+    Ok(AlignmentInfo {
+        reference: reference.source.clone(),
+        atoms: reference.selection,
+        matched_atoms: keys.len(),
+        rmsd,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Rotation3;
+
+    fn antibody() -> PDB {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        pdbtbx::open(path, StrictnessLevel::Medium).unwrap().0
+    }
+
+    fn reference(pdb: &PDB, selection: AlignAtoms) -> Reference {
+        Reference { source: "reference.pdb".to_string(), selection, atoms: keyed_atoms(pdb, selection) }
+    }
+
+    fn moved(f: impl Fn(Vector3<f64>) -> Vector3<f64>) -> PDB {
+        let mut pdb = antibody();
+        for atom in pdb.atoms_mut() {
+            let (x, y, z) = atom.pos();
+            let p = f(Vector3::new(x, y, z));
+            atom.set_pos((p.x, p.y, p.z)).unwrap();
+        }
+        pdb
+    }
+
+    #[test]
+    fn recovers_rigid_motions() {
+        let original = antibody();
+        let rotation = Rotation3::from_euler_angles(0.4, -1.1, 2.3);
+        let mut pdb = moved(|p| rotation * p + Vector3::new(12.0, -3.0, 40.0));
+        let info = align(&mut pdb, &reference(&original, AlignAtoms::Ca)).unwrap();
+        assert_eq!(info.matched_atoms, 15);
+        assert!(info.rmsd < 1e-6);
+        for (a, b) in pdb.atoms().zip(original.atoms()) {
+            assert!(a.distance(b) < 1e-6);
+        }
+    }
+
+    #[test]
+    fn never_reflects() {
+        // A mirror image can only be superposed by a reflection, which is ruled out
+        let mut mirrored = moved(|p| Vector3::new(-p.x, p.y, p.z));
+        let info = align(&mut mirrored, &reference(&antibody(), AlignAtoms::All)).unwrap();
+        assert!(info.rmsd > 0.1);
+    }
+
+    #[test]
+    fn needs_three_paired_atoms() {
+        let mut pdb = antibody();
+        let seen = std::cell::Cell::new(0);
+        pdb.remove_atoms_by(|a| {
+            seen.set(seen.get() + usize::from(a.name() == "CA"));
+            a.name() == "CA" && seen.get() > 2
+        });
+        let mut few = pdb.clone();
+        let err = align(&mut few, &reference(&pdb, AlignAtoms::Ca)).unwrap_err();
+        assert!(err.to_string().starts_with("Only 2 atoms could be paired"), "{}", err);
+    }
+}
@@ -0,0 +1,134 @@
+use pdbtbx::PDB;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+
+/// How atoms with alternate locations (altlocs) are turned into nodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum AltlocPolicy {
+    /// Every alternate location becomes its own node
+    All,
+    /// One node per atom at the occupancy-weighted average position of its alternate locations
+    Average,
+    /// One node per atom at its alternate location of highest occupancy, the first one among ties
+    HighestOccupancy,
+    /// One node per atom at its first alternate location in the file
+    First,
+}
+
+
+/// How the position of a node with alternate locations was chosen, recorded per node.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AltlocResolution {
+    Average,
+    HighestOccupancy,
+    First,
+}
+
+impl AltlocResolution {
+    pub const NAMES: [&'static str; 3] = ["average", "highest-occupancy", "first"];
+}
+
+
+/// One alternate location of an atom within a residue.
+struct Location {
+    conformer: usize,
+    serial_number: usize,
+    pos: (f64, f64, f64),
+    occupancy: f64,
+}
+
+
+/// Applies `policy` to every residue with alternate locations. Returns the
+/// resolution applied to each remaining atom, keyed by serial number.
+pub fn resolve_altlocs(pdb: &mut PDB, policy: AltlocPolicy) -> HashMap<usize, AltlocResolution> {
+    let mut resolved = HashMap::new();
+    if policy == AltlocPolicy::All {
+        return resolved;
+    }
+    for residue in pdb.residues_mut() {
+        let mut locations: BTreeMap<String, Vec<Location>> = BTreeMap::new();
+        for (ci, conformer) in residue.conformers().enumerate() {
+            if conformer.alternative_location().is_none() {
+                continue;
+            }
+            for atom in conformer.atoms() {
+                locations.entry(atom.name().to_string()).or_default().push(Location {
+                    conformer: ci,
+                    serial_number: atom.serial_number(),
+                    pos: atom.pos(),
+                    occupancy: atom.occupancy(),
+                });
+            }
+        }
+        for (name, locs) in locations.iter().filter(|(_, l)| l.len() > 1) {
+            // pdbtbx copies atoms without an altloc into every alternate
+            // conformer of their residue, those only need deduplicating
+            let shared = locs.iter().all(|l| l.serial_number == locs[0].serial_number);
+            let mut keep = 0;
+            if !shared && policy != AltlocPolicy::Average {
+                let resolution = if policy == AltlocPolicy::First {
+                    AltlocResolution::First
+                } else {
+                    // The first of the locations with the highest occupancy
+                    keep = (0..locs.len()).rev().max_by(|&a, &b| locs[a].occupancy.total_cmp(&locs[b].occupancy)).unwrap_or(0);
+                    AltlocResolution::HighestOccupancy
+                };
+                resolved.insert(locs[keep].serial_number, resolution);
+            } else if !shared {
+                let total: f64 = locs.iter().map(|l| l.occupancy).sum();
+                let mut pos = (0.0, 0.0, 0.0);
+                for l in locs {
+                    let w = if total > 0.0 { l.occupancy / total } else { 1.0 / locs.len() as f64 };
+                    pos = (pos.0 + w * l.pos.0, pos.1 + w * l.pos.1, pos.2 + w * l.pos.2);
+                }
+                let Some(atom) = residue.conformer_mut(locs[0].conformer)
+                    .and_then(|c| c.atoms_mut().find(|a| a.name() == name)) else {
+                    continue;
+                };
+                if atom.set_pos(pos).is_err() || atom.set_occupancy(total.min(1.0)).is_err() {
+                    continue;
+                }
+                resolved.insert(atom.serial_number(), AltlocResolution::Average);
+            }
+            for (_, l) in locs.iter().enumerate().filter(|(i, _)| *i != keep) {
+                if let Some(conformer) = residue.conformer_mut(l.conformer) {
+                    conformer.remove_atoms_by(|a| a.name() == name);
+                }
+            }
+        }
+        residue.remove_empty();
+    }
+    resolved
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdbtbx::StrictnessLevel;
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn policies_keep_one_location() {
+        let text = "\
+ATOM      1  N   SER A   1       0.000   0.000   0.000  1.00 10.00           N
+ATOM      2  CA  SER A   1       1.450   0.000   0.000  1.00 10.00           C
+ATOM      3  OG ASER A   1       2.000   1.000   0.000  0.40 10.00           O
+ATOM      4  OG BSER A   1       2.000  -1.000   0.000  0.60 10.00           O
+END
+";
+        let reader = BufReader::new(Cursor::new(text.as_bytes()));
+        let (pdb, _) = pdbtbx::open_pdb_raw(reader, pdbtbx::Context::none(), StrictnessLevel::Medium).unwrap();
+        for (policy, serial, y) in [(AltlocPolicy::HighestOccupancy, 4, -1.0), (AltlocPolicy::First, 3, 1.0), (AltlocPolicy::Average, 3, -0.2)] {
+            let mut resolved_pdb = pdb.clone();
+            let resolved = resolve_altlocs(&mut resolved_pdb, policy);
+            assert_eq!(resolved.keys().copied().collect::<Vec<_>>(), [serial], "{:?}", policy);
+            let atoms: Vec<_> = resolved_pdb.atoms().collect();
+            assert_eq!(atoms.len(), 3, "{:?}", policy);
+            let og = atoms.iter().find(|a| a.name() == "OG").unwrap();
+            assert!((og.y() - y).abs() < 1e-9, "{:?}", policy);
+        }
+    }
+}
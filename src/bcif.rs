@@ -0,0 +1,311 @@
+use crate::input::{build_pdb, AtomRecord};
+use crate::msgpack::{self, Value};
+use anyhow::{anyhow, bail, Context, Result};
+use pdbtbx::PDB;
+
+
+/// A column decoded from BinaryCIF's encodings.
+enum Data {
+    Bytes(Vec<u8>),
+    Ints(Vec<i64>),
+    Floats(Vec<f64>),
+    Strings(Vec<String>),
+}
+
+impl Data {
+    fn ints(self) -> Result<Vec<i64>> {
+        match self {
+            Data::Ints(v) => Ok(v),
+            _ => bail!("expected integer data"),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Data::Bytes(v) => v.len(),
+            Data::Ints(v) => v.len(),
+            Data::Floats(v) => v.len(),
+            Data::Strings(v) => v.len(),
+        }
+    }
+}
+
+
+fn param<'a>(encoding: &'a Value, key: &str) -> Result<&'a Value> {
+    encoding.get(key).ok_or_else(|| anyhow!("encoding is missing {}", key))
+}
+
+
+fn int_param(encoding: &Value, key: &str) -> Result<i64> {
+    param(encoding, key)?.as_int().ok_or_else(|| anyhow!("{} is not an integer", key))
+}
+
+
+fn float_param(encoding: &Value, key: &str) -> Result<f64> {
+    param(encoding, key)?.as_float().ok_or_else(|| anyhow!("{} is not a number", key))
+}
+
+
+/// Interprets little-endian bytes as an array of the given BinaryCIF type.
+fn byte_array(bytes: &[u8], data_type: i64) -> Result<Data> {
+    macro_rules! read {
+        ($t:ty, $variant:ident, $as:ty) => {
+            Data::$variant(bytes.chunks_exact(std::mem::size_of::<$t>())
+                .map(|c| <$t>::from_le_bytes(c.try_into().unwrap()) as $as)
+                .collect())
+        };
+    }
+    Ok(match data_type {
+        1 => read!(i8, Ints, i64),
+        2 => read!(i16, Ints, i64),
+        3 => read!(i32, Ints, i64),
+        4 => read!(u8, Ints, i64),
+        5 => read!(u16, Ints, i64),
+        6 => read!(u32, Ints, i64),
+        32 => read!(f32, Floats, f64),
+        33 => read!(f64, Floats, f64),
+        _ => bail!("unsupported ByteArray type {}", data_type),
+    })
+}
+
+
+/// Reverses one encoding step.
+fn apply(encoding: &Value, data: Data) -> Result<Data> {
+    let kind = param(encoding, "kind")?.as_str().unwrap_or_default();
+    Ok(match (kind, data) {
+        ("ByteArray", Data::Bytes(bytes)) => byte_array(&bytes, int_param(encoding, "type")?)?,
+        ("FixedPoint", Data::Ints(ints)) => {
+            let factor = float_param(encoding, "factor")?;
+            Data::Floats(ints.into_iter().map(|i| i as f64 / factor).collect())
+        }
+        ("IntervalQuantization", Data::Ints(ints)) => {
+            let (min, max) = (float_param(encoding, "min")?, float_param(encoding, "max")?);
+            let steps = int_param(encoding, "numSteps")?;
+            let delta = (max - min) / (steps - 1).max(1) as f64;
+            Data::Floats(ints.into_iter().map(|i| min + delta * i as f64).collect())
+        }
+        ("RunLength", Data::Ints(ints)) => Data::Ints(
+            ints.chunks_exact(2).flat_map(|p| std::iter::repeat_n(p[0], p[1].max(0) as usize)).collect(),
+        ),
+        ("Delta", Data::Ints(ints)) => {
+            let origin = int_param(encoding, "origin")?;
+            Data::Ints(ints.into_iter().scan(origin, |acc, v| {
+                *acc += v;
+                Some(*acc)
+            }).collect())
+        }
+        ("IntegerPacking", Data::Ints(ints)) => {
+            let bytes = int_param(encoding, "byteCount")?;
+            let unsigned = matches!(encoding.get("isUnsigned"), Some(Value::Bool(true)));
+            let (min, max) = match (bytes, unsigned) {
+                (1, false) => (i8::MIN as i64, i8::MAX as i64),
+                (2, false) => (i16::MIN as i64, i16::MAX as i64),
+                (1, true) => (i64::MIN, u8::MAX as i64),
+                (2, true) => (i64::MIN, u16::MAX as i64),
+                _ => bail!("unsupported IntegerPacking byte count {}", bytes),
+            };
+            let mut out = Vec::new();
+            let mut acc = 0;
+            for v in ints {
+                acc += v;
+                if v != min && v != max {
+                    out.push(acc);
+                    acc = 0;
+                }
+            }
+            Data::Ints(out)
+        }
+        ("StringArray", data) => {
+            let indices = apply_all(param(encoding, "dataEncoding")?, data)?.ints()?;
+            let offsets = param(encoding, "offsets")?.as_bin().ok_or_else(|| anyhow!("offsets is not binary"))?;
+            let offsets = apply_all(param(encoding, "offsetEncoding")?, Data::Bytes(offsets.to_vec()))?.ints()?;
+            let strings = param(encoding, "stringData")?.as_str().unwrap_or_default();
+            let string = |i: i64| -> Option<&str> {
+                let i = usize::try_from(i).ok()?;
+                strings.get(*offsets.get(i)? as usize..*offsets.get(i + 1)? as usize)
+            };
+            Data::Strings(indices.into_iter().map(|i| string(i).unwrap_or_default().to_string()).collect())
+        }
+        (kind, _) => bail!("unsupported encoding {} for its input", kind),
+    })
+}
+
+
+/// Decodes data by reversing a list of encodings.
+fn apply_all(encodings: &Value, mut data: Data) -> Result<Data> {
+    for encoding in encodings.as_array().ok_or_else(|| anyhow!("encoding is not a list"))?.iter().rev() {
+        data = apply(encoding, data)?;
+    }
+    Ok(data)
+}
+
+
+/// Decodes an `EncodedData` map of binary data and its encodings.
+fn decode(encoded: &Value) -> Result<Data> {
+    let data = encoded.get("data").and_then(|d| d.as_bin()).ok_or_else(|| anyhow!("data is not binary"))?;
+    apply_all(encoded.get("encoding").ok_or_else(|| anyhow!("missing encoding"))?, Data::Bytes(data.to_vec()))
+}
+
+
+/// A decoded column with its mask. Masked values are `.` or `?` in mmCIF.
+struct Column {
+    data: Data,
+    present: Option<Vec<bool>>,
+}
+
+impl Column {
+    fn is_present(&self, row: usize) -> bool {
+        self.present.as_ref().is_none_or(|p| p[row])
+    }
+
+    fn string(&self, row: usize) -> Option<String> {
+        if !self.is_present(row) {
+            return None;
+        }
+        Some(match &self.data {
+            Data::Strings(v) => v[row].clone(),
+            Data::Ints(v) => v[row].to_string(),
+            Data::Floats(v) => v[row].to_string(),
+            Data::Bytes(v) => v[row].to_string(),
+        }).filter(|s| !s.is_empty())
+    }
+
+    fn float(&self, row: usize) -> Option<f64> {
+        if !self.is_present(row) {
+            return None;
+        }
+        match &self.data {
+            Data::Ints(v) => Some(v[row] as f64),
+            Data::Floats(v) => Some(v[row]),
+            Data::Strings(v) => v[row].parse().ok(),
+            Data::Bytes(v) => Some(v[row] as f64),
+        }
+    }
+}
+
+
+/// Reads a structure from the `atom_site` category of the first data block
+/// of BinaryCIF data (uncompressed MessagePack).
+pub fn read(data: &[u8]) -> Result<PDB> {
+    let root = msgpack::decode(data)?;
+    let block = root.get("dataBlocks").and_then(|b| b.as_array()).and_then(|b| b.first())
+        .ok_or_else(|| anyhow!("no data blocks"))?;
+    let category = block.get("categories").and_then(|c| c.as_array()).unwrap_or(&[]).iter()
+        .find(|c| c.get("name").and_then(|n| n.as_str()).map(|n| n.trim_start_matches('_')) == Some("atom_site"))
+        .ok_or_else(|| anyhow!("no atom_site category"))?;
+    let rows = category.get("rowCount").and_then(|r| r.as_int()).unwrap_or(0) as usize;
+
+    let mut columns = std::collections::HashMap::new();
+    for column in category.get("columns").and_then(|c| c.as_array()).unwrap_or(&[]) {
+        let name = column.get("name").and_then(|n| n.as_str()).unwrap_or_default();
+        let data = column.get("data").ok_or_else(|| anyhow!("column {} has no data", name))
+            .and_then(decode).with_context(|| format!("decoding column {}", name))?;
+        let present = match column.get("mask") {
+            None | Some(Value::Nil) => None,
+            Some(mask) => Some(decode(mask).and_then(Data::ints).with_context(|| format!("decoding mask of {}", name))?
+                .into_iter().map(|m| m == 0).collect::<Vec<_>>()),
+        };
+        if data.len() != rows || present.as_ref().is_some_and(|p| p.len() != rows) {
+            bail!("column {} doesn't have {} rows", name, rows);
+        }
+        columns.insert(name.to_string(), Column { data, present });
+    }
+    let string = |names: &[&str], row: usize| names.iter().find_map(|n| columns.get(*n)?.string(row));
+    let float = |names: &[&str], row: usize| names.iter().find_map(|n| columns.get(*n)?.float(row));
+
+    let atoms = (0..rows).map(|row| {
+        let coordinate = |axis: &str| float(&[axis], row).ok_or_else(|| anyhow!("atom in row {} has no {}", row, axis));
+        Ok(AtomRecord {
+            model: float(&["pdbx_PDB_model_num"], row).map_or(1, |m| m as usize),
+            chain: string(&["auth_asym_id", "label_asym_id"], row).unwrap_or_default(),
+            resseq: float(&["auth_seq_id", "label_seq_id"], row).map_or(0, |s| s as isize),
+            icode: string(&["pdbx_PDB_ins_code"], row),
+            resname: string(&["auth_comp_id", "label_comp_id"], row).unwrap_or_default(),
+            name: string(&["auth_atom_id", "label_atom_id"], row).unwrap_or_default(),
+            altloc: string(&["label_alt_id"], row),
+            element: string(&["type_symbol"], row).unwrap_or_default(),
+            pos: (coordinate("Cartn_x")?, coordinate("Cartn_y")?, coordinate("Cartn_z")?),
+            occupancy: float(&["occupancy"], row).unwrap_or(1.0),
+            b_factor: float(&["B_iso_or_equiv"], row).unwrap_or(0.0),
+            charge: float(&["pdbx_formal_charge"], row).map_or(0, |c| c as isize),
+            hetero: string(&["group_PDB"], row).as_deref() == Some("HETATM"),
+            serial_number: float(&["id"], row).map_or(row + 1, |i| i as usize),
+        })
+    }).collect::<Result<Vec<_>>>()?;
+    let identifier = block.get("header").and_then(|h| h.as_str()).map(|s| s.to_string());
+    build_pdb(identifier, atoms)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn str(s: &str) -> Value {
+        Value::Str(s.to_string())
+    }
+
+    fn map(entries: Vec<(&str, Value)>) -> Value {
+        Value::Map(entries.into_iter().map(|(k, v)| (str(k), v)).collect())
+    }
+
+    fn byte_array(data_type: i64) -> Value {
+        map(vec![("kind", str("ByteArray")), ("type", Value::Int(data_type))])
+    }
+
+    fn column(name: &str, data: Vec<u8>, encoding: Vec<Value>) -> Value {
+        map(vec![
+            ("name", str(name)),
+            ("data", map(vec![("data", Value::Bin(data)), ("encoding", Value::Array(encoding))])),
+            ("mask", Value::Nil),
+        ])
+    }
+
+    #[test]
+    fn reads_encoded_atom_site() {
+        // Fixed point 1.5, 2.25 packed as int32 with factor 100
+        let x: Vec<u8> = [150i32, 225].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let fixed = vec![map(vec![("kind", str("FixedPoint")), ("factor", Value::Int(100))]), byte_array(3)];
+        // Sequence ids 7, 7 as run length [7, 2]
+        let seq: Vec<u8> = [7i32, 2].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let run_length = vec![map(vec![("kind", str("RunLength"))]), byte_array(3)];
+        // Atom names N and CA as indices into "NCA"
+        let names = map(vec![
+            ("kind", str("StringArray")),
+            ("dataEncoding", Value::Array(vec![byte_array(4)])),
+            ("stringData", str("NCA")),
+            ("offsetEncoding", Value::Array(vec![byte_array(4)])),
+            ("offsets", Value::Bin(vec![0, 1, 3])),
+        ]);
+        let chain = map(vec![
+            ("kind", str("StringArray")),
+            ("dataEncoding", Value::Array(vec![byte_array(4)])),
+            ("stringData", str("A")),
+            ("offsetEncoding", Value::Array(vec![byte_array(4)])),
+            ("offsets", Value::Bin(vec![0, 1])),
+        ]);
+        let zeros: Vec<u8> = [0f64, 0.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let atom_site = map(vec![
+            ("name", str("_atom_site")),
+            ("rowCount", Value::Int(2)),
+            ("columns", Value::Array(vec![
+                column("Cartn_x", x, fixed),
+                column("Cartn_y", zeros.clone(), vec![byte_array(33)]),
+                column("Cartn_z", zeros, vec![byte_array(33)]),
+                column("auth_seq_id", seq, run_length),
+                column("auth_atom_id", vec![0, 1], vec![names.clone()]),
+                column("type_symbol", vec![0, 1], vec![names]),
+                column("auth_asym_id", vec![0, 0], vec![chain]),
+            ])),
+        ]);
+        let root = map(vec![(
+            "dataBlocks",
+            Value::Array(vec![map(vec![("header", str("1ABC")), ("categories", Value::Array(vec![atom_site]))])]),
+        )]);
+        let pdb = read(&msgpack::encode(&root)).unwrap();
+        let atoms: Vec<(String, f64)> = pdb.atoms().map(|a| (a.name().to_string(), a.x())).collect();
+        assert_eq!(atoms, vec![("N".to_string(), 1.5), ("CA".to_string(), 2.25)]);
+        assert_eq!(pdb.residue_count(), 1);
+        assert_eq!(pdb.residues().next().unwrap().serial_number(), 7);
+    }
+}
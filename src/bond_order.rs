@@ -0,0 +1,211 @@
+use crate::hbond::bonded;
+use pdbtbx::{Atom, Element, Residue};
+use serde::{Deserialize, Serialize};
+
+
+/// Order of a covalent bond.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum BondOrder {
+    Single,
+    Double,
+    Triple,
+    Aromatic,
+}
+
+pub const BOND_ORDERS: [&str; 4] = ["single", "double", "triple", "aromatic"];
+
+
+/// Bond between two atoms of a residue template, by atom name.
+type TemplateBond = (&'static str, &'static str, BondOrder);
+
+use BondOrder::{Aromatic, Double};
+
+const PEPTIDE: &[TemplateBond] = &[("C", "O", Double)];
+const PHOSPHATE: &[TemplateBond] = &[("P", "OP1", Double)];
+
+const ARG: &[TemplateBond] = &[("CZ", "NH2", Double)];
+const ASN: &[TemplateBond] = &[("CG", "OD1", Double)];
+const ASP: &[TemplateBond] = &[("CG", "OD1", Double)];
+const GLN: &[TemplateBond] = &[("CD", "OE1", Double)];
+const GLU: &[TemplateBond] = &[("CD", "OE1", Double)];
+const HIS: &[TemplateBond] = &[
+    ("CG", "ND1", Aromatic), ("ND1", "CE1", Aromatic), ("CE1", "NE2", Aromatic), ("NE2", "CD2", Aromatic), ("CD2", "CG", Aromatic),
+];
+const PHE: &[TemplateBond] = &[
+    ("CG", "CD1", Aromatic), ("CD1", "CE1", Aromatic), ("CE1", "CZ", Aromatic),
+    ("CZ", "CE2", Aromatic), ("CE2", "CD2", Aromatic), ("CD2", "CG", Aromatic),
+];
+const TRP: &[TemplateBond] = &[
+    ("CG", "CD1", Aromatic), ("CD1", "NE1", Aromatic), ("NE1", "CE2", Aromatic), ("CE2", "CD2", Aromatic), ("CD2", "CG", Aromatic),
+    ("CE2", "CZ2", Aromatic), ("CZ2", "CH2", Aromatic), ("CH2", "CZ3", Aromatic), ("CZ3", "CE3", Aromatic), ("CE3", "CD2", Aromatic),
+];
+
+const PURINE: &[TemplateBond] = &[
+    ("N9", "C8", Aromatic), ("C8", "N7", Aromatic), ("N7", "C5", Aromatic), ("C5", "C6", Aromatic), ("C6", "N1", Aromatic),
+    ("N1", "C2", Aromatic), ("C2", "N3", Aromatic), ("N3", "C4", Aromatic), ("C4", "C5", Aromatic), ("C4", "N9", Aromatic),
+    ("C6", "O6", Double),
+];
+const PYRIMIDINE: &[TemplateBond] = &[
+    ("N1", "C2", Aromatic), ("C2", "N3", Aromatic), ("N3", "C4", Aromatic), ("C4", "C5", Aromatic), ("C5", "C6", Aromatic),
+    ("C6", "N1", Aromatic), ("C2", "O2", Double), ("C4", "O4", Double),
+];
+
+
+/// Non-single bonds of a standard residue, from the Chemical Component
+/// Dictionary. Bonds of a template residue that aren't listed are single.
+/// Purines and pyrimidines share one template each, as their carbonyl
+/// oxygens are named the same where they exist.
+fn template(residue: &str) -> Option<[&'static [TemplateBond]; 2]> {
+    let template = match residue {
+        "ALA" | "CYS" | "GLY" | "ILE" | "LEU" | "LYS" | "MET" | "MSE" | "PRO" | "SER" | "THR" | "VAL" => [PEPTIDE, &[]],
+        "ARG" => [PEPTIDE, ARG],
+        "ASN" => [PEPTIDE, ASN],
+        "ASP" => [PEPTIDE, ASP],
+        "GLN" => [PEPTIDE, GLN],
+        "GLU" => [PEPTIDE, GLU],
+        "HIS" => [PEPTIDE, HIS],
+        "PHE" | "TYR" => [PEPTIDE, PHE],
+        "TRP" => [PEPTIDE, TRP],
+        "A" | "G" | "DA" | "DG" => [PHOSPHATE, PURINE],
+        "C" | "U" | "DC" | "DT" => [PHOSPHATE, PYRIMIDINE],
+        _ => return None,
+    };
+    Some(template)
+}
+
+
+/// Usual valence of an atom with `degree` bonded neighbours, adjusted for
+/// its formal charge. Elements with several valences take the smallest
+/// one that fits the neighbours; unknown elements have no free valence.
+fn valence(atom: &Atom, degree: usize) -> usize {
+    let charge = atom.charge();
+    let fitting = |valences: &[usize]| valences.iter().copied().find(|v| *v >= degree).unwrap_or(degree);
+    let valence = match atom.element() {
+        Some(Element::H | Element::F | Element::Cl | Element::Br | Element::I) => 1,
+        Some(Element::C) => 4 - charge.abs(),
+        Some(Element::N) => 3 + charge,
+        Some(Element::O) => 2 + charge,
+        Some(Element::B) => 3,
+        Some(Element::S | Element::Se) => return fitting(&[2, 4, 6]),
+        Some(Element::P) => return fitting(&[3, 5]),
+        _ => return degree,
+    };
+    valence.max(0) as usize
+}
+
+
+/// Bond orders that saturate the valences of `atoms` with their `bonds`:
+/// starting from single bonds, bonds between atoms with free valence are
+/// raised one at a time, the bond of the atom with the fewest such bonds
+/// first and the shortest bond among ties, which gives the Kekulé form of
+/// rings and conjugated chains. Only meaningful with explicit hydrogens.
+fn saturate(atoms: &[&Atom], bonds: &[(usize, usize)]) -> Vec<BondOrder> {
+    let mut degree = vec![0; atoms.len()];
+    for &(i, j) in bonds {
+        degree[i] += 1;
+        degree[j] += 1;
+    }
+    let mut free: Vec<usize> = atoms.iter().zip(&degree).map(|(atom, d)| valence(atom, *d).saturating_sub(*d)).collect();
+    let mut orders = vec![1; bonds.len()];
+    loop {
+        let open: Vec<usize> = (0..bonds.len()).filter(|&b| free[bonds[b].0] > 0 && free[bonds[b].1] > 0).collect();
+        let mut open_bonds = vec![0; atoms.len()];
+        for &b in &open {
+            open_bonds[bonds[b].0] += 1;
+            open_bonds[bonds[b].1] += 1;
+        }
+        let Some(&raised) = open.iter().min_by(|&&x, &&y| {
+            let key = |b: usize| (open_bonds[bonds[b].0].min(open_bonds[bonds[b].1]), atoms[bonds[b].0].distance(atoms[bonds[b].1]));
+            let ((fx, dx), (fy, dy)) = (key(x), key(y));
+            fx.cmp(&fy).then(dx.total_cmp(&dy))
+        }) else {
+            break;
+        };
+        orders[raised] += 1;
+        free[bonds[raised].0] -= 1;
+        free[bonds[raised].1] -= 1;
+    }
+    orders.into_iter().map(|order| match order {
+        1 => BondOrder::Single,
+        2 => BondOrder::Double,
+        _ => BondOrder::Triple,
+    }).collect()
+}
+
+
+/// Orders of the covalent bonds within each conformer of `residue`, with
+/// bonds detected as for edges with `tolerance`. Standard amino acids and
+/// nucleotides take their orders from templates; other residues with
+/// explicit hydrogens from their valences, and those without hydrogens get
+/// none, as their valences can't be told apart from missing atoms.
+pub fn residue_bond_orders(residue: &Residue, tolerance: f64) -> Vec<(&Atom, &Atom, BondOrder)> {
+    let mut found = Vec::new();
+    for conformer in residue.conformers() {
+        let atoms: Vec<&Atom> = conformer.atoms().collect();
+        let bonds: Vec<(usize, usize)> = (0..atoms.len())
+            .flat_map(|i| (i + 1..atoms.len()).map(move |j| (i, j)))
+            .filter(|&(i, j)| bonded(atoms[i], atoms[j], tolerance))
+            .collect();
+        let orders = match template(conformer.name()) {
+            Some(template) => bonds.iter().map(|&(i, j)| {
+                let (a, b) = (atoms[i].name(), atoms[j].name());
+                template.iter().flat_map(|bonds| bonds.iter())
+                    .find(|(x, y, _)| (*x == a && *y == b) || (*x == b && *y == a))
+                    .map_or(BondOrder::Single, |bond| bond.2)
+            }).collect(),
+            None if atoms.iter().any(|a| a.element() == Some(&Element::H)) => saturate(&atoms, &bonds),
+            None => continue,
+        };
+        found.extend(bonds.iter().zip(orders).map(|(&(i, j), order)| (atoms[i], atoms[j], order)));
+    }
+    found
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdbtbx::{Conformer, Residue};
+
+    fn residue(name: &str, atoms: &[(&str, &str, [f64; 3])]) -> Residue {
+        let mut conformer = Conformer::new(name, None, None).unwrap();
+        for (i, (atom, element, [x, y, z])) in atoms.iter().enumerate() {
+            conformer.add_atom(Atom::new(true, i + 1, *atom, *x, *y, *z, 1.0, 20.0, *element, 0).unwrap());
+        }
+        let mut residue = Residue::new(1, None, None).unwrap();
+        residue.add_conformer(conformer);
+        residue
+    }
+
+    fn order(orders: &[(&Atom, &Atom, BondOrder)], a: &str, b: &str) -> Option<BondOrder> {
+        orders.iter().find(|(x, y, _)| (x.name() == a && y.name() == b) || (x.name() == b && y.name() == a)).map(|o| o.2)
+    }
+
+    #[test]
+    fn templates_and_valences() {
+        let asp = residue("ASP", &[
+            ("C", "C", [0.0, 0.0, 0.0]), ("O", "O", [1.23, 0.0, 0.0]), ("CA", "C", [-0.77, 1.27, 0.0]),
+            ("CB", "C", [-2.29, 1.05, 0.0]), ("CG", "C", [-3.06, 2.32, 0.0]),
+            ("OD1", "O", [-2.45, 3.39, 0.0]), ("OD2", "O", [-4.31, 2.27, 0.0]),
+        ]);
+        let orders = residue_bond_orders(&asp, 0.45);
+        assert_eq!(order(&orders, "C", "O"), Some(BondOrder::Double));
+        assert_eq!(order(&orders, "CG", "OD1"), Some(BondOrder::Double));
+        assert_eq!(order(&orders, "CG", "OD2"), Some(BondOrder::Single));
+        assert_eq!(order(&orders, "CA", "CB"), Some(BondOrder::Single));
+
+        // Ethene with its hydrogens, and without them
+        let ethene = [
+            ("C1", "C", [0.0, 0.0, 0.0]), ("C2", "C", [1.33, 0.0, 0.0]),
+            ("H1", "H", [-0.55, 0.93, 0.0]), ("H2", "H", [-0.55, -0.93, 0.0]),
+            ("H3", "H", [1.88, 0.93, 0.0]), ("H4", "H", [1.88, -0.93, 0.0]),
+        ];
+        let ete = residue("ETE", &ethene);
+        let orders = residue_bond_orders(&ete, 0.45);
+        assert_eq!(orders.len(), 5);
+        assert_eq!(order(&orders, "C1", "C2"), Some(BondOrder::Double));
+        assert_eq!(order(&orders, "C1", "H1"), Some(BondOrder::Single));
+        assert!(residue_bond_orders(&residue("ETE", &ethene[..2]), 0.45).is_empty());
+    }
+}
@@ -0,0 +1,81 @@
+use crate::AtomNode;
+use petgraph::graph::{Graph, NodeIndex};
+use std::cmp::Ordering;
+
+
+/// Orders atoms by where they came from: structure, then serial number.
+/// Atoms that compare equal keep their relative order.
+pub fn provenance_order(a: &AtomNode, b: &AtomNode) -> Ordering {
+    (a.structure_id.as_deref(), a.id).cmp(&(b.structure_id.as_deref(), b.id))
+}
+
+
+/// Rebuilds `graph` with nodes sorted by `order` and edges sorted by their
+/// renumbered source and target, so graphs with the same content are
+/// serialized identically however they were built.
+pub fn canonicalize_by<N, E>(graph: Graph<N, E>, order: impl Fn(&N, &N) -> Ordering) -> Graph<N, E> {
+    let (nodes, edges) = graph.into_nodes_edges();
+    let mut nodes: Vec<(usize, N)> = nodes.into_iter().map(|n| n.weight).enumerate().collect();
+    nodes.sort_by(|(_, a), (_, b)| order(a, b));
+    let mut renumbered = vec![0; nodes.len()];
+    for (new, (old, _)) in nodes.iter().enumerate() {
+        renumbered[*old] = new;
+    }
+    let mut edges: Vec<(usize, usize, E)> = edges.into_iter()
+        .map(|e| (renumbered[e.source().index()], renumbered[e.target().index()], e.weight))
+        .collect();
+    edges.sort_by_key(|(source, target, _)| (*source, *target));
+
+    let mut canonical = Graph::with_capacity(nodes.len(), edges.len());
+    for (_, node) in nodes {
+        canonical.add_node(node);
+    }
+    for (source, target, edge) in edges {
+        canonical.add_edge(NodeIndex::new(source), NodeIndex::new(target), edge);
+    }
+    canonical
+}
+
+
+/// Canonical form of an atom graph, with nodes in provenance order.
+pub fn canonicalize<E>(graph: Graph<AtomNode, E>) -> Graph<AtomNode, E> {
+    canonicalize_by(graph, provenance_order)
+}
+
+
+/// Canonical form of a graph whose nodes are already built in a
+/// deterministic order, such as residue graphs: only edges are sorted.
+pub fn sort_edges<N, E>(graph: Graph<N, E>) -> Graph<N, E> {
+    canonicalize_by(graph, |_, _| Ordering::Equal)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_content_same_order() {
+        let mut a = Graph::<(&str, usize), f64>::new();
+        let x = a.add_node(("1abc", 2));
+        let y = a.add_node(("1abc", 1));
+        let z = a.add_node(("0xyz", 5));
+        a.add_edge(x, y, 1.0);
+        a.add_edge(y, x, 1.0);
+        a.add_edge(z, x, 2.0);
+        let mut b = Graph::<(&str, usize), f64>::new();
+        let z = b.add_node(("0xyz", 5));
+        let y = b.add_node(("1abc", 1));
+        let x = b.add_node(("1abc", 2));
+        b.add_edge(z, x, 2.0);
+        b.add_edge(y, x, 1.0);
+        b.add_edge(x, y, 1.0);
+
+        let a = canonicalize_by(a, |p, q| p.cmp(q));
+        let b = canonicalize_by(b, |p, q| p.cmp(q));
+        assert_eq!(a.node_weights().collect::<Vec<_>>(), [&("0xyz", 5), &("1abc", 1), &("1abc", 2)]);
+        assert_eq!(serde_json::to_string(&a).unwrap(), serde_json::to_string(&b).unwrap());
+        let edges: Vec<_> = a.raw_edges().iter().map(|e| (e.source().index(), e.target().index())).collect();
+        assert_eq!(edges, [(0, 2), (1, 2), (2, 1)]);
+    }
+}
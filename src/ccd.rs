@@ -0,0 +1,454 @@
+use crate::bond_order::BondOrder;
+use crate::entity::{classify_residue, EntityType};
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use pdbtbx::{Element, PDB};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+
+/// Atom of a chemical component.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentAtom {
+    pub name: String,
+    pub element: String,
+    pub charge: isize,
+    pub aromatic: bool,
+}
+
+
+/// Chemical description of a residue type from the Chemical Component
+/// Dictionary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Component {
+    pub id: String,
+    pub atoms: Vec<ComponentAtom>,
+    /// Bonds between atoms by name. Bonds flagged aromatic have order
+    /// `Aromatic` whatever their Kekulé order.
+    pub bonds: Vec<(String, String, BondOrder)>,
+}
+
+
+/// Tags read from a dictionary, all others are skipped.
+const TAGS: [&str; 9] = [
+    "_chem_comp.id",
+    "_chem_comp_atom.atom_id",
+    "_chem_comp_atom.type_symbol",
+    "_chem_comp_atom.charge",
+    "_chem_comp_atom.pdbx_aromatic_flag",
+    "_chem_comp_bond.atom_id_1",
+    "_chem_comp_bond.atom_id_2",
+    "_chem_comp_bond.value_order",
+    "_chem_comp_bond.pdbx_aromatic_flag",
+];
+
+
+/// Values of the CIF text `line`, with whether each was quoted. Unquoted
+/// values starting with `_`, `loop_` or `data_` are tags and keywords.
+fn tokens(line: &str) -> Vec<(&str, bool)> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() && !rest.starts_with('#') {
+        let quote = rest.chars().next().filter(|c| *c == '\'' || *c == '"');
+        let (token, quoted, remainder) = match quote {
+            // A quote only closes a value when followed by whitespace
+            Some(q) => match rest[1..].match_indices(q).find(|(i, _)| rest[i + 2..].chars().next().is_none_or(char::is_whitespace)) {
+                Some((i, _)) => (&rest[1..i + 1], true, &rest[i + 2..]),
+                None => (&rest[1..], true, ""),
+            },
+            None => {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                (&rest[..end], false, &rest[end..])
+            }
+        };
+        tokens.push((token, quoted));
+        rest = remainder.trim_start();
+    }
+    tokens
+}
+
+
+/// The chemical component dictionary, or the part of it that was read.
+#[derive(Debug, Default)]
+pub struct Dictionary {
+    pub path: String,
+    components: HashMap<String, Component>,
+}
+
+impl Dictionary {
+    /// Reads a dictionary in the format of the PDB's `components.cif`,
+    /// which may be gzipped, or a file of some of its entries.
+    pub fn read(path: &Path) -> Result<Dictionary> {
+        let file = std::fs::File::open(path).with_context(|| format!("failed to open CCD {}", path.display()))?;
+        let reader: Box<dyn BufRead> = match path.extension().is_some_and(|e| e == "gz") {
+            true => Box::new(BufReader::new(GzDecoder::new(file))),
+            false => Box::new(BufReader::new(file)),
+        };
+        let dictionary = Dictionary::parse(&path.display().to_string(), reader)?;
+        if dictionary.components.is_empty() {
+            bail!("{} holds no chemical components", path.display());
+        }
+        Ok(dictionary)
+    }
+
+    pub fn parse(path: &str, reader: impl BufRead) -> Result<Dictionary> {
+        let mut dictionary = Dictionary { path: path.to_string(), components: HashMap::new() };
+        // Columns of the current block by tag, and the tags of the current loop
+        let mut columns: HashMap<&'static str, Vec<String>> = HashMap::new();
+        let mut header: Vec<Option<&'static str>> = Vec::new();
+        let (mut in_header, mut column) = (false, 0);
+        let mut pending: Option<&'static str> = None;
+        let mut text: Option<String> = None;
+        for (number, line) in reader.lines().enumerate() {
+            let line = line.with_context(|| format!("failed to read {}", path))?;
+            // Multi-line text values between lines starting with a semicolon
+            if let Some(value) = &mut text {
+                if line.starts_with(';') {
+                    let value = text.take().unwrap();
+                    Dictionary::value(&mut columns, &header, &mut column, &mut pending, value);
+                } else {
+                    value.push_str(&line);
+                }
+                continue;
+            }
+            if let Some(start) = line.strip_prefix(';') {
+                text = Some(start.to_string());
+                in_header = false;
+                continue;
+            }
+            for (token, quoted) in tokens(&line) {
+                if !quoted && token.starts_with("data_") {
+                    dictionary.finish(std::mem::take(&mut columns));
+                    header.clear();
+                } else if !quoted && token == "loop_" {
+                    header.clear();
+                    (in_header, column) = (true, 0);
+                } else if !quoted && token.starts_with('_') {
+                    let tag = TAGS.iter().copied().find(|t| *t == token);
+                    if in_header {
+                        header.push(tag);
+                    } else {
+                        header.clear();
+                        pending = Some(tag.unwrap_or(""));
+                    }
+                } else {
+                    if pending.is_none() && header.is_empty() {
+                        bail!("{} line {}: value {} without a tag", path, number + 1, token);
+                    }
+                    in_header = false;
+                    Dictionary::value(&mut columns, &header, &mut column, &mut pending, token.to_string());
+                }
+            }
+        }
+        dictionary.finish(columns);
+        Ok(dictionary)
+    }
+
+    /// Stores a value of the item `pending` or of the current loop.
+    fn value(columns: &mut HashMap<&'static str, Vec<String>>, header: &[Option<&'static str>], column: &mut usize, pending: &mut Option<&'static str>, value: String) {
+        let tag = match pending.take() {
+            Some(tag) => Some(tag).filter(|t| !t.is_empty()),
+            None if header.is_empty() => None,
+            None => {
+                let tag = header[*column % header.len()];
+                *column += 1;
+                tag
+            }
+        };
+        if let Some(tag) = tag {
+            columns.entry(tag).or_default().push(value);
+        }
+    }
+
+    /// Turns the columns of a data block into a component.
+    fn finish(&mut self, columns: HashMap<&'static str, Vec<String>>) {
+        let Some(id) = columns.get("_chem_comp.id").and_then(|ids| ids.first()) else {
+            return;
+        };
+        let column = |tag: &str| columns.get(tag).map_or(&[][..], |values| values.as_slice());
+        let flag = |values: &[String], i: usize| values.get(i).is_some_and(|v| v == "Y");
+        let (charges, aromatic) = (column("_chem_comp_atom.charge"), column("_chem_comp_atom.pdbx_aromatic_flag"));
+        let atoms = column("_chem_comp_atom.atom_id").iter().zip(column("_chem_comp_atom.type_symbol")).enumerate().map(|(i, (name, element))| {
+            ComponentAtom {
+                name: name.clone(),
+                element: element.clone(),
+                charge: charges.get(i).and_then(|c| c.parse().ok()).unwrap_or(0),
+                aromatic: flag(aromatic, i),
+            }
+        }).collect();
+        let (orders, aromatic) = (column("_chem_comp_bond.value_order"), column("_chem_comp_bond.pdbx_aromatic_flag"));
+        let bonds = column("_chem_comp_bond.atom_id_1").iter().zip(column("_chem_comp_bond.atom_id_2")).enumerate().map(|(i, (a, b))| {
+            let order = match orders.get(i).map(|o| o.to_ascii_uppercase()).as_deref() {
+                _ if flag(aromatic, i) => BondOrder::Aromatic,
+                Some("DOUB") => BondOrder::Double,
+                Some("TRIP") => BondOrder::Triple,
+                Some("AROM") => BondOrder::Aromatic,
+                _ => BondOrder::Single,
+            };
+            (a.clone(), b.clone(), order)
+        }).collect();
+        self.components.insert(id.clone(), Component { id: id.clone(), atoms, bonds });
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Component> {
+        self.components.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+}
+
+
+/// Dictionary data of an atom of a residue resolved in the CCD.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CcdAtom {
+    /// The atom is part of an aromatic system
+    pub aromatic: bool,
+    /// Index of the atom's residue among the resolved residues of its
+    /// structure, to tell bonds within a residue from those between residues
+    #[serde(skip)]
+    pub residue: usize,
+}
+
+
+/// Dictionary data of the atoms of a structure, by serial number.
+#[derive(Debug, Default)]
+pub struct CcdAnnotations {
+    pub atoms: HashMap<usize, CcdAtom>,
+    /// Dictionary bonds within resolved residues, in both directions
+    pub bonds: HashMap<(usize, usize), BondOrder>,
+}
+
+
+/// Resolution of the hetero residues of a structure and validation of the
+/// atom names of all its residues.
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct CcdInfo {
+    /// Hetero residues resolved, by component id
+    pub resolved: BTreeMap<String, usize>,
+    /// Residue names not in the dictionary
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub missing: BTreeSet<String>,
+    /// Atoms renamed to the names of the dictionary, by component id and
+    /// original name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub renamed: BTreeMap<String, BTreeMap<String, String>>,
+    /// Atom names not in the component of their residue, by component id
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub unknown_atoms: BTreeMap<String, BTreeSet<String>>,
+}
+
+
+/// Legacy and force field atom names, with the names of the dictionary
+/// they stand for.
+const ALIASES: [(&str, &str); 12] = [
+    ("OT1", "O"), ("OT2", "OXT"), ("OT", "OXT"), ("HN", "H"), ("HT1", "H"), ("HT2", "H2"), ("HT3", "H3"),
+    ("O1P", "OP1"), ("O2P", "OP2"), ("O3P", "OP3"), ("C5M", "C7"), ("CD", "CD1"),
+];
+
+
+/// Names of the dictionary for the atoms `names` of a residue of
+/// `component`, `None` for names that are already in it or can't be
+/// matched. Unknown names are tried as aliases, with asterisks for primes
+/// and with the leading digits of PDB version 2 hydrogen names moved to
+/// the end, e.g. `1HG1` to `HG11`. Groups of hydrogens numbered from 1
+/// where the dictionary numbers from 2, e.g. `HB1` and `HB2` of a
+/// methylene, are shifted up by one. Names two atoms would share are kept.
+fn normalize_names(component: &Component, names: &[&str]) -> Vec<Option<String>> {
+    let known = |name: &str| component.atoms.iter().any(|a| a.name == name);
+    let mut candidates: Vec<String> = names.iter().map(|&name| {
+        if known(name) {
+            return name.to_string();
+        }
+        let name = name.replace('*', "'");
+        if let Some((_, alias)) = ALIASES.iter().find(|(legacy, alias)| *legacy == name && known(alias)) {
+            return alias.to_string();
+        }
+        let digits = name.len() - name.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        format!("{}{}", &name[digits..], &name[..digits])
+    }).collect();
+
+    let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, candidate) in candidates.iter().enumerate() {
+        if candidate.starts_with('H') && candidate.ends_with(|c: char| c.is_ascii_digit()) {
+            groups.entry(candidate[..candidate.len() - 1].to_string()).or_default().push(i);
+        }
+    }
+    let shifted = |name: &str| {
+        let digit = name[name.len() - 1..].parse::<usize>().ok().filter(|d| *d < 9)?;
+        Some(format!("{}{}", &name[..name.len() - 1], digit + 1))
+    };
+    for members in groups.values() {
+        let renamed: Vec<Option<String>> = members.iter().map(|&i| shifted(&candidates[i]).filter(|name| known(name))).collect();
+        if members.iter().any(|&i| !known(&candidates[i])) && renamed.iter().all(Option::is_some) {
+            for (&i, name) in members.iter().zip(renamed) {
+                candidates[i] = name.unwrap();
+            }
+        }
+    }
+
+    names.iter().zip(&candidates).map(|(&name, candidate)| {
+        let unique = candidates.iter().filter(|c| *c == candidate).count() == 1;
+        (name != candidate && known(candidate) && unique).then(|| candidate.clone())
+    }).collect()
+}
+
+
+/// Resolves the residues of `pdb` other than waters in `dictionary` by
+/// residue name. Atoms are renamed to the names of the dictionary where
+/// their own aren't in it, see `normalize_names`. Atoms of resolved hetero
+/// residues without an element get the element of the dictionary and those
+/// with no charge its formal charge; the dictionary's aromaticity and bonds
+/// are returned for the graph.
+pub fn resolve(pdb: &mut PDB, dictionary: &Dictionary) -> (CcdAnnotations, CcdInfo) {
+    let mut annotations = CcdAnnotations::default();
+    let mut info = CcdInfo::default();
+    let mut index = 0;
+    for residue in pdb.residues_mut() {
+        if classify_residue(residue) == EntityType::Water {
+            continue;
+        }
+        for conformer in residue.conformers_mut() {
+            let Some(component) = dictionary.get(conformer.name()) else {
+                info.missing.insert(conformer.name().to_string());
+                continue;
+            };
+            let names: Vec<String> = conformer.atoms().map(|a| a.name().to_string()).collect();
+            let renames = normalize_names(component, &names.iter().map(String::as_str).collect::<Vec<_>>());
+            for ((atom, name), rename) in conformer.atoms_mut().zip(&names).zip(renames) {
+                match rename {
+                    Some(new) if atom.set_name(new.as_str()).is_ok() => {
+                        info.renamed.entry(component.id.clone()).or_default().insert(name.clone(), new);
+                    }
+                    _ if component.atoms.iter().all(|a| &a.name != name) => {
+                        info.unknown_atoms.entry(component.id.clone()).or_default().insert(name.clone());
+                    }
+                    _ => {}
+                }
+            }
+            if !conformer.atoms().all(|a| a.hetero()) {
+                continue;
+            }
+            index += 1;
+            *info.resolved.entry(component.id.clone()).or_default() += 1;
+            let mut serials = HashMap::new();
+            for atom in conformer.atoms_mut() {
+                let Some(entry) = component.atoms.iter().find(|a| a.name == atom.name()) else {
+                    continue;
+                };
+                if atom.element().is_none() {
+                    if let Some(element) = Element::from_symbol(&entry.element) {
+                        atom.set_element(element);
+                    }
+                }
+                if atom.charge() == 0 {
+                    atom.set_charge(entry.charge);
+                }
+                annotations.atoms.insert(atom.serial_number(), CcdAtom { aromatic: entry.aromatic, residue: index });
+                serials.insert(entry.name.as_str(), atom.serial_number());
+            }
+            for (a, b, order) in &component.bonds {
+                if let (Some(&a), Some(&b)) = (serials.get(a.as_str()), serials.get(b.as_str())) {
+                    annotations.bonds.insert((a, b), *order);
+                    annotations.bonds.insert((b, a), *order);
+                }
+            }
+        }
+    }
+    (annotations, info)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden::Fixture;
+
+    const BENZOATE: &str = r#"data_BEZ
+#
+_chem_comp.id                                    BEZ
+_chem_comp.name                                  "BENZOIC ACID"
+_chem_comp.pdbx_synonyms                         ?
+#
+loop_
+_chem_comp_atom.comp_id
+_chem_comp_atom.atom_id
+_chem_comp_atom.type_symbol
+_chem_comp_atom.charge
+_chem_comp_atom.pdbx_aromatic_flag
+BEZ C7  C 0 N
+BEZ O1  O 0 N
+BEZ O2  O 0 N
+BEZ C1  C 0 Y
+BEZ C2  C 0 Y
+BEZ C3  C 0 Y
+BEZ C4  C 0 Y
+BEZ C5  C 0 Y
+BEZ C6  C 0 Y
+#
+loop_
+_chem_comp_bond.comp_id
+_chem_comp_bond.atom_id_1
+_chem_comp_bond.atom_id_2
+_chem_comp_bond.value_order
+_chem_comp_bond.pdbx_aromatic_flag
+BEZ C7 O1 DOUB N
+BEZ C7 O2 SING N
+BEZ C7 C1 SING N
+BEZ C1 C2 DOUB Y
+BEZ C2 C3 SING Y
+BEZ C3 C4 DOUB Y
+BEZ C4 C5 SING Y
+BEZ C5 C6 DOUB Y
+BEZ C6 C1 SING Y
+#
+data_ZN
+_chem_comp.id ZN
+_chem_comp.name
+;ZINC ION
+;
+_chem_comp_atom.comp_id ZN
+_chem_comp_atom.atom_id ZN
+_chem_comp_atom.type_symbol ZN
+_chem_comp_atom.charge 2
+_chem_comp_atom.pdbx_aromatic_flag N
+"#;
+
+    #[test]
+    fn resolves_hetero_residues() {
+        let dictionary = Dictionary::parse("components.cif", BENZOATE.as_bytes()).unwrap();
+        assert_eq!(dictionary.len(), 2);
+        let zinc = dictionary.get("ZN").unwrap();
+        assert_eq!(zinc.atoms, [ComponentAtom { name: "ZN".into(), element: "ZN".into(), charge: 2, aromatic: false }]);
+        let benzoate = dictionary.get("BEZ").unwrap();
+        assert_eq!(benzoate.bonds[0], ("C7".to_string(), "O1".to_string(), BondOrder::Double));
+        assert_eq!(benzoate.bonds[3].2, BondOrder::Aromatic);
+
+        let mut pdb = Fixture::get("benzoate_stacking").unwrap().structure().unwrap();
+        let (annotations, info) = resolve(&mut pdb, &dictionary);
+        assert_eq!(info.resolved, BTreeMap::from([("BEZ".to_string(), 1)]));
+        let ring = pdb.atoms().filter(|a| annotations.atoms.get(&a.serial_number()).is_some_and(|c| c.aromatic)).count();
+        assert_eq!(ring, 6);
+        assert!(info.unknown_atoms.is_empty());
+        assert_eq!(info.missing, BTreeSet::from(["LYS".to_string(), "PHE".to_string()]));
+        assert_eq!(annotations.bonds.len(), 2 * 9);
+    }
+
+    #[test]
+    fn normalizes_legacy_names() {
+        let atom = |name: &str| ComponentAtom { name: name.into(), element: name[..1].into(), charge: 0, aromatic: false };
+        let glycine = Component { id: "GLY".into(), atoms: ["N", "CA", "C", "O", "OXT", "H", "H2", "HA2", "HA3"].map(atom).to_vec(), bonds: Vec::new() };
+        let some = |names: &[&str]| names.iter().map(|n| (!n.is_empty()).then(|| n.to_string())).collect::<Vec<_>>();
+        assert_eq!(normalize_names(&glycine, &["N", "CA", "C", "OT1", "OT2", "HN", "1HA", "2HA"]), some(&["", "", "", "O", "OXT", "H", "HA2", "HA3"]));
+        assert_eq!(normalize_names(&glycine, &["HA1", "HA2", "H"]), some(&["HA2", "HA3", ""]));
+        // Renaming to a name another atom already has is left alone
+        assert_eq!(normalize_names(&glycine, &["O", "OT1", "XX"]), some(&["", "", ""]));
+    }
+}
@@ -0,0 +1,128 @@
+use pdbtbx::{Atom, PDB};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+
+/// One box of a spatially partitioned structure. Every node belongs to the
+/// core of exactly one chunk; the halo holds nodes of neighbouring boxes
+/// within `halo` Å of the core, so edges crossing box borders are kept.
+/// Nodes are atoms identified by their index in `pdb.atoms()` order, as
+/// serial numbers repeat across models.
+pub struct Chunk {
+    pub info: ChunkInfo,
+    pub core: HashSet<usize>,
+    pub halo: HashSet<usize>,
+}
+
+
+/// Position of a chunk in the grid, stored in the graph metadata.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChunkInfo {
+    pub index: [usize; 3],
+    /// Lower corner of the core box
+    pub origin: [f64; 3],
+    pub size: f64,
+    pub halo: f64,
+    pub core_nodes: usize,
+    pub halo_nodes: usize,
+}
+
+impl ChunkInfo {
+    /// Suffix added to output file names, e.g. `chunk_0_1_0`.
+    pub fn suffix(&self) -> String {
+        format!("chunk_{}_{}_{}", self.index[0], self.index[1], self.index[2])
+    }
+}
+
+
+/// Partitions the nodes of `pdb` into boxes of edge length `size`, with
+/// overlapping halos of width `halo`. Boxes without core nodes are skipped.
+pub fn partition(pdb: &PDB, size: f64, halo: f64) -> Vec<Chunk> {
+    let atoms: Vec<(usize, &Atom)> = pdb.atoms().enumerate().filter(|(_, a)| a.element().is_some()).collect();
+    if atoms.is_empty() {
+        return Vec::new();
+    }
+    let mut min = [f64::INFINITY; 3];
+    for (_, a) in &atoms {
+        let p = a.pos();
+        for (m, v) in min.iter_mut().zip([p.0, p.1, p.2]) {
+            *m = m.min(v);
+        }
+    }
+    let cell = |v: f64, axis: usize| ((v - min[axis]) / size).floor().max(0.0) as usize;
+
+    let mut cores: BTreeMap<[usize; 3], HashSet<usize>> = BTreeMap::new();
+    for (i, a) in &atoms {
+        let p = a.pos();
+        cores.entry([cell(p.0, 0), cell(p.1, 1), cell(p.2, 2)]).or_default().insert(*i);
+    }
+
+    cores.into_iter().map(|(index, core)| {
+        let origin = [0, 1, 2].map(|axis| min[axis] + index[axis] as f64 * size);
+        let in_halo = |a: &Atom| {
+            let p = a.pos();
+            [p.0, p.1, p.2].iter().enumerate()
+                .all(|(axis, v)| *v >= origin[axis] - halo && *v < origin[axis] + size + halo)
+        };
+        let halo_nodes: HashSet<usize> = atoms.iter()
+            .filter(|(i, a)| !core.contains(i) && in_halo(a))
+            .map(|(i, _)| *i)
+            .collect();
+        Chunk {
+            info: ChunkInfo {
+                index,
+                origin,
+                size,
+                halo,
+                core_nodes: core.len(),
+                halo_nodes: halo_nodes.len(),
+            },
+            core,
+            halo: halo_nodes,
+        }
+    }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Carbon atoms on the x axis, in as many models as `models`, with
+    /// serial numbers restarting in every model.
+    fn structure(xs: &[f64], models: usize) -> PDB {
+        let mut text = String::new();
+        for model in 1..=models {
+            text += &format!("MODEL     {:>4}\n", model);
+            for (i, x) in xs.iter().enumerate() {
+                text += &format!("ATOM  {:>5}  CA  GLY A{:>4}    {:>8.3}{:>8.3}{:>8.3}  1.00  0.00           C\n", i + 1, i + 1, x, 0.0, 0.0);
+            }
+            text += "ENDMDL\n";
+        }
+        text += "END\n";
+        pdbtbx::open_pdb_raw(std::io::BufReader::new(text.as_bytes()), pdbtbx::Context::None, pdbtbx::StrictnessLevel::Loose).unwrap().0
+    }
+
+    #[test]
+    fn partitions_into_cores_and_halos() {
+        let (size, halo) = (4.0, 1.5);
+        for models in [1, 2] {
+            let pdb = structure(&[0.0, 1.0, 3.0, 5.0, 6.0, 7.0], models);
+            let positions: Vec<f64> = pdb.atoms().map(|a| a.x()).collect();
+            let chunks = partition(&pdb, size, halo);
+            assert_eq!(chunks.len(), 2);
+
+            let mut cores: Vec<usize> = chunks.iter().flat_map(|c| c.core.iter().copied()).collect();
+            cores.sort();
+            assert_eq!(cores, (0..positions.len()).collect::<Vec<_>>());
+            for chunk in &chunks {
+                assert!(chunk.core.is_disjoint(&chunk.halo));
+                assert_eq!(chunk.halo.len(), models);
+                for x in chunk.halo.iter().map(|i| positions[*i]) {
+                    let (low, high) = (chunk.info.origin[0], chunk.info.origin[0] + size);
+                    assert!(x < low && low - x <= halo || x >= high && x - high <= halo);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use pdbtbx::PDB;
+use std::fmt;
+
+
+/// Elements and residues a structure must contain to be converted.
+#[derive(Debug, Clone, Default)]
+pub struct Requirements {
+    /// Element symbols, compared case-insensitively
+    pub elements: Vec<String>,
+    /// Residue names, e.g. `HEM`
+    pub residues: Vec<String>,
+}
+
+impl Requirements {
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty() && self.residues.is_empty()
+    }
+}
+
+
+/// Error of a structure left out by the composition filter. It is not a
+/// failure: the structure is valid, just not asked for.
+#[derive(Debug)]
+pub struct Excluded {
+    pub missing: Vec<String>,
+}
+
+impl fmt::Display for Excluded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "structure lacks {}", self.missing.join(", "))
+    }
+}
+
+impl std::error::Error for Excluded {}
+
+
+/// Required elements and residues that `pdb` doesn't contain, in any model.
+pub fn missing(pdb: &PDB, requirements: &Requirements) -> Vec<String> {
+    let mut missing: Vec<String> = requirements.elements.iter()
+        .filter(|e| !pdb.atoms().any(|a| a.element().is_some_and(|ele| ele.symbol().eq_ignore_ascii_case(e))))
+        .cloned()
+        .collect();
+    missing.extend(requirements.residues.iter()
+        .filter(|r| !pdb.residues().any(|res| res.name().is_some_and(|name| name.eq_ignore_ascii_case(r))))
+        .cloned());
+    missing
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requirements(elements: &[&str], residues: &[&str]) -> Requirements {
+        Requirements {
+            elements: elements.iter().map(|e| e.to_string()).collect(),
+            residues: residues.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn finds_missing_elements_and_residues() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        let pdb = pdbtbx::open(path, pdbtbx::StrictnessLevel::Medium).unwrap().0;
+        assert!(requirements(&[], &[]).is_empty());
+        assert!(missing(&pdb, &requirements(&["N", "o"], &["TYR", "leu"])).is_empty());
+        assert_eq!(missing(&pdb, &requirements(&["C", "Fe", "ZN"], &["SER", "HEM"])), ["Fe", "ZN", "HEM"]);
+        assert_eq!(Excluded { missing: missing(&pdb, &requirements(&["S"], &[])) }.to_string(), "structure lacks S");
+    }
+
+    #[test]
+    fn looks_at_every_model() {
+        let text = "\
+MODEL        1
+ATOM      1  CA  GLY A   1       0.000   0.000   0.000  1.00  0.00           C
+ENDMDL
+MODEL        2
+ATOM      1  CA  GLY A   1       0.000   0.000   0.000  1.00  0.00           C
+HETATM    2 FE   HEM A   2       3.000   0.000   0.000  1.00  0.00          FE
+ENDMDL
+END
+";
+        let pdb = pdbtbx::open_pdb_raw(std::io::BufReader::new(text.as_bytes()), pdbtbx::Context::None, pdbtbx::StrictnessLevel::Loose).unwrap().0;
+        assert!(missing(&pdb, &requirements(&["Fe"], &["HEM"])).is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+use crate::constants::{Constants, EdgeFilter, Target};
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+
+/// Run settings read from a TOML file, keyed by the long option they set,
+/// e.g. `cutoff = 4.5` or `features = ["charge", "sasa"]`. Keys may be
+/// grouped into tables such as `[nodes]` and `[edges]`, which only serve to
+/// organize the file, apart from `[node-constants]` and `[edge-constants]`,
+/// which define features of every node and edge, and `[edge-filter]`. Stored
+/// in the output metadata for reproducibility.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RunConfig {
+    pub path: String,
+    pub settings: BTreeMap<String, toml::Value>,
+    /// Settings overridden on the command line
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub overridden: BTreeSet<String>,
+    #[serde(skip_serializing_if = "Constants::is_empty")]
+    pub constants: Constants,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edge_filter: Option<EdgeFilter>,
+}
+
+impl RunConfig {
+    pub fn read(path: &Path) -> Result<RunConfig> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("failed to read config {}", path.display()))?;
+        RunConfig::parse(&path.display().to_string(), &text)
+    }
+
+    pub fn parse(path: &str, text: &str) -> Result<RunConfig> {
+        let table: toml::Table = text.parse().with_context(|| format!("invalid config {}", path))?;
+        let mut settings = BTreeMap::new();
+        let mut constants = Constants::default();
+        let mut edge_filter = None;
+        for (key, value) in table {
+            if key == "edge-filter" {
+                let Some(table) = value.as_table() else {
+                    bail!("{} sets {} to {}, expected a table", path, key, value);
+                };
+                edge_filter = Some(EdgeFilter::parse_table(table).with_context(|| format!("invalid {} in {}", key, path))?);
+                continue;
+            }
+            let target = match key.as_str() {
+                "node-constants" => Some(Target::Node),
+                "edge-constants" => Some(Target::Edge),
+                _ => None,
+            };
+            if let Some(target) = target {
+                let Some(table) = value.as_table() else {
+                    bail!("{} sets {} to {}, expected a table", path, key, value);
+                };
+                let parsed = Constants::parse_table(table, target).with_context(|| format!("invalid {} in {}", key, path))?;
+                match target {
+                    Target::Node => constants.nodes = parsed,
+                    Target::Edge => constants.edges = parsed,
+                }
+                continue;
+            }
+            let entries = match value {
+                toml::Value::Table(section) => section.into_iter().map(|(k, v)| (format!("{}.{}", key, k), k, v)).collect(),
+                value => vec![(key.clone(), key, value)],
+            };
+            for (qualified, key, value) in entries {
+                if value.is_table() {
+                    bail!("{} sets {} to a table, only one level of tables is allowed", path, qualified);
+                }
+                if settings.insert(key.clone(), value).is_some() {
+                    bail!("{} sets {} more than once", path, key);
+                }
+            }
+        }
+        Ok(RunConfig { path: path.to_string(), settings, overridden: BTreeSet::new(), constants, edge_filter })
+    }
+
+    /// Command line arguments of the settings of `cmd` that aren't
+    /// `given` on the command line, which takes precedence and is recorded
+    /// in `overridden`. Flags are set by `true`, lists are joined with commas
+    /// or, for options that can be repeated, passed once per value.
+    pub fn args(&mut self, cmd: &clap::Command, given: impl Fn(&str) -> bool) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+        for (key, value) in &self.settings {
+            let Some(arg) = cmd.get_arguments().find(|a| a.get_long() == Some(key.as_str()) && key != "config") else {
+                bail!("{} sets {}, which is not an option", self.path, key);
+            };
+            if given(arg.get_id().as_str()) {
+                self.overridden.insert(key.clone());
+                continue;
+            }
+            let text = |value: &toml::Value| match value {
+                toml::Value::String(s) => Ok(s.clone()),
+                toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => Ok(value.to_string()),
+                _ => bail!("{} sets {} to {}, expected a string, number or boolean", self.path, key, value),
+            };
+            match value {
+                toml::Value::Boolean(set) if !arg.get_action().takes_values() => {
+                    if *set {
+                        args.push(format!("--{}", key));
+                    }
+                }
+                _ if !arg.get_action().takes_values() => bail!("{} sets the flag {} to {}, expected true or false", self.path, key, value),
+                toml::Value::Array(values) if matches!(arg.get_action(), clap::ArgAction::Append) => {
+                    for value in values {
+                        args.push(format!("--{}={}", key, text(value)?));
+                    }
+                }
+                toml::Value::Array(values) => {
+                    let values = values.iter().map(text).collect::<Result<Vec<_>>>()?;
+                    args.push(format!("--{}={}", key, values.join(",")));
+                }
+                value => args.push(format!("--{}={}", key, text(value)?)),
+            }
+        }
+        Ok(args)
+    }
+}
+
+
+/// Named bundles of settings for common uses. A preset is applied like a
+/// config file whose settings give way to the command line and `--config`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Preset {
+    /// Atom graphs with 4.5 Å contacts, bond orders and hydrogen bonds
+    AtomContact,
+    /// Residue graphs of residues with atoms within 5 Å, with a table of
+    /// their hydrogen bonds, salt bridges and disulfides
+    ResidueInteraction,
+    /// Residue graphs of CA atoms within 8 Å as PyTorch Geometric arrays
+    PygCa,
+}
+
+impl Preset {
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::AtomContact => "atom-contact",
+            Preset::ResidueInteraction => "residue-interaction",
+            Preset::PygCa => "pyg-ca",
+        }
+    }
+
+    /// Settings of the preset in config file syntax.
+    pub fn settings(self) -> &'static str {
+        match self {
+            Preset::AtomContact => r#"
+                granularity = "atom"
+                cutoff = 4.5
+                features = ["valence", "electronegativity", "charge", "coordinates"]
+                bond-orders = true
+                hbonds = true
+                format = "json"
+            "#,
+            Preset::ResidueInteraction => r#"
+                granularity = "residue"
+                residue-edges = "min-distance"
+                interactions = true
+                format = "json"
+            "#,
+            Preset::PygCa => r#"
+                granularity = "residue"
+                residue-edges = "ca"
+                format = "pyg"
+            "#,
+        }
+    }
+
+    pub fn config(self) -> RunConfig {
+        RunConfig::parse(&format!("preset {}", self.name()), self.settings()).expect("presets are valid configs")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_become_arguments() {
+        let cmd = clap::Command::new("graphein")
+            .arg(clap::arg!(--"cutoff" <ANGSTROM> ""))
+            .arg(clap::arg!(--"features" <FEATURES> "").value_delimiter(','))
+            .arg(clap::arg!(--"labels" <TABLE> "").action(clap::ArgAction::Append))
+            .arg(clap::arg!(--"hbonds" ""))
+            .arg(clap::arg!(--"verify" ""));
+        let mut config = RunConfig::parse("run.toml", r#"
+            labels = ["go.tsv", "ec.tsv"]
+            [nodes]
+            features = ["charge", "sasa"]
+            [edges]
+            cutoff = 4.5
+            hbonds = true
+            verify = false
+        "#).unwrap();
+        let args = config.args(&cmd, |id| id == "cutoff").unwrap();
+        assert_eq!(args, ["--features=charge,sasa", "--hbonds", "--labels=go.tsv", "--labels=ec.tsv"]);
+        assert_eq!(config.overridden, BTreeSet::from(["cutoff".to_string()]));
+
+        assert!(RunConfig::parse("run.toml", "cutoff = 4\n[edges]\ncutoff = 5").is_err());
+        let unknown = RunConfig::parse("run.toml", "cutof = 4").unwrap().args(&cmd, |_| false).unwrap_err();
+        assert_eq!(unknown.to_string(), "run.toml sets cutof, which is not an option");
+    }
+
+    #[test]
+    fn presets_are_valid() {
+        for preset in [Preset::AtomContact, Preset::ResidueInteraction, Preset::PygCa] {
+            assert!(preset.config().settings.contains_key("granularity"), "{}", preset.name());
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use crate::correspond::needleman_wunsch;
+use crate::entity::{classify_residue, EntityType};
+use crate::residue::one_letter_code;
+use anyhow::{anyhow, bail, Context, Result};
+use pdbtbx::{Residue, PDB};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+
+/// Amino acid order of the profiles attached to nodes.
+pub const ALPHABET: &str = "ACDEFGHIKLMNPQRSTVWY";
+
+
+/// File format of a sequence profile.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileFormat {
+    /// PSI-BLAST ASCII PSSM (`-out_ascii_pssm`)
+    Pssm,
+    /// HH-suite HMM
+    Hhm,
+}
+
+
+/// Per-position amino acid frequencies of a sequence profile.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub format: ProfileFormat,
+    /// Query sequence of the profile
+    pub sequence: Vec<char>,
+    /// Frequencies of every position in `ALPHABET` order
+    pub frequencies: Vec<Vec<f64>>,
+    /// Information content of every position in bits
+    pub conservation: Vec<f64>,
+}
+
+
+/// Conservation features of a residue, attached to its atoms.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResidueConservation {
+    /// Information content of the aligned profile position in bits
+    pub conservation: f64,
+    /// Amino acid frequencies of the aligned profile position
+    pub profile: Vec<f64>,
+}
+
+
+/// How a chain was aligned to its profile.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChainAlignment {
+    pub chain: String,
+    pub residues: usize,
+    /// Residues aligned to a profile position
+    pub aligned: usize,
+    /// Fraction of aligned residues identical to the profile's query
+    pub identity: f64,
+}
+
+
+/// Summary of the profiles attached to a graph, stored in the graph metadata.
+#[derive(Serialize, Debug, Clone)]
+pub struct ConservationInfo {
+    pub format: ProfileFormat,
+    /// Amino acid order of the node `profile`s
+    pub alphabet: &'static str,
+    pub chains: Vec<ChainAlignment>,
+}
+
+
+/// Reorders the columns of `letters` into `ALPHABET` order.
+fn reorder(letters: &[char], values: &[f64]) -> Vec<f64> {
+    ALPHABET.chars()
+        .map(|c| letters.iter().position(|l| *l == c).map_or(0.0, |i| values[i]))
+        .collect()
+}
+
+
+/// Parses a PSI-BLAST ASCII PSSM. The weighted observed percentages are the
+/// frequencies and the information per position column the conservation.
+pub fn parse_pssm(text: &str) -> Result<Profile> {
+    let mut lines = text.lines().skip_while(|l| !l.starts_with("Last position-specific scoring matrix"));
+    lines.next().context("not a PSI-BLAST PSSM")?;
+    let letters: Vec<char> = lines.next()
+        .context("truncated PSSM")?
+        .split_whitespace()
+        .take(20)
+        .filter_map(|l| l.chars().next())
+        .collect();
+    let mut profile = Profile { format: ProfileFormat::Pssm, sequence: Vec::new(), frequencies: Vec::new(), conservation: Vec::new() };
+    for line in lines {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 43 || fields[0].parse::<usize>().is_err() {
+            break;
+        }
+        let percentages: Vec<f64> = fields[22..42].iter().map(|v| v.parse::<f64>().map(|p| p / 100.0)).collect::<Result<_, _>>()?;
+        profile.sequence.push(fields[1].chars().next().unwrap_or('X'));
+        profile.frequencies.push(reorder(&letters, &percentages));
+        profile.conservation.push(fields[42].parse()?);
+    }
+    if profile.sequence.is_empty() {
+        bail!("PSSM has no positions");
+    }
+    Ok(profile)
+}
+
+
+/// HMM values are `-1000 * log2(p)`, `*` for zero probability.
+fn hhm_probability(value: &str) -> Result<f64> {
+    match value {
+        "*" => Ok(0.0),
+        v => Ok(2f64.powf(-v.parse::<f64>()? / 1000.0)),
+    }
+}
+
+
+/// Parses an HH-suite HMM. The match state emissions are the frequencies and
+/// their relative entropy to the `NULL` background the conservation.
+pub fn parse_hhm(text: &str) -> Result<Profile> {
+    let mut background = Vec::new();
+    let mut letters = Vec::new();
+    let mut profile = Profile { format: ProfileFormat::Hhm, sequence: Vec::new(), frequencies: Vec::new(), conservation: Vec::new() };
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        if let Some(values) = line.strip_prefix("NULL") {
+            background = values.split_whitespace().take(20).map(hhm_probability).collect::<Result<_>>()?;
+        } else if let Some(header) = line.strip_prefix("HMM ") {
+            letters = header.split_whitespace().filter_map(|l| l.chars().next()).collect();
+            // Transition names and the transitions of the begin state
+            lines.next();
+            lines.next();
+            break;
+        }
+    }
+    if letters.len() != 20 || background.len() != 20 {
+        bail!("not an HH-suite HMM");
+    }
+    let background = reorder(&letters, &background);
+    for line in lines.take_while(|l| !l.starts_with("//")) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // Emission lines start with the query residue and position, transition lines with a number
+        if fields.len() < 22 || fields[1].parse::<usize>().is_err() || fields[0].parse::<f64>().is_ok() {
+            continue;
+        }
+        let emissions: Vec<f64> = fields[2..22].iter().map(|v| hhm_probability(v)).collect::<Result<_>>()?;
+        let frequencies = reorder(&letters, &emissions);
+        let conservation = frequencies.iter().zip(&background)
+            .filter(|(p, q)| **p > 0.0 && **q > 0.0)
+            .map(|(p, q)| p * (p / q).log2())
+            .sum();
+        profile.sequence.push(fields[0].chars().next().unwrap_or('X'));
+        profile.frequencies.push(frequencies);
+        profile.conservation.push(conservation);
+    }
+    if profile.sequence.is_empty() {
+        bail!("HMM has no positions");
+    }
+    Ok(profile)
+}
+
+
+/// Profile of a chain from `dir`: `<name>_<chain>.pssm` or `.hhm`, or
+/// `<name>.pssm` or `.hhm` for a structure with a single protein chain.
+fn load(dir: &Path, name: &str, chain: &str, single: bool) -> Result<Option<Profile>> {
+    let mut candidates = vec![format!("{}_{}", name, chain)];
+    if single {
+        candidates.push(name.to_string());
+    }
+    for stem in candidates {
+        for (extension, parse) in [("pssm", parse_pssm as fn(&str) -> Result<Profile>), ("hhm", parse_hhm)] {
+            let path = dir.join(format!("{}.{}", stem, extension));
+            if path.is_file() {
+                let text = std::fs::read_to_string(&path)?;
+                return parse(&text).with_context(|| format!("failed to read {}", path.display())).map(Some);
+            }
+        }
+    }
+    Ok(None)
+}
+
+
+/// Conservation features of every protein atom of the first model by serial
+/// number. The sequence of each chain is aligned to the query sequence of
+/// its profile, so profiles of the full construct can be used for
+/// structures with unmodelled residues; residues aligned to a gap get none.
+pub fn atom_conservation(pdb: &PDB, dir: &Path, name: &str) -> Result<(HashMap<usize, ResidueConservation>, ConservationInfo)> {
+    let model = pdb.model(0).context("structure has no models")?;
+    let protein_chains: Vec<(String, Vec<&Residue>)> = model.chains()
+        .map(|c| (c.id().to_string(), c.residues().filter(|r| classify_residue(r) == EntityType::Protein).collect::<Vec<_>>()))
+        .filter(|(_, residues)| !residues.is_empty())
+        .collect();
+    let mut atoms = HashMap::new();
+    let mut info = ConservationInfo { format: ProfileFormat::Pssm, alphabet: ALPHABET, chains: Vec::new() };
+    for (chain, residues) in &protein_chains {
+        let profile = load(dir, name, chain, protein_chains.len() == 1)?
+            .ok_or_else(|| anyhow!("no profile for chain {} of {} in {}", chain, name, dir.display()))?;
+        let sequence: Vec<char> = residues.iter().map(|r| one_letter_code(r.name().unwrap_or(""))).collect();
+        let pairs = needleman_wunsch(&sequence, &profile.sequence);
+        let identical = pairs.iter().filter(|(i, j)| sequence[*i] == profile.sequence[*j]).count();
+        for &(i, j) in &pairs {
+            let features = ResidueConservation { conservation: profile.conservation[j], profile: profile.frequencies[j].clone() };
+            atoms.extend(residues[i].atoms().map(|a| (a.serial_number(), features.clone())));
+        }
+        info.format = profile.format;
+        info.chains.push(ChainAlignment {
+            chain: chain.clone(),
+            residues: residues.len(),
+            aligned: pairs.len(),
+            identity: identical as f64 / pairs.len().max(1) as f64,
+        });
+    }
+    Ok((atoms, info))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pssm_and_hhm() {
+        let pssm = "\
+Last position-specific scoring matrix computed, weighted observed percentages rounded down, information per position, and relative weight of gapless real matches to pseudocounts
+           A   R   N   D   C   Q   E   G   H   I   L   K   M   F   P   S   T   W   Y   V   A   R   N   D   C   Q   E   G   H   I   L   K   M   F   P   S   T   W   Y   V
+    1 M    -1  -2  -2  -3  -2  -1  -2  -3  -2   1   2  -1   6   0  -3  -2  -1  -2  -1   1    0   0   0   0   0   0   0   0   0   0  20   0  80   0   0   0   0   0   0   0  2.86 0.08
+    2 K    -1   2   0  -1  -3   1   1  -2  -1  -3  -3   5  -2  -3  -1   0  -1  -3  -2  -3    0   0   0   0   0   0   0   0   0   0   0 100   0   0   0   0   0   0   0   0  1.10 0.12
+
+                      K         Lambda
+";
+        let profile = parse_pssm(pssm).unwrap();
+        assert_eq!(profile.sequence, vec!['M', 'K']);
+        assert_eq!(profile.conservation, vec![2.86, 1.10]);
+        assert_eq!(profile.frequencies[0][ALPHABET.find('M').unwrap()], 0.8);
+        assert_eq!(profile.frequencies[0][ALPHABET.find('L').unwrap()], 0.2);
+
+        let hhm = "\
+HHsearch 1.5
+NULL   3706\t5728\t4211\t4064\t4839\t3729\t4763\t4308\t4069\t3323\t5509\t4640\t4464\t4937\t4285\t4423\t3815\t3783\t6325\t4665\t
+HMM    A\tC\tD\tE\tF\tG\tH\tI\tK\tL\tM\tN\tP\tQ\tR\tS\tT\tV\tW\tY\t
+       M->M\tM->I\tM->D\tI->M\tI->I\tD->M\tD->D\tNeff\tNeff_I\tNeff_D
+       0\t*\t*\t0\t*\t0\t*\t*\t*\t*\t
+M 1    *\t*\t*\t*\t*\t*\t*\t*\t*\t*\t0\t*\t*\t*\t*\t*\t*\t*\t*\t*\t1
+       0\t*\t*\t*\t*\t*\t*\t1000\t0\t0\t
+//
+";
+        let profile = parse_hhm(hhm).unwrap();
+        assert_eq!(profile.sequence, vec!['M']);
+        assert_eq!(profile.frequencies[0][ALPHABET.find('M').unwrap()], 1.0);
+        // -log2 of the background frequency of M
+        assert!((profile.conservation[0] - 5.509).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,637 @@
+use crate::bond_order::BondOrder;
+use crate::{BondType, EdgeData, EdgeKind};
+use anyhow::{bail, Result};
+use pdbtbx::{Atom, Chain, Residue};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+
+/// An atom with the residue and chain it belongs to, which node and edge
+/// expressions are evaluated on.
+#[derive(Debug, Clone, Copy)]
+pub struct AtomContext<'a> {
+    pub chain: &'a Chain,
+    pub residue: &'a Residue,
+    pub atom: &'a Atom,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Number,
+    Text,
+}
+
+
+/// Property of an atom usable in expressions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AtomVariable {
+    Element,
+    AtomName,
+    ResidueName,
+    Chain,
+    ResidueNumber,
+    AtomicNumber,
+    Charge,
+    Bfactor,
+    Occupancy,
+    X,
+    Y,
+    Z,
+    Hetero,
+}
+
+const ATOM_VARIABLES: [(&str, AtomVariable); 13] = [
+    ("element", AtomVariable::Element),
+    ("atom_name", AtomVariable::AtomName),
+    ("residue_name", AtomVariable::ResidueName),
+    ("chain", AtomVariable::Chain),
+    ("residue_number", AtomVariable::ResidueNumber),
+    ("atomic_number", AtomVariable::AtomicNumber),
+    ("charge", AtomVariable::Charge),
+    ("bfactor", AtomVariable::Bfactor),
+    ("occupancy", AtomVariable::Occupancy),
+    ("x", AtomVariable::X),
+    ("y", AtomVariable::Y),
+    ("z", AtomVariable::Z),
+    ("hetero", AtomVariable::Hetero),
+];
+
+impl AtomVariable {
+    fn value_type(self) -> Type {
+        match self {
+            AtomVariable::Element | AtomVariable::AtomName | AtomVariable::ResidueName | AtomVariable::Chain => Type::Text,
+            _ => Type::Number,
+        }
+    }
+
+    fn value(self, context: AtomContext) -> Value {
+        let AtomContext { chain, residue, atom } = context;
+        let text = |s: &str| Value::Text(s.to_string());
+        match self {
+            AtomVariable::Element => text(&atom.element().map_or(String::new(), |e| e.symbol().to_uppercase())),
+            AtomVariable::AtomName => text(atom.name()),
+            AtomVariable::ResidueName => text(residue.name().unwrap_or("")),
+            AtomVariable::Chain => text(chain.id()),
+            AtomVariable::ResidueNumber => Value::Number(residue.serial_number() as f64),
+            AtomVariable::AtomicNumber => Value::Number(atom.element().map_or(0.0, |e| e.atomic_number() as f64)),
+            AtomVariable::Charge => Value::Number(atom.charge() as f64),
+            AtomVariable::Bfactor => Value::Number(atom.b_factor()),
+            AtomVariable::Occupancy => Value::Number(atom.occupancy()),
+            AtomVariable::X => Value::Number(atom.x()),
+            AtomVariable::Y => Value::Number(atom.y()),
+            AtomVariable::Z => Value::Number(atom.z()),
+            AtomVariable::Hetero => Value::Number(atom.hetero() as u8 as f64),
+        }
+    }
+}
+
+
+/// Variable of an expression: a property of the node's atom, of the edge or
+/// of the edge's source (`a.`) or target (`b.`) atom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    Atom(AtomVariable),
+    Source(AtomVariable),
+    Target(AtomVariable),
+    Distance,
+    Covalent,
+    BondOrder,
+    Hbond,
+    Disulfide,
+    /// The atoms are in different chains
+    Interchain,
+    /// Residues between the atoms along their chain, infinite between chains
+    SeqSep,
+}
+
+impl Variable {
+    fn lookup(name: &str, target: Target) -> Option<Variable> {
+        let atom = |name: &str| ATOM_VARIABLES.iter().find(|(n, _)| *n == name).map(|(_, v)| *v);
+        match target {
+            Target::Node => atom(name).map(Variable::Atom),
+            Target::Edge => match name {
+                "distance" => Some(Variable::Distance),
+                "covalent" => Some(Variable::Covalent),
+                "bond_order" => Some(Variable::BondOrder),
+                "hbond" => Some(Variable::Hbond),
+                "disulfide" => Some(Variable::Disulfide),
+                "interchain" => Some(Variable::Interchain),
+                "seq_sep" => Some(Variable::SeqSep),
+                _ => match name.split_once('.') {
+                    Some(("a", name)) => atom(name).map(Variable::Source),
+                    Some(("b", name)) => atom(name).map(Variable::Target),
+                    _ => None,
+                },
+            },
+        }
+    }
+
+    fn value_type(self) -> Type {
+        match self {
+            Variable::Atom(v) | Variable::Source(v) | Variable::Target(v) => v.value_type(),
+            Variable::BondOrder => Type::Text,
+            _ => Type::Number,
+        }
+    }
+}
+
+
+/// What an expression is evaluated on, which decides its variables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Node,
+    Edge,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Abs,
+    Min,
+    Max,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Text(String),
+    Variable(Variable),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(f64),
+    Text(String),
+}
+
+impl Value {
+    fn number(&self) -> f64 {
+        match self {
+            Value::Number(x) => *x,
+            Value::Text(_) => f64::NAN,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Name(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 19] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "+", "-", "*", "/", "?", ":", "(", ")", ",", "="];
+
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() || (c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit())) {
+            let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '.')).unwrap_or(rest.len());
+            let Ok(number) = rest[..len].parse() else {
+                bail!("invalid number {}", &rest[..len]);
+            };
+            tokens.push(Token::Number(number));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.')).unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_string()));
+            len
+        } else if c == '\'' || c == '"' {
+            let Some(end) = rest[1..].find(c) else {
+                bail!("unclosed string {}", rest);
+            };
+            tokens.push(Token::Text(rest[1..end + 1].to_string()));
+            end + 2
+        } else {
+            let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) else {
+                bail!("unexpected {}", c);
+            };
+            if *symbol == "=" {
+                bail!("unexpected =, compare with ==");
+            }
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+
+/// Recursive descent parser of expressions, from the lowest precedence:
+/// `c ? x : y`, `||`, `&&`, comparisons, `+ -`, `* /`, `! -` and values.
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    target: Target,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        let found = self.peek() == Some(&Token::Symbol(SYMBOLS.iter().find(|s| **s == symbol).unwrap()));
+        self.next += found as usize;
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<()> {
+        if !self.eat(symbol) {
+            bail!("expected {}", symbol);
+        }
+        Ok(())
+    }
+
+    fn conditional(&mut self) -> Result<Expr> {
+        let condition = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let then = self.conditional()?;
+        self.expect(":")?;
+        let otherwise = self.conditional()?;
+        Ok(Expr::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise)))
+    }
+
+    /// Binary operations of `level` and above, left associative.
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        const LEVELS: [&[(&str, Op)]; 5] = [
+            &[("||", Op::Or)],
+            &[("&&", Op::And)],
+            &[("==", Op::Eq), ("!=", Op::Ne), ("<=", Op::Le), (">=", Op::Ge), ("<", Op::Lt), (">", Op::Gt)],
+            &[("+", Op::Add), ("-", Op::Sub)],
+            &[("*", Op::Mul), ("/", Op::Div)],
+        ];
+        let Some(ops) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        while let Some((_, op)) = ops.iter().find(|(symbol, _)| self.eat(symbol)) {
+            left = Expr::Binary(*op, Box::new(left), Box::new(self.binary(level + 1)?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.conditional()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        let token = self.peek().cloned();
+        self.next += 1;
+        Ok(match token {
+            Some(Token::Number(x)) => Expr::Number(x),
+            Some(Token::Text(s)) => Expr::Text(s),
+            Some(Token::Name(name)) if name == "true" || name == "false" => Expr::Number((name == "true") as u8 as f64),
+            Some(Token::Name(name)) if self.eat("(") => {
+                let function = match name.as_str() {
+                    "abs" => Function::Abs,
+                    "min" => Function::Min,
+                    "max" => Function::Max,
+                    _ => bail!("unknown function {}, expected abs, min or max", name),
+                };
+                let mut args = vec![self.conditional()?];
+                while self.eat(",") {
+                    args.push(self.conditional()?);
+                }
+                self.expect(")")?;
+                Expr::Call(function, args)
+            }
+            Some(Token::Name(name)) => match Variable::lookup(&name, self.target) {
+                Some(variable) => Expr::Variable(variable),
+                None => bail!("unknown {} variable {}", if self.target == Target::Node { "node" } else { "edge" }, name),
+            },
+            Some(Token::Symbol(symbol)) => bail!("unexpected {}", symbol),
+            None => bail!("unexpected end"),
+        })
+    }
+}
+
+
+impl Expr {
+    fn parse(source: &str, target: Target) -> Result<Expr> {
+        let mut parser = Parser { tokens: tokenize(source)?, next: 0, target };
+        let expr = parser.conditional()?;
+        if let Some(token) = parser.peek() {
+            bail!("unexpected {:?} after the expression", token);
+        }
+        if expr.check()? != Type::Number {
+            bail!("the expression is text, expected a number");
+        }
+        Ok(expr)
+    }
+
+    /// Type of the expression, an error where text is used as a number.
+    fn check(&self) -> Result<Type> {
+        let number = |expr: &Expr| match expr.check()? {
+            Type::Number => Ok(Type::Number),
+            Type::Text => bail!("text used as a number"),
+        };
+        match self {
+            Expr::Number(_) => Ok(Type::Number),
+            Expr::Text(_) => Ok(Type::Text),
+            Expr::Variable(v) => Ok(v.value_type()),
+            Expr::Not(x) | Expr::Negate(x) => number(x),
+            Expr::Binary(Op::Eq | Op::Ne, x, y) => {
+                if x.check()? != y.check()? {
+                    bail!("text compared with a number");
+                }
+                Ok(Type::Number)
+            }
+            Expr::Binary(_, x, y) => number(x).and(number(y)),
+            Expr::Conditional(condition, then, otherwise) => {
+                number(condition)?;
+                let t = then.check()?;
+                if t != otherwise.check()? {
+                    bail!("the branches of ?: are text and a number");
+                }
+                Ok(t)
+            }
+            Expr::Call(function, args) => {
+                let arity_ok = match function {
+                    Function::Abs => args.len() == 1,
+                    Function::Min | Function::Max => args.len() >= 2,
+                };
+                if !arity_ok {
+                    bail!("wrong number of arguments to {:?}", function);
+                }
+                args.iter().try_for_each(|arg| number(arg).map(|_| ()))?;
+                Ok(Type::Number)
+            }
+        }
+    }
+
+    fn eval(&self, variables: &impl Fn(Variable) -> Value) -> Value {
+        let truth = |x: bool| Value::Number(x as u8 as f64);
+        let number = |expr: &Expr| expr.eval(variables).number();
+        match self {
+            Expr::Number(x) => Value::Number(*x),
+            Expr::Text(s) => Value::Text(s.clone()),
+            Expr::Variable(v) => variables(*v),
+            Expr::Not(x) => truth(number(x) == 0.0),
+            Expr::Negate(x) => Value::Number(-number(x)),
+            Expr::Binary(Op::Or, x, y) => truth(number(x) != 0.0 || number(y) != 0.0),
+            Expr::Binary(Op::And, x, y) => truth(number(x) != 0.0 && number(y) != 0.0),
+            Expr::Binary(Op::Eq, x, y) => truth(x.eval(variables) == y.eval(variables)),
+            Expr::Binary(Op::Ne, x, y) => truth(x.eval(variables) != y.eval(variables)),
+            Expr::Binary(op, x, y) => {
+                let (x, y) = (number(x), number(y));
+                match op {
+                    Op::Lt => truth(x < y),
+                    Op::Le => truth(x <= y),
+                    Op::Gt => truth(x > y),
+                    Op::Ge => truth(x >= y),
+                    Op::Add => Value::Number(x + y),
+                    Op::Sub => Value::Number(x - y),
+                    Op::Mul => Value::Number(x * y),
+                    _ => Value::Number(x / y),
+                }
+            }
+            Expr::Conditional(condition, then, otherwise) => {
+                if number(condition) != 0.0 { then.eval(variables) } else { otherwise.eval(variables) }
+            }
+            Expr::Call(function, args) => {
+                let mut values = args.iter().map(number);
+                Value::Number(match function {
+                    Function::Abs => values.next().unwrap().abs(),
+                    Function::Min => values.fold(f64::INFINITY, f64::min),
+                    Function::Max => values.fold(f64::NEG_INFINITY, f64::max),
+                })
+            }
+        }
+    }
+}
+
+
+/// A user-defined feature: a number, or an expression evaluated on every
+/// node or edge. Serialized as written in the config.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constant {
+    source: String,
+    expr: Expr,
+}
+
+impl Constant {
+    /// Parses a config value: numbers and booleans are constants, strings
+    /// expressions.
+    pub fn parse(value: &toml::Value, target: Target) -> Result<Constant> {
+        let expr = match value {
+            toml::Value::Integer(x) => Expr::Number(*x as f64),
+            toml::Value::Float(x) => Expr::Number(*x),
+            toml::Value::Boolean(b) => Expr::Number(*b as u8 as f64),
+            toml::Value::String(s) => Expr::parse(s, target)?,
+            _ => bail!("expected a number, boolean or expression"),
+        };
+        let source = value.as_str().map_or_else(|| value.to_string(), |s| s.to_string());
+        Ok(Constant { source, expr })
+    }
+}
+
+impl Serialize for Constant {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+
+/// Features added to every node and edge from the `[node-constants]` and
+/// `[edge-constants]` tables of a config, by feature name.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Constants {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub nodes: BTreeMap<String, Constant>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub edges: BTreeMap<String, Constant>,
+}
+
+impl Constants {
+    /// Parses the features of a config table, e.g. `is_cofactor = "element == 'ZN'"`.
+    pub fn parse_table(table: &toml::Table, target: Target) -> Result<BTreeMap<String, Constant>> {
+        let mut constants = BTreeMap::new();
+        for (name, value) in table {
+            if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                bail!("invalid feature name {}, expected letters, digits and _", name);
+            }
+            let constant = Constant::parse(value, target).map_err(|e| anyhow::anyhow!("invalid {} = {}: {}", name, value, e))?;
+            constants.insert(name.clone(), constant);
+        }
+        Ok(constants)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.edges.is_empty()
+    }
+
+    /// Node features of an atom, `None` without node constants.
+    pub fn node_values(&self, atom: AtomContext) -> Option<BTreeMap<String, f64>> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let variables = |v: Variable| match v {
+            Variable::Atom(v) => v.value(atom),
+            _ => unreachable!("edge variable in a node expression"),
+        };
+        Some(self.nodes.iter().map(|(name, c)| (name.clone(), c.expr.eval(&variables).number())).collect())
+    }
+
+    /// Edge features of an edge from `source` to `target`, `None` without edge
+    /// constants.
+    pub fn edge_values(&self, edge: &EdgeData, source: AtomContext, target: AtomContext) -> Option<BTreeMap<String, f64>> {
+        if self.edges.is_empty() {
+            return None;
+        }
+        let variables = |v: Variable| edge_variable(v, edge, source, target);
+        Some(self.edges.iter().map(|(name, c)| (name.clone(), c.expr.eval(&variables).number())).collect())
+    }
+}
+
+
+/// Value of an edge variable of the edge from `source` to `target`.
+fn edge_variable(variable: Variable, edge: &EdgeData, source: AtomContext, target: AtomContext) -> Value {
+    let truth = |x: bool| Value::Number(x as u8 as f64);
+    let same_chain = std::ptr::eq(source.chain, target.chain);
+    match variable {
+        Variable::Source(v) => v.value(source),
+        Variable::Target(v) => v.value(target),
+        Variable::Distance => Value::Number(edge.distance),
+        Variable::Covalent => truth(edge.bond_type == BondType::Covalent),
+        Variable::BondOrder => Value::Text(match edge.bond_order {
+            Some(BondOrder::Single) => "single",
+            Some(BondOrder::Double) => "double",
+            Some(BondOrder::Triple) => "triple",
+            Some(BondOrder::Aromatic) => "aromatic",
+            None => "",
+        }.to_string()),
+        Variable::Hbond => truth(edge.edge_kind == Some(EdgeKind::Hbond)),
+        Variable::Disulfide => truth(edge.edge_kind == Some(EdgeKind::Disulfide)),
+        Variable::Interchain => truth(!same_chain),
+        Variable::SeqSep if same_chain => Value::Number((source.residue.serial_number() - target.residue.serial_number()).abs() as f64),
+        Variable::SeqSep => Value::Number(f64::INFINITY),
+        Variable::Atom(_) => unreachable!("node variable in an edge expression"),
+    }
+}
+
+
+/// Which candidate edges are added to atom graphs, from the `[edge-filter]`
+/// table of a config: those `keep` is true for and `drop` is false for.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct EdgeFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep: Option<Constant>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drop: Option<Constant>,
+}
+
+impl EdgeFilter {
+    /// Parses an `[edge-filter]` table, e.g. `keep = "distance < 6.0 && (interchain || seq_sep > 4)"`.
+    pub fn parse_table(table: &toml::Table) -> Result<EdgeFilter> {
+        let mut filter = EdgeFilter::default();
+        for (key, value) in table {
+            let condition = Constant::parse(value, Target::Edge).map_err(|e| anyhow::anyhow!("invalid {} = {}: {}", key, value, e))?;
+            match key.as_str() {
+                "keep" => filter.keep = Some(condition),
+                "drop" => filter.drop = Some(condition),
+                _ => bail!("unknown key {}, expected keep or drop", key),
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn keeps(&self, edge: &EdgeData, source: AtomContext, target: AtomContext) -> bool {
+        let variables = |v: Variable| edge_variable(v, edge, source, target);
+        let holds = |condition: &Constant| condition.expr.eval(&variables).number() != 0.0;
+        self.keep.as_ref().is_none_or(holds) && !self.drop.as_ref().is_some_and(holds)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_node_and_edge_expressions() {
+        let pdb = crate::golden::Fixture::get("benzoate_stacking").unwrap().structure().unwrap();
+        let table: toml::Table = r#"
+            is_cofactor = "element == 'O' && hetero"
+            scaled = "residue_number * 2 + (chain == 'B' ? 0.5 : -atomic_number)"
+            one = 1
+        "#.parse().unwrap();
+        let constants = Constants {
+            nodes: Constants::parse_table(&table, Target::Node).unwrap(),
+            edges: Constants::parse_table(&"weight = \"a.hetero || b.hetero ? min(distance, 2) : 1\"".parse().unwrap(), Target::Edge).unwrap(),
+        };
+        let contexts: Vec<AtomContext> = pdb.chains()
+            .flat_map(|chain| chain.residues().flat_map(move |residue| residue.atoms().map(move |atom| AtomContext { chain, residue, atom })))
+            .collect();
+        let oxygen = contexts.iter().find(|c| c.atom.name() == "O1").unwrap();
+        let values = constants.node_values(*oxygen).unwrap();
+        assert_eq!(values, BTreeMap::from([("is_cofactor".to_string(), 1.0), ("one".to_string(), 1.0), ("scaled".to_string(), 2.5)]));
+        assert_eq!(constants.node_values(contexts[0]).unwrap()["scaled"], -4.0);
+
+        let edge = EdgeData { distance: 3.5, bond_type: BondType::Contact, bond_order: None, edge_kind: None, constants: None, cutoff: None };
+        assert_eq!(constants.edge_values(&edge, contexts[0], *oxygen).unwrap()["weight"], 2.0);
+        assert_eq!(constants.edge_values(&edge, contexts[0], contexts[1]).unwrap()["weight"], 1.0);
+
+        for (source, error) in [
+            ("element + 1", "text used as a number"),
+            ("element == 8", "text compared with a number"),
+            ("distance", "unknown node variable distance"),
+            ("hetero = 1", "unexpected =, compare with =="),
+            ("residue_name", "the expression is text, expected a number"),
+        ] {
+            assert_eq!(Expr::parse(source, Target::Node).unwrap_err().to_string(), error, "{}", source);
+        }
+    }
+
+    #[test]
+    fn filters_candidate_edges() {
+        let pdb = crate::golden::Fixture::get("benzoate_stacking").unwrap().structure().unwrap();
+        let filter = |text: &str| EdgeFilter::parse_table(&text.parse().unwrap()).unwrap();
+        let all = crate::GraphBuilder::new().build_graph(&pdb);
+        let graph = crate::GraphBuilder::new().edge_filter(filter("keep = \"distance < 6.0 && (interchain || seq_sep > 0)\"")).build_graph(&pdb);
+        assert!(graph.edge_count() > 0 && graph.edge_count() < all.edge_count());
+        assert!(graph.edge_weights().all(|e| e.distance < 6.0 && e.bond_type == BondType::Contact));
+        let graph = crate::GraphBuilder::new().edge_filter(filter("drop = \"covalent\"")).build_graph(&pdb);
+        assert_eq!(graph.edge_count(), all.edge_weights().filter(|e| e.bond_type == BondType::Contact).count());
+        assert!(EdgeFilter::parse_table(&"kep = 1".parse().unwrap()).is_err());
+    }
+}
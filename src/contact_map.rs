@@ -0,0 +1,131 @@
+use crate::atomic::{self, AtomicFile};
+use crate::npy::npy_bytes;
+use crate::residue::ResidueKey;
+use crate::residue_graph::{build_residue_graph, ResidueGraphNode, ResidueGraphOptions};
+use anyhow::Result;
+use pdbtbx::PDB;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+
+/// Values of a contact map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ContactValues {
+    /// 1 for residues within the cutoff, including each residue with itself, else 0
+    Contacts,
+    /// Distance between every pair of residues, ignoring the cutoff
+    Distances,
+}
+
+
+/// Residues of the first model and the `n × n` matrix of their contacts or
+/// distances, row-major.
+#[derive(Debug, Clone)]
+pub struct ContactMap {
+    pub residues: Vec<ResidueGraphNode>,
+    pub values: Vec<f64>,
+    pub kind: ContactValues,
+}
+
+
+/// Contact map of `pdb` from the neighbour search of its residue graph:
+/// residues are in contact where the graph connects them. Distances are
+/// measured the same way with an unbounded cutoff, which for
+/// `min-distance` compares every pair of atoms.
+pub fn contact_map(pdb: &PDB, options: &ResidueGraphOptions, kind: ContactValues) -> ContactMap {
+    let options = match kind {
+        ContactValues::Contacts => options.clone(),
+        ContactValues::Distances => ResidueGraphOptions { cutoff: f64::INFINITY, ..options.clone() },
+    };
+    let graph = build_residue_graph(pdb, &options);
+    let n = graph.node_count();
+    let mut values = vec![0.0; n * n];
+    if kind == ContactValues::Contacts {
+        for i in 0..n {
+            values[i * n + i] = 1.0;
+        }
+    }
+    for edge in graph.raw_edges() {
+        values[edge.source().index() * n + edge.target().index()] = match kind {
+            ContactValues::Contacts => 1.0,
+            ContactValues::Distances => edge.weight,
+        };
+    }
+    ContactMap { residues: graph.node_weights().cloned().collect(), values, kind }
+}
+
+
+fn label(residue: &ResidueGraphNode) -> String {
+    ResidueKey { chain: residue.chain.clone(), resseq: residue.resseq, icode: residue.icode.clone() }.to_string()
+}
+
+
+/// Writes the matrix as an `.npy` array, `uint8` for contacts and `float32`
+/// for distances.
+pub fn write_npy(map: &ContactMap, save_fname: &Path) -> Result<()> {
+    let n = map.residues.len();
+    let bytes = match map.kind {
+        ContactValues::Contacts => npy_bytes(&map.values.iter().map(|v| *v as u8).collect::<Vec<_>>(), &[n, n]),
+        ContactValues::Distances => npy_bytes(&map.values.iter().map(|v| *v as f32).collect::<Vec<_>>(), &[n, n]),
+    };
+    atomic::write(save_fname, &bytes)?;
+    Ok(())
+}
+
+
+/// Writes the matrix as CSV with the residues (`A:52B`) as the header and
+/// first column.
+pub fn write_csv(map: &ContactMap, save_fname: &Path) -> Result<()> {
+    let labels: Vec<String> = map.residues.iter().map(label).collect();
+    let mut out = BufWriter::new(AtomicFile::create(save_fname)?);
+    writeln!(out, "residue,{}", labels.join(","))?;
+    for (label, row) in labels.iter().zip(map.values.chunks(labels.len().max(1))) {
+        let row: Vec<String> = match map.kind {
+            ContactValues::Contacts => row.iter().map(|v| format!("{}", *v as u8)).collect(),
+            ContactValues::Distances => row.iter().map(|v| format!("{:.3}", v)).collect(),
+        };
+        writeln!(out, "{},{}", label, row.join(","))?;
+    }
+    out.into_inner().map_err(|e| e.into_error())?.commit()?;
+    Ok(())
+}
+
+
+/// Writes the residues of the rows and columns of an `.npy` contact map.
+pub fn write_residues(map: &ContactMap, save_fname: &Path) -> Result<()> {
+    let mut out = BufWriter::new(AtomicFile::create(save_fname)?);
+    writeln!(out, "index,residue,residue_name")?;
+    for (i, residue) in map.residues.iter().enumerate() {
+        writeln!(out, "{},{},{}", i, label(residue), residue.residue_name)?;
+    }
+    out.into_inner().map_err(|e| e.into_error())?.commit()?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::residue_graph::ResidueEdges;
+
+    #[test]
+    fn maps_contacts_and_distances() {
+        let pdb = crate::golden::Fixture::get("benzoate_stacking").unwrap().structure().unwrap();
+        let options = ResidueGraphOptions { edges: ResidueEdges::MinDistance, cutoff: 4.0 };
+
+        let contacts = contact_map(&pdb, &options, ContactValues::Contacts);
+        let n = contacts.residues.len();
+        assert_eq!(n, 3);
+        assert!((0..n).all(|i| contacts.values[i * n + i] == 1.0));
+
+        let distances = contact_map(&pdb, &options, ContactValues::Distances);
+        assert_eq!(distances.residues.len(), n);
+        for i in 0..n {
+            for j in 0..n {
+                let d = distances.values[i * n + j];
+                assert_eq!(d, distances.values[j * n + i]);
+                assert_eq!(contacts.values[i * n + j] == 1.0, d <= 4.0);
+            }
+        }
+    }
+}
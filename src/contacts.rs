@@ -0,0 +1,309 @@
+use crate::atomic::AtomicFile;
+use crate::residue::{protein_residues, ResidueKey};
+use crate::residue_graph::neighbour_pairs;
+use anyhow::Result;
+use nalgebra::Vector3;
+use pdbtbx::PDB;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rstar::primitives::GeomWithData;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+
+/// Heavy atoms of a residue.
+struct ResidueAtoms {
+    key: ResidueKey,
+    /// Position of the residue among the residues of its chain with heavy atoms
+    index: usize,
+    atoms: Vec<Vector3<f64>>,
+}
+
+
+fn residue_atoms(pdb: &PDB) -> Vec<ResidueAtoms> {
+    let mut index_in_chain: BTreeMap<String, usize> = BTreeMap::new();
+    protein_residues(pdb).into_iter().filter_map(|(key, r)| {
+        let atoms: Vec<Vector3<f64>> = r.atoms()
+            .filter(|a| a.element().is_some_and(|e| e.symbol() != "H" && e.symbol() != "D"))
+            .map(|a| {
+                let (x, y, z) = a.pos();
+                Vector3::new(x, y, z)
+            })
+            .collect();
+        if atoms.is_empty() {
+            return None;
+        }
+        let index = index_in_chain.entry(key.chain.clone()).or_default();
+        *index += 1;
+        Some(ResidueAtoms { key, index: *index, atoms })
+    }).collect()
+}
+
+
+/// Sequence separation class of a residue pair. Negatives are drawn per class
+/// so their separation statistics match those of the contacts. Short
+/// separations are kept exact, longer ones are binned by powers of two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum SeparationBin {
+    /// Residues of the same chain, lower bound of the binned separation
+    Intra(usize),
+    /// Residues of different chains
+    Interface,
+}
+
+impl SeparationBin {
+    fn of(a: &ResidueAtoms, b: &ResidueAtoms) -> SeparationBin {
+        if a.key.chain != b.key.chain {
+            return SeparationBin::Interface;
+        }
+        SeparationBin::intra(a.index.abs_diff(b.index))
+    }
+
+    fn intra(separation: usize) -> SeparationBin {
+        if separation <= 5 {
+            SeparationBin::Intra(separation)
+        } else {
+            // 6-11, 12-23, 24-47...
+            let mut lower = 6;
+            while lower * 2 <= separation {
+                lower *= 2;
+            }
+            SeparationBin::Intra(lower)
+        }
+    }
+}
+
+
+/// Pairs of residues of one separation bin, numbered so that a uniformly
+/// drawn number is a uniformly drawn pair.
+enum PairBlock<'a> {
+    /// Residues `separation` apart in a chain, by position in the residue list
+    Diagonal { chain: &'a [usize], separation: usize },
+    /// Every residue of one chain with every residue of another
+    Cross { a: &'a [usize], b: &'a [usize] },
+}
+
+impl PairBlock<'_> {
+    fn len(&self) -> usize {
+        match self {
+            PairBlock::Diagonal { chain, separation } => chain.len().saturating_sub(*separation),
+            PairBlock::Cross { a, b } => a.len() * b.len(),
+        }
+    }
+
+    fn pair(&self, rank: usize) -> (usize, usize) {
+        match self {
+            PairBlock::Diagonal { chain, separation } => (chain[rank], chain[rank + separation]),
+            PairBlock::Cross { a, b } => {
+                let (i, j) = (a[rank / b.len()], b[rank % b.len()]);
+                (i.min(j), i.max(j))
+            }
+        }
+    }
+}
+
+
+/// The pair of `blocks` numbered `rank`, counting through the blocks in order.
+fn nth_pair(blocks: &[PairBlock], mut rank: usize) -> (usize, usize) {
+    for block in blocks {
+        if rank < block.len() {
+            return block.pair(rank);
+        }
+        rank -= block.len();
+    }
+    unreachable!("rank beyond the pairs of the bin")
+}
+
+
+/// A labelled residue pair.
+pub struct ContactPair {
+    pub a: ResidueKey,
+    pub b: ResidueKey,
+    /// Number of residues between the pair along the chain, `None` across chains
+    pub separation: Option<usize>,
+    /// Shortest heavy atom distance in Å
+    pub min_distance: f64,
+    pub contact: bool,
+}
+
+
+/// Settings of the contact pair export.
+pub struct ContactOptions {
+    /// Heavy atom distance at which residues are in contact
+    pub contact_distance: f64,
+    /// Negatives drawn per contact
+    pub negative_ratio: f64,
+    /// Only pairs of residues in different chains
+    pub interface_only: bool,
+}
+
+
+/// Contacting pairs of protein residues of the first model, followed by
+/// non-contacting pairs sampled with `seed`. For every separation bin
+/// `negative_ratio` times as many negatives as contacts are drawn (fewer if
+/// the bin has fewer non-contacting pairs), so both classes share their
+/// sequence separation distribution. Pairs are unordered and adjacent
+/// residues are never included.
+///
+/// Contacts come from the neighbour search of residue graphs over heavy
+/// atoms. Negatives are drawn by number from the pairs of their bin and
+/// redrawn if they are contacts, so the non-contacting pairs of large
+/// complexes are never listed.
+pub fn contact_pairs(pdb: &PDB, opts: &ContactOptions, seed: u64) -> Vec<ContactPair> {
+    let residues = residue_atoms(pdb);
+    let points = residues.iter().enumerate()
+        .flat_map(|(i, r)| r.atoms.iter().map(move |p| GeomWithData::new([p.x, p.y, p.z], i)))
+        .collect();
+    let mut positives: BTreeMap<SeparationBin, Vec<(usize, usize)>> = BTreeMap::new();
+    let mut contacts: HashSet<(usize, usize)> = HashSet::new();
+    for ((i, j), _) in neighbour_pairs(points, opts.contact_distance) {
+        let bin = SeparationBin::of(&residues[i], &residues[j]);
+        if bin == SeparationBin::Intra(1) || (opts.interface_only && bin != SeparationBin::Interface) {
+            continue;
+        }
+        positives.entry(bin).or_default().push((i, j));
+        contacts.insert((i, j));
+    }
+
+    // Positions of the residues of every chain, in chain order
+    let mut chains: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, r) in residues.iter().enumerate() {
+        chains.entry(&r.key.chain).or_default().push(i);
+    }
+    let chains: Vec<&Vec<usize>> = chains.values().collect();
+    let blocks = |bin: SeparationBin| -> Vec<PairBlock> {
+        match bin {
+            SeparationBin::Interface => chains.iter().enumerate()
+                .flat_map(|(k, a)| chains[k + 1..].iter().map(move |b| PairBlock::Cross { a, b }))
+                .collect(),
+            SeparationBin::Intra(lower) => {
+                let longest = chains.iter().map(|c| c.len()).max().unwrap_or(0);
+                (lower..longest)
+                    .take_while(|s| SeparationBin::intra(*s) == bin)
+                    .flat_map(|separation| chains.iter().map(move |chain| PairBlock::Diagonal { chain, separation }))
+                    .collect()
+            }
+        }
+    };
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut pairs = Vec::new();
+    for (bin, positive) in positives {
+        let blocks = blocks(bin);
+        let candidates = blocks.iter().map(PairBlock::len).sum::<usize>() - positive.len();
+        let count = ((positive.len() as f64 * opts.negative_ratio).round() as usize).min(candidates);
+        let mut picked: HashSet<(usize, usize)> = HashSet::new();
+        while picked.len() < count {
+            let pair = nth_pair(&blocks, rng.gen_range(0..candidates + positive.len()));
+            if !contacts.contains(&pair) {
+                picked.insert(pair);
+            }
+        }
+        let mut picked: Vec<(usize, usize)> = picked.into_iter().collect();
+        picked.sort_unstable();
+        pairs.extend(positive.into_iter().chain(picked).map(|(i, j)| pair(&residues[i], &residues[j]).labelled(opts.contact_distance)));
+    }
+    pairs
+}
+
+
+/// Residue pair before labelling.
+struct Unlabelled<'a> {
+    a: &'a ResidueAtoms,
+    b: &'a ResidueAtoms,
+    min_distance: f64,
+}
+
+impl Unlabelled<'_> {
+    fn labelled(&self, contact_distance: f64) -> ContactPair {
+        ContactPair {
+            a: self.a.key.clone(),
+            b: self.b.key.clone(),
+            separation: (self.a.key.chain == self.b.key.chain).then(|| self.a.index.abs_diff(self.b.index)),
+            min_distance: self.min_distance,
+            contact: self.min_distance <= contact_distance,
+        }
+    }
+}
+
+
+fn pair<'a>(a: &'a ResidueAtoms, b: &'a ResidueAtoms) -> Unlabelled<'a> {
+    let min_distance = a.atoms.iter()
+        .flat_map(|p| b.atoms.iter().map(move |q| (p - q).norm()))
+        .fold(f64::INFINITY, f64::min);
+    Unlabelled { a, b, min_distance }
+}
+
+
+/// Writes labelled pairs as a flat CSV table. The separation is left empty
+/// for pairs of different chains.
+pub fn write_contact_pairs(pairs: &[ContactPair], save_fname: &Path) -> Result<()> {
+    let mut out = BufWriter::new(AtomicFile::create(save_fname)?);
+    writeln!(out, "residue_a,residue_b,separation,interface,min_distance,label")?;
+    for p in pairs {
+        writeln!(
+            out,
+            "{},{},{},{},{:.3},{}",
+            p.a,
+            p.b,
+            p.separation.map(|s| s.to_string()).unwrap_or_default(),
+            p.separation.is_none() as u8,
+            p.min_distance,
+            p.contact as u8
+        )?;
+    }
+    out.into_inner().map_err(|e| e.into_error())?.commit()?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn antibody() -> PDB {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        pdbtbx::open(path, pdbtbx::StrictnessLevel::Medium).unwrap().0
+    }
+
+    fn bin(pair: &ContactPair) -> SeparationBin {
+        pair.separation.map_or(SeparationBin::Interface, SeparationBin::intra)
+    }
+
+    #[test]
+    fn samples_negatives_per_separation_bin() {
+        let pdb = antibody();
+        let opts = ContactOptions { contact_distance: 4.5, negative_ratio: 1.0, interface_only: false };
+        let pairs = contact_pairs(&pdb, &opts, 1);
+        assert!(pairs.iter().any(|p| p.contact) && pairs.iter().any(|p| !p.contact));
+        assert!(pairs.iter().all(|p| p.separation != Some(1) && p.separation != Some(0)));
+        assert!(pairs.iter().all(|p| p.contact == (p.min_distance <= 4.5)));
+
+        let mut counts: BTreeMap<SeparationBin, (usize, usize)> = BTreeMap::new();
+        for p in &pairs {
+            let count = counts.entry(bin(p)).or_default();
+            if p.contact { count.0 += 1 } else { count.1 += 1 }
+        }
+        // Every bin with contacts gets as many negatives, unless it runs out
+        let residues = residue_atoms(&pdb);
+        for (bin, (positive, negative)) in counts {
+            let size = (0..residues.len())
+                .flat_map(|i| (i + 1..residues.len()).map(move |j| (i, j)))
+                .filter(|(i, j)| SeparationBin::of(&residues[*i], &residues[*j]) == bin)
+                .count();
+            assert_eq!(negative, positive.min(size - positive), "{:?}", bin);
+        }
+
+        let key = |p: &ContactPair| (p.a.to_string(), p.b.to_string());
+        let again: Vec<_> = contact_pairs(&pdb, &opts, 1).iter().map(key).collect();
+        assert_eq!(pairs.iter().map(key).collect::<Vec<_>>(), again);
+        // Sparser negatives leave room for seeds to differ
+        let sparse = ContactOptions { negative_ratio: 0.5, ..opts };
+        let seeded = |seed| contact_pairs(&pdb, &sparse, seed).iter().map(key).collect::<Vec<_>>();
+        assert_eq!(seeded(1), seeded(1));
+        assert!((2..10).any(|seed| seeded(seed) != seeded(1)));
+
+        let interface = contact_pairs(&pdb, &ContactOptions { interface_only: true, ..sparse }, 1);
+        assert!(interface.iter().all(|p| p.separation.is_none()));
+    }
+}
@@ -0,0 +1,210 @@
+use crate::residue::{one_letter_code, protein_residues, ResidueKey};
+use anyhow::{bail, Context, Result};
+use pdbtbx::PDB;
+use serde::Serialize;
+use std::collections::HashMap;
+
+
+const MATCH: i32 = 2;
+const MISMATCH: i32 = -1;
+const GAP: i32 = -2;
+
+
+/// Global alignment of two sequences with linear gap penalties. Returns the
+/// aligned index pairs.
+pub fn needleman_wunsch(a: &[char], b: &[char]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len(), b.len());
+    let mut score = vec![vec![0i32; m + 1]; n + 1];
+    for (i, row) in score.iter_mut().enumerate() {
+        row[0] = i as i32 * GAP;
+    }
+    for (j, cell) in score[0].iter_mut().enumerate() {
+        *cell = j as i32 * GAP;
+    }
+    let substitution = |i: usize, j: usize| if a[i] == b[j] && a[i] != 'X' { MATCH } else { MISMATCH };
+    for i in 1..=n {
+        for j in 1..=m {
+            score[i][j] = (score[i - 1][j - 1] + substitution(i - 1, j - 1))
+                .max(score[i - 1][j] + GAP)
+                .max(score[i][j - 1] + GAP);
+        }
+    }
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (n, m);
+    while i > 0 && j > 0 {
+        if score[i][j] == score[i - 1][j - 1] + substitution(i - 1, j - 1) {
+            pairs.push((i - 1, j - 1));
+            i -= 1;
+            j -= 1;
+        } else if score[i][j] == score[i - 1][j] + GAP {
+            i -= 1;
+        } else {
+            j -= 1;
+        }
+    }
+    pairs.reverse();
+    pairs
+}
+
+
+/// A pairwise alignment read from a file.
+pub struct PairwiseAlignment {
+    /// Ungapped sequences
+    pub seq_a: String,
+    pub seq_b: String,
+    /// Aligned index pairs into the ungapped sequences
+    pub pairs: Vec<(usize, usize)>,
+}
+
+
+/// Reads a pairwise alignment from a FASTA file holding exactly two gapped sequences.
+pub fn read_fasta_alignment(fname: &str) -> Result<PairwiseAlignment> {
+    let text = std::fs::read_to_string(fname).with_context(|| format!("Reading alignment {}", fname))?;
+    let mut records: Vec<String> = Vec::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if line.starts_with('>') {
+            records.push(String::new());
+        } else if let Some(seq) = records.last_mut() {
+            seq.push_str(line);
+        }
+    }
+    if records.len() != 2 || records[0].len() != records[1].len() {
+        bail!("Alignment {} must contain two gapped sequences of equal length", fname);
+    }
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (ca, cb) in records[0].chars().zip(records[1].chars()) {
+        match (ca == '-', cb == '-') {
+            (false, false) => {
+                pairs.push((i, j));
+                i += 1;
+                j += 1;
+            }
+            (false, true) => i += 1,
+            (true, false) => j += 1,
+            (true, true) => {}
+        }
+    }
+    let ungapped = |s: &str| s.chars().filter(|c| *c != '-').collect::<String>();
+    Ok(PairwiseAlignment { seq_a: ungapped(&records[0]), seq_b: ungapped(&records[1]), pairs })
+}
+
+
+/// Correspondence between the nodes of the graphs of two homologous structures.
+#[derive(Serialize)]
+pub struct Correspondence {
+    pub source_a: String,
+    pub source_b: String,
+    pub aligned_residues: usize,
+    /// Fraction of aligned residue pairs with the same amino acid
+    pub identity: f64,
+    pub residues: Vec<(ResidueKey, ResidueKey)>,
+    /// Pairs of node ids (atom serial numbers) of atoms with the same name in aligned residues
+    pub nodes: Vec<(usize, usize)>,
+}
+
+
+/// Maps residues and atoms of `b` onto `a`, aligning their protein
+/// sequences with Needleman-Wunsch unless an alignment file is given.
+pub fn correspond(source_a: &str, a: &PDB, source_b: &str, b: &PDB, alignment: Option<&str>) -> Result<Correspondence> {
+    let res_a = protein_residues(a);
+    let res_b = protein_residues(b);
+    let seq = |residues: &[(ResidueKey, &pdbtbx::Residue)]| -> Vec<char> {
+        residues.iter().map(|(_, r)| one_letter_code(r.name().unwrap_or(""))).collect()
+    };
+    let (seq_a, seq_b) = (seq(&res_a), seq(&res_b));
+    let pairs = match alignment {
+        Some(fname) => {
+            let aln = read_fasta_alignment(fname)?;
+            if aln.seq_a != seq_a.iter().collect::<String>() || aln.seq_b != seq_b.iter().collect::<String>() {
+                bail!("Sequences in alignment {} don't match the protein sequences of {} and {}", fname, source_a, source_b);
+            }
+            aln.pairs
+        }
+        None => needleman_wunsch(&seq_a, &seq_b),
+    };
+
+    let identical = pairs.iter().filter(|(i, j)| seq_a[*i] == seq_b[*j]).count();
+    let mut nodes = Vec::new();
+    for (i, j) in &pairs {
+        let atoms_b: HashMap<&str, usize> = res_b[*j].1.atoms()
+            .filter(|a| a.element().is_some())
+            .map(|a| (a.name(), a.serial_number()))
+            .collect();
+        for atom in res_a[*i].1.atoms().filter(|a| a.element().is_some()) {
+            if let Some(serial_b) = atoms_b.get(atom.name()) {
+                nodes.push((atom.serial_number(), *serial_b));
+            }
+        }
+    }
+    Ok(Correspondence {
+        source_a: source_a.to_string(),
+        source_b: source_b.to_string(),
+        aligned_residues: pairs.len(),
+        identity: if pairs.is_empty() { 0.0 } else { identical as f64 / pairs.len() as f64 },
+        residues: pairs.iter().map(|(i, j)| (res_a[*i].0.clone(), res_b[*j].0.clone())).collect(),
+        nodes,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn antibody() -> PDB {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        pdbtbx::open(path, pdbtbx::StrictnessLevel::Medium).unwrap().0
+    }
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn aligns_sequences() {
+        assert_eq!(needleman_wunsch(&chars("ACDEFG"), &chars("ACEFG")), vec![(0, 0), (1, 1), (3, 2), (4, 3), (5, 4)]);
+        assert_eq!(needleman_wunsch(&chars("ACDE"), &chars("ACDE")), vec![(0, 0), (1, 1), (2, 2), (3, 3)]);
+        assert!(needleman_wunsch(&chars(""), &chars("ACDE")).is_empty());
+    }
+
+    #[test]
+    fn reads_gapped_alignments() {
+        let dir = std::env::temp_dir().join(format!("graphein_correspond_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("aln.fasta");
+        std::fs::write(&path, ">a\nAC-D-E\n\n>b\nA-GD-E\n").unwrap();
+        let aln = read_fasta_alignment(path.to_str().unwrap()).unwrap();
+        assert_eq!((aln.seq_a.as_str(), aln.seq_b.as_str()), ("ACDE", "AGDE"));
+        assert_eq!(aln.pairs, vec![(0, 0), (2, 2), (3, 3)]);
+
+        std::fs::write(&path, ">a\nACDE\n>b\nACD\n").unwrap();
+        assert!(read_fasta_alignment(path.to_str().unwrap()).is_err());
+        std::fs::write(&path, ">a\nACDE\n").unwrap();
+        assert!(read_fasta_alignment(path.to_str().unwrap()).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn maps_atoms_of_aligned_residues() {
+        let a = antibody();
+        let mut b = antibody();
+        // Drop H:52A from b
+        b.remove_residues_by(|r| r.serial_number() == 52 && r.insertion_code() == Some("A"));
+        let c = correspond("a.pdb", &a, "b.pdb", &b, None).unwrap();
+        assert_eq!(c.aligned_residues, 14);
+        assert_eq!(c.identity, 1.0);
+        assert!(c.residues.iter().all(|(ra, rb)| ra == rb));
+        assert!(!c.residues.iter().any(|(ra, _)| ra.to_string() == "H:52A"));
+
+        // Residue and name of every atom by serial number
+        let named = |pdb: &PDB| -> HashMap<usize, (String, String)> {
+            pdb.chains()
+                .flat_map(|ch| ch.residues().flat_map(move |r| r.atoms().map(move |at| (at.serial_number(), (ResidueKey::new(ch, r).to_string(), at.name().to_string())))))
+                .collect()
+        };
+        let (serials_a, serials_b) = (named(&a), named(&b));
+        assert_eq!(c.nodes.len(), serials_b.len());
+        assert!(c.nodes.iter().all(|(na, nb)| serials_a[na] == serials_b[nb]));
+    }
+}
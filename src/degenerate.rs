@@ -0,0 +1,89 @@
+use petgraph::graph::Graph;
+use serde::Serialize;
+use std::fmt;
+
+
+/// Limits beyond which a graph is considered unusable for training.
+pub struct Thresholds {
+    /// Largest acceptable mean node degree
+    pub max_mean_degree: f64,
+    /// Largest acceptable fraction of nodes without edges
+    pub max_isolated_fraction: f64,
+}
+
+
+/// Why a graph was flagged, stored in the graph metadata.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Degeneracy {
+    NoEdges,
+    Dense { mean_degree: f64 },
+    IsolatedNodes { fraction: f64 },
+}
+
+impl fmt::Display for Degeneracy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Degeneracy::NoEdges => write!(f, "no edges"),
+            Degeneracy::Dense { mean_degree } => write!(f, "mean degree {:.1}", mean_degree),
+            Degeneracy::IsolatedNodes { fraction } => write!(f, "{:.0}% isolated nodes", fraction * 100.0),
+        }
+    }
+}
+
+
+/// Checks a graph for signs that the cutoff doesn't suit the structure:
+/// no edges at all, too many edges per node or too many nodes without any.
+/// Edges are stored in both directions, so the mean degree is the edge count
+/// over the node count.
+pub fn check<N, E>(graph: &Graph<N, E>, thresholds: &Thresholds) -> Vec<Degeneracy> {
+    if graph.edge_count() == 0 {
+        return vec![Degeneracy::NoEdges];
+    }
+    let mut issues = Vec::new();
+    let nodes = graph.node_count() as f64;
+    let mean_degree = graph.edge_count() as f64 / nodes;
+    if mean_degree > thresholds.max_mean_degree {
+        issues.push(Degeneracy::Dense { mean_degree });
+    }
+    let isolated = graph.node_indices().filter(|&n| graph.neighbors_undirected(n).next().is_none()).count();
+    let fraction = isolated as f64 / nodes;
+    if fraction > thresholds.max_isolated_fraction {
+        issues.push(Degeneracy::IsolatedNodes { fraction });
+    }
+    issues
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THRESHOLDS: Thresholds = Thresholds { max_mean_degree: 3.0, max_isolated_fraction: 0.3 };
+
+    /// Graph of `n` nodes with both directions of each of `edges`.
+    fn graph(n: usize, edges: &[(usize, usize)]) -> Graph<(), ()> {
+        let mut graph = Graph::new();
+        let nodes: Vec<_> = (0..n).map(|_| graph.add_node(())).collect();
+        for (a, b) in edges {
+            graph.add_edge(nodes[*a], nodes[*b], ());
+            graph.add_edge(nodes[*b], nodes[*a], ());
+        }
+        graph
+    }
+
+    #[test]
+    fn flags_degenerate_graphs() {
+        let path = graph(4, &[(0, 1), (1, 2), (2, 3)]);
+        assert!(check(&path, &THRESHOLDS).is_empty());
+
+        assert_eq!(check(&graph(0, &[]), &THRESHOLDS), vec![Degeneracy::NoEdges]);
+        assert_eq!(check(&graph(5, &[]), &THRESHOLDS), vec![Degeneracy::NoEdges]);
+
+        let complete = graph(5, &[(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)]);
+        assert_eq!(check(&complete, &THRESHOLDS), vec![Degeneracy::Dense { mean_degree: 4.0 }]);
+
+        let scattered = graph(5, &[(0, 1), (1, 2)]);
+        assert_eq!(check(&scattered, &THRESHOLDS), vec![Degeneracy::IsolatedNodes { fraction: 0.4 }]);
+    }
+}
@@ -0,0 +1,54 @@
+use pdbtbx::{Atom, PDB};
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+
+
+/// Default largest SG–SG distance of disulfide bridges in Å.
+pub const DEFAULT_DISULFIDE_DISTANCE: f64 = 2.05;
+
+
+/// Finds the disulfide bridges of the given structures: pairs of cysteine SG
+/// atoms at most `max_distance` apart, with their distance. Atoms of
+/// different structures are only paired with `inter_structure`.
+pub fn disulfides<'a>(structures: &[&'a PDB], max_distance: f64, inter_structure: bool) -> Vec<(&'a Atom, &'a Atom, f64)> {
+    let mut sulfurs: Vec<(usize, &Atom)> = Vec::new();
+    for (structure, pdb) in structures.iter().enumerate() {
+        for residue in pdb.residues().filter(|r| matches!(r.name(), Some("CYS" | "CYX"))) {
+            sulfurs.extend(residue.atoms().filter(|a| a.name() == "SG").map(|a| (structure, a)));
+        }
+    }
+    let tree = RTree::bulk_load(sulfurs.iter().enumerate().map(|(i, (_, a))| GeomWithData::new([a.x(), a.y(), a.z()], i)).collect());
+    let mut bridges = Vec::new();
+    for (i, (structure, atom)) in sulfurs.iter().enumerate() {
+        for neighbor in tree.locate_within_distance([atom.x(), atom.y(), atom.z()], max_distance * max_distance) {
+            let (other_structure, other) = sulfurs[neighbor.data];
+            if neighbor.data > i && (*structure == other_structure || inter_structure) {
+                bridges.push((*atom, other, atom.distance(other)));
+            }
+        }
+    }
+    bridges
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdbtbx::Model;
+
+    #[test]
+    fn pairs_close_cysteines() {
+        let mut model = Model::new(0);
+        for (serial, x) in [(1, 0.0), (2, 2.03), (3, 6.0)] {
+            let sg = Atom::new(false, serial, "SG", x, 0.0, 0.0, 1.0, 20.0, "S", 0).unwrap();
+            model.add_atom(sg, "A", (serial as isize, None), ("CYS", None));
+        }
+        let mut pdb = PDB::new();
+        pdb.add_model(model);
+
+        let bridges = disulfides(&[&pdb], DEFAULT_DISULFIDE_DISTANCE, false);
+        assert_eq!(bridges.len(), 1);
+        assert_eq!((bridges[0].0.serial_number(), bridges[0].1.serial_number()), (1, 2));
+        assert!(disulfides(&[&pdb], 2.0, false).is_empty());
+    }
+}
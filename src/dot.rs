@@ -0,0 +1,65 @@
+use crate::{AtomNode, BondType, EdgeData};
+use pdbtbx::Element;
+use petgraph::graph::Graph;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+
+/// Quotes a Graphviz id.
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+
+/// Graphviz text of an atom graph named `name`, for looking at small
+/// structures. Nodes are named by their index, since serial numbers repeat
+/// in merged graphs, and labelled with their element and edges with their
+/// distance, which is also the preferred edge length of `neato`. Covalent
+/// bonds are drawn bold. Edges stored in both directions are written once.
+pub fn atom_graph_dot(graph: &Graph<AtomNode, EdgeData>, name: &str) -> String {
+    let mut dot = format!("graph {} {{\n", quote(name));
+    for (index, node) in graph.node_weights().enumerate() {
+        let element = Element::new(node.atom_number as usize).map_or("?", |e| e.symbol());
+        writeln!(dot, "  {} [label=\"{}\"];", index, element).unwrap();
+    }
+    let mut written = HashSet::new();
+    for edge in graph.raw_edges() {
+        let (source, target) = (edge.source().index(), edge.target().index());
+        if !written.insert((source.min(target), source.max(target))) {
+            continue;
+        }
+        let style = if edge.weight.bond_type == BondType::Covalent { ", style=bold" } else { "" };
+        writeln!(
+            dot,
+            "  {} -- {} [label=\"{:.2}\", len={:.2}{}];",
+            source,
+            target,
+            edge.weight.distance,
+            edge.weight.distance,
+            style,
+        ).unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_graph;
+    use pdbtbx::StrictnessLevel;
+
+    #[test]
+    fn writes_each_edge_once() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        let pdb = pdbtbx::open(path, StrictnessLevel::Medium).unwrap().0;
+        let graph = build_graph(&pdb);
+
+        let dot = atom_graph_dot(&graph, "ab\"1");
+        assert!(dot.starts_with("graph \"ab\\\"1\" {\n"));
+        assert_eq!(dot.matches(" -- ").count(), graph.edge_count() / 2);
+        assert_eq!(dot.matches("[label=\"N\"]").count(), graph.node_weights().filter(|n| n.atom_number == 7).count());
+        assert!(dot.contains("style=bold"));
+    }
+}
@@ -0,0 +1,322 @@
+use crate::residue::protein_residues;
+use nalgebra::Vector3;
+use pdbtbx::{Residue, PDB};
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+
+/// DSSP codes in order of priority: α helix, isolated bridge, strand, 3₁₀
+/// helix, π helix, turn, bend and coil.
+pub const DSSP_CODES: [char; 8] = ['H', 'B', 'E', 'G', 'I', 'T', 'S', '-'];
+
+/// Energy (kcal/mol) below which a backbone hydrogen bond is assigned
+const HBOND_ENERGY: f64 = -0.5;
+/// Lowest energy DSSP assigns, for nearly overlapping groups
+const MIN_ENERGY: f64 = -9.9;
+/// 0.084 × 332, the product of the partial charges of the C=O and N–H
+/// groups and the dimensional factor of the Kabsch–Sander model
+const COUPLING: f64 = 27.888;
+/// Residues with CA atoms further apart (in Å) can't be hydrogen bonded
+const CA_DISTANCE: f64 = 9.0;
+/// Longest C–N distance (in Å) of a peptide bond; longer ones are chain breaks
+pub(crate) const PEPTIDE_BOND: f64 = 2.5;
+/// Smallest CA(i-2)–CA(i)–CA(i+2) direction change (in degrees) of a bend
+const BEND_ANGLE: f64 = 70.0;
+
+
+/// Three state secondary structure of a residue.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SecondaryStructure {
+    Helix,
+    Sheet,
+    Coil,
+}
+
+impl SecondaryStructure {
+    /// Reduces a DSSP code: α, 3₁₀ and π helices are helices, strands and
+    /// bridges are sheets, everything else is coil.
+    pub fn from_code(code: char) -> SecondaryStructure {
+        match code {
+            'H' | 'G' | 'I' => SecondaryStructure::Helix,
+            'E' | 'B' => SecondaryStructure::Sheet,
+            _ => SecondaryStructure::Coil,
+        }
+    }
+}
+
+
+/// Secondary structure of a residue, attached to its atoms.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ResidueSecondaryStructure {
+    pub secondary_structure: SecondaryStructure,
+    /// Eight state DSSP code, one of `DSSP_CODES`
+    pub dssp: char,
+}
+
+
+/// Residue counts of each secondary structure, stored in the graph metadata.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct SecondaryStructureInfo {
+    pub helix: usize,
+    pub sheet: usize,
+    pub coil: usize,
+}
+
+
+/// Backbone of a residue with a complete N, CA, C and O.
+struct Backbone {
+    n: Vector3<f64>,
+    ca: Vector3<f64>,
+    c: Vector3<f64>,
+    o: Vector3<f64>,
+    /// Amide hydrogen placed from the previous residue, `None` for prolines
+    /// and residues after a chain break
+    h: Option<Vector3<f64>>,
+}
+
+
+fn position(residue: &Residue, name: &str) -> Option<Vector3<f64>> {
+    residue.atoms().find(|a| a.name() == name).map(|a| {
+        let (x, y, z) = a.pos();
+        Vector3::new(x, y, z)
+    })
+}
+
+
+/// Kabsch–Sander electrostatic energy of the hydrogen bond from the N–H of
+/// `donor` to the C=O of `acceptor`.
+fn hbond_energy(donor: &Backbone, acceptor: &Backbone) -> f64 {
+    let Some(h) = donor.h else {
+        return 0.0;
+    };
+    let d = |a: Vector3<f64>, b: Vector3<f64>| (a - b).norm();
+    let energy = COUPLING * (1.0 / d(acceptor.o, donor.n) + 1.0 / d(acceptor.c, h) - 1.0 / d(acceptor.o, h) - 1.0 / d(acceptor.c, donor.n));
+    energy.max(MIN_ENERGY)
+}
+
+
+/// Backbone geometry and hydrogen bonds of the residues of a structure with
+/// complete backbones, in chain order.
+struct Backbones {
+    residues: Vec<Backbone>,
+    /// Index of the unbroken chain segment of every residue
+    segment: Vec<usize>,
+    /// Hydrogen bonds as (C=O residue, N–H residue), keeping the two
+    /// strongest per N–H as DSSP does
+    hbonds: HashSet<(usize, usize)>,
+    /// Pairs of residues with CA atoms close enough to be hydrogen bonded
+    near: Vec<(usize, usize)>,
+}
+
+impl Backbones {
+    fn new(residues: Vec<(&str, &Residue)>) -> Backbones {
+        let mut backbones: Vec<Backbone> = Vec::new();
+        let mut segment: Vec<usize> = Vec::new();
+        let mut previous_chain = None;
+        for (chain, residue) in residues {
+            let (Some(n), Some(ca), Some(c), Some(o)) = (position(residue, "N"), position(residue, "CA"), position(residue, "C"), position(residue, "O")) else {
+                previous_chain = None;
+                continue;
+            };
+            let previous = backbones.last().filter(|p| previous_chain == Some(chain) && (p.c - n).norm() < PEPTIDE_BOND);
+            let h = previous.filter(|_| residue.name() != Some("PRO")).map(|p| n + (p.c - p.o).normalize());
+            let next_segment = segment.last().map_or(0, |s| s + 1);
+            segment.push(if previous.is_some() { *segment.last().unwrap() } else { next_segment });
+            backbones.push(Backbone { n, ca, c, o, h });
+            previous_chain = Some(chain);
+        }
+
+        let tree = RTree::bulk_load(backbones.iter().enumerate().map(|(i, b)| GeomWithData::new([b.ca.x, b.ca.y, b.ca.z], i)).collect());
+        let mut near = Vec::new();
+        let mut acceptors: Vec<Vec<(f64, usize)>> = vec![Vec::new(); backbones.len()];
+        for (i, donor) in backbones.iter().enumerate() {
+            for neighbor in tree.locate_within_distance([donor.ca.x, donor.ca.y, donor.ca.z], CA_DISTANCE * CA_DISTANCE) {
+                let j = neighbor.data;
+                if j == i {
+                    continue;
+                }
+                if i < j {
+                    near.push((i, j));
+                }
+                // The N–H of a residue can't bond the C=O just before it
+                if j + 1 == i {
+                    continue;
+                }
+                let energy = hbond_energy(donor, &backbones[j]);
+                if energy < HBOND_ENERGY {
+                    acceptors[i].push((energy, j));
+                }
+            }
+        }
+        let mut hbonds = HashSet::new();
+        for (donor, mut bonds) in acceptors.into_iter().enumerate() {
+            bonds.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            hbonds.extend(bonds.into_iter().take(2).map(|(_, acceptor)| (acceptor, donor)));
+        }
+        near.sort_unstable();
+        Backbones { residues: backbones, segment, hbonds, near }
+    }
+
+    /// Whether the C=O of `i` is hydrogen bonded to the N–H of `j`.
+    fn hbond(&self, i: usize, j: usize) -> bool {
+        self.hbonds.contains(&(i, j))
+    }
+
+    /// Whether residues `i` to `j` are in one unbroken segment.
+    fn unbroken(&self, i: usize, j: usize) -> bool {
+        j < self.residues.len() && self.segment[i] == self.segment[j]
+    }
+
+    /// Whether there is an `n`-turn at `i`, a hydrogen bond from the C=O of
+    /// `i` to the N–H of `i + n`.
+    fn turn(&self, n: usize, i: usize) -> bool {
+        self.unbroken(i, i + n) && self.hbond(i, i + n)
+    }
+
+    /// Kind of β bridge between `i` and `j`: `Some(true)` for parallel,
+    /// `Some(false)` for antiparallel.
+    fn bridge(&self, i: usize, j: usize) -> Option<bool> {
+        if i == 0 || j == 0 || j < i + 3 || !self.unbroken(i - 1, i + 1) || !self.unbroken(j - 1, j + 1) {
+            return None;
+        }
+        if (self.hbond(i - 1, j) && self.hbond(j, i + 1)) || (self.hbond(j - 1, i) && self.hbond(i, j + 1)) {
+            Some(true)
+        } else if (self.hbond(i, j) && self.hbond(j, i)) || (self.hbond(i - 1, j + 1) && self.hbond(j - 1, i + 1)) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// DSSP code of every residue.
+    fn codes(&self) -> Vec<char> {
+        let len = self.residues.len();
+        let mut codes = vec!['-'; len];
+        let helix = |n: usize| (1..len).filter(move |&i| self.turn(n, i - 1) && self.turn(n, i));
+
+        for i in helix(4) {
+            codes[i..i + 4].fill('H');
+        }
+
+        let bridges: HashMap<(usize, usize), bool> = self.near.iter()
+            .filter_map(|&(i, j)| self.bridge(i, j).map(|parallel| ((i, j), parallel)))
+            .collect();
+        for (&(i, j), &parallel) in &bridges {
+            // Consecutive bridges of the same kind form a ladder
+            let next = if parallel { [(i + 1, j + 1), (i.wrapping_sub(1), j.wrapping_sub(1))] } else { [(i + 1, j.wrapping_sub(1)), (i.wrapping_sub(1), j + 1)] };
+            let ladder = next.iter().any(|pair| bridges.get(pair) == Some(&parallel));
+            for k in [i, j] {
+                if codes[k] == '-' || codes[k] == 'B' {
+                    codes[k] = if ladder { 'E' } else { 'B' };
+                }
+            }
+        }
+
+        // 3₁₀ and π helices only where nothing else was assigned
+        for (n, code) in [(3, 'G'), (5, 'I')] {
+            for i in helix(n) {
+                if codes[i..i + n].iter().all(|&c| c == '-' || c == code) {
+                    codes[i..i + n].fill(code);
+                }
+            }
+        }
+
+        for n in [3, 4, 5] {
+            for i in (0..len).filter(|&i| self.turn(n, i)) {
+                for code in &mut codes[i + 1..i + n] {
+                    if *code == '-' {
+                        *code = 'T';
+                    }
+                }
+            }
+        }
+
+        let bends: Vec<usize> = (2..len.saturating_sub(2))
+            .filter(|&i| self.unbroken(i - 2, i + 2))
+            .filter(|&i| {
+                let (before, after) = (self.residues[i].ca - self.residues[i - 2].ca, self.residues[i + 2].ca - self.residues[i].ca);
+                before.angle(&after).to_degrees() > BEND_ANGLE
+            })
+            .collect();
+        for i in bends {
+            if codes[i] == '-' {
+                codes[i] = 'S';
+            }
+        }
+        codes
+    }
+}
+
+
+/// Assigns the secondary structure of the amino acids of the first model of
+/// `pdb` from backbone hydrogen bonds as DSSP does, without β-bulges, and
+/// attaches it to their atoms by serial number. Amino acids with an
+/// incomplete backbone are coil.
+pub fn atom_secondary_structure(pdb: &PDB) -> (HashMap<usize, ResidueSecondaryStructure>, SecondaryStructureInfo) {
+    let residues = protein_residues(pdb);
+    let complete: Vec<bool> = residues.iter()
+        .map(|(_, r)| ["N", "CA", "C", "O"].iter().all(|name| r.atoms().any(|a| a.name() == *name)))
+        .collect();
+    let backbones = Backbones::new(residues.iter().zip(&complete).filter(|(_, c)| **c).map(|((key, r), _)| (key.chain.as_str(), *r)).collect());
+    let mut codes = backbones.codes().into_iter();
+
+    let mut atoms = HashMap::new();
+    let mut info = SecondaryStructureInfo::default();
+    for ((_, residue), complete) in residues.iter().zip(complete) {
+        let dssp = if complete { codes.next().unwrap_or('-') } else { '-' };
+        let secondary_structure = SecondaryStructure::from_code(dssp);
+        match secondary_structure {
+            SecondaryStructure::Helix => info.helix += 1,
+            SecondaryStructure::Sheet => info.sheet += 1,
+            SecondaryStructure::Coil => info.coil += 1,
+        }
+        atoms.extend(residue.atoms().map(|a| (a.serial_number(), ResidueSecondaryStructure { secondary_structure, dssp })));
+    }
+    (atoms, info)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdbtbx::{Atom, Chain, Model};
+
+    /// Backbone of an ideal α helix, 1.5 Å rise and 100° turn per residue.
+    fn helix(residues: usize) -> PDB {
+        // Cylindrical coordinates (radius, phase, rise) of the backbone atoms
+        let offsets = [("N", 1.55, -28.0_f64, -0.85), ("CA", 2.3, 0.0_f64, 0.0), ("C", 1.65, 30.0_f64, 0.95), ("O", 1.9, 43.0_f64, 2.15)];
+        let mut chain = Chain::new("A").unwrap();
+        let mut serial = 1;
+        for i in 0..residues {
+            let mut residue = Residue::new(i as isize + 1, None, None).unwrap();
+            let mut conformer = pdbtbx::Conformer::new("ALA", None, None).unwrap();
+            for (name, radius, phase, rise) in offsets {
+                let angle = (i as f64 * 100.0 + phase).to_radians();
+                let element = &name[..1];
+                conformer.add_atom(Atom::new(false, serial, name, radius * angle.cos(), radius * angle.sin(), i as f64 * 1.5 + rise, 1.0, 20.0, element, 0).unwrap());
+                serial += 1;
+            }
+            residue.add_conformer(conformer);
+            chain.add_residue(residue);
+        }
+        let mut model = Model::new(0);
+        model.add_chain(chain);
+        let mut pdb = PDB::new();
+        pdb.add_model(model);
+        pdb
+    }
+
+    #[test]
+    fn assigns_ideal_helix() {
+        let pdb = helix(12);
+        let (atoms, info) = atom_secondary_structure(&pdb);
+        assert_eq!(atoms.len(), 48);
+        assert!(info.helix >= 8, "{:?}", info);
+        assert_eq!(info.sheet, 0);
+        let middle = pdb.atoms().nth(6 * 4).unwrap().serial_number();
+        assert_eq!(atoms[&middle], ResidueSecondaryStructure { secondary_structure: SecondaryStructure::Helix, dssp: 'H' });
+    }
+}
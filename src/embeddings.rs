@@ -0,0 +1,237 @@
+use crate::entity::{classify_residue, EntityType};
+use anyhow::{anyhow, bail, Context, Result};
+use pdbtbx::PDB;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+
+/// Per-residue embeddings of one chain, `rows` x `dim` in C order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    pub rows: usize,
+    pub dim: usize,
+    pub data: Vec<f32>,
+}
+
+impl Matrix {
+    /// Reads a `[residues, dim]` array, or `[1, residues, dim]` as written
+    /// for a batch of one sequence.
+    fn new(shape: &[usize], data: Vec<f32>) -> Result<Matrix> {
+        match shape {
+            [rows, dim] | [1, rows, dim] => Ok(Matrix { rows: *rows, dim: *dim, data }),
+            _ => bail!("expected a [residues, dim] array, got shape {:?}", shape),
+        }
+    }
+
+    fn row(&self, i: usize) -> &[f32] {
+        &self.data[i * self.dim..(i + 1) * self.dim]
+    }
+}
+
+
+/// Summary of the embeddings attached to a graph, stored in the graph metadata.
+#[derive(Serialize, Debug, Clone)]
+pub struct EmbeddingInfo {
+    /// File the embeddings were read from, or the first of the per-chain files
+    pub source: String,
+    pub dim: usize,
+    pub chains: Vec<String>,
+    /// Begin and end of sequence tokens were dropped from the embeddings
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub special_tokens: bool,
+}
+
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (bits >> 10) & 0x1f;
+    let fraction = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => sign * fraction * 2f32.powi(-24),
+        0x1f if fraction == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + fraction / 1024.0) * 2f32.powi(exponent as i32 - 15),
+    }
+}
+
+
+/// Decodes little-endian floats of the given width and kind.
+fn decode(bytes: &[u8], dtype: &str) -> Result<Vec<f32>> {
+    let values = match dtype {
+        "f2" => bytes.chunks_exact(2).map(|b| f16_to_f32(u16::from_le_bytes([b[0], b[1]]))).collect(),
+        "bf2" => bytes.chunks_exact(2).map(|b| f32::from_bits((u16::from_le_bytes([b[0], b[1]]) as u32) << 16)).collect(),
+        "f4" => bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect(),
+        "f8" => bytes.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32).collect(),
+        _ => bail!("unsupported dtype {}", dtype),
+    };
+    Ok(values)
+}
+
+
+fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{}':", key))? + key.len() + 3;
+    Some(header[start..].trim_start())
+}
+
+
+/// Reads a little-endian float `.npy` array in C order.
+pub fn read_npy(bytes: &[u8]) -> Result<Matrix> {
+    if !bytes.starts_with(b"\x93NUMPY") || bytes.len() < 10 {
+        bail!("not a .npy file");
+    }
+    let (header_len, offset) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        _ => (u32::from_le_bytes(bytes.get(8..12).context("truncated .npy header")?.try_into().unwrap()) as usize, 12),
+    };
+    let header = std::str::from_utf8(bytes.get(offset..offset + header_len).context("truncated .npy header")?)?;
+    let descr = header_value(header, "descr").and_then(|v| v.get(1..4)).context("no dtype in .npy header")?;
+    if header_value(header, "fortran_order").is_some_and(|v| v.starts_with("True")) {
+        bail!("Fortran order .npy arrays are not supported");
+    }
+    let shape: Vec<usize> = header_value(header, "shape")
+        .and_then(|v| v.strip_prefix('('))
+        .and_then(|v| v.split(')').next())
+        .context("no shape in .npy header")?
+        .split(',')
+        .map(|d| d.trim())
+        .filter(|d| !d.is_empty())
+        .map(|d| d.parse())
+        .collect::<Result<_, _>>()?;
+    let dtype = match descr {
+        "<f2" => "f2",
+        "<f4" => "f4",
+        "<f8" => "f8",
+        _ => bail!("unsupported .npy dtype {}", descr),
+    };
+    Matrix::new(&shape, decode(&bytes[offset + header_len..], dtype)?)
+}
+
+
+#[derive(Deserialize)]
+struct TensorInfo {
+    dtype: String,
+    shape: Vec<usize>,
+    data_offsets: (usize, usize),
+}
+
+
+/// Reads the float tensors of a `.safetensors` file by name.
+pub fn read_safetensors(bytes: &[u8]) -> Result<BTreeMap<String, Matrix>> {
+    let header_len = u64::from_le_bytes(bytes.get(..8).context("not a .safetensors file")?.try_into().unwrap()) as usize;
+    let header = bytes.get(8..8 + header_len).context("truncated .safetensors header")?;
+    let mut tensors: BTreeMap<String, serde_json::Value> = serde_json::from_slice(header)?;
+    tensors.remove("__metadata__");
+    let data = &bytes[8 + header_len..];
+    let mut matrices = BTreeMap::new();
+    for (name, info) in tensors {
+        let info: TensorInfo = serde_json::from_value(info)?;
+        let dtype = match info.dtype.as_str() {
+            "F16" => "f2",
+            "BF16" => "bf2",
+            "F32" => "f4",
+            "F64" => "f8",
+            other => bail!("unsupported dtype {} of tensor {}", other, name),
+        };
+        let (begin, end) = info.data_offsets;
+        let bytes = data.get(begin..end).ok_or_else(|| anyhow!("tensor {} is out of bounds", name))?;
+        let matrix = Matrix::new(&info.shape, decode(bytes, dtype)?).with_context(|| format!("tensor {}", name))?;
+        matrices.insert(name, matrix);
+    }
+    Ok(matrices)
+}
+
+
+/// Embeddings of the chains of a structure from `dir`: the tensors of
+/// `<name>.safetensors` named by chain id, or else `<name>_<chain>.npy` files,
+/// or `<name>.npy` for a structure with a single protein chain.
+fn load(dir: &Path, name: &str, chains: &[String]) -> Result<(HashMap<String, Matrix>, String)> {
+    let safetensors = dir.join(format!("{}.safetensors", name));
+    if safetensors.is_file() {
+        let bytes = std::fs::read(&safetensors)?;
+        let tensors = read_safetensors(&bytes).with_context(|| format!("failed to read {}", safetensors.display()))?;
+        return Ok((tensors.into_iter().collect(), safetensors.display().to_string()));
+    }
+    let mut embeddings = HashMap::new();
+    let mut source = None;
+    let single = dir.join(format!("{}.npy", name));
+    for chain in chains {
+        let mut path = dir.join(format!("{}_{}.npy", name, chain));
+        if !path.is_file() && chains.len() == 1 {
+            path = single.clone();
+        }
+        if !path.is_file() {
+            continue;
+        }
+        let bytes = std::fs::read(&path)?;
+        let matrix = read_npy(&bytes).with_context(|| format!("failed to read {}", path.display()))?;
+        source.get_or_insert_with(|| path.display().to_string());
+        embeddings.insert(chain.clone(), matrix);
+    }
+    match source {
+        Some(source) => Ok((embeddings, source)),
+        None => bail!("no embeddings for {} in {}", name, dir.display()),
+    }
+}
+
+
+/// Embeddings of every protein atom of the first model by serial number,
+/// taken from the row of its residue. Rows follow the protein residues of
+/// each chain in file order; embeddings with two more rows than residues
+/// include begin and end of sequence tokens (ESM's `<cls>` and `<eos>`),
+/// which are dropped.
+pub fn atom_embeddings(pdb: &PDB, dir: &Path, name: &str) -> Result<(HashMap<usize, Vec<f32>>, EmbeddingInfo)> {
+    let model = pdb.model(0).context("structure has no models")?;
+    let protein_chains: Vec<(String, Vec<&pdbtbx::Residue>)> = model.chains()
+        .map(|c| (c.id().to_string(), c.residues().filter(|r| classify_residue(r) == EntityType::Protein).collect::<Vec<_>>()))
+        .filter(|(_, residues)| !residues.is_empty())
+        .collect();
+    let chain_ids: Vec<String> = protein_chains.iter().map(|(id, _)| id.clone()).collect();
+    let (embeddings, source) = load(dir, name, &chain_ids)?;
+    let mut atoms = HashMap::new();
+    let mut info = EmbeddingInfo { source, dim: 0, chains: Vec::new(), special_tokens: false };
+    for (chain, residues) in &protein_chains {
+        let matrix = embeddings.get(chain).ok_or_else(|| anyhow!("no embeddings for chain {} of {}", chain, name))?;
+        if info.dim != 0 && matrix.dim != info.dim {
+            bail!("embeddings of chain {} have dimension {}, expected {}", chain, matrix.dim, info.dim);
+        }
+        let skip = match matrix.rows {
+            rows if rows == residues.len() => 0,
+            rows if rows == residues.len() + 2 => {
+                info.special_tokens = true;
+                1
+            }
+            rows => bail!("{} embeddings for the {} residues of chain {}", rows, residues.len(), chain),
+        };
+        for (i, residue) in residues.iter().enumerate() {
+            let row = matrix.row(i + skip);
+            atoms.extend(residue.atoms().map(|a| (a.serial_number(), row.to_vec())));
+        }
+        info.dim = matrix.dim;
+        info.chains.push(chain.clone());
+    }
+    Ok((atoms, info))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npy::npy_bytes;
+
+    #[test]
+    fn reads_npy_and_safetensors() {
+        let data = [0.5f32, -1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(read_npy(&npy_bytes(&data, &[1, 3, 2])).unwrap(), Matrix { rows: 3, dim: 2, data: data.to_vec() });
+        assert!(read_npy(&npy_bytes(&data, &[6])).is_err());
+
+        let header = br#"{"__metadata__": {"model": "esm2"}, "A": {"dtype": "F16", "shape": [1, 2], "data_offsets": [0, 4]}}"#;
+        let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+        bytes.extend_from_slice(header);
+        // 1.0 and -0.5 as half precision floats
+        bytes.extend_from_slice(&[0x00, 0x3c, 0x00, 0xb8]);
+        let tensors = read_safetensors(&bytes).unwrap();
+        assert_eq!(tensors["A"].data, vec![1.0, -0.5]);
+        assert_eq!(tensors.len(), 1);
+    }
+}
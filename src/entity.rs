@@ -0,0 +1,172 @@
+use crate::residue::ResidueKey;
+use pdbtbx::{Chain, Residue, PDB};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+
+/// Kind of molecule a residue or chain belongs to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum EntityType {
+    Protein,
+    Dna,
+    Rna,
+    Carbohydrate,
+    Ligand,
+    Water,
+}
+
+const AMINO_ACIDS: &[&str] = &[
+    "ALA", "ARG", "ASN", "ASP", "CYS", "GLN", "GLU", "GLY", "HIS", "ILE",
+    "LEU", "LYS", "MET", "PHE", "PRO", "SER", "THR", "TRP", "TYR", "VAL",
+    // Non-standard residues commonly found within protein chains
+    "MSE", "SEC", "PYL", "HYP", "SEP", "TPO", "PTR", "CSO", "MLY", "KCX",
+    "HID", "HIE", "HIP", "CYX", "ASX", "GLX", "UNK",
+];
+
+const DNA_NUCLEOTIDES: &[&str] = &["DA", "DC", "DG", "DT", "DU", "DI"];
+
+const RNA_NUCLEOTIDES: &[&str] = &["A", "C", "G", "U", "I", "PSU"];
+
+const CARBOHYDRATES: &[&str] = &[
+    "NAG", "NDG", "MAN", "BMA", "GLC", "BGC", "GAL", "GLA", "FUC", "FUL",
+    "SIA", "XYS", "XYP", "FRU", "SUC", "TRE", "MAL", "A2G", "NGA", "GCS",
+];
+
+const WATERS: &[&str] = &["HOH", "WAT", "H2O", "DOD", "D2O"];
+
+
+/// Classifies a residue by its name. Anything that is not a known monomer of
+/// a polymer, a sugar or water is a ligand (this includes ions).
+pub fn classify_residue_name(name: &str) -> EntityType {
+    let name = name.trim().to_ascii_uppercase();
+    let name = name.as_str();
+    if AMINO_ACIDS.contains(&name) {
+        EntityType::Protein
+    } else if DNA_NUCLEOTIDES.contains(&name) {
+        EntityType::Dna
+    } else if RNA_NUCLEOTIDES.contains(&name) {
+        EntityType::Rna
+    } else if CARBOHYDRATES.contains(&name) {
+        EntityType::Carbohydrate
+    } else if WATERS.contains(&name) {
+        EntityType::Water
+    } else {
+        EntityType::Ligand
+    }
+}
+
+
+pub fn classify_residue(residue: &Residue) -> EntityType {
+    residue.name().map(classify_residue_name).unwrap_or(EntityType::Ligand)
+}
+
+
+/// Classification of a single chain, with its residue composition.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChainInfo {
+    pub id: String,
+    pub entity_type: EntityType,
+    pub residue_count: usize,
+    pub first_residue: Option<ResidueKey>,
+    pub last_residue: Option<ResidueKey>,
+    pub composition: BTreeMap<EntityType, usize>,
+    /// Names of the ligand residues in the chain
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub ligands: BTreeSet<String>,
+}
+
+
+/// Classifies a chain from its residue composition. PDB files put waters,
+/// ions and ligands in the chain they're bound to, so a chain is classified
+/// as its most common polymer type if it has polymer residues at all, and
+/// falls back to carbohydrate, ligand and finally water otherwise.
+pub fn classify_chain(chain: &Chain) -> ChainInfo {
+    let mut composition: BTreeMap<EntityType, usize> = BTreeMap::new();
+    let mut ligands = BTreeSet::new();
+    for residue in chain.residues() {
+        let entity_type = classify_residue(residue);
+        *composition.entry(entity_type).or_default() += 1;
+        if entity_type == EntityType::Ligand {
+            ligands.extend(residue.name().map(|n| n.trim().to_string()));
+        }
+    }
+    let most_common = |types: &[EntityType]| {
+        types.iter()
+            .filter_map(|t| composition.get(t).map(|c| (*c, *t)))
+            .max_by_key(|(c, _)| *c)
+            .map(|(_, t)| t)
+    };
+    let entity_type = most_common(&[EntityType::Protein, EntityType::Dna, EntityType::Rna])
+        .or_else(|| most_common(&[EntityType::Carbohydrate]))
+        .or_else(|| most_common(&[EntityType::Ligand]))
+        .unwrap_or(EntityType::Water);
+    ChainInfo {
+        id: chain.id().to_string(),
+        entity_type,
+        residue_count: chain.residue_count(),
+        first_residue: chain.residues().next().map(|r| ResidueKey::new(chain, r)),
+        last_residue: chain.residues().next_back().map(|r| ResidueKey::new(chain, r)),
+        composition,
+        ligands,
+    }
+}
+
+
+/// Removes every residue whose entity type is not in `keep`, along with any
+/// chains left empty.
+pub fn retain_entities(pdb: &mut PDB, keep: &[EntityType]) {
+    pdb.remove_residues_by(|r| !keep.contains(&classify_residue(r)));
+    pdb.remove_empty();
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One atom per residue, with the residues of each chain in order.
+    fn structure(chains: &[(&str, &[&str])]) -> PDB {
+        let mut text = String::new();
+        let mut serial = 0;
+        for (chain, residues) in chains {
+            for (i, name) in residues.iter().enumerate() {
+                serial += 1;
+                let (record, element) = if AMINO_ACIDS.contains(name) || DNA_NUCLEOTIDES.contains(name) || RNA_NUCLEOTIDES.contains(name) {
+                    ("ATOM  ", "C")
+                } else {
+                    ("HETATM", "O")
+                };
+                text += &format!("{}{:>5}  C1  {:>3} {}{:>4}    {:>8.3}{:>8.3}{:>8.3}  1.00  0.00           {}\n", record, serial, name, chain, i + 1, serial as f64 * 2.0, 0.0, 0.0, element);
+            }
+        }
+        text += "END\n";
+        pdbtbx::open_pdb_raw(std::io::BufReader::new(text.as_bytes()), pdbtbx::Context::None, pdbtbx::StrictnessLevel::Loose).unwrap().0
+    }
+
+    #[test]
+    fn classifies_chains() {
+        let pdb = structure(&[
+            ("P", &["MET", "ALA", "MSE", "HOH", "HEM"]),
+            ("D", &["DA", "DG", "DT", "HOH"]),
+            ("R", &["A", "U", "PSU", "DA"]),
+            ("S", &["NAG", "NAG", "BMA", "HOH"]),
+            ("L", &["ATP", "MG", "HOH", "HOH", "HOH"]),
+            ("W", &["HOH", "DOD"]),
+        ]);
+        let chains: Vec<ChainInfo> = pdb.chains().map(classify_chain).collect();
+        let types: Vec<EntityType> = chains.iter().map(|c| c.entity_type).collect();
+        use EntityType::*;
+        assert_eq!(types, [Protein, Dna, Rna, Carbohydrate, Ligand, Water]);
+
+        assert_eq!(chains[0].composition, BTreeMap::from([(Protein, 3), (Ligand, 1), (Water, 1)]));
+        assert_eq!(chains[0].ligands, BTreeSet::from(["HEM".to_string()]));
+        assert_eq!(chains[0].residue_count, 5);
+        assert_eq!(chains[0].first_residue.as_ref().unwrap().to_string(), "P:1");
+        assert_eq!(chains[0].last_residue.as_ref().unwrap().to_string(), "P:5");
+        // The most common polymer type wins over the others
+        assert_eq!(chains[2].composition[&Dna], 1);
+        // Ligands win over the waters they come with
+        assert_eq!(chains[4].ligands, BTreeSet::from(["ATP".to_string(), "MG".to_string()]));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::ident::StructureId;
+use crate::residue::ResidueKey;
+use anyhow::{bail, Context, Result};
+use pdbtbx::PDB;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+
+/// Feature values by name.
+pub type NamedFeatures = BTreeMap<String, f64>;
+
+
+/// Summary of the external residue features attached to a graph, stored in
+/// the graph metadata.
+#[derive(Serialize, Debug, Clone)]
+pub struct ExternalInfo {
+    pub names: BTreeSet<String>,
+    /// Residues of the structure with at least one feature
+    pub residues: usize,
+}
+
+
+/// Per-residue values computed by external tools (disorder predictors and
+/// the like), by lowercase structure id and residue.
+#[derive(Debug, Default)]
+pub struct ResidueFeatureTable {
+    features: HashMap<String, HashMap<ResidueKey, NamedFeatures>>,
+}
+
+
+impl ResidueFeatureTable {
+    /// Adds the rows of a tab separated table with the columns structure id,
+    /// chain, residue number (with insertion code, e.g. `52A`), value and
+    /// feature name. A header line starting with `id` and lines starting
+    /// with `#` are skipped.
+    pub fn add(&mut self, text: &str) -> Result<()> {
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') || (i == 0 && line.starts_with("id\t")) {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').map(|f| f.trim()).collect();
+            let [id, chain, number, value, name] = fields[..] else {
+                bail!("line {} has {} columns instead of 5", i + 1, fields.len());
+            };
+            let digits = number.find(|c: char| !c.is_ascii_digit() && c != '-').unwrap_or(number.len());
+            let key = ResidueKey {
+                chain: chain.to_string(),
+                resseq: number[..digits].parse().with_context(|| format!("invalid residue number {} on line {}", number, i + 1))?,
+                icode: Some(number[digits..].to_string()).filter(|c| !c.is_empty()),
+            };
+            let value: f64 = value.parse().with_context(|| format!("invalid value {} on line {}", value, i + 1))?;
+            self.features.entry(id.to_ascii_lowercase()).or_default().entry(key).or_default().insert(name.to_string(), value);
+        }
+        Ok(())
+    }
+
+    pub fn read(&mut self, path: &Path) -> Result<()> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        self.add(&text).with_context(|| format!("invalid residue feature table {}", path.display()))
+    }
+
+    /// Number of structures with features.
+    pub fn structures(&self) -> usize {
+        self.features.len()
+    }
+
+    /// Features of every atom of the first model by serial number. Rows are
+    /// matched by the structure name and else by the PDB id.
+    pub fn atom_features(&self, pdb: &PDB, id: &StructureId) -> Option<(HashMap<usize, NamedFeatures>, ExternalInfo)> {
+        let rows = self.features.get(&id.name.to_ascii_lowercase())
+            .or_else(|| id.pdb_id.as_ref().and_then(|p| self.features.get(p)))?;
+        let mut atoms = HashMap::new();
+        let mut info = ExternalInfo { names: BTreeSet::new(), residues: 0 };
+        for chain in pdb.model(0)?.chains() {
+            for residue in chain.residues() {
+                let Some(features) = rows.get(&ResidueKey::new(chain, residue)) else { continue };
+                atoms.extend(residue.atoms().map(|a| (a.serial_number(), features.clone())));
+                info.names.extend(features.keys().cloned());
+                info.residues += 1;
+            }
+        }
+        Some((atoms, info))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_residue_features() {
+        let mut table = ResidueFeatureTable::default();
+        table.add("id\tchain\tresnum\tvalue\tname\n1ABC\tA\t52A\t0.91\tdisorder\n1abc\tA\t52A\t0.2\tflexibility\n").unwrap();
+        let key = ResidueKey { chain: "A".to_string(), resseq: 52, icode: Some("A".to_string()) };
+        let features = &table.features["1abc"][&key];
+        assert_eq!(features["disorder"], 0.91);
+        assert_eq!(features.len(), 2);
+        assert!(table.add("1abc\tA\t1\tnan-ish\tdisorder\n").is_err());
+        assert!(table.add("1abc\tA\t1\n").is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use crate::atomic;
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+
+/// Server structures are downloaded from by default.
+pub const DEFAULT_BASE_URL: &str = "https://files.rcsb.org/download";
+
+/// File name of the download manifest in the output directory.
+pub const MANIFEST: &str = "fetch_manifest.json";
+
+/// Exit status of curl when the server can't resume a download.
+const CURL_CANNOT_RESUME: i32 = 33;
+
+
+/// File format of downloaded structures.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FetchFormat {
+    Pdb,
+    /// mmCIF, which also exists for structures too large for the PDB format
+    Cif,
+}
+
+impl FetchFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            FetchFormat::Pdb => "pdb",
+            FetchFormat::Cif => "cif",
+        }
+    }
+}
+
+
+/// The id in lower case, an error unless it is a 4 character PDB id.
+pub fn normalize_id(id: &str) -> Result<String> {
+    let id = id.trim().to_ascii_lowercase();
+    if id.len() != 4 || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        bail!("invalid PDB id {}, expected 4 letters and digits", id);
+    }
+    Ok(id)
+}
+
+
+/// Where the structure `id` is downloaded from, e.g.
+/// `https://files.rcsb.org/download/1ABC.cif.gz`.
+pub fn url(base_url: &str, id: &str, format: FetchFormat) -> Result<String> {
+    Ok(format!("{}/{}.{}.gz", base_url.trim_end_matches('/'), normalize_id(id)?.to_uppercase(), format.extension()))
+}
+
+
+/// Where the structure `id` is downloaded to in `dir`, e.g. `1abc.cif.gz`.
+pub fn fetched_path(dir: &Path, id: &str, format: FetchFormat) -> Result<PathBuf> {
+    Ok(dir.join(format!("{}.{}.gz", normalize_id(id)?, format.extension())))
+}
+
+
+/// A downloaded structure, recorded in the download manifest.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    pub id: String,
+    pub format: FetchFormat,
+    pub url: String,
+    /// Size of the gzipped file
+    pub bytes: u64,
+    /// CRC-32 of the decompressed structure, from the gzip trailer
+    pub crc32: u32,
+}
+
+
+/// Structures downloaded into a directory, keyed by file name, so a dataset
+/// can be checked against the files it was built from.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Manifest {
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    /// The manifest of `dir`, empty if there is none yet.
+    pub fn load(dir: &Path) -> Result<Manifest> {
+        let path = dir.join(MANIFEST);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).with_context(|| format!("invalid download manifest {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Manifest::default()),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        atomic::write(dir.join(MANIFEST), serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&ManifestEntry> {
+        self.files.get(path.file_name()?.to_str()?)
+    }
+
+    pub fn insert(&mut self, path: &Path, entry: ManifestEntry) {
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            self.files.insert(name.to_string(), entry);
+        }
+    }
+}
+
+
+/// Size and CRC-32 of a gzipped file, after checking that it decompresses
+/// completely and matches the CRC-32 and size of its trailer.
+pub fn verify(path: &Path) -> Result<(u64, u32)> {
+    let data = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    // Header and trailer of an empty member
+    if data.len() < 18 {
+        bail!("{} is truncated", path.display());
+    }
+    std::io::copy(&mut GzDecoder::new(data.as_slice()), &mut std::io::sink())
+        .with_context(|| format!("{} is corrupt or truncated", path.display()))?;
+    let trailer: [u8; 4] = data[data.len() - 8..data.len() - 4].try_into().unwrap();
+    Ok((data.len() as u64, u32::from_le_bytes(trailer)))
+}
+
+
+/// Size and CRC-32 of the file at `path` if it is intact and, if the
+/// manifest has it, the one that was downloaded.
+fn intact(path: &Path, expected: Option<&ManifestEntry>) -> Option<(u64, u32)> {
+    verify(path).ok().filter(|(bytes, crc32)| expected.is_none_or(|e| e.bytes == *bytes && e.crc32 == *crc32))
+}
+
+
+/// Downloads the gzipped structure `id` from `base_url` into `dir` with the
+/// `curl` executable, unless an intact copy matching `expected`, its
+/// manifest entry, is already there. Files are written under a temporary
+/// name first, so interrupted downloads don't leave truncated structures
+/// behind, and a later call resumes them. Transient failures such as
+/// timeouts and 5xx responses are retried up to `retries` times, waiting
+/// twice as long each time. Returns the path, its manifest entry and whether
+/// it was downloaded.
+pub fn fetch(id: &str, format: FetchFormat, dir: &Path, base_url: &str, expected: Option<&ManifestEntry>, retries: usize) -> Result<(PathBuf, ManifestEntry, bool)> {
+    let path = fetched_path(dir, id, format)?;
+    let url = url(base_url, id, format)?;
+    if path.is_file() {
+        if let Some((bytes, crc32)) = intact(&path, expected) {
+            let entry = expected.cloned().unwrap_or(ManifestEntry { id: normalize_id(id)?, format, url, bytes, crc32 });
+            return Ok((path, entry, false));
+        }
+        log::warn!("{} is corrupt or differs from the download manifest, downloading it again", path.display());
+        std::fs::remove_file(&path).with_context(|| format!("failed to remove {}", path.display()))?;
+    }
+    let partial = path.with_extension("gz.part");
+    let output = match Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", "--continue-at", "-", "--retry"])
+        .arg(retries.to_string())
+        .arg("--output")
+        .arg(&partial)
+        .arg(&url)
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => bail!("downloading structures requires the curl executable on PATH"),
+        Err(e) => return Err(e).context("failed to run curl"),
+    };
+    if !output.status.success() {
+        // Keep what was downloaded for the next attempt, unless it can't be resumed
+        let empty = std::fs::metadata(&partial).map_or(true, |m| m.len() == 0);
+        if empty || output.status.code() == Some(CURL_CANNOT_RESUME) {
+            let _ = std::fs::remove_file(&partial);
+        }
+        bail!("failed to download {}: {}", url, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    let (bytes, crc32) = match verify(&partial) {
+        Ok(checksum) => checksum,
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            return Err(e.context(format!("failed to download {}", url)));
+        }
+    };
+    std::fs::rename(&partial, &path).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok((path, ManifestEntry { id: normalize_id(id)?, format, url, bytes, crc32 }, true))
+}
+
+
+/// Places the structure at `cached` into `dir` as a hard link, or a copy
+/// where the cache is on another file system, replacing any file there.
+pub fn place(cached: &Path, dir: &Path) -> Result<PathBuf> {
+    let path = dir.join(cached.file_name().context("cached structure without a file name")?);
+    if path.exists() && std::fs::canonicalize(&path)? == std::fs::canonicalize(cached)? {
+        return Ok(path);
+    }
+    let _ = std::fs::remove_file(&path);
+    if std::fs::hard_link(cached, &path).is_err() {
+        std::fs::copy(cached, &path).with_context(|| format!("failed to copy {} to {}", cached.display(), path.display()))?;
+    }
+    Ok(path)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_downloads() {
+        assert_eq!(url("https://files.rcsb.org/download/", " 1abc", FetchFormat::Cif).unwrap(), "https://files.rcsb.org/download/1ABC.cif.gz");
+        assert_eq!(fetched_path(Path::new("pdbs"), "1ABC", FetchFormat::Pdb).unwrap(), Path::new("pdbs/1abc.pdb.gz"));
+        assert_eq!(url(DEFAULT_BASE_URL, "1ab/", FetchFormat::Pdb).unwrap_err().to_string(), "invalid PDB id 1ab/, expected 4 letters and digits");
+    }
+
+    #[test]
+    fn verifies_gzip_checksums() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("graphein_fetch_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"HEADER    TEST\nEND\n").unwrap();
+        let data = encoder.finish().unwrap();
+        let path = dir.join("1abc.pdb.gz");
+        std::fs::write(&path, &data).unwrap();
+
+        let (bytes, crc32) = verify(&path).unwrap();
+        assert_eq!(bytes, data.len() as u64);
+        let entry = ManifestEntry { id: "1abc".to_string(), format: FetchFormat::Pdb, url: String::new(), bytes, crc32 };
+        assert!(intact(&path, Some(&entry)).is_some());
+        assert!(intact(&path, Some(&ManifestEntry { crc32: crc32 ^ 1, ..entry.clone() })).is_none());
+
+        std::fs::write(&path, &data[..data.len() - 4]).unwrap();
+        assert!(verify(&path).is_err());
+
+        let mut manifest = Manifest::default();
+        manifest.insert(&path, entry.clone());
+        manifest.save(&dir).unwrap();
+        assert_eq!(Manifest::load(&dir).unwrap().get(&path), Some(&entry));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
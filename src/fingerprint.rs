@@ -0,0 +1,390 @@
+use crate::atomic::AtomicFile;
+use crate::entity::{classify_residue, EntityType};
+use crate::hbond::{bonded, hydrogen_bonds};
+use crate::interactions::{charge, salt_bridge_pairs, InteractionKind, InteractionOptions};
+use crate::residue::ResidueKey;
+use anyhow::Result;
+use pdbtbx::{Atom, Element, Residue, PDB};
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+
+/// Largest distance between carbons of hydrophobic contacts in Å.
+const HYDROPHOBIC_DISTANCE: f64 = 4.0;
+
+/// Largest distance between the centroids of stacked rings in Å.
+const PI_STACKING_DISTANCE: f64 = 5.5;
+
+/// Largest offset of a ring centroid from the axis of the other ring in Å.
+const PI_STACKING_OFFSET: f64 = 2.0;
+
+/// Largest angle between the planes of parallel stacked rings, and smallest
+/// of T-shaped ones, in degrees.
+const PARALLEL_ANGLE: f64 = 30.0;
+const T_SHAPED_ANGLE: f64 = 60.0;
+
+/// Largest distance of a ring atom from the plane of the ring in Å, so only
+/// flat, aromatic-like rings of ligands stack.
+const RING_PLANARITY: f64 = 0.1;
+
+/// Longest covalent bond searched for bonded neighbours, before the bond
+/// tolerance, in Å.
+const LONGEST_BOND: f64 = 2.0;
+
+/// Aromatic rings of amino acids, with their atoms in ring order.
+const AROMATIC_RINGS: &[(&str, &[&str])] = &[
+    ("PHE", &["CG", "CD1", "CE1", "CZ", "CE2", "CD2"]),
+    ("TYR", &["CG", "CD1", "CE1", "CZ", "CE2", "CD2"]),
+    ("TRP", &["CG", "CD1", "NE1", "CE2", "CD2"]),
+    ("TRP", &["CD2", "CE2", "CZ2", "CH2", "CZ3", "CE3"]),
+    ("HIS", &["CG", "ND1", "CE1", "NE2", "CD2"]),
+    ("HID", &["CG", "ND1", "CE1", "NE2", "CD2"]),
+    ("HIE", &["CG", "ND1", "CE1", "NE2", "CD2"]),
+    ("HIP", &["CG", "ND1", "CE1", "NE2", "CD2"]),
+];
+
+/// Kinds of interactions counted by a fingerprint, in the order of its counts.
+pub const FINGERPRINT_KINDS: [InteractionKind; 4] =
+    [InteractionKind::Hbond, InteractionKind::Hydrophobic, InteractionKind::PiStacking, InteractionKind::SaltBridge];
+
+
+/// The interactions of one protein residue with the ligand.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResidueFingerprint {
+    pub residue: ResidueKey,
+    pub residue_name: String,
+    /// Number of interactions of every kind of `FINGERPRINT_KINDS`
+    pub counts: [usize; 4],
+}
+
+
+/// The residues on one side of a complex with their heavy atoms.
+struct Side<'a> {
+    residues: Vec<(ResidueKey, &'a str)>,
+    /// Every atom of the residues, including hydrogens, with its residue
+    residue_of: HashMap<*const Atom, usize>,
+    heavy: Vec<(&'a Atom, usize)>,
+    tree: RTree<GeomWithData<[f64; 3], usize>>,
+    bond_tolerance: f64,
+}
+
+impl<'a> Side<'a> {
+    fn new(pdb: &'a PDB, keep: impl Fn(&Residue) -> bool, bond_tolerance: f64) -> Side<'a> {
+        let (mut residues, mut residue_of, mut heavy) = (Vec::new(), HashMap::new(), Vec::new());
+        for chain in pdb.model(0).into_iter().flat_map(|m| m.chains()) {
+            for residue in chain.residues().filter(|r| keep(r)) {
+                for atom in residue.atoms() {
+                    residue_of.insert(atom as *const Atom, residues.len());
+                    if atom.element().is_some_and(|e| e.symbol() != "H" && e.symbol() != "D") {
+                        heavy.push((atom, residues.len()));
+                    }
+                }
+                residues.push((ResidueKey::new(chain, residue), residue.name().unwrap_or("")));
+            }
+        }
+        let tree = RTree::bulk_load(heavy.iter().enumerate().map(|(i, (a, _))| GeomWithData::new([a.x(), a.y(), a.z()], i)).collect());
+        Side { residues, residue_of, heavy, tree, bond_tolerance }
+    }
+
+    /// Heavy atoms covalently bonded to heavy atom `i`.
+    fn bonded(&self, i: usize) -> Vec<usize> {
+        let atom = self.heavy[i].0;
+        let search = LONGEST_BOND + self.bond_tolerance;
+        self.tree.locate_within_distance([atom.x(), atom.y(), atom.z()], search * search)
+            .map(|n| n.data)
+            .filter(|j| *j != i && bonded(atom, self.heavy[*j].0, self.bond_tolerance))
+            .collect()
+    }
+
+    fn element(&self, i: usize) -> Option<&Element> {
+        self.heavy[i].0.element()
+    }
+
+    /// Carbons bonded only to carbons and hydrogens.
+    fn hydrophobic(&self) -> Vec<usize> {
+        (0..self.heavy.len())
+            .filter(|i| self.element(*i) == Some(&Element::C) && self.bonded(*i).iter().all(|j| self.element(*j) == Some(&Element::C)))
+            .collect()
+    }
+}
+
+
+/// A planar ring with its centroid and unit normal.
+struct Ring {
+    residue: usize,
+    centroid: [f64; 3],
+    normal: [f64; 3],
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+impl Ring {
+    /// The ring through `atoms` in ring order, `None` if it isn't planar.
+    fn new(residue: usize, atoms: &[&Atom]) -> Option<Ring> {
+        let points: Vec<[f64; 3]> = atoms.iter().map(|a| [a.x(), a.y(), a.z()]).collect();
+        let n = points.len() as f64;
+        let centroid = [0, 1, 2].map(|k| points.iter().map(|p| p[k]).sum::<f64>() / n);
+        // Newell's method, robust to slightly puckered rings
+        let mut normal = [0.0; 3];
+        for (i, p) in points.iter().enumerate() {
+            let c = cross(sub(*p, centroid), sub(points[(i + 1) % points.len()], centroid));
+            normal = [normal[0] + c[0], normal[1] + c[1], normal[2] + c[2]];
+        }
+        let length = dot(normal, normal).sqrt();
+        if length == 0.0 {
+            return None;
+        }
+        let normal = normal.map(|x| x / length);
+        points.iter().all(|p| dot(sub(*p, centroid), normal).abs() <= RING_PLANARITY).then_some(Ring { residue, centroid, normal })
+    }
+
+    /// Whether two rings stack face to face or edge to face.
+    fn stacks(&self, other: &Ring) -> bool {
+        let v = sub(other.centroid, self.centroid);
+        let distance = dot(v, v).sqrt();
+        let angle = dot(self.normal, other.normal).abs().clamp(0.0, 1.0).acos().to_degrees();
+        let offset = [self.normal, other.normal].map(|n| (distance * distance - dot(v, n).powi(2)).max(0.0).sqrt());
+        distance <= PI_STACKING_DISTANCE
+            && offset[0].min(offset[1]) <= PI_STACKING_OFFSET
+            && (angle <= PARALLEL_ANGLE || angle >= T_SHAPED_ANGLE)
+    }
+}
+
+
+/// Aromatic rings of the amino acids of a side.
+fn residue_rings(side: &Side) -> Vec<Ring> {
+    let mut rings = Vec::new();
+    for (residue, (_, name)) in side.residues.iter().enumerate() {
+        for (_, names) in AROMATIC_RINGS.iter().filter(|(n, _)| n == name) {
+            let atoms: Vec<&Atom> = names.iter()
+                .filter_map(|n| side.heavy.iter().find(|(a, r)| *r == residue && a.name() == *n).map(|(a, _)| *a))
+                .collect();
+            if atoms.len() == names.len() {
+                rings.extend(Ring::new(residue, &atoms));
+            }
+        }
+    }
+    rings
+}
+
+
+/// Planar five- and six-membered rings of a side, perceived from its
+/// covalent bonds.
+fn ligand_rings(side: &Side) -> Vec<Ring> {
+    let bonds: Vec<Vec<usize>> = (0..side.heavy.len()).map(|i| side.bonded(i)).collect();
+    let mut seen = BTreeSet::new();
+    let mut rings = Vec::new();
+    // Paths starting at their smallest atom, so every ring is found from one atom
+    let mut stack: Vec<Vec<usize>> = (0..side.heavy.len()).map(|i| vec![i]).collect();
+    while let Some(path) = stack.pop() {
+        let (start, last) = (path[0], path[path.len() - 1]);
+        for next in &bonds[last] {
+            if *next == start && path.len() >= 5 {
+                let mut members = path.clone();
+                members.sort_unstable();
+                if seen.insert(members) {
+                    let atoms: Vec<&Atom> = path.iter().map(|i| side.heavy[*i].0).collect();
+                    rings.extend(Ring::new(side.heavy[start].1, &atoms));
+                }
+            } else if *next > start && path.len() < 6 && !path.contains(next) {
+                stack.push([path.as_slice(), &[*next]].concat());
+            }
+        }
+    }
+    rings
+}
+
+
+/// Whether heavy atom `i` of a ligand is charged: `Some(false)` for the
+/// oxygens of carboxylates, phosphates and sulfates, `Some(true)` for
+/// quaternary nitrogens and the terminal nitrogens of amines, amidines and
+/// guanidines (but not amides).
+fn ligand_charge(side: &Side, i: usize) -> Option<bool> {
+    let neighbors = side.bonded(i);
+    let terminal = |j: usize, element: Element| side.element(j) == Some(&element) && side.bonded(j).len() == 1;
+    match (side.element(i)?, neighbors.as_slice()) {
+        (Element::O, [center]) if matches!(side.element(*center), Some(Element::C | Element::P | Element::S)) => {
+            let oxygens = side.bonded(*center).into_iter().filter(|j| terminal(*j, Element::O)).count();
+            (oxygens >= 2).then_some(false)
+        }
+        (Element::N, [_, _, _, _]) => Some(true),
+        (Element::N, [carbon]) if side.element(*carbon) == Some(&Element::C) => {
+            let others = side.bonded(*carbon);
+            let amide = others.iter().any(|j| terminal(*j, Element::O));
+            (!amide).then_some(true)
+        }
+        _ => None,
+    }
+}
+
+
+/// Counts the hydrogen bonds, hydrophobic contacts, π-stacking and salt
+/// bridges between the protein residues of `protein` and the non-water
+/// residues of `ligand`, in their first models, per protein residue.
+/// Hydrogen bonds and salt bridges are detected as for the interaction
+/// table. Hydrophobic contacts are pairs of carbons bonded only to carbons
+/// and hydrogens at most 4 Å apart, counted once per pair of residues like
+/// salt bridges. Stacked rings are within 5.5 Å, at most 2 Å off axis, and
+/// parallel or T-shaped. Only residues with interactions are returned, in
+/// residue order.
+pub fn fingerprint(protein: &PDB, ligand: &PDB, opts: &InteractionOptions, bond_tolerance: f64) -> Vec<ResidueFingerprint> {
+    let receptor = Side::new(protein, |r| classify_residue(r) == EntityType::Protein, bond_tolerance);
+    let ligand_side = Side::new(ligand, |r| classify_residue(r) != EntityType::Water, bond_tolerance);
+    let mut counts: BTreeMap<usize, [usize; 4]> = BTreeMap::new();
+    let mut count = |residue: usize, kind: InteractionKind| {
+        let k = FINGERPRINT_KINDS.iter().position(|x| *x == kind).expect("a fingerprint kind");
+        counts.entry(residue).or_default()[k] += 1;
+    };
+
+    for bond in hydrogen_bonds(&[protein, ligand], &opts.hbonds, bond_tolerance, true) {
+        for (a, b) in [(bond.donor, bond.acceptor), (bond.acceptor, bond.donor)] {
+            if let (Some(r), true) = (receptor.residue_of.get(&(a as *const Atom)), ligand_side.residue_of.contains_key(&(b as *const Atom))) {
+                count(*r, InteractionKind::Hbond);
+            }
+        }
+    }
+
+    let mut hydrophobic = BTreeSet::new();
+    let carbons = receptor.hydrophobic();
+    for i in ligand_side.hydrophobic() {
+        let (atom, ligand_residue) = ligand_side.heavy[i];
+        for neighbor in receptor.tree.locate_within_distance([atom.x(), atom.y(), atom.z()], HYDROPHOBIC_DISTANCE * HYDROPHOBIC_DISTANCE) {
+            if carbons.contains(&neighbor.data) {
+                hydrophobic.insert((receptor.heavy[neighbor.data].1, ligand_residue));
+            }
+        }
+    }
+    for (residue, _) in hydrophobic {
+        count(residue, InteractionKind::Hydrophobic);
+    }
+
+    let ligand_rings = ligand_rings(&ligand_side);
+    for ring in residue_rings(&receptor) {
+        for _ in ligand_rings.iter().filter(|other| ring.stacks(other)) {
+            count(ring.residue, InteractionKind::PiStacking);
+        }
+    }
+
+    let (mut acids, mut bases) = ([Vec::new(), Vec::new()], [Vec::new(), Vec::new()]);
+    for (atom, residue) in &receptor.heavy {
+        match charge(receptor.residues[*residue].1, atom.name()) {
+            Some(true) => bases[0].push(*atom),
+            Some(false) => acids[0].push(*atom),
+            None => {}
+        }
+    }
+    for (i, (atom, _)) in ligand_side.heavy.iter().enumerate() {
+        match ligand_charge(&ligand_side, i) {
+            Some(true) => bases[1].push(*atom),
+            Some(false) => acids[1].push(*atom),
+            None => {}
+        }
+    }
+    let mut bridges = BTreeSet::new();
+    for (acid, base, _) in salt_bridge_pairs(&acids[0], &bases[1], opts.salt_bridge_distance)
+        .into_iter()
+        .chain(salt_bridge_pairs(&acids[1], &bases[0], opts.salt_bridge_distance).into_iter().map(|(a, b, d)| (b, a, d)))
+    {
+        bridges.insert((receptor.residue_of[&(acid as *const Atom)], ligand_side.residue_of[&(base as *const Atom)]));
+    }
+    for (residue, _) in bridges {
+        count(residue, InteractionKind::SaltBridge);
+    }
+
+    counts.into_iter()
+        .map(|(residue, counts)| {
+            let (key, name) = &receptor.residues[residue];
+            ResidueFingerprint { residue: key.clone(), residue_name: name.to_string(), counts }
+        })
+        .collect()
+}
+
+
+/// Splits a structure into its ligand residues and everything else, for
+/// fingerprints of complexes in a single file.
+pub fn split_ligands(pdb: &PDB) -> (PDB, PDB) {
+    let (mut rest, mut ligands) = (pdb.clone(), pdb.clone());
+    rest.remove_residues_by(|r| classify_residue(r) == EntityType::Ligand);
+    ligands.remove_residues_by(|r| classify_residue(r) != EntityType::Ligand);
+    (rest, ligands)
+}
+
+
+/// Writes a fingerprint as a CSV table with a row per residue and a column
+/// of counts per kind of interaction.
+pub fn write_fingerprint(fingerprint: &[ResidueFingerprint], save_fname: &Path) -> Result<()> {
+    let mut out = BufWriter::new(AtomicFile::create(save_fname)?);
+    write!(out, "residue,residue_name")?;
+    for kind in FINGERPRINT_KINDS {
+        write!(out, ",{}", kind)?;
+    }
+    writeln!(out)?;
+    for r in fingerprint {
+        write!(out, "{},{}", r.residue, r.residue_name)?;
+        for count in r.counts {
+            write!(out, ",{}", count)?;
+        }
+        writeln!(out)?;
+    }
+    out.into_inner().map_err(|e| e.into_error())?.commit()?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hbond::HbondCriteria;
+    use pdbtbx::Model;
+
+    #[test]
+    fn counts_stacking_and_salt_bridges() {
+        // A benzoate stacked 3.8 Å above a phenylalanine ring, its carboxylate
+        // oxygens 3.2 Å from a lysine amine
+        let mut model = Model::new(0);
+        let hexagon = |i: usize| {
+            let angle = (i as f64 * 60.0).to_radians();
+            (1.39 * angle.cos(), 1.39 * angle.sin())
+        };
+        let mut serial = 0;
+        let mut add = |model: &mut Model, chain: &str, resseq: isize, residue: &str, name: &str, element: &str, (x, y, z): (f64, f64, f64)| {
+            serial += 1;
+            let atom = Atom::new(chain == "B", serial, name, x, y, z, 1.0, 20.0, element, 0).unwrap();
+            model.add_atom(atom, chain, (resseq, None), (residue, None));
+        };
+        for (i, name) in ["CG", "CD1", "CE1", "CZ", "CE2", "CD2"].into_iter().enumerate() {
+            let (x, y) = hexagon(i);
+            add(&mut model, "A", 1, "PHE", name, "C", (x, y, 0.0));
+        }
+        add(&mut model, "A", 2, "LYS", "CE", "C", (6.3, 0.0, 1.0));
+        add(&mut model, "A", 2, "LYS", "NZ", "N", (6.3, 0.0, 2.5));
+        for i in 0..6 {
+            let (x, y) = hexagon(i);
+            add(&mut model, "B", 1, "BEZ", &format!("C{}", i + 1), "C", (x, y, 3.8));
+        }
+        add(&mut model, "B", 1, "BEZ", "C7", "C", (2.9, 0.0, 3.8));
+        add(&mut model, "B", 1, "BEZ", "O1", "O", (3.6, 1.1, 3.8));
+        add(&mut model, "B", 1, "BEZ", "O2", "O", (3.6, -1.1, 3.8));
+        let mut pdb = PDB::new();
+        pdb.add_model(model);
+
+        let opts = InteractionOptions { hbonds: HbondCriteria::default(), salt_bridge_distance: 4.0, disulfide_distance: None };
+        let (protein, ligands) = split_ligands(&pdb);
+        let found = fingerprint(&protein, &ligands, &opts, 0.45);
+        let counts: Vec<(String, [usize; 4])> = found.iter().map(|r| (r.residue.to_string(), r.counts)).collect();
+        assert_eq!(counts, vec![("A:1".to_string(), [0, 1, 1, 0]), ("A:2".to_string(), [2, 0, 0, 1])]);
+    }
+}
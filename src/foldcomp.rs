@@ -0,0 +1,34 @@
+use anyhow::{bail, Context, Result};
+use std::io::ErrorKind;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+static SCRATCH: AtomicUsize = AtomicUsize::new(0);
+
+
+/// Decompresses a Foldcomp file (`.fcz`) to PDB text with the `foldcomp`
+/// executable. Foldcomp stores structures as quantized torsion angles and
+/// rebuilds coordinates from residue templates, so the reference
+/// implementation is used rather than a port of its geometry tables. Each
+/// file is decompressed to a scratch file that is removed right after.
+pub fn decompress(fname: &str) -> Result<String> {
+    let scratch = std::env::temp_dir().join(format!(
+        "graphein-foldcomp-{}-{}.pdb",
+        std::process::id(),
+        SCRATCH.fetch_add(1, Ordering::Relaxed)
+    ));
+    let output = match Command::new("foldcomp").arg("decompress").arg(fname).arg(&scratch).output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            bail!("reading .fcz files requires the foldcomp executable on PATH (https://github.com/steineggerlab/foldcomp)")
+        }
+        Err(e) => return Err(e).context("failed to run foldcomp"),
+    };
+    let text = std::fs::read_to_string(&scratch);
+    let _ = std::fs::remove_file(&scratch);
+    if !output.status.success() {
+        bail!("foldcomp failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    text.context("foldcomp wrote no structure")
+}
@@ -0,0 +1,128 @@
+use crate::{canonical, AtomNode, EdgeData, GraphBuilder};
+use anyhow::{bail, Context, Result};
+use pdbtbx::{StrictnessLevel, PDB};
+use petgraph::graph::Graph;
+use serde::Serialize;
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+
+
+/// Environment variable that makes `check_golden` write the golden files
+/// instead of comparing against them.
+pub const UPDATE_GOLDEN: &str = "GRAPHEIN_UPDATE_GOLDEN";
+
+
+/// Small structure bundled with the crate, for tests of code built on it.
+pub struct Fixture {
+    pub name: &'static str,
+    /// What the structure exercises
+    pub description: &'static str,
+    /// Contents of the PDB file
+    pub pdb: &'static str,
+}
+
+/// Bundled fixtures, by name.
+pub const FIXTURES: &[Fixture] = &[
+    Fixture {
+        name: "antibody_insertion_codes",
+        description: "Antibody fragment with two chains and Kabat insertion codes",
+        pdb: include_str!("../tests/fixtures/antibody_insertion_codes.pdb"),
+    },
+    Fixture {
+        name: "benzoate_stacking",
+        description: "Benzoate stacked on a phenylalanine ring, its carboxylate next to a lysine amine",
+        pdb: include_str!("../tests/fixtures/benzoate_stacking.pdb"),
+    },
+];
+
+impl Fixture {
+    pub fn get(name: &str) -> Option<&'static Fixture> {
+        FIXTURES.iter().find(|f| f.name == name)
+    }
+
+    /// Parses the fixture.
+    pub fn structure(&self) -> Result<PDB> {
+        let reader = BufReader::new(Cursor::new(self.pdb.as_bytes()));
+        match pdbtbx::open_pdb_raw(reader, pdbtbx::Context::show(self.name), StrictnessLevel::Medium) {
+            Ok((pdb, _)) => Ok(pdb),
+            Err(e) => bail!("fixture {} doesn't parse - {:?}", self.name, e),
+        }
+    }
+
+    /// Canonical graph of the fixture built by `builder`.
+    pub fn graph(&self, builder: &GraphBuilder) -> Result<Graph<AtomNode, EdgeData>> {
+        Ok(canonical::canonicalize(builder.build_graph(&self.structure()?)))
+    }
+}
+
+
+/// Golden file form of a graph: one JSON node per line in node order, an
+/// empty line, then one `[source, target, edge]` JSON array per line in
+/// edge order. Graphs should be canonicalized first so the text only
+/// changes when their content does, and a line diff points at the nodes
+/// and edges that changed.
+pub fn golden_text<N: Serialize, E: Serialize>(graph: &Graph<N, E>) -> Result<String> {
+    let mut text = String::new();
+    for node in graph.node_weights() {
+        text.push_str(&serde_json::to_string(node)?);
+        text.push('\n');
+    }
+    text.push('\n');
+    for edge in graph.raw_edges() {
+        text.push_str(&serde_json::to_string(&(edge.source().index(), edge.target().index(), &edge.weight))?);
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+
+/// Compares `actual` to the golden file at `path`, failing with the first
+/// differing line. With `GRAPHEIN_UPDATE_GOLDEN` set, writes `actual` to
+/// `path` instead, to create or bless golden files.
+pub fn check_golden(path: &Path, actual: &str) -> Result<()> {
+    if std::env::var_os(UPDATE_GOLDEN).is_some() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        return std::fs::write(path, actual).with_context(|| format!("failed to write {}", path.display()));
+    }
+    let expected = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read golden file {} - set {}=1 to create it", path.display(), UPDATE_GOLDEN))?;
+    if expected == actual {
+        return Ok(());
+    }
+    let (expected_lines, actual_lines): (Vec<&str>, Vec<&str>) = (expected.lines().collect(), actual.lines().collect());
+    let line = expected_lines.iter().zip(&actual_lines).position(|(e, a)| e != a).unwrap_or(expected_lines.len().min(actual_lines.len()));
+    bail!(
+        "{} differs from the output at line {} ({} lines expected, {} written)\n  expected: {}\n  actual:   {}\nset {}=1 to update it if the change is intended",
+        path.display(),
+        line + 1,
+        expected_lines.len(),
+        actual_lines.len(),
+        expected_lines.get(line).unwrap_or(&"<end of file>"),
+        actual_lines.get(line).unwrap_or(&"<end of file>"),
+        UPDATE_GOLDEN,
+    );
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_match_golden_graphs() {
+        let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+        for fixture in FIXTURES {
+            let graph = fixture.graph(&GraphBuilder::new()).unwrap();
+            let path = golden.join(format!("{}.golden", fixture.name));
+            if let Err(e) = check_golden(&path, &golden_text(&graph).unwrap()) {
+                panic!("{:#}", e);
+            }
+        }
+        if std::env::var_os(UPDATE_GOLDEN).is_none() {
+            let err = check_golden(&golden.join("antibody_insertion_codes.golden"), "{}\n").unwrap_err();
+            assert!(err.to_string().contains("at line 1"), "{}", err);
+        }
+    }
+}
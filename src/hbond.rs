@@ -0,0 +1,186 @@
+use crate::entity::{classify_residue_name, EntityType};
+use pdbtbx::{Atom, Element, PDB};
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+use serde::Serialize;
+use std::collections::HashSet;
+
+
+/// Default largest donor–acceptor distance of hydrogen bonds in Å.
+pub const DEFAULT_HBOND_DISTANCE: f64 = 3.5;
+
+/// Default smallest donor–hydrogen–acceptor angle of hydrogen bonds in degrees.
+pub const DEFAULT_HBOND_ANGLE: f64 = 120.0;
+
+/// Smallest angle between a bond of the donor and the acceptor when the
+/// donor has no modelled hydrogens, in degrees.
+const MIN_ANTECEDENT_ANGLE: f64 = 90.0;
+
+
+/// Geometric criteria of hydrogen bonds.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct HbondCriteria {
+    /// Largest donor–acceptor distance in Å
+    pub max_distance: f64,
+    /// Smallest donor–hydrogen–acceptor angle in degrees
+    pub min_angle: f64,
+}
+
+impl Default for HbondCriteria {
+    fn default() -> HbondCriteria {
+        HbondCriteria { max_distance: DEFAULT_HBOND_DISTANCE, min_angle: DEFAULT_HBOND_ANGLE }
+    }
+}
+
+
+/// A detected hydrogen bond.
+#[derive(Debug, Clone, Copy)]
+pub struct HydrogenBond<'a> {
+    pub donor: &'a Atom,
+    pub acceptor: &'a Atom,
+    /// Donor–acceptor distance in Å
+    pub distance: f64,
+    /// Largest donor–hydrogen–acceptor angle in degrees, `None` if the donor
+    /// has no modelled hydrogens
+    pub angle: Option<f64>,
+}
+
+
+struct Site<'a> {
+    atom: &'a Atom,
+    structure: usize,
+    residue: usize,
+    donor: bool,
+    acceptor: bool,
+}
+
+
+/// Nitrogens and oxygens can donate, except the backbone nitrogen of
+/// proline and backbone carbonyl oxygens which carry no hydrogen.
+fn is_donor(atom: &Atom, residue_name: &str) -> bool {
+    match atom.element() {
+        Some(Element::O) => !(atom.name() == "O" && classify_residue_name(residue_name) == EntityType::Protein),
+        Some(Element::N) => !(atom.name() == "N" && residue_name == "PRO"),
+        _ => false,
+    }
+}
+
+
+/// Oxygens accept. Nitrogens of amino acids only do in the histidine ring,
+/// the others carry hydrogens or a positive charge; nitrogens of nucleic
+/// acids and ligands are taken as acceptors.
+fn is_acceptor(atom: &Atom, residue_name: &str) -> bool {
+    match atom.element() {
+        Some(Element::O) => true,
+        Some(Element::N) if classify_residue_name(residue_name) == EntityType::Protein => {
+            matches!(residue_name, "HIS" | "HID" | "HIE") && matches!(atom.name(), "ND1" | "NE2")
+        }
+        Some(Element::N) => true,
+        _ => false,
+    }
+}
+
+
+/// Angle at `vertex` between `a` and `b` in degrees.
+fn angle(a: &Atom, vertex: &Atom, b: &Atom) -> f64 {
+    let u = (a.x() - vertex.x(), a.y() - vertex.y(), a.z() - vertex.z());
+    let v = (b.x() - vertex.x(), b.y() - vertex.y(), b.z() - vertex.z());
+    let dot = u.0 * v.0 + u.1 * v.1 + u.2 * v.2;
+    let norms = (u.0 * u.0 + u.1 * u.1 + u.2 * u.2).sqrt() * (v.0 * v.0 + v.1 * v.1 + v.2 * v.2).sqrt();
+    (dot / norms).clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+
+pub(crate) fn bonded(a: &Atom, b: &Atom, tolerance: f64) -> bool {
+    match (a.element(), b.element()) {
+        (Some(x), Some(y)) => a.distance(b) <= x.atomic_radius().covalent_single + y.atomic_radius().covalent_single + tolerance,
+        _ => false,
+    }
+}
+
+
+/// Finds the hydrogen bonds between residues of the given structures. With
+/// modelled hydrogens the
+/// donor–hydrogen–acceptor angle must reach `min_angle`; without them every
+/// bond of the donor must make at least 90° with the acceptor. Covalent
+/// bonds are inferred with `bond_tolerance` as for edges. Atoms of different
+/// structures are only paired with `inter_structure`.
+pub fn hydrogen_bonds<'a>(
+    structures: &[&'a PDB],
+    criteria: &HbondCriteria,
+    bond_tolerance: f64,
+    inter_structure: bool,
+) -> Vec<HydrogenBond<'a>> {
+    let mut sites = Vec::new();
+    let mut residue = 0;
+    for (structure, pdb) in structures.iter().enumerate() {
+        for model in pdb.models() {
+            for chain in model.chains() {
+                for r in chain.residues() {
+                    let name = r.name().unwrap_or("");
+                    for atom in r.atoms().filter(|a| a.element().is_some()) {
+                        sites.push(Site { atom, structure, residue, donor: is_donor(atom, name), acceptor: is_acceptor(atom, name) });
+                    }
+                    residue += 1;
+                }
+            }
+        }
+    }
+    let tree = RTree::bulk_load(sites.iter().enumerate().map(|(i, s)| GeomWithData::new([s.atom.x(), s.atom.y(), s.atom.z()], i)).collect());
+    // Longest covalent bond searched for the donor's neighbours
+    let bond_search = 2.0 + bond_tolerance;
+
+    let mut seen = HashSet::new();
+    let mut bonds = Vec::new();
+    for (d, donor) in sites.iter().enumerate().filter(|(_, s)| s.donor) {
+        let position = [donor.atom.x(), donor.atom.y(), donor.atom.z()];
+        let (hydrogens, heavy): (Vec<&Atom>, Vec<&Atom>) = tree.locate_within_distance(position, bond_search * bond_search)
+            .map(|p| sites[p.data].atom)
+            .filter(|a| a.pos() != donor.atom.pos() && bonded(donor.atom, a, bond_tolerance))
+            .partition(|a| a.element() == Some(&Element::H));
+        for candidate in tree.locate_within_distance(position, criteria.max_distance * criteria.max_distance) {
+            let acceptor = &sites[candidate.data];
+            if !acceptor.acceptor || acceptor.residue == donor.residue || (acceptor.structure != donor.structure && !inter_structure) {
+                continue;
+            }
+            let best_angle = hydrogens.iter().map(|h| angle(donor.atom, h, acceptor.atom)).reduce(f64::max);
+            let geometry = match best_angle {
+                None => heavy.iter().all(|x| angle(x, donor.atom, acceptor.atom) >= MIN_ANTECEDENT_ANGLE),
+                Some(best) => best >= criteria.min_angle,
+            };
+            if geometry && seen.insert((d.min(candidate.data), d.max(candidate.data))) {
+                bonds.push(HydrogenBond {
+                    donor: donor.atom,
+                    acceptor: acceptor.atom,
+                    distance: donor.atom.distance(acceptor.atom),
+                    angle: best_angle,
+                });
+            }
+        }
+    }
+    bonds
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atom(serial: usize, name: &str, element: &str, pos: (f64, f64, f64)) -> Atom {
+        Atom::new(false, serial, name, pos.0, pos.1, pos.2, 1.0, 20.0, element, 0).unwrap()
+    }
+
+    #[test]
+    fn checks_hydrogen_bond_geometry() {
+        let n = atom(1, "N", "N", (0.0, 0.0, 0.0));
+        let h = atom(2, "H", "H", (1.0, 0.0, 0.0));
+        let linear = atom(3, "O", "O", (2.9, 0.0, 0.0));
+        let bent = atom(4, "O", "O", (0.0, 2.9, 0.0));
+        assert!((angle(&n, &h, &linear) - 180.0).abs() < 1e-9);
+        assert!(angle(&n, &h, &bent) < DEFAULT_HBOND_ANGLE);
+        assert!(bonded(&n, &h, 0.45) && !bonded(&n, &linear, 0.45));
+
+        assert!(is_donor(&n, "GLY") && !is_donor(&n, "PRO") && !is_donor(&linear, "GLY"));
+        assert!(!is_acceptor(&n, "GLY") && is_acceptor(&atom(5, "NE2", "N", (0.0, 0.0, 0.0)), "HIS"));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::input::BUNDLE_INFIX;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::path::Path;
+
+
+/// Extensions of structure files, removed to form structure ids.
+const STRUCTURE_EXTENSIONS: &[&str] = &["pdb", "ent", "cif", "mmcif", "mmtf", "bcif", "pdbqt", "fcz"];
+
+
+/// Identifier of a structure derived from its file name. Names that follow
+/// PDB conventions are canonicalized to the lowercase PDB id followed by
+/// assembly, model and chain qualifiers, e.g. `1abc-assembly2-model1_A_B`,
+/// so the same structure gets the same id however a mirror or tool named
+/// the file. Other names are kept as they are, without directory,
+/// compression and extension.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StructureId {
+    /// Four character PDB id, lowercase
+    pub pdb_id: Option<String>,
+    /// File name without directory, compression and extension, used as the
+    /// id when it doesn't follow PDB conventions
+    pub name: String,
+    /// Biological assembly
+    pub assembly: Option<usize>,
+    pub model: Option<usize>,
+    pub chains: Vec<String>,
+}
+
+impl StructureId {
+    /// Parses the structure id from the file name of `fname`. Understands
+    /// RCSB mirror names (`pdb1abc.ent.gz`), assembly files (`1abc.pdb2`,
+    /// `1abc-assembly2.cif`), PDB bundles (`1abc-pdb-bundle.tar.gz`), chain
+    /// suffixes (`1abc_A`, `1abcA`) and model suffixes (`1abc_model3`).
+    pub fn from_path(fname: &str) -> StructureId {
+        let name = Path::new(fname).file_name().and_then(|n| n.to_str()).unwrap_or(fname);
+        let name = name.strip_suffix(".gz").unwrap_or(name);
+        let name = name.strip_suffix(".tar").unwrap_or(name);
+        let (mut stem, extension) = match name.rsplit_once('.') {
+            Some((stem, ext)) if STRUCTURE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()) => (stem, Some(ext)),
+            // RCSB biological assembly files, e.g. 1abc.pdb2
+            Some((stem, ext)) if assembly_extension(ext).is_some() => (stem, Some(ext)),
+            _ => (name, None),
+        };
+        if let Some((id, part)) = stem.rsplit_once(BUNDLE_INFIX) {
+            if part.chars().all(|c| c.is_ascii_digit()) {
+                stem = id;
+            }
+        }
+        if extension == Some("ent") {
+            stem = stem.strip_prefix("pdb").filter(|id| id.get(..4).is_some_and(is_pdb_id)).unwrap_or(stem);
+        }
+        let verbatim = StructureId { pdb_id: None, name: stem.to_string(), assembly: None, model: None, chains: Vec::new() };
+        if !stem.get(..4).is_some_and(is_pdb_id) {
+            return verbatim;
+        }
+        let (id, rest) = stem.split_at(4);
+        let mut parsed = StructureId {
+            pdb_id: Some(id.to_ascii_lowercase()),
+            assembly: extension.and_then(assembly_extension),
+            ..verbatim.clone()
+        };
+        // CATH and SCOP style chain suffix without separator, e.g. 1abcA
+        if rest.len() == 1 && rest.chars().all(|c| c.is_ascii_alphanumeric()) {
+            parsed.chains.push(rest.to_string());
+            return parsed;
+        }
+        if !rest.is_empty() && !rest.starts_with(['_', '-', '.']) {
+            return verbatim;
+        }
+        for token in rest.split(['_', '-', '.']).filter(|t| !t.is_empty()) {
+            let lower = token.to_ascii_lowercase();
+            if let Some(n) = qualifier(&lower, &["assembly", "assem", "ba"]) {
+                parsed.assembly = Some(n);
+            } else if let Some(n) = qualifier(&lower, &["model"]) {
+                parsed.model = Some(n);
+            } else if token.len() <= 4 && token.chars().all(|c| c.is_ascii_alphanumeric()) {
+                parsed.chains.push(token.to_string());
+            } else {
+                return verbatim;
+            }
+        }
+        parsed
+    }
+}
+
+
+fn is_pdb_id(s: &str) -> bool {
+    s.len() == 4
+        && s.starts_with(|c: char| c.is_ascii_digit() && c != '0')
+        && s.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+
+/// Number of a token like `assembly2` for any of the given prefixes.
+fn qualifier(token: &str, prefixes: &[&str]) -> Option<usize> {
+    prefixes.iter().find_map(|p| token.strip_prefix(p)?.parse().ok())
+}
+
+
+/// Assembly number of RCSB assembly file extensions like `pdb2`.
+fn assembly_extension(ext: &str) -> Option<usize> {
+    ext.to_ascii_lowercase().strip_prefix("pdb")?.parse().ok()
+}
+
+
+/// Formats as the canonical id, e.g. `1abc-assembly2_A`.
+impl fmt::Display for StructureId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(pdb_id) = &self.pdb_id else {
+            return write!(f, "{}", self.name);
+        };
+        write!(f, "{}", pdb_id)?;
+        if let Some(assembly) = self.assembly {
+            write!(f, "-assembly{}", assembly)?;
+        }
+        if let Some(model) = self.model {
+            write!(f, "-model{}", model)?;
+        }
+        for chain in &self.chains {
+            write!(f, "_{}", chain)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for StructureId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(fname: &str) -> String {
+        StructureId::from_path(fname).to_string()
+    }
+
+    #[test]
+    fn mirror_and_bundle_names() {
+        assert_eq!(id("data/ab/pdb1abc.ent.gz"), "1abc");
+        assert_eq!(id("1ABC.pdb"), "1abc");
+        assert_eq!(id("4v6x-pdb-bundle.tar.gz"), "4v6x");
+        assert_eq!(id("4v6x-pdb-bundle2.pdb"), "4v6x");
+    }
+
+    #[test]
+    fn qualifiers() {
+        assert_eq!(id("1abc.pdb2.gz"), "1abc-assembly2");
+        assert_eq!(id("1abc-assembly1.cif.gz"), "1abc-assembly1");
+        assert_eq!(id("1abc_A.pdb"), "1abc_A");
+        assert_eq!(id("1abcB.pdb"), "1abc_B");
+        assert_eq!(id("1abc_model3_A_B.pdb"), "1abc-model3_A_B");
+    }
+
+    #[test]
+    fn other_names_are_kept() {
+        assert_eq!(id("dir/my_design.pdb"), "my_design");
+        assert_eq!(id("1abc_relaxed_0001.pdb"), "1abc_relaxed_0001");
+        assert_eq!(id("a.pdb"), "a");
+    }
+}
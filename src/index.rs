@@ -0,0 +1,351 @@
+use crate::quality::QualityFlags;
+use anyhow::{bail, Context, Result};
+use glob::glob;
+use log::{debug, warn};
+use rusqlite::{params, Connection};
+use serde::de::{Deserializer, IgnoredAny, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS graphs (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    modified INTEGER NOT NULL,
+    structure_id TEXT,
+    pdb_id TEXT,
+    source TEXT,
+    nodes INTEGER NOT NULL,
+    edges INTEGER NOT NULL,
+    chain_count INTEGER NOT NULL,
+    recovered INTEGER NOT NULL,
+    degenerate INTEGER NOT NULL,
+    mean_plddt REAL,
+    relative_contact_order REAL,
+    long_range_order REAL,
+    contact_density REAL,
+    clashes INTEGER,
+    altloc_atoms INTEGER,
+    chain_breaks INTEGER,
+    unknown_elements INTEGER,
+    parse_warnings INTEGER,
+    coordinate_issues INTEGER
+);
+CREATE TABLE IF NOT EXISTS chains (
+    graph_id INTEGER NOT NULL REFERENCES graphs(id) ON DELETE CASCADE,
+    chain TEXT NOT NULL,
+    entity_type TEXT NOT NULL,
+    residues INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS ligands (
+    graph_id INTEGER NOT NULL REFERENCES graphs(id) ON DELETE CASCADE,
+    chain TEXT NOT NULL,
+    ligand TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS chains_graph ON chains(graph_id);
+CREATE INDEX IF NOT EXISTS ligands_ligand ON ligands(ligand);
+";
+
+/// Columns added to `graphs` after its first version, added to older indexes
+/// when they are opened.
+const ADDED_COLUMNS: &[(&str, &str)] = &[
+    ("relative_contact_order", "REAL"),
+    ("long_range_order", "REAL"),
+    ("contact_density", "REAL"),
+    ("clashes", "INTEGER"),
+    ("altloc_atoms", "INTEGER"),
+    ("chain_breaks", "INTEGER"),
+    ("unknown_elements", "INTEGER"),
+    ("parse_warnings", "INTEGER"),
+    ("coordinate_issues", "INTEGER"),
+];
+
+
+/// Number of elements of a JSON array, counted without keeping them.
+struct Count(usize);
+
+impl<'de> Deserialize<'de> for Count {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct CountVisitor;
+
+        impl<'de> Visitor<'de> for CountVisitor {
+            type Value = Count;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an array")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Count, A::Error> {
+                let mut n = 0;
+                while seq.next_element::<IgnoredAny>()?.is_some() {
+                    n += 1;
+                }
+                Ok(Count(n))
+            }
+        }
+
+        deserializer.deserialize_seq(CountVisitor)
+    }
+}
+
+
+/// The parts of a `_graph.json` output that are indexed.
+#[derive(Deserialize)]
+struct IndexedFile {
+    metadata: IndexedMetadata,
+    graph: IndexedGraph,
+}
+
+#[derive(Deserialize)]
+struct IndexedMetadata {
+    source: String,
+    structure_id: String,
+    pdb_id: Option<String>,
+    parse: IndexedParse,
+    chains: Vec<IndexedChain>,
+    #[serde(default)]
+    degenerate: Vec<IgnoredAny>,
+    prediction: Option<IndexedPrediction>,
+    topology: Option<IndexedTopology>,
+    /// Missing in outputs of older versions
+    quality: Option<QualityFlags>,
+}
+
+#[derive(Deserialize)]
+struct IndexedParse {
+    recovered: bool,
+}
+
+#[derive(Deserialize)]
+struct IndexedChain {
+    id: String,
+    entity_type: String,
+    residue_count: usize,
+    #[serde(default)]
+    ligands: BTreeSet<String>,
+}
+
+#[derive(Deserialize)]
+struct IndexedPrediction {
+    mean_plddt: f64,
+}
+
+#[derive(Deserialize)]
+struct IndexedTopology {
+    relative_contact_order: f64,
+    long_range_order: f64,
+    contact_density: f64,
+}
+
+#[derive(Deserialize)]
+struct IndexedGraph {
+    nodes: Count,
+    edges: Count,
+}
+
+
+fn modified(path: &Path) -> Result<i64> {
+    let modified = std::fs::metadata(path)?.modified()?;
+    Ok(modified.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64))
+}
+
+
+fn open(db: &Path) -> Result<Connection> {
+    let conn = Connection::open(db).with_context(|| format!("failed to open index {}", db.display()))?;
+    conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+    conn.execute_batch(SCHEMA)?;
+    let columns: HashSet<String> = conn.prepare("SELECT name FROM pragma_table_info('graphs')")?
+        .query_map([], |r| r.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    for (name, kind) in ADDED_COLUMNS.iter().filter(|(name, _)| !columns.contains(*name)) {
+        conn.execute_batch(&format!("ALTER TABLE graphs ADD COLUMN {} {};", name, kind))?;
+    }
+    Ok(conn)
+}
+
+
+/// Outcome of updating an index.
+#[derive(Debug, Default)]
+pub struct IndexReport {
+    pub added: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    pub skipped: usize,
+}
+
+
+/// Indexes every `_graph.json` output under `dir` into the SQLite database
+/// `db`. Files already indexed with the same modification time are not read
+/// again and files that disappeared are removed, so the index can be kept up
+/// to date as a dataset grows. Merged and temporal graphs are skipped.
+pub fn build_index(dir: &Path, db: &Path) -> Result<IndexReport> {
+    let mut conn = open(db)?;
+    let tx = conn.transaction()?;
+    let mut report = IndexReport::default();
+    let mut seen = HashSet::new();
+    let pattern = dir.join("**").join("*_graph.json");
+    for entry in glob(&pattern.to_string_lossy())? {
+        let path = entry?;
+        let path_str = path.to_string_lossy().into_owned();
+        let modified = modified(&path)?;
+        seen.insert(path_str.clone());
+        let indexed: Option<i64> = tx.query_row("SELECT modified FROM graphs WHERE path = ?1", [&path_str], |r| r.get(0)).ok();
+        if indexed == Some(modified) {
+            report.unchanged += 1;
+            continue;
+        }
+        let file: IndexedFile = match std::fs::File::open(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|f| Ok(serde_json::from_reader(std::io::BufReader::new(f))?))
+        {
+            Ok(file) => file,
+            Err(e) => {
+                debug!("Not indexing {} - {}", path_str, e);
+                report.skipped += 1;
+                continue;
+            }
+        };
+        let meta = &file.metadata;
+        tx.execute("DELETE FROM graphs WHERE path = ?1", [&path_str])?;
+        tx.execute(
+            "INSERT INTO graphs (path, modified, structure_id, pdb_id, source, nodes, edges, chain_count, recovered, degenerate, mean_plddt,
+                                 relative_contact_order, long_range_order, contact_density,
+                                 clashes, altloc_atoms, chain_breaks, unknown_elements, parse_warnings, coordinate_issues)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
+            params![
+                path_str,
+                modified,
+                meta.structure_id,
+                meta.pdb_id,
+                meta.source,
+                file.graph.nodes.0,
+                file.graph.edges.0,
+                meta.chains.len(),
+                meta.parse.recovered,
+                !meta.degenerate.is_empty(),
+                meta.prediction.as_ref().map(|p| p.mean_plddt),
+                meta.topology.as_ref().map(|t| t.relative_contact_order),
+                meta.topology.as_ref().map(|t| t.long_range_order),
+                meta.topology.as_ref().map(|t| t.contact_density),
+                meta.quality.as_ref().map(|q| q.clashes),
+                meta.quality.as_ref().map(|q| q.altloc_atoms),
+                meta.quality.as_ref().map(|q| q.chain_breaks),
+                meta.quality.as_ref().map(|q| q.unknown_elements),
+                meta.quality.as_ref().map(|q| q.parse_warnings),
+                meta.quality.as_ref().map(|q| q.coordinate_issues),
+            ],
+        )?;
+        let graph_id = tx.last_insert_rowid();
+        for chain in &meta.chains {
+            tx.execute(
+                "INSERT INTO chains (graph_id, chain, entity_type, residues) VALUES (?1, ?2, ?3, ?4)",
+                params![graph_id, chain.id, chain.entity_type, chain.residue_count],
+            )?;
+            for ligand in &chain.ligands {
+                tx.execute("INSERT INTO ligands (graph_id, chain, ligand) VALUES (?1, ?2, ?3)", params![graph_id, chain.id, ligand])?;
+            }
+        }
+        report.added += 1;
+    }
+    let indexed: Vec<String> = tx.prepare("SELECT path FROM graphs")?
+        .query_map([], |r| r.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    for path in indexed.iter().filter(|p| !seen.contains(*p) && Path::new(p).starts_with(dir)) {
+        tx.execute("DELETE FROM graphs WHERE path = ?1", [path])?;
+        report.removed += 1;
+    }
+    tx.commit()?;
+    if report.skipped > 0 {
+        warn!("Skipped {} files that are not single structure graphs", report.skipped);
+    }
+    Ok(report)
+}
+
+
+/// Selection of indexed graphs.
+#[derive(Debug, Default)]
+pub struct Query {
+    /// SQL condition on the `graphs` table
+    pub condition: Option<String>,
+    /// Graphs must contain all of these ligands
+    pub ligands: Vec<String>,
+    pub min_nodes: Option<usize>,
+    pub max_nodes: Option<usize>,
+}
+
+
+/// Paths of the indexed graphs matching `query`.
+pub fn query(db: &Path, query: &Query) -> Result<Vec<String>> {
+    if !db.is_file() {
+        bail!("index {} does not exist, create it with `graphein index`", db.display());
+    }
+    let conn = open(db)?;
+    let mut conditions = Vec::new();
+    let mut values: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+    if let Some(condition) = &query.condition {
+        conditions.push(format!("({})", condition));
+    }
+    for ligand in &query.ligands {
+        values.push(Box::new(ligand.to_ascii_uppercase()));
+        conditions.push(format!("id IN (SELECT graph_id FROM ligands WHERE ligand = ?{})", values.len()));
+    }
+    if let Some(min) = query.min_nodes {
+        values.push(Box::new(min));
+        conditions.push(format!("nodes >= ?{}", values.len()));
+    }
+    if let Some(max) = query.max_nodes {
+        values.push(Box::new(max));
+        conditions.push(format!("nodes <= ?{}", values.len()));
+    }
+    let mut sql = String::from("SELECT path FROM graphs");
+    if !conditions.is_empty() {
+        sql.push_str(" WHERE ");
+        sql.push_str(&conditions.join(" AND "));
+    }
+    sql.push_str(" ORDER BY path");
+    let mut statement = conn.prepare(&sql).with_context(|| format!("invalid query: {}", sql))?;
+    let paths = statement
+        .query_map(rusqlite::params_from_iter(values.iter()), |r| r.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(paths)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexes_and_queries_graphs() {
+        let dir = std::env::temp_dir().join(format!("graphein-index-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let graph = r#"{"metadata": {"source": "1abc.pdb", "structure_id": "1abc", "pdb_id": "1abc",
+            "parse": {"strictness": "Medium", "recovered": false, "warnings": []},
+            "chains": [{"id": "A", "entity_type": "protein", "residue_count": 2, "ligands": ["HEM"]}],
+            "topology": {"residues": 2, "contacts": 0, "contact_density": 0.0, "absolute_contact_order": 0.0,
+                         "relative_contact_order": 0.0, "long_range_contacts": 0, "long_range_order": 0.0},
+            "quality": {"clashes": 3, "altloc_atoms": 0, "chain_breaks": 1, "unknown_elements": 0, "parse_warnings": 0, "coordinate_issues": 0}},
+            "graph": {"nodes": [{}, {}, {}], "node_holes": [], "edge_property": "undirected", "edges": [[0, 1, 1.5]]}}"#;
+        std::fs::write(dir.join("1abc_graph.json"), graph).unwrap();
+        std::fs::write(dir.join("merged_graph.json"), r#"{"metadata": {"structures": []}, "graph": {}}"#).unwrap();
+        let db = dir.join("index.sqlite");
+
+        let report = build_index(&dir, &db).unwrap();
+        assert_eq!((report.added, report.skipped), (1, 1));
+        assert_eq!(build_index(&dir, &db).unwrap().unchanged, 1);
+        let hem = Query { ligands: vec!["hem".to_string()], max_nodes: Some(3), ..Default::default() };
+        assert_eq!(query(&db, &hem).unwrap(), vec![dir.join("1abc_graph.json").to_string_lossy().into_owned()]);
+        let large = Query { condition: Some("edges > 1".to_string()), ..Default::default() };
+        assert!(query(&db, &large).unwrap().is_empty());
+        let unfolded = Query { condition: Some("relative_contact_order < 0.1".to_string()), ..Default::default() };
+        assert_eq!(query(&db, &unfolded).unwrap().len(), 1);
+        let clean = Query { condition: Some("clashes = 0 AND chain_breaks = 0".to_string()), ..Default::default() };
+        assert!(query(&db, &clean).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
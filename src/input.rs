@@ -0,0 +1,274 @@
+use crate::{bcif, foldcomp, mmtf, pdbqt};
+use flate2::read::GzDecoder;
+use anyhow::bail;
+use pdbtbx::{Atom, Chain, Conformer, Context, ErrorLevel, Model, PDBError, Residue, StrictnessLevel, PDB};
+use std::collections::HashMap;
+use std::io::{BufReader, Cursor, Read};
+use std::path::Path;
+
+
+type ParseResult = Result<(PDB, Vec<PDBError>), Vec<PDBError>>;
+
+
+/// Infix of the files of a PDB bundle, e.g. `1abc-pdb-bundle1.pdb` in
+/// `1abc-pdb-bundle.tar.gz`. Structures too large for the PDB format are
+/// distributed as bundles of several PDB files with remapped chain ids.
+pub const BUNDLE_INFIX: &str = "-pdb-bundle";
+
+
+fn error(fname: &str, short: &str, long: impl std::fmt::Display) -> Vec<PDBError> {
+    vec![PDBError::new(ErrorLevel::BreakingError, short, long.to_string(), Context::show(fname))]
+}
+
+
+/// Opens a structure file like `pdbtbx::open`, additionally reading
+/// gzipped files, `.ent` files of RCSB mirrors, PDB bundles (`.tar` or
+/// `.tar.gz`), whose parts are joined into a single structure, and the
+/// binary MMTF (`.mmtf`) and BinaryCIF (`.bcif`) formats, AutoDock PDBQT
+/// files (`.pdbqt`) and Foldcomp-compressed structures (`.fcz`).
+pub fn open(fname: &str, level: StrictnessLevel) -> ParseResult {
+    let lower = fname.to_ascii_lowercase();
+    let gzipped = lower.ends_with(".gz");
+    let plain = lower.strip_suffix(".gz").unwrap_or(&lower);
+    if !gzipped && (plain.ends_with(".pdb") || plain.ends_with(".cif")) {
+        return pdbtbx::open(fname, level);
+    }
+    if lower.ends_with(".fcz") {
+        return match foldcomp::decompress(fname) {
+            Ok(text) => pdbtbx::open_pdb_raw(BufReader::new(Cursor::new(text.into_bytes())), Context::show(fname), level),
+            Err(e) => Err(error(fname, "Invalid Foldcomp file", format!("{:#}", e))),
+        };
+    }
+    let mut data = Vec::new();
+    let read = std::fs::File::open(fname).and_then(|f| {
+        if gzipped {
+            GzDecoder::new(f).read_to_end(&mut data)
+        } else {
+            BufReader::new(f).read_to_end(&mut data)
+        }
+    });
+    if let Err(e) = read {
+        return Err(error(fname, "File could not be read", e));
+    }
+    if plain.ends_with(".tar") {
+        open_bundle(fname, &data, level)
+    } else if plain.ends_with(".mmtf") {
+        mmtf::read(&data).map(|pdb| (pdb, Vec::new())).map_err(|e| error(fname, "Invalid MMTF file", format!("{:#}", e)))
+    } else if plain.ends_with(".bcif") {
+        bcif::read(&data).map(|pdb| (pdb, Vec::new())).map_err(|e| error(fname, "Invalid BinaryCIF file", format!("{:#}", e)))
+    } else if plain.ends_with(".pdbqt") {
+        match String::from_utf8(data) {
+            Ok(text) => pdbqt::read(&text).map(|(pdb, _)| (pdb, Vec::new())).map_err(|e| error(fname, "Invalid PDBQT file", format!("{:#}", e))),
+            Err(e) => Err(error(fname, "File is not valid UTF-8", e)),
+        }
+    } else if plain.ends_with(".cif") || plain.ends_with(".mmcif") {
+        match String::from_utf8(data) {
+            Ok(text) => pdbtbx::open_mmcif_raw(&text, level),
+            Err(e) => Err(error(fname, "File is not valid UTF-8", e)),
+        }
+    } else {
+        pdbtbx::open_pdb_raw(BufReader::new(Cursor::new(data)), Context::show(fname), level)
+    }
+}
+
+
+/// Reads a text file, decompressing it if it ends in `.gz`.
+pub fn read_text(fname: &str) -> std::io::Result<String> {
+    let mut text = String::new();
+    let file = std::fs::File::open(fname)?;
+    if fname.to_ascii_lowercase().ends_with(".gz") {
+        GzDecoder::new(file).read_to_string(&mut text)?;
+    } else {
+        BufReader::new(file).read_to_string(&mut text)?;
+    }
+    Ok(text)
+}
+
+
+/// Whether `fname` is a PDB format text file, possibly gzipped.
+pub fn is_pdb_text(fname: &str) -> bool {
+    let lower = fname.to_ascii_lowercase();
+    let plain = lower.strip_suffix(".gz").unwrap_or(&lower);
+    plain.ends_with(".pdb") || plain.ends_with(".ent")
+}
+
+
+/// Regular files of an uncompressed (ustar) tar archive, with their names.
+fn tar_entries(data: &[u8]) -> Result<Vec<(String, &[u8])>, String> {
+    let field = |header: &[u8], range: std::ops::Range<usize>| {
+        let bytes = &header[range];
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+    };
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset + 512 <= data.len() {
+        let header = &data[offset..offset + 512];
+        if header.iter().all(|b| *b == 0) {
+            break;
+        }
+        let size = usize::from_str_radix(&field(header, 124..136), 8).map_err(|e| format!("invalid entry size: {}", e))?;
+        let start = offset + 512;
+        if start + size > data.len() {
+            return Err("archive is truncated".to_string());
+        }
+        let typeflag = header[156];
+        if typeflag == b'0' || typeflag == 0 {
+            let (prefix, name) = (field(header, 345..500), field(header, 0..100));
+            let name = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+            entries.push((name, &data[start..start + size]));
+        }
+        offset = start + size.div_ceil(512) * 512;
+    }
+    Ok(entries)
+}
+
+
+/// Original chain ids of the chains of each bundle file, from the
+/// `<id>-chain-id-mapping.txt` of the bundle.
+fn chain_id_mapping(text: &str) -> HashMap<String, HashMap<String, String>> {
+    let mut mapping: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut current = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(file) = line.strip_suffix(':') {
+            current = Some(file.to_string());
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if let (Some(file), [new, original]) = (&current, fields.as_slice()) {
+            mapping.entry(file.clone()).or_default().insert(new.to_string(), original.to_string());
+        }
+    }
+    mapping
+}
+
+
+/// Joins the files of a PDB bundle into one structure, restoring the
+/// original chain ids. Atom serial numbers restart in every bundle file, so
+/// atoms are renumbered sequentially.
+fn open_bundle(fname: &str, data: &[u8], level: StrictnessLevel) -> ParseResult {
+    let entries = tar_entries(data).map_err(|e| error(fname, "Invalid PDB bundle", e))?;
+    let file_name = |path: &str| Path::new(path).file_name().and_then(|n| n.to_str()).unwrap_or(path).to_string();
+    let mapping = entries.iter()
+        .find(|(name, _)| name.ends_with("chain-id-mapping.txt"))
+        .map(|(_, text)| chain_id_mapping(&String::from_utf8_lossy(text)))
+        .unwrap_or_default();
+    let mut parts: Vec<&(String, &[u8])> = entries.iter()
+        .filter(|(name, _)| name.contains(BUNDLE_INFIX) && name.ends_with(".pdb"))
+        .collect();
+    parts.sort_by_key(|(name, _)| {
+        let stem = name.trim_end_matches(".pdb");
+        stem.rsplit_once(BUNDLE_INFIX).and_then(|(_, n)| n.parse::<usize>().ok()).unwrap_or(0)
+    });
+    if parts.is_empty() {
+        return Err(error(fname, "Invalid PDB bundle", "no bundle files found in archive"));
+    }
+
+    let mut joined: Option<PDB> = None;
+    let mut warnings = Vec::new();
+    for (name, content) in parts {
+        let (mut pdb, errors) = pdbtbx::open_pdb_raw(BufReader::new(Cursor::new(*content)), Context::show(name), level)?;
+        warnings.extend(errors);
+        if let Some(chains) = mapping.get(&file_name(name)) {
+            for chain in pdb.chains_mut() {
+                if let Some(original) = chains.get(chain.id()) {
+                    chain.set_id(original);
+                }
+            }
+        }
+        match &mut joined {
+            Some(joined) => joined.join(pdb),
+            None => joined = Some(pdb),
+        }
+    }
+    let mut pdb = joined.unwrap();
+    for (i, atom) in pdb.atoms_mut().enumerate() {
+        atom.set_serial_number(i + 1);
+    }
+    Ok((pdb, warnings))
+}
+
+
+/// An atom read from a binary format, before it is placed into a `PDB`.
+pub struct AtomRecord {
+    pub model: usize,
+    pub chain: String,
+    pub resseq: isize,
+    pub icode: Option<String>,
+    pub resname: String,
+    pub name: String,
+    pub altloc: Option<String>,
+    pub element: String,
+    pub pos: (f64, f64, f64),
+    pub occupancy: f64,
+    pub b_factor: f64,
+    pub charge: isize,
+    pub hetero: bool,
+    pub serial_number: usize,
+}
+
+
+/// Builds a structure from atoms in file order. Models, chains and residues
+/// are created as they first appear. Atoms without a chain id are put in
+/// chain `A` and atoms without a residue name in `UNK` residues.
+pub fn build_pdb(identifier: Option<String>, atoms: impl IntoIterator<Item = AtomRecord>) -> anyhow::Result<PDB> {
+    let mut pdb = PDB::new();
+    pdb.identifier = identifier;
+    let mut models: Vec<Model> = Vec::new();
+    for a in atoms {
+        let chain = if a.chain.is_empty() { "A" } else { &a.chain };
+        let resname = if a.resname.is_empty() { "UNK" } else { &a.resname };
+        // pdbtbx panics on invalid identifiers when adding atoms
+        if Chain::new(chain).is_none()
+            || Residue::new(a.resseq, a.icode.as_deref(), None).is_none()
+            || Conformer::new(resname, a.altloc.as_deref(), None).is_none()
+        {
+            bail!("Invalid chain, residue or alternate location id of atom {}", a.serial_number);
+        }
+        let Some(mut atom) = Atom::new(
+            a.hetero, a.serial_number, &a.name, a.pos.0, a.pos.1, a.pos.2, a.occupancy, a.b_factor, &a.element, 0,
+        ) else {
+            bail!("Invalid atom {} {:?} in residue {}", a.serial_number, a.name, a.resseq);
+        };
+        atom.set_charge(a.charge);
+        if models.last().map(|m| m.serial_number()) != Some(a.model) {
+            models.push(Model::new(a.model));
+        }
+        models.last_mut().unwrap().add_atom(atom, chain, (a.resseq, a.icode.as_deref()), (resname, a.altloc.as_deref()));
+    }
+    for model in models {
+        pdb.add_model(model);
+    }
+    Ok(pdb)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::{BufWriter, Write};
+
+    #[test]
+    fn gzipped_mmcif_reads_like_pdb() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        let (pdb, _) = open(path, StrictnessLevel::Medium).unwrap();
+        let mut cif = Vec::new();
+        pdbtbx::save_mmcif_raw(&pdb, BufWriter::new(&mut cif));
+        let dir = std::env::temp_dir().join(format!("graphein-input-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let gz = dir.join("1abc.cif.gz");
+        let mut encoder = GzEncoder::new(std::fs::File::create(&gz).unwrap(), Compression::default());
+        encoder.write_all(&cif).unwrap();
+        encoder.finish().unwrap();
+
+        let (read, _) = open(gz.to_str().unwrap(), StrictnessLevel::Medium).unwrap();
+        assert_eq!(read.atom_count(), pdb.atom_count());
+        for (a, b) in read.atoms().zip(pdb.atoms()) {
+            assert_eq!((a.serial_number(), a.name()), (b.serial_number(), b.name()));
+            assert!(a.distance(b) < 1e-6);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
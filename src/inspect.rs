@@ -0,0 +1,173 @@
+use crate::entity::{classify_chain, classify_residue, ChainInfo, EntityType};
+use crate::residue_graph::{build_residue_graph, ResidueEdges, ResidueGraphOptions};
+use crate::stats::GraphSummary;
+use crate::GraphBuilder;
+use pdbtbx::{Element, PDB};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+
+/// Report format of `graphein inspect`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum InspectFormat {
+    /// Aligned text for reading in a terminal
+    Text,
+    Json,
+}
+
+
+/// Size of the graph a structure would give under some parameters.
+#[derive(Serialize, Debug, Clone)]
+pub struct GraphPreview {
+    /// `atom` or `residue` graph
+    pub granularity: &'static str,
+    /// Residue distance of residue graphs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub residue_edges: Option<ResidueEdges>,
+    pub cutoff: f64,
+    #[serde(flatten)]
+    pub summary: GraphSummary,
+}
+
+
+/// What `graphein inspect` reports on a structure, to choose the parameters
+/// of a run before converting a dataset.
+#[derive(Serialize, Debug, Clone)]
+pub struct Report {
+    pub source: String,
+    pub structure_id: String,
+    /// Highest resolution in Å, from the header
+    pub resolution: Option<f64>,
+    pub models: usize,
+    /// Atoms of the first model
+    pub atoms: usize,
+    /// Atoms with alternate locations
+    pub altloc_atoms: usize,
+    pub hydrogens: usize,
+    pub parse_warnings: usize,
+    pub chains: Vec<ChainInfo>,
+    /// Residues that aren't protein or nucleic acid by name, with their
+    /// counts, including waters
+    pub hetero_residues: BTreeMap<String, usize>,
+    pub graphs: Vec<GraphPreview>,
+}
+
+
+/// Resolution of a structure in Å: REMARK 2 of PDB files, otherwise the
+/// refinement, reflection or EM reconstruction resolution of mmCIF `text`.
+pub fn resolution(pdb: &PDB, text: Option<&str>) -> Option<f64> {
+    let number = |s: &str| s.trim().trim_matches(['\'', '"']).parse::<f64>().ok();
+    let remark = pdb.remarks().filter(|(n, _)| *n == 2).find_map(|(_, text)| {
+        text.trim().strip_prefix("RESOLUTION.").and_then(|r| r.split_whitespace().next()).and_then(number)
+    });
+    remark.or_else(|| {
+        text?.lines().find_map(|line| {
+            ["_refine.ls_d_res_high", "_reflns.d_resolution_high", "_em_3d_reconstruction.resolution"]
+                .iter()
+                .find_map(|key| line.strip_prefix(key).filter(|v| v.starts_with(char::is_whitespace)).and_then(number))
+        })
+    })
+}
+
+
+impl Report {
+    /// Reports on `pdb` with atom graph previews at each of `cutoffs` and a
+    /// residue graph preview with `residues`.
+    pub fn new(source: &str, structure_id: &str, pdb: &PDB, parse_warnings: usize, resolution: Option<f64>, cutoffs: &[f64], residues: &ResidueGraphOptions) -> Report {
+        let model = pdb.model(0);
+        let atoms = || model.into_iter().flat_map(|m| m.atoms());
+        let mut hetero_residues = BTreeMap::new();
+        for residue in model.into_iter().flat_map(|m| m.residues()) {
+            if !matches!(classify_residue(residue), EntityType::Protein | EntityType::Dna | EntityType::Rna) {
+                *hetero_residues.entry(residue.name().unwrap_or("UNK").trim().to_string()).or_default() += 1;
+            }
+        }
+        let mut graphs: Vec<GraphPreview> = cutoffs.iter().map(|cutoff| GraphPreview {
+            granularity: "atom",
+            residue_edges: None,
+            cutoff: *cutoff,
+            summary: GraphSummary::of_graph(source, structure_id, &GraphBuilder::new().cutoff(*cutoff).build_graph(pdb)),
+        }).collect();
+        let residue_graph = build_residue_graph(pdb, residues);
+        let edges: Vec<(usize, usize)> = residue_graph.raw_edges().iter().map(|e| (e.source().index(), e.target().index())).collect();
+        graphs.push(GraphPreview {
+            granularity: "residue",
+            residue_edges: Some(residues.edges),
+            cutoff: residues.cutoff,
+            summary: GraphSummary::new(source, structure_id, &vec![None; residue_graph.node_count()], &edges),
+        });
+        Report {
+            source: source.to_string(),
+            structure_id: structure_id.to_string(),
+            resolution,
+            models: pdb.model_count(),
+            atoms: atoms().count(),
+            altloc_atoms: model.into_iter().flat_map(|m| m.conformers()).filter(|c| c.alternative_location().is_some()).map(|c| c.atom_count()).sum(),
+            hydrogens: atoms().filter(|a| a.element() == Some(&Element::H)).count(),
+            parse_warnings,
+            chains: model.into_iter().flat_map(|m| m.chains()).map(classify_chain).collect(),
+            hetero_residues,
+            graphs,
+        }
+    }
+
+    /// The report as aligned text for reading in a terminal.
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        let resolution = self.resolution.map_or("unknown".to_string(), |r| format!("{:.2} Å", r));
+        let _ = writeln!(out, "{} ({})", self.source, self.structure_id);
+        let _ = writeln!(out, "  resolution      {}", resolution);
+        let _ = writeln!(out, "  models          {}", self.models);
+        let _ = writeln!(out, "  atoms           {} ({} hydrogens, {} with alternate locations)", self.atoms, self.hydrogens, self.altloc_atoms);
+        let _ = writeln!(out, "  parse warnings  {}", self.parse_warnings);
+        let _ = writeln!(out, "chains");
+        for chain in &self.chains {
+            let range = match (&chain.first_residue, &chain.last_residue) {
+                (Some(first), Some(last)) => format!("{}-{}", first, last),
+                _ => String::new(),
+            };
+            let entity_type = serde_json::to_value(chain.entity_type).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default();
+            let _ = writeln!(out, "  {:<4} {:<12} {:>6} residues  {}", chain.id, entity_type, chain.residue_count, range);
+        }
+        if !self.hetero_residues.is_empty() {
+            let _ = writeln!(out, "hetero residues");
+            for (name, count) in &self.hetero_residues {
+                let _ = writeln!(out, "  {:<4} {:>6}", name, count);
+            }
+        }
+        let _ = writeln!(out, "graphs");
+        for graph in &self.graphs {
+            let edges = graph.residue_edges.and_then(|e| clap::ValueEnum::to_possible_value(&e)).map_or(String::new(), |v| format!(" {}", v.get_name()));
+            let summary = &graph.summary;
+            let _ = writeln!(
+                out,
+                "  {:<7} cutoff {:>5.2}{:<13} {:>7} nodes {:>9} edges  mean degree {:>6.2}  isolated {:>5}  components {:>5}",
+                graph.granularity, graph.cutoff, edges, summary.nodes, summary.edges, summary.degree.mean, summary.isolated, summary.components
+            );
+        }
+        out
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_structure_and_graphs() {
+        let pdb = crate::golden::Fixture::get("benzoate_stacking").unwrap().structure().unwrap();
+        let residues = ResidueGraphOptions { edges: ResidueEdges::Ca, cutoff: 8.0 };
+        let report = Report::new("benzoate_stacking.pdb", "benzoate_stacking", &pdb, 0, None, &[3.5, 5.0], &residues);
+        assert_eq!((report.atoms, report.chains.len()), (17, 2));
+        assert_eq!(report.hetero_residues, BTreeMap::from([("BEZ".to_string(), 1)]));
+        assert_eq!(report.graphs.len(), 3);
+        assert!(report.graphs[0].summary.edges <= report.graphs[1].summary.edges);
+        assert_eq!(report.graphs[2].summary.nodes, 3);
+        assert!(report.text().contains("resolution      unknown"));
+
+        let cif = "data_1ABC\n_refine.ls_d_res_high    1.85\n";
+        assert_eq!(resolution(&pdb, Some(cif)), Some(1.85));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::atomic::AtomicFile;
+use crate::disulfide::disulfides;
+use crate::hbond::{hydrogen_bonds, HbondCriteria};
+use crate::residue::ResidueKey;
+use anyhow::Result;
+use pdbtbx::{Atom, PDB};
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+
+/// Default largest distance between oppositely charged side chain atoms of
+/// salt bridges in Å.
+pub const DEFAULT_SALT_BRIDGE_DISTANCE: f64 = 4.0;
+
+
+/// Which interactions are detected and their criteria.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct InteractionOptions {
+    pub hbonds: HbondCriteria,
+    /// Largest distance of salt bridges in Å
+    pub salt_bridge_distance: f64,
+    /// Largest SG–SG distance of disulfide bridges in Å, not detected if unset
+    pub disulfide_distance: Option<f64>,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum InteractionKind {
+    Hbond,
+    SaltBridge,
+    Disulfide,
+    Hydrophobic,
+    PiStacking,
+}
+
+impl fmt::Display for InteractionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InteractionKind::Hbond => "hbond",
+            InteractionKind::SaltBridge => "salt_bridge",
+            InteractionKind::Disulfide => "disulfide",
+            InteractionKind::Hydrophobic => "hydrophobic",
+            InteractionKind::PiStacking => "pi_stacking",
+        })
+    }
+}
+
+
+/// One interaction between atoms of two residues. For hydrogen bonds `a` is
+/// the donor, for salt bridges the acidic residue.
+#[derive(Debug, Clone)]
+pub struct Interaction {
+    pub kind: InteractionKind,
+    pub residue_a: ResidueKey,
+    pub residue_name_a: String,
+    pub atom_a: String,
+    pub residue_b: ResidueKey,
+    pub residue_name_b: String,
+    pub atom_b: String,
+    /// Distance between the atoms in Å
+    pub distance: f64,
+    /// Donor–hydrogen–acceptor angle of hydrogen bonds with modelled
+    /// hydrogens, in degrees
+    pub angle: Option<f64>,
+}
+
+
+/// Charged side chain atoms of salt bridges: carboxylate oxygens are
+/// acidic, the amine and guanidinium nitrogens of lysine and arginine and the
+/// ring nitrogens of histidine basic.
+pub(crate) fn charge(residue_name: &str, atom_name: &str) -> Option<bool> {
+    match (residue_name, atom_name) {
+        ("ASP", "OD1" | "OD2") | ("GLU", "OE1" | "OE2") => Some(false),
+        ("LYS", "NZ") | ("ARG", "NE" | "NH1" | "NH2") | ("HIS" | "HIP", "ND1" | "NE2") => Some(true),
+        _ => None,
+    }
+}
+
+
+/// Pairs of an acidic and a basic atom at most `max_distance` apart, with
+/// their distance.
+pub(crate) fn salt_bridge_pairs<'a>(acids: &[&'a Atom], bases: &[&'a Atom], max_distance: f64) -> Vec<(&'a Atom, &'a Atom, f64)> {
+    let tree = RTree::bulk_load(bases.iter().enumerate().map(|(i, a)| GeomWithData::new([a.x(), a.y(), a.z()], i)).collect());
+    let mut pairs = Vec::new();
+    for acid in acids {
+        for neighbor in tree.locate_within_distance([acid.x(), acid.y(), acid.z()], max_distance * max_distance) {
+            let base = bases[neighbor.data];
+            pairs.push((*acid, base, acid.distance(base)));
+        }
+    }
+    pairs
+}
+
+
+/// Detects hydrogen bonds, salt bridges and disulfide bridges between the
+/// residues of the first model, sorted by kind and residues. Salt bridges
+/// are reported once per residue pair, at their closest atoms.
+pub fn interactions(pdb: &PDB, opts: &InteractionOptions, bond_tolerance: f64) -> Vec<Interaction> {
+    let Some(model) = pdb.model(0) else {
+        return Vec::new();
+    };
+    let mut residue_of: HashMap<*const Atom, (ResidueKey, &str)> = HashMap::new();
+    let (mut acids, mut bases) = (Vec::new(), Vec::new());
+    for chain in model.chains() {
+        for residue in chain.residues() {
+            let key = ResidueKey::new(chain, residue);
+            let name = residue.name().unwrap_or("");
+            for atom in residue.atoms() {
+                residue_of.insert(atom as *const Atom, (key.clone(), name));
+                match charge(name, atom.name()) {
+                    Some(true) => bases.push(atom),
+                    Some(false) => acids.push(atom),
+                    None => {}
+                }
+            }
+        }
+    }
+    let interaction = |kind, a: &Atom, b: &Atom, distance, angle| {
+        let ((residue_a, name_a), (residue_b, name_b)) = (&residue_of[&(a as *const Atom)], &residue_of[&(b as *const Atom)]);
+        Interaction {
+            kind,
+            residue_a: residue_a.clone(),
+            residue_name_a: name_a.to_string(),
+            atom_a: a.name().to_string(),
+            residue_b: residue_b.clone(),
+            residue_name_b: name_b.to_string(),
+            atom_b: b.name().to_string(),
+            distance,
+            angle,
+        }
+    };
+
+    // The detectors look at all models, only pairs within the first are kept
+    let in_model = |a: &Atom, b: &Atom| residue_of.contains_key(&(a as *const Atom)) && residue_of.contains_key(&(b as *const Atom));
+
+    let mut found = Vec::new();
+    for bond in hydrogen_bonds(&[pdb], &opts.hbonds, bond_tolerance, false).into_iter().filter(|b| in_model(b.donor, b.acceptor)) {
+        found.push(interaction(InteractionKind::Hbond, bond.donor, bond.acceptor, bond.distance, bond.angle));
+    }
+
+    let mut closest: BTreeMap<(ResidueKey, ResidueKey), (f64, &Atom, &Atom)> = BTreeMap::new();
+    for (acid, base, distance) in salt_bridge_pairs(&acids, &bases, opts.salt_bridge_distance) {
+        let key = (residue_of[&(acid as *const Atom)].0.clone(), residue_of[&(base as *const Atom)].0.clone());
+        if closest.get(&key).is_none_or(|(d, _, _)| distance < *d) {
+            closest.insert(key, (distance, acid, base));
+        }
+    }
+    found.extend(closest.into_values().map(|(distance, acid, base)| interaction(InteractionKind::SaltBridge, acid, base, distance, None)));
+
+    if let Some(max_distance) = opts.disulfide_distance {
+        for (a, b, distance) in disulfides(&[pdb], max_distance, false).into_iter().filter(|(a, b, _)| in_model(a, b)) {
+            found.push(interaction(InteractionKind::Disulfide, a, b, distance, None));
+        }
+    }
+    found.sort_by(|x, y| (x.kind, &x.residue_a, &x.residue_b).cmp(&(y.kind, &y.residue_a, &y.residue_b)));
+    found
+}
+
+
+/// Writes interactions as a flat CSV table. The angle is left empty where
+/// it doesn't apply.
+pub fn write_interactions(interactions: &[Interaction], save_fname: &Path) -> Result<()> {
+    let mut out = BufWriter::new(AtomicFile::create(save_fname)?);
+    writeln!(out, "kind,residue_a,residue_name_a,atom_a,residue_b,residue_name_b,atom_b,distance,angle")?;
+    for i in interactions {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{:.3},{}",
+            i.kind,
+            i.residue_a,
+            i.residue_name_a,
+            i.atom_a,
+            i.residue_b,
+            i.residue_name_b,
+            i.atom_b,
+            i.distance,
+            i.angle.map(|a| format!("{:.1}", a)).unwrap_or_default()
+        )?;
+    }
+    out.into_inner().map_err(|e| e.into_error())?.commit()?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdbtbx::Model;
+
+    #[test]
+    fn reports_closest_salt_bridge_atoms() {
+        let mut model = Model::new(0);
+        for (serial, residue, resseq, name, element, x) in [
+            (1, "ASP", 1, "OD1", "O", 0.0),
+            (2, "ASP", 1, "OD2", "O", 1.0),
+            (3, "LYS", 2, "NZ", "N", 4.0),
+            (4, "ALA", 3, "CB", "C", 2.5),
+        ] {
+            let atom = Atom::new(false, serial, name, x, 0.0, 0.0, 1.0, 20.0, element, 0).unwrap();
+            model.add_atom(atom, "A", (resseq, None), (residue, None));
+        }
+        let mut pdb = PDB::new();
+        pdb.add_model(model);
+
+        let opts = InteractionOptions { hbonds: HbondCriteria::default(), salt_bridge_distance: DEFAULT_SALT_BRIDGE_DISTANCE, disulfide_distance: None };
+        let found = interactions(&pdb, &opts, 0.45);
+        let bridges: Vec<&Interaction> = found.iter().filter(|i| i.kind == InteractionKind::SaltBridge).collect();
+        assert_eq!(bridges.len(), 1);
+        assert_eq!((bridges[0].atom_a.as_str(), bridges[0].atom_b.as_str(), bridges[0].distance), ("OD2", "NZ", 3.0));
+        assert_eq!(bridges[0].residue_b.to_string(), "A:2");
+    }
+}
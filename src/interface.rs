@@ -0,0 +1,177 @@
+use crate::residue::{protein_residues, ResidueKey};
+use pdbtbx::PDB;
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+
+/// Default largest heavy atom distance of residues in contact across chains, in Å.
+pub const DEFAULT_INTERFACE_DISTANCE: f64 = 5.0;
+
+/// Smallest share of residue contacts two interfaces of chains with identical
+/// sequences have in common to be symmetric copies.
+const SYMMETRIC_OVERLAP: f64 = 0.9;
+
+
+/// How chain pairs are enumerated.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct InterfaceOptions {
+    /// Residues of two chains with heavy atoms at most this far apart (in Å)
+    /// are in contact
+    pub distance: f64,
+    /// Pairs of chains with fewer residue contacts are skipped
+    pub min_contacts: usize,
+    /// Keep symmetric copies of an interface instead of only the first
+    pub keep_symmetric: bool,
+}
+
+
+/// One pair of chains with its interface. The graph of the pair holds the
+/// atoms of the interface residues of both chains.
+pub struct ChainPair {
+    pub info: ChainPairInfo,
+    /// Serial numbers of the atoms of the interface residues
+    pub atoms: HashSet<usize>,
+}
+
+
+/// The chains of a pair and the size of their interface, stored in the
+/// graph metadata.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChainPairInfo {
+    pub chains: [String; 2],
+    /// Pairs of residues, one of each chain, in contact
+    pub contacts: usize,
+    /// Residues of each chain in contact with the other
+    pub interface_residues: [usize; 2],
+    /// Chain pairs left out as symmetric copies of this one
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub symmetric_copies: Vec<[String; 2]>,
+}
+
+impl ChainPairInfo {
+    /// Suffix added to output file names, e.g. `pair_H_L`.
+    pub fn suffix(&self) -> String {
+        format!("pair_{}_{}", self.chains[0], self.chains[1])
+    }
+}
+
+
+/// Share of contacts two interfaces have in common, from 0 to 1.
+fn overlap(a: &BTreeSet<(usize, usize)>, b: &BTreeSet<(usize, usize)>) -> f64 {
+    a.intersection(b).count() as f64 / a.union(b).count().max(1) as f64
+}
+
+
+/// Enumerates the pairs of protein chains of the first model with at least
+/// `min_contacts` residue contacts, ordered by chain id. Unless
+/// `keep_symmetric` is set, a pair is left out as a symmetric copy of an
+/// earlier one if their chains have the same sequences, in either order, and
+/// their interfaces share nearly all residue contacts.
+pub fn chain_pairs(pdb: &PDB, opts: &InterfaceOptions) -> Vec<ChainPair> {
+    let residues = protein_residues(pdb);
+    // Position of every residue in its chain, and the sequence of every chain
+    let mut positions = Vec::with_capacity(residues.len());
+    let mut sequences: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (key, residue) in &residues {
+        let sequence = sequences.entry(&key.chain).or_default();
+        positions.push(sequence.len());
+        sequence.push(residue.name().unwrap_or(""));
+    }
+    let mut points = Vec::new();
+    for (i, (_, residue)) in residues.iter().enumerate() {
+        for atom in residue.atoms().filter(|a| a.element().is_some_and(|e| e.symbol() != "H" && e.symbol() != "D")) {
+            points.push(GeomWithData::new([atom.x(), atom.y(), atom.z()], i));
+        }
+    }
+    let tree = RTree::bulk_load(points.clone());
+
+    let mut contacts: BTreeMap<(&str, &str), BTreeSet<(usize, usize)>> = BTreeMap::new();
+    for point in &points {
+        let a = point.data;
+        for neighbor in tree.locate_within_distance(*point.geom(), opts.distance * opts.distance) {
+            let b = neighbor.data;
+            let (key_a, key_b): (&ResidueKey, &ResidueKey) = (&residues[a].0, &residues[b].0);
+            if key_a.chain < key_b.chain {
+                contacts.entry((&key_a.chain, &key_b.chain)).or_default().insert((a, b));
+            }
+        }
+    }
+
+    // Kept pairs with their contacts as positions in the chains
+    let mut kept: Vec<(ChainPair, BTreeSet<(usize, usize)>)> = Vec::new();
+    for ((first, second), pairs) in contacts.into_iter().filter(|(_, pairs)| pairs.len() >= opts.min_contacts) {
+        let positioned: BTreeSet<(usize, usize)> = pairs.iter().map(|(a, b)| (positions[*a], positions[*b])).collect();
+        if !opts.keep_symmetric {
+            let swapped: BTreeSet<(usize, usize)> = positioned.iter().map(|(a, b)| (*b, *a)).collect();
+            let copy_of = kept.iter_mut().find(|(pair, contacts)| {
+                let [a, b] = [&pair.info.chains[0], &pair.info.chains[1]].map(|c| &sequences[c.as_str()]);
+                (sequences[first] == *a && sequences[second] == *b && overlap(&positioned, contacts) >= SYMMETRIC_OVERLAP)
+                    || (sequences[first] == *b && sequences[second] == *a && overlap(&swapped, contacts) >= SYMMETRIC_OVERLAP)
+            });
+            if let Some((representative, _)) = copy_of {
+                representative.info.symmetric_copies.push([first.to_string(), second.to_string()]);
+                continue;
+            }
+        }
+        let interface: [BTreeSet<usize>; 2] = [
+            pairs.iter().map(|(a, _)| *a).collect(),
+            pairs.iter().map(|(_, b)| *b).collect(),
+        ];
+        let pair = ChainPair {
+            info: ChainPairInfo {
+                chains: [first.to_string(), second.to_string()],
+                contacts: pairs.len(),
+                interface_residues: [interface[0].len(), interface[1].len()],
+                symmetric_copies: Vec::new(),
+            },
+            atoms: interface.iter().flatten().flat_map(|i| residues[*i].1.atoms().map(|a| a.serial_number())).collect(),
+        };
+        kept.push((pair, positioned));
+    }
+    kept.into_iter().map(|(pair, _)| pair).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdbtbx::{Atom, Model, StrictnessLevel};
+
+    #[test]
+    fn finds_chain_interfaces() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        let pdb = pdbtbx::open(path, StrictnessLevel::Medium).unwrap().0;
+
+        let pairs = chain_pairs(&pdb, &InterfaceOptions { distance: 100.0, min_contacts: 1, keep_symmetric: false });
+        assert_eq!(pairs.len(), 1);
+        let info = &pairs[0].info;
+        assert_eq!((info.chains.clone(), info.suffix()), (["H".to_string(), "L".to_string()], "pair_H_L".to_string()));
+        assert_eq!(info.contacts, info.interface_residues[0] * info.interface_residues[1]);
+        assert_eq!(pairs[0].atoms.len(), pdb.atom_count());
+
+        let min_contacts = info.contacts + 1;
+        assert!(chain_pairs(&pdb, &InterfaceOptions { distance: 100.0, min_contacts, keep_symmetric: false }).is_empty());
+    }
+
+    #[test]
+    fn skips_symmetric_copies() {
+        // Three glycines in a row, so A-B and B-C are the same interface
+        let mut model = Model::new(0);
+        for (serial, (chain, x)) in [("A", 0.0), ("B", 4.0), ("C", 8.0)].into_iter().enumerate() {
+            let ca = Atom::new(false, serial + 1, "CA", x, 0.0, 0.0, 1.0, 20.0, "C", 0).unwrap();
+            model.add_atom(ca, chain, (1, None), ("GLY", None));
+        }
+        let mut pdb = PDB::new();
+        pdb.add_model(model);
+
+        let mut opts = InterfaceOptions { distance: DEFAULT_INTERFACE_DISTANCE, min_contacts: 1, keep_symmetric: false };
+        let pairs = chain_pairs(&pdb, &opts);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].info.chains, ["A", "B"]);
+        assert_eq!(pairs[0].info.symmetric_copies, vec![["B", "C"]]);
+        opts.keep_symmetric = true;
+        assert_eq!(chain_pairs(&pdb, &opts).len(), 2);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::residue_graph::{ResidueGraphNode, ResidueGraphOptions};
+use crate::{AtomNode, EdgeData, GraphBuilder};
+use petgraph::graph::Graph;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+
+
+/// Largest difference (in Å) between an edge's distance and the distance
+/// of its nodes' coordinates.
+const DISTANCE_TOLERANCE: f64 = 1e-6;
+
+/// Length of a node feature vector, if the node has it.
+type FeatureLength = fn(&AtomNode) -> Option<usize>;
+
+
+/// A broken guarantee of the graph builder. Nodes are given by index.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Violation {
+    SelfLoop { node: usize },
+    DuplicateEdge { source: usize, target: usize },
+    /// Edge without a reverse edge with the same data
+    Asymmetric { source: usize, target: usize },
+    /// Node with more outgoing edges than the neighbour cap
+    TooManyNeighbors { node: usize, neighbors: usize, max: usize },
+    BeyondCutoff { source: usize, target: usize, distance: f64 },
+    /// Edge distance that isn't the distance between the node coordinates
+    WrongDistance { source: usize, target: usize, distance: f64, actual: f64 },
+    /// Node feature vector of a different length than on the other nodes
+    FeatureLength { node: usize, feature: &'static str, length: usize, expected: usize },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::SelfLoop { node } => write!(f, "self-loop on node {}", node),
+            Violation::DuplicateEdge { source, target } => write!(f, "duplicate edge {} -> {}", source, target),
+            Violation::Asymmetric { source, target } => write!(f, "edge {} -> {} has no matching reverse edge", source, target),
+            Violation::TooManyNeighbors { node, neighbors, max } => write!(f, "node {} has {} neighbours, more than {}", node, neighbors, max),
+            Violation::BeyondCutoff { source, target, distance } => write!(f, "edge {} -> {} is {:.3} Å long, beyond the cutoff", source, target, distance),
+            Violation::WrongDistance { source, target, distance, actual } => {
+                write!(f, "edge {} -> {} has distance {:.3} Å but its nodes are {:.3} Å apart", source, target, distance, actual)
+            }
+            Violation::FeatureLength { node, feature, length, expected } => {
+                write!(f, "{} of node {} has {} values, other nodes {}", feature, node, length, expected)
+            }
+        }
+    }
+}
+
+
+/// Checks that a graph has no self-loops or duplicate edges and, if
+/// `symmetric`, that every edge has a reverse edge with the same data,
+/// which holds for every graph graphein builds without a neighbour cap.
+pub fn check_structure<N, E: PartialEq>(graph: &Graph<N, E>, symmetric: bool) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut edges: HashMap<(usize, usize), &E> = HashMap::new();
+    for edge in graph.raw_edges() {
+        let (source, target) = (edge.source().index(), edge.target().index());
+        if source == target {
+            violations.push(Violation::SelfLoop { node: source });
+        }
+        if edges.insert((source, target), &edge.weight).is_some() {
+            violations.push(Violation::DuplicateEdge { source, target });
+        }
+    }
+    for (&(source, target), weight) in edges.iter().filter(|_| symmetric) {
+        if edges.get(&(target, source)) != Some(weight) {
+            violations.push(Violation::Asymmetric { source, target });
+        }
+    }
+    violations.sort_by_key(|v| match v {
+        Violation::Asymmetric { source, target } | Violation::DuplicateEdge { source, target } => (*source, *target),
+        Violation::SelfLoop { node } => (*node, *node),
+        _ => (0, 0),
+    });
+    violations
+}
+
+
+/// Checks an atom graph built by `builder`: the structural invariants,
+/// symmetric edges or, with a neighbour cap, at most that many outgoing
+/// edges per node besides detected interactions, edge distances within the
+/// cutoff (except for k-nearest-neighbour graphs and detected interactions,
+/// which may be longer) and matching the node coordinates where nodes have
+/// them, and node feature vectors of the same length on every node.
+pub fn check(graph: &Graph<AtomNode, EdgeData>, builder: &GraphBuilder) -> Vec<Violation> {
+    let cap = builder.knn.or(builder.max_neighbors);
+    let mut violations = check_structure(graph, cap.is_none());
+    if let Some(max) = cap {
+        for node in graph.node_indices() {
+            let neighbors = graph.edges(node).filter(|e| e.weight().edge_kind.is_none()).count();
+            if neighbors > max {
+                violations.push(Violation::TooManyNeighbors { node: node.index(), neighbors, max });
+            }
+        }
+    }
+    for edge in graph.raw_edges() {
+        let (source, target) = (edge.source().index(), edge.target().index());
+        let distance = edge.weight.distance;
+        if builder.knn.is_none() && edge.weight.edge_kind.is_none() && exceeds(distance, builder.cutoff) {
+            violations.push(Violation::BeyondCutoff { source, target, distance });
+        }
+        if let (Some(a), Some(b)) = (graph[edge.source()].pos, graph[edge.target()].pos) {
+            let actual = ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt();
+            if exceeds((distance - actual).abs(), DISTANCE_TOLERANCE) {
+                violations.push(Violation::WrongDistance { source, target, distance, actual });
+            }
+        }
+    }
+    let lengths: [(&'static str, FeatureLength); 2] = [
+        ("embedding", |n| n.embedding.as_ref().map(|e| e.len())),
+        ("profile", |n| n.conservation.as_ref().map(|c| c.profile.len())),
+    ];
+    for (feature, length) in lengths {
+        let mut expected = None;
+        for (node, weight) in graph.node_weights().enumerate() {
+            let Some(length) = length(weight) else { continue };
+            match expected {
+                None => expected = Some(length),
+                Some(expected) if expected != length => violations.push(Violation::FeatureLength { node, feature, length, expected }),
+                _ => {}
+            }
+        }
+    }
+    violations
+}
+
+
+/// Checks a residue graph built with `options`: the structural invariants
+/// and edge distances within the cutoff.
+pub fn check_residues(graph: &Graph<ResidueGraphNode, f64>, options: &ResidueGraphOptions) -> Vec<Violation> {
+    let mut violations = check_structure(graph, true);
+    for edge in graph.raw_edges().iter().filter(|e| exceeds(e.weight, options.cutoff)) {
+        violations.push(Violation::BeyondCutoff { source: edge.source().index(), target: edge.target().index(), distance: edge.weight });
+    }
+    violations
+}
+
+
+/// Whether `value` is above `limit` or not a number.
+fn exceeds(value: f64, limit: f64) -> bool {
+    value.is_nan() || value > limit
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden::FIXTURES;
+    use crate::hbond::HbondCriteria;
+    use crate::{EdgeKind, NodeFeature};
+    use pdbtbx::{Atom, Chain, Conformer, Model, Residue, PDB};
+    use petgraph::graph::NodeIndex;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// Random cloud of carbon, nitrogen and oxygen atoms, with some atoms
+    /// at identical positions.
+    fn random_structure(rng: &mut StdRng, atoms: usize) -> PDB {
+        let mut chain = Chain::new("A").unwrap();
+        let mut position = [0.0; 3];
+        for i in 0..atoms {
+            let mut residue = Residue::new(i as isize + 1, None, None).unwrap();
+            let mut conformer = Conformer::new("LIG", None, None).unwrap();
+            let element = ["C", "N", "O"][rng.gen_range(0..3)];
+            if rng.gen_bool(0.9) {
+                position = [0; 3].map(|_| rng.gen_range(0.0..8.0));
+            }
+            let [x, y, z] = position;
+            conformer.add_atom(Atom::new(true, i + 1, element, x, y, z, 1.0, 20.0, element, 0).unwrap());
+            residue.add_conformer(conformer);
+            chain.add_residue(residue);
+        }
+        let mut model = Model::new(0);
+        model.add_chain(chain);
+        let mut pdb = PDB::new();
+        pdb.add_model(model);
+        pdb
+    }
+
+    #[test]
+    fn built_graphs_keep_invariants() {
+        let mut rng = StdRng::seed_from_u64(266);
+        let mut structures: Vec<PDB> = FIXTURES.iter().map(|f| f.structure().unwrap()).collect();
+        structures.extend((0..20).map(|_| random_structure(&mut rng, 60)));
+        for (i, pdb) in structures.iter().enumerate() {
+            let mut builder = GraphBuilder::new().cutoff(rng.gen_range(1.0..6.0));
+            if rng.gen_bool(0.3) {
+                builder = builder.max_neighbors(rng.gen_range(1..8));
+            }
+            if rng.gen_bool(0.3) {
+                builder = builder.knn(rng.gen_range(1..8));
+            }
+            if rng.gen_bool(0.5) {
+                builder = builder.hbonds(HbondCriteria::default());
+            }
+            if rng.gen_bool(0.3) {
+                builder = builder.features(&[NodeFeature::Valence]);
+            }
+            let graph = builder.build_graph(pdb);
+            assert_eq!(check(&graph, &builder), [], "structure {} with {:?}", i, builder);
+        }
+
+        let mut broken = GraphBuilder::new().build_graph(&structures[0]);
+        let edge = EdgeData { distance: 1.0, bond_type: crate::BondType::Contact, bond_order: None, edge_kind: Some(EdgeKind::Hbond), constants: None, cutoff: None };
+        broken.add_edge(NodeIndex::new(0), NodeIndex::new(0), edge);
+        let violations = check(&broken, &GraphBuilder::new());
+        assert!(violations.contains(&Violation::SelfLoop { node: 0 }));
+        assert!(violations.contains(&Violation::WrongDistance { source: 0, target: 0, distance: 1.0, actual: 0.0 }));
+    }
+}
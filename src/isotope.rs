@@ -0,0 +1,73 @@
+use pdbtbx::{Element, PDB};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+
+/// Hydrogen isotope of an atom, by its element symbol.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Isotope {
+    #[serde(rename = "D")]
+    Deuterium,
+    #[serde(rename = "T")]
+    Tritium,
+}
+
+impl Isotope {
+    pub const SYMBOLS: [&'static str; 2] = ["D", "T"];
+
+    /// Isotope of an atom named `name`, from the first letter after the
+    /// digits that some files put in front of hydrogen names, e.g. `1DG1`.
+    fn from_atom_name(name: &str) -> Option<Isotope> {
+        match name.trim_start_matches(|c: char| c.is_ascii_digit()).chars().next() {
+            Some('D') => Some(Isotope::Deuterium),
+            Some('T') => Some(Isotope::Tritium),
+            _ => None,
+        }
+    }
+}
+
+
+/// Turns the deuterium and tritium atoms of `pdb`, common in neutron
+/// structures, into hydrogens so they are featurized as such. pdbtbx knows
+/// no element D or T and leaves these atoms without an element, so they are
+/// recognized by their names. Returns the isotope of each of them, keyed by
+/// serial number. Other isotopes are written with the symbol of their
+/// element and need no handling.
+pub fn resolve_isotopes(pdb: &mut PDB) -> HashMap<usize, Isotope> {
+    let mut isotopes = HashMap::new();
+    for atom in pdb.atoms_mut().filter(|a| a.element().is_none()) {
+        if let Some(isotope) = Isotope::from_atom_name(atom.name()) {
+            atom.set_element(Element::H);
+            isotopes.insert(atom.serial_number(), isotope);
+        }
+    }
+    isotopes
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdbtbx::StrictnessLevel;
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn deuterium_becomes_hydrogen() {
+        let text = "\
+ATOM      1  N   GLY A   1       0.000   0.000   0.000  1.00 10.00           N
+ATOM      2  D   GLY A   1       1.010   0.000   0.000  1.00 10.00           D
+ATOM      3  CA  GLY A   1      -0.700   1.200   0.000  1.00 10.00           C
+ATOM      4  DA2 GLY A   1      -0.200   2.000   0.400  1.00 10.00           D
+ATOM      5  HA3 GLY A   1      -1.700   1.200   0.400  1.00 10.00           H
+END
+";
+        let reader = BufReader::new(Cursor::new(text.as_bytes()));
+        let (mut pdb, _) = pdbtbx::open_pdb_raw(reader, pdbtbx::Context::none(), StrictnessLevel::Medium).unwrap();
+        assert_eq!(pdb.atoms().filter(|a| a.element().is_none()).count(), 2);
+        let isotopes = resolve_isotopes(&mut pdb);
+        assert_eq!(isotopes, HashMap::from([(2, Isotope::Deuterium), (4, Isotope::Deuterium)]));
+        assert!(pdb.atoms().all(|a| a.element().is_some()));
+        assert_eq!(pdb.atoms().filter(|a| a.element() == Some(&Element::H)).count(), 3);
+        assert_eq!(Isotope::from_atom_name("1TG1"), Some(Isotope::Tritium));
+    }
+}
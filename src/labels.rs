@@ -0,0 +1,133 @@
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+
+/// GO terms and EC numbers of a chain or structure.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Labels {
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub go: BTreeSet<String>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub ec: BTreeSet<String>,
+}
+
+impl Labels {
+    pub fn is_empty(&self) -> bool {
+        self.go.is_empty() && self.ec.is_empty()
+    }
+
+    fn extend(&mut self, other: &Labels) {
+        self.go.extend(other.go.iter().cloned());
+        self.ec.extend(other.ec.iter().cloned());
+    }
+}
+
+
+/// Function labels of a structure, stored in the graph metadata.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StructureLabels {
+    /// Labels of all chains of the graph
+    #[serde(flatten)]
+    pub labels: Labels,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub chains: BTreeMap<String, Labels>,
+}
+
+
+/// Labels by lowercase PDB id and chain, `None` for rows without a chain.
+#[derive(Debug, Default)]
+pub struct LabelTable {
+    labels: HashMap<String, BTreeMap<Option<String>, Labels>>,
+}
+
+
+fn column(header: &[&str], names: &[&str]) -> Option<usize> {
+    header.iter().position(|h| names.iter().any(|n| h.eq_ignore_ascii_case(n)))
+}
+
+
+impl LabelTable {
+    /// Adds the rows of an annotation table such as SIFTS' `pdb_chain_go.tsv`
+    /// and `pdb_chain_enzyme.tsv`: tab separated (comma separated for `.csv`
+    /// files) with a header naming a `PDB` column, optionally a `CHAIN`
+    /// column, and `GO_ID` and/or `EC_NUMBER` columns. Lines starting with
+    /// `#` before the header are skipped.
+    pub fn add(&mut self, text: &str, delimiter: char) -> Result<()> {
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty()).skip_while(|l| l.starts_with('#'));
+        let header: Vec<&str> = lines.next().context("empty annotation table")?.split(delimiter).map(|h| h.trim()).collect();
+        let pdb = column(&header, &["PDB", "PDB_ID"]).context("annotation table has no PDB column")?;
+        let chain = column(&header, &["CHAIN", "CHAIN_ID"]);
+        let go = column(&header, &["GO_ID", "GO"]);
+        let ec = column(&header, &["EC_NUMBER", "EC"]);
+        if go.is_none() && ec.is_none() {
+            bail!("annotation table has neither a GO_ID nor an EC_NUMBER column");
+        }
+        for line in lines {
+            let fields: Vec<&str> = line.split(delimiter).map(|f| f.trim()).collect();
+            let Some(id) = fields.get(pdb) else { continue };
+            let chain = chain.and_then(|c| fields.get(c)).map(|c| c.to_string());
+            let labels = self.labels.entry(id.to_ascii_lowercase()).or_default().entry(chain).or_default();
+            if let Some(term) = go.and_then(|c| fields.get(c)).filter(|t| !t.is_empty()) {
+                labels.go.insert(term.to_string());
+            }
+            if let Some(number) = ec.and_then(|c| fields.get(c)).filter(|n| !n.is_empty()) {
+                labels.ec.insert(number.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads an annotation table, see `add`.
+    pub fn read(&mut self, path: &Path) -> Result<()> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let delimiter = if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("csv")) { ',' } else { '\t' };
+        self.add(&text, delimiter).with_context(|| format!("invalid annotation table {}", path.display()))
+    }
+
+    /// Number of structures with labels.
+    pub fn structures(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Labels of the given chains of a structure, `None` if it has none.
+    /// Rows without a chain apply to the whole structure.
+    pub fn get(&self, pdb_id: &str, chains: &[&str]) -> Option<StructureLabels> {
+        let rows = self.labels.get(&pdb_id.to_ascii_lowercase())?;
+        let mut structure = StructureLabels { labels: rows.get(&None).cloned().unwrap_or_default(), chains: BTreeMap::new() };
+        for chain in chains {
+            if let Some(labels) = rows.get(&Some(chain.to_string())) {
+                structure.labels.extend(labels);
+                structure.chains.insert(chain.to_string(), labels.clone());
+            }
+        }
+        Some(structure).filter(|s| !s.labels.is_empty())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_sifts_tables() {
+        let mut table = LabelTable::default();
+        let go = "\
+# 2024/01/01 - 12:00 | PDB: 01.24 | UniProt: 2024.01
+PDB\tCHAIN\tSP_PRIMARY\tWITH_STRING\tEVIDENCE\tGO_ID
+1abc\tA\tP00001\tIEA\tIEA\tGO:0005524
+1abc\tB\tP00002\tIEA\tIEA\tGO:0004672
+";
+        table.add(go, '\t').unwrap();
+        table.add("PDB,CHAIN,ACCESSION,EC_NUMBER\n1ABC,A,P00001,2.7.11.1\n", ',').unwrap();
+
+        let labels = table.get("1abc", &["A"]).unwrap();
+        assert_eq!(labels.labels.go, BTreeSet::from(["GO:0005524".to_string()]));
+        assert_eq!(labels.chains["A"].ec, BTreeSet::from(["2.7.11.1".to_string()]));
+        assert_eq!(labels.chains.len(), 1);
+        assert!(table.get("1abc", &["C"]).is_none());
+        assert!(table.add("PDB\tCHAIN\n", '\t').is_err());
+    }
+}
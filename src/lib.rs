@@ -0,0 +1,854 @@
+//! Turns molecular structures into featurized graphs of atoms. Atoms are
+//! nodes and atoms closer than a cutoff are connected by edges carrying
+//! their distance and whether they are covalently bonded.
+//!
+//! ```no_run
+//! use graphein::{GraphBuilder, NodeFeature};
+//!
+//! let (pdb, _) = pdbtbx::open("1abc.pdb", pdbtbx::StrictnessLevel::Medium).unwrap();
+//! let graph = GraphBuilder::new()
+//!     .cutoff(4.5)
+//!     .max_neighbors(16)
+//!     .features(&[NodeFeature::Electronegativity])
+//!     .build_graph(&pdb);
+//! println!("{} nodes, {} edges", graph.node_count(), graph.edge_count() / 2);
+//! ```
+
+use pdbtbx::*;
+use petgraph::{graph::Graph, graph::NodeIndex};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+pub mod align;
+pub mod altloc;
+pub mod atomic;
+pub mod bcif;
+pub mod bond_order;
+pub mod canonical;
+pub mod ccd;
+pub mod chunk;
+pub mod composition;
+pub mod config;
+pub mod conservation;
+pub mod constants;
+pub mod contact_map;
+pub mod contacts;
+pub mod correspond;
+pub mod degenerate;
+pub mod disulfide;
+pub mod dot;
+pub mod dssp;
+pub mod embeddings;
+pub mod entity;
+pub mod external;
+pub mod fetch;
+pub mod fingerprint;
+pub mod golden;
+pub mod foldcomp;
+pub mod hbond;
+pub mod interactions;
+pub mod interface;
+pub mod invariants;
+pub mod isotope;
+pub mod ident;
+pub mod index;
+pub mod input;
+pub mod inspect;
+pub mod labels;
+pub mod manifest;
+pub mod memory;
+pub mod mirror;
+pub mod mmtf;
+pub mod msgpack;
+pub mod npy;
+pub mod packed;
+pub mod pairs;
+pub mod pdbqt;
+pub mod pointcloud;
+pub mod prediction;
+pub mod pyg;
+pub mod quality;
+pub mod quantize;
+pub mod radius;
+pub mod residue;
+pub mod residue_graph;
+pub mod sanity;
+pub mod sasa;
+pub mod schema;
+pub mod selection;
+pub mod scores;
+pub mod sequence;
+pub mod sifts;
+pub mod stats;
+pub mod tables;
+pub mod temporal;
+pub mod thin;
+pub mod topology;
+pub mod trim;
+pub mod typing;
+pub mod voxel;
+
+use altloc::AltlocResolution;
+
+
+/// Default edge cutoff in Å.
+pub const DEFAULT_CUTOFF: f64 = 3.5;
+
+/// Default slack in Å added to the sum of covalent radii when inferring bonds.
+pub const DEFAULT_BOND_TOLERANCE: f64 = 0.45;
+
+
+/// Van der Waals radius in Å, `None` for elements without a tabulated
+/// value. See `radius::Radii` for the handling of those.
+pub fn van_der_waals_radius(element: &Element) -> Option<f64> {
+    let radius = match element {
+        Element::C => 1.70,
+        Element::H => 1.20,
+        Element::N => 1.55,
+        Element::O => 1.52,
+        Element::P => 1.80,
+        Element::S => 1.80,
+        Element::Ca => 2.31,
+        Element::K => 2.75,
+        Element::Na => 2.27,
+        Element::Cl => 1.75,
+        Element::Mg => 1.73,
+        Element::I => 1.98,
+        Element::Se => 1.90,
+        Element::Cu => 1.40,
+        Element::F => 1.47,
+        Element::Br => 1.85,
+        _ => return None,
+    };
+    Some(radius)
+}
+
+/// Atomic number, known for every element.
+pub fn atomic_number(element: &Element) -> u8 {
+    element.atomic_number() as u8
+}
+
+/// Valence electrons, `None` for elements without a tabulated value.
+pub fn valence_electrons(element: &Element) -> Option<u8> {
+    let valence = match element {
+        Element::H => 1,
+        Element::C => 4,
+        Element::N => 5,
+        Element::O => 6,
+        Element::F => 7,
+        Element::Na => 1,
+        Element::Mg => 2,
+        Element::P => 5,
+        Element::S => 6,
+        Element::Cl => 7,
+        Element::K => 1,
+        Element::Ca => 2,
+        Element::Cu => 1, // Typically in a +2 oxidation state, it loses 2 electrons
+        Element::Br => 7,
+        Element::Se => 6,
+        Element::I => 7,
+        _ => return None,
+    };
+    Some(valence)
+}
+
+/// Pauling electronegativity, `None` for elements without a tabulated value.
+pub fn electronegativity(element: &Element) -> Option<f64> {
+    let electronegativity = match element {
+        Element::H => 2.20,
+        Element::C => 2.55,
+        Element::N => 3.04,
+        Element::O => 3.44,
+        Element::F => 3.98,
+        Element::Na => 0.93,
+        Element::Mg => 1.31,
+        Element::P => 2.19,
+        Element::S => 2.58,
+        Element::Cl => 3.16,
+        Element::K => 0.82,
+        Element::Ca => 1.00,
+        Element::Cu => 1.90,
+        Element::Br => 2.96,
+        Element::Se => 2.55,
+        Element::I => 2.66,
+        _ => return None,
+    };
+    Some(electronegativity)
+}
+
+
+/// Featurized atom.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AtomNode {
+    pub id: usize,
+    pub atom_number: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valence: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub electronegativity: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub charge: Option<isize>,
+    /// Cartesian coordinates in Å
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pos: Option<[f64; 3]>,
+    /// Temperature factor from the structure file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bfactor: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub occupancy: Option<f64>,
+    /// Chain, residue and atom name of the atom
+    #[serde(default, skip_serializing_if = "Option::is_none", flatten)]
+    pub identity: Option<residue::AtomIdentity>,
+    /// Solvent accessible surface area of the atom and its residue
+    #[serde(default, skip_serializing_if = "Option::is_none", flatten)]
+    pub sasa: Option<sasa::AtomSasa>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub altloc: Option<AltlocResolution>,
+    /// Hydrogen isotope of hydrogen nodes from deuterium or tritium atoms
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isotope: Option<isotope::Isotope>,
+    /// Chemical Component Dictionary data of atoms of resolved hetero residues
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ccd: Option<ccd::CcdAtom>,
+    /// Node belongs to a neighbouring chunk and is only included for context
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub halo: bool,
+    /// Structure the node came from in a merged graph
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structure_id: Option<String>,
+    /// Partial charge and atom type of PDBQT inputs
+    #[serde(default, skip_serializing_if = "Option::is_none", flatten)]
+    pub docking: Option<pdbqt::DockingAtom>,
+    /// Scores of the node's residue from a modeling output's score table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scores: Option<BTreeMap<String, f64>>,
+    /// Predicted confidence of the node's residue
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plddt: Option<f64>,
+    /// Precomputed language model embedding of the node's residue
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+    /// Conservation and profile of the node's residue
+    #[serde(default, skip_serializing_if = "Option::is_none", flatten)]
+    pub conservation: Option<conservation::ResidueConservation>,
+    /// UniProt residue of the node's residue from SIFTS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uniprot: Option<sifts::UniprotResidue>,
+    /// Externally computed features of the node's residue by name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub residue_features: Option<BTreeMap<String, f64>>,
+    /// Secondary structure of the node's residue
+    #[serde(default, skip_serializing_if = "Option::is_none", flatten)]
+    pub secondary_structure: Option<dssp::ResidueSecondaryStructure>,
+    /// Atom type under the scheme of `--atom-types`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chemical_type: Option<String>,
+    /// Features of the config's `[node-constants]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constants: Option<BTreeMap<String, f64>>,
+}
+
+impl AtomNode {
+    /// Whether the node has each of the features that can be missing, by
+    /// feature name.
+    pub fn feature_presence(&self) -> [(&'static str, bool); 17] {
+        [
+            ("valence", self.valence.is_some()),
+            ("electronegativity", self.electronegativity.is_some()),
+            ("charge", self.charge.is_some()),
+            ("pos", self.pos.is_some()),
+            ("bfactor", self.bfactor.is_some()),
+            ("occupancy", self.occupancy.is_some()),
+            ("identity", self.identity.is_some()),
+            ("sasa", self.sasa.is_some()),
+            ("partial_charge", self.docking.is_some()),
+            ("scores", self.scores.is_some()),
+            ("plddt", self.plddt.is_some()),
+            ("embedding", self.embedding.is_some()),
+            ("conservation", self.conservation.is_some()),
+            ("uniprot", self.uniprot.is_some()),
+            ("residue_features", self.residue_features.is_some()),
+            ("secondary_structure", self.secondary_structure.is_some()),
+            ("chemical_type", self.chemical_type.is_some()),
+        ]
+    }
+}
+
+
+/// Boolean masks of the features that are missing on some but not all
+/// nodes, by feature name, `true` where the node has the feature. Features
+/// without a mask are present on every node or on none.
+pub fn feature_masks<'a>(nodes: impl IntoIterator<Item = &'a AtomNode>) -> BTreeMap<String, Vec<bool>> {
+    let mut masks: BTreeMap<String, Vec<bool>> = BTreeMap::new();
+    for (count, node) in nodes.into_iter().enumerate() {
+        for (name, present) in node.feature_presence() {
+            if present || masks.contains_key(name) {
+                masks.entry(name.to_string()).or_insert_with(|| vec![false; count]).push(present);
+            }
+        }
+    }
+    masks.retain(|_, mask| mask.contains(&false));
+    masks
+}
+
+
+/// Features of `required` (by their names in masks) missing on some nodes,
+/// with the number of nodes missing them.
+pub fn missing_features<'a>(nodes: impl IntoIterator<Item = &'a AtomNode>, required: &[&str]) -> Vec<(String, usize)> {
+    let mut missing: BTreeMap<&str, usize> = BTreeMap::new();
+    for node in nodes {
+        for (name, present) in node.feature_presence() {
+            if !present && required.contains(&name) {
+                *missing.entry(name).or_default() += 1;
+            }
+        }
+    }
+    missing.into_iter().map(|(name, count)| (name.to_string(), count)).collect()
+}
+
+
+/// Kind of interaction an edge stands for.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum BondType {
+    /// Atoms closer than the sum of their covalent radii plus the tolerance
+    Covalent,
+    /// Atoms within the cutoff but not bonded
+    Contact,
+}
+
+
+/// Specific interaction an edge was detected as, on top of its bond type.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    Hbond,
+    /// Bond between the SG atoms of two cysteines
+    Disulfide,
+}
+
+
+/// Edge data of atom graphs.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EdgeData {
+    /// Distance between the atoms in Å
+    pub distance: f64,
+    pub bond_type: BondType,
+    /// Order of covalent bonds, if assigned and known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bond_order: Option<bond_order::BondOrder>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edge_kind: Option<EdgeKind>,
+    /// Features of the config's `[edge-constants]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constants: Option<BTreeMap<String, f64>>,
+    /// Smallest of several cutoffs the atoms are within, if built with them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cutoff: Option<f64>,
+}
+
+
+/// Node features that can be left out of the graph. The node id and atomic
+/// number are always included.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeFeature {
+    Valence,
+    Electronegativity,
+    /// Formal charge from the structure file
+    Charge,
+    /// Atom coordinates, for equivariant models
+    Coordinates,
+    /// Temperature factor, a flexibility signal
+    Bfactor,
+    /// Occupancy, a quality signal
+    Occupancy,
+    /// Chain, residue name and number, and atom name, to map nodes back to
+    /// the structure
+    Identity,
+    /// Solvent accessible surface area of the atom and its residue
+    Sasa,
+}
+
+
+impl NodeFeature {
+    /// Name of the feature in nodes and masks.
+    pub fn name(self) -> &'static str {
+        match self {
+            NodeFeature::Valence => "valence",
+            NodeFeature::Electronegativity => "electronegativity",
+            NodeFeature::Charge => "charge",
+            NodeFeature::Coordinates => "pos",
+            NodeFeature::Bfactor => "bfactor",
+            NodeFeature::Occupancy => "occupancy",
+            NodeFeature::Identity => "identity",
+            NodeFeature::Sasa => "sasa",
+        }
+    }
+}
+
+
+/// Per-node data worked out while preparing a structure, looked up by atom
+/// serial number when its graph is built.
+#[derive(Default)]
+pub struct NodeAnnotations {
+    pub altlocs: HashMap<usize, AltlocResolution>,
+    pub isotopes: HashMap<usize, isotope::Isotope>,
+    pub ccd: ccd::CcdAnnotations,
+    pub docking: HashMap<usize, pdbqt::DockingAtom>,
+    pub scores: HashMap<usize, BTreeMap<String, f64>>,
+    pub plddt: HashMap<usize, f64>,
+    pub embeddings: HashMap<usize, Vec<f32>>,
+    pub conservation: HashMap<usize, conservation::ResidueConservation>,
+    pub uniprot: HashMap<usize, sifts::UniprotResidue>,
+    pub residue_features: HashMap<usize, BTreeMap<String, f64>>,
+    pub sasa: HashMap<usize, sasa::AtomSasa>,
+    pub secondary_structure: HashMap<usize, dssp::ResidueSecondaryStructure>,
+    pub chemical_types: HashMap<usize, String>,
+}
+
+
+/// Graph construction settings.
+#[derive(Debug, Clone)]
+pub struct GraphBuilder {
+    /// Atoms at most this far apart (in Å) are connected
+    pub cutoff: f64,
+    /// Keep only edges to the k nearest neighbours within the cutoff, if set
+    pub max_neighbors: Option<usize>,
+    /// Connect every atom to its k nearest neighbours at any distance
+    /// instead of the atoms within the cutoff, if set
+    pub knn: Option<usize>,
+    /// Optional node features to compute
+    pub features: Vec<NodeFeature>,
+    /// Slack (in Å) on the sum of covalent radii below which atoms are bonded
+    pub bond_tolerance: f64,
+    /// Detect hydrogen bonds with these criteria, if set
+    pub hbonds: Option<hbond::HbondCriteria>,
+    /// Largest SG–SG distance (in Å) of disulfide bridges, not detected if unset
+    pub disulfide_distance: Option<f64>,
+    /// Assign bond orders to covalent edges
+    pub bond_orders: bool,
+    /// User-defined node and edge features
+    pub constants: constants::Constants,
+    /// Keep only the candidate edges this filter keeps, if set
+    pub edge_filter: Option<constants::EdgeFilter>,
+    /// Label every edge with the smallest of these cutoffs (in Å, ascending)
+    /// it is within, if not empty. The largest is `cutoff`.
+    pub cutoffs: Vec<f64>,
+}
+
+impl Default for GraphBuilder {
+    fn default() -> GraphBuilder {
+        GraphBuilder {
+            cutoff: DEFAULT_CUTOFF,
+            max_neighbors: None,
+            knn: None,
+            features: vec![NodeFeature::Valence, NodeFeature::Electronegativity, NodeFeature::Charge, NodeFeature::Coordinates],
+            bond_tolerance: DEFAULT_BOND_TOLERANCE,
+            hbonds: None,
+            disulfide_distance: Some(disulfide::DEFAULT_DISULFIDE_DISTANCE),
+            bond_orders: false,
+            constants: constants::Constants::default(),
+            edge_filter: None,
+            cutoffs: Vec::new(),
+        }
+    }
+}
+
+impl GraphBuilder {
+    /// Builder with the default cutoff and the Valence, Electronegativity,
+    /// Charge and Coordinates features.
+    pub fn new() -> GraphBuilder {
+        GraphBuilder::default()
+    }
+
+    pub fn cutoff(mut self, cutoff: f64) -> GraphBuilder {
+        self.cutoff = cutoff;
+        self
+    }
+
+    pub fn max_neighbors(mut self, k: usize) -> GraphBuilder {
+        self.max_neighbors = Some(k);
+        self
+    }
+
+    /// Connects every atom to its `k` nearest neighbours, ignoring the cutoff.
+    pub fn knn(mut self, k: usize) -> GraphBuilder {
+        self.knn = Some(k);
+        self
+    }
+
+    pub fn features(mut self, features: &[NodeFeature]) -> GraphBuilder {
+        self.features = features.to_vec();
+        self
+    }
+
+    pub fn bond_tolerance(mut self, tolerance: f64) -> GraphBuilder {
+        self.bond_tolerance = tolerance;
+        self
+    }
+
+    pub fn hbonds(mut self, criteria: hbond::HbondCriteria) -> GraphBuilder {
+        self.hbonds = Some(criteria);
+        self
+    }
+
+    /// Sets the largest SG–SG distance of disulfide bridges, `None` to not
+    /// detect them.
+    pub fn disulfide_distance(mut self, distance: Option<f64>) -> GraphBuilder {
+        self.disulfide_distance = distance;
+        self
+    }
+
+    /// Assigns bond orders to covalent edges, see
+    /// `bond_order::residue_bond_orders`.
+    pub fn bond_orders(mut self, bond_orders: bool) -> GraphBuilder {
+        self.bond_orders = bond_orders;
+        self
+    }
+
+    pub fn constants(mut self, constants: constants::Constants) -> GraphBuilder {
+        self.constants = constants;
+        self
+    }
+
+    pub fn edge_filter(mut self, filter: constants::EdgeFilter) -> GraphBuilder {
+        self.edge_filter = Some(filter);
+        self
+    }
+
+    /// Builds the graph of the largest of several cutoffs with a single
+    /// neighbour search and labels each edge with the smallest cutoff it is
+    /// within, so the graphs of the smaller ones are subgraphs by label.
+    pub fn cutoffs(mut self, mut cutoffs: Vec<f64>) -> GraphBuilder {
+        cutoffs.sort_by(f64::total_cmp);
+        cutoffs.dedup();
+        if let Some(largest) = cutoffs.last() {
+            self.cutoff = *largest;
+        }
+        self.cutoffs = cutoffs;
+        self
+    }
+
+    /// Whether the edge filter keeps a candidate edge between two nodes. The
+    /// node added first is the source, so both directions of an edge are
+    /// kept or dropped together.
+    fn keeps(&self, edge: &EdgeData, contexts: &[constants::AtomContext], a: NodeIndex, b: NodeIndex) -> bool {
+        let (source, target) = (a.min(b), a.max(b));
+        self.edge_filter.as_ref().is_none_or(|filter| filter.keeps(edge, contexts[source.index()], contexts[target.index()]))
+    }
+
+    fn has(&self, feature: NodeFeature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    /// Featurizes an atom of `residue` in `chain`, `None` if its element is
+    /// unknown.
+    pub fn node(&self, chain: &Chain, residue: &Residue, atom: &Atom, annotations: &NodeAnnotations, structure_id: Option<&str>) -> Option<AtomNode> {
+        let ele = atom.element()?;
+        let serial = atom.serial_number();
+        Some(AtomNode {
+            id: serial,
+            atom_number: atomic_number(ele),
+            valence: self.has(NodeFeature::Valence).then(|| valence_electrons(ele)).flatten(),
+            electronegativity: self.has(NodeFeature::Electronegativity).then(|| electronegativity(ele)).flatten(),
+            charge: self.has(NodeFeature::Charge).then(|| atom.charge()),
+            pos: self.has(NodeFeature::Coordinates).then(|| [atom.x(), atom.y(), atom.z()]),
+            bfactor: self.has(NodeFeature::Bfactor).then(|| atom.b_factor()),
+            occupancy: self.has(NodeFeature::Occupancy).then(|| atom.occupancy()),
+            identity: self.has(NodeFeature::Identity).then(|| residue::AtomIdentity::new(chain, residue, atom)),
+            sasa: self.has(NodeFeature::Sasa).then(|| annotations.sasa.get(&serial).copied()).flatten(),
+            altloc: annotations.altlocs.get(&serial).copied(),
+            isotope: annotations.isotopes.get(&serial).copied(),
+            ccd: annotations.ccd.atoms.get(&serial).copied(),
+            halo: false,
+            structure_id: structure_id.map(|s| s.to_string()),
+            docking: annotations.docking.get(&serial).cloned(),
+            scores: annotations.scores.get(&serial).cloned(),
+            plddt: annotations.plddt.get(&serial).copied(),
+            embedding: annotations.embeddings.get(&serial).cloned(),
+            conservation: annotations.conservation.get(&serial).cloned(),
+            uniprot: annotations.uniprot.get(&serial).cloned(),
+            residue_features: annotations.residue_features.get(&serial).cloned(),
+            secondary_structure: annotations.secondary_structure.get(&serial).copied(),
+            chemical_type: annotations.chemical_types.get(&serial).cloned(),
+            constants: self.constants.node_values(constants::AtomContext { chain, residue, atom }),
+        })
+    }
+
+    /// Classifies the edge between two atoms from their single bond
+    /// covalent radii.
+    pub fn bond_type(&self, a: &Atom, b: &Atom) -> BondType {
+        let radii = a.element().zip(b.element()).map(|(x, y)| x.atomic_radius().covalent_single + y.atomic_radius().covalent_single);
+        match radii {
+            Some(radii) if a.distance(b) <= radii + self.bond_tolerance => BondType::Covalent,
+            _ => BondType::Contact,
+        }
+    }
+
+    /// Featurizes every atom of a structure with a known element, in file
+    /// order.
+    pub fn nodes(&self, pdb: &PDB, annotations: &NodeAnnotations) -> Vec<AtomNode> {
+        let mut nodes = Vec::new();
+        for chain in pdb.models().flat_map(|m| m.chains()) {
+            for residue in chain.residues() {
+                nodes.extend(residue.atoms().filter_map(|atom| self.node(chain, residue, atom, annotations, None)));
+            }
+        }
+        nodes
+    }
+
+    /// Builds the graph of a structure, computing the SASA of its atoms if
+    /// requested, with the default radius for unknown elements.
+    pub fn build_graph(&self, pdb: &PDB) -> Graph<AtomNode, EdgeData> {
+        let sasa = match self.has(NodeFeature::Sasa) {
+            true => sasa::atom_sasa(pdb, &mut radius::Radii::default()).expect("unknown elements get a fixed radius"),
+            false => HashMap::new(),
+        };
+        let annotations = NodeAnnotations {
+            sasa,
+            ..Default::default()
+        };
+        self.build_annotated_graph(pdb, &annotations)
+    }
+
+    /// Builds the graph of a structure with additional per-node data.
+    pub fn build_annotated_graph(&self, pdb: &PDB, annotations: &NodeAnnotations) -> Graph<AtomNode, EdgeData> {
+        self.build_merged_graph(&[(pdb, annotations, None)], false)
+    }
+
+    /// Builds one graph over several structures. Nodes are tagged with the
+    /// structure id given next to each structure. Edges between atoms of
+    /// different structures are only added with `inter_structure_edges`, which
+    /// only makes sense when the structures share a coordinate frame.
+    pub fn build_merged_graph(
+        &self,
+        structures: &[(&PDB, &NodeAnnotations, Option<&str>)],
+        inter_structure_edges: bool,
+    ) -> Graph<AtomNode, EdgeData> {
+        let mut protein_graph = Graph::<AtomNode, EdgeData>::new();
+        // Serial numbers repeat across structures, so atoms are identified by address
+        let mut node_of: HashMap<*const Atom, (NodeIndex, usize)> = HashMap::new();
+        let mut atoms: Vec<&Atom> = Vec::new();
+        // Atoms of the nodes by node index, for edge constants and filters
+        let mut contexts: Vec<constants::AtomContext> = Vec::new();
+
+        for (structure, (pdb, annotations, structure_id)) in structures.iter().enumerate() {
+            for chain in pdb.models().flat_map(|m| m.chains()) {
+                for residue in chain.residues() {
+                    for atom in residue.atoms() {
+                        let an = match self.node(chain, residue, atom, annotations, *structure_id) {
+                            Some(an) => an,
+                            None => continue
+                        };
+                        let node_id = protein_graph.add_node(an);
+                        node_of.insert(atom as *const Atom, (node_id, structure));
+                        atoms.push(atom);
+                        contexts.push(constants::AtomContext { chain, residue, atom });
+                    }
+                }
+            }
+        }
+
+        // Orders of bonds within residues; bonds between residues are single
+        let mut bond_orders: HashMap<(*const Atom, *const Atom), bond_order::BondOrder> = HashMap::new();
+        let mut residue_of: HashMap<*const Atom, *const Residue> = HashMap::new();
+        if self.bond_orders {
+            for residue in structures.iter().flat_map(|(pdb, _, _)| pdb.residues()) {
+                residue_of.extend(residue.atoms().map(|a| (a as *const Atom, residue as *const Residue)));
+                for (a, b, order) in bond_order::residue_bond_orders(residue, self.bond_tolerance) {
+                    bond_orders.insert((a, b), order);
+                    bond_orders.insert((b, a), order);
+                }
+            }
+        }
+        let bond_order = |a: &Atom, b: &Atom, bond_type: BondType| {
+            if !self.bond_orders || bond_type != BondType::Covalent {
+                return None;
+            }
+            let (a, b) = (a as *const Atom, b as *const Atom);
+            bond_orders.get(&(a, b)).copied().or((residue_of.get(&a) != residue_of.get(&b)).then_some(bond_order::BondOrder::Single))
+        };
+        // Atoms of a residue resolved in the CCD are bonded as the dictionary says
+        let dictionary_bond = |a: &Atom, b: &Atom, structure: usize| {
+            let ccd = &structures[structure].1.ccd;
+            let (a, b) = (a.serial_number(), b.serial_number());
+            match (ccd.atoms.get(&a), ccd.atoms.get(&b)) {
+                (Some(x), Some(y)) if x.residue == y.residue => Some(ccd.bonds.get(&(a, b)).copied()),
+                _ => None,
+            }
+        };
+
+        let tree = rstar::RTree::bulk_load(atoms.clone());
+        for atom in &atoms {
+            let (atom_node_id, structure) = node_of[&(*atom as *const Atom)];
+            let candidates: Box<dyn Iterator<Item = &&Atom>> = match self.knn {
+                Some(_) => Box::new(tree.nearest_neighbor_iter(&atom.pos())),
+                None => Box::new(tree.locate_within_distance(atom.pos(), self.cutoff * self.cutoff)),
+            };
+            let mut neighbors: Vec<(NodeIndex, EdgeData)> = Vec::new();
+            for neighbor_atom in candidates {
+                if atom.pos() == neighbor_atom.pos() {  // Same atom
+                    continue;
+                };
+                let (node_id, neighbor_structure) = node_of[&(*neighbor_atom as *const Atom)];
+                if structure != neighbor_structure && !inter_structure_edges {
+                    continue;
+                }
+                let dictionary = (structure == neighbor_structure).then(|| dictionary_bond(atom, neighbor_atom, structure)).flatten();
+                let (bond_type, bond_order) = match dictionary {
+                    Some(Some(order)) => (BondType::Covalent, self.bond_orders.then_some(order)),
+                    Some(None) => (BondType::Contact, None),
+                    None => {
+                        let bond_type = self.bond_type(atom, neighbor_atom);
+                        (bond_type, bond_order(atom, neighbor_atom, bond_type))
+                    }
+                };
+                let edge = EdgeData { distance: atom.distance(neighbor_atom), bond_type, bond_order, edge_kind: None, constants: None, cutoff: None };
+                if !self.keeps(&edge, &contexts, atom_node_id, node_id) {
+                    continue;
+                }
+                neighbors.push((node_id, edge));
+                if self.knn == Some(neighbors.len()) {
+                    break;
+                }
+            }
+            if let Some(k) = self.max_neighbors {
+                neighbors.sort_by(|a, b| a.1.distance.total_cmp(&b.1.distance).then(a.0.cmp(&b.0)));
+                neighbors.truncate(k);
+            }
+            for (node_id, edge) in neighbors {
+                protein_graph.update_edge(atom_node_id, node_id, edge);
+            }
+        }
+
+        // Detected interactions are tagged on their edges, or added if longer than the cutoff
+        let pdbs: Vec<&PDB> = structures.iter().map(|(pdb, _, _)| *pdb).collect();
+        let mut tag = |pairs: Vec<(&Atom, &Atom, f64)>, kind: EdgeKind, bond_type: BondType| {
+            for (atom_a, atom_b, distance) in pairs {
+                let (Some((a, _)), Some((b, _))) = (node_of.get(&(atom_a as *const Atom)), node_of.get(&(atom_b as *const Atom))) else {
+                    continue;
+                };
+                let bond_order = bond_order(atom_a, atom_b, bond_type);
+                for (source, target) in [(*a, *b), (*b, *a)] {
+                    match protein_graph.find_edge(source, target) {
+                        Some(e) => protein_graph[e].edge_kind = Some(kind),
+                        None => {
+                            let edge = EdgeData { distance, bond_type, bond_order, edge_kind: Some(kind), constants: None, cutoff: None };
+                            if self.keeps(&edge, &contexts, source, target) {
+                                protein_graph.add_edge(source, target, edge);
+                            }
+                        }
+                    }
+                }
+            }
+        };
+        if let Some(criteria) = &self.hbonds {
+            let bonds = hbond::hydrogen_bonds(&pdbs, criteria, self.bond_tolerance, inter_structure_edges);
+            tag(bonds.iter().map(|b| (b.donor, b.acceptor, b.distance)).collect(), EdgeKind::Hbond, BondType::Contact);
+        }
+        if let Some(max_distance) = self.disulfide_distance {
+            tag(disulfide::disulfides(&pdbs, max_distance, inter_structure_edges), EdgeKind::Disulfide, BondType::Covalent);
+        }
+        if !self.constants.edges.is_empty() {
+            for edge in protein_graph.edge_indices() {
+                let (source, target) = protein_graph.edge_endpoints(edge).unwrap();
+                let values = self.constants.edge_values(&protein_graph[edge], contexts[source.index()], contexts[target.index()]);
+                protein_graph[edge].constants = values;
+            }
+        }
+        if !self.cutoffs.is_empty() {
+            for edge in protein_graph.edge_weights_mut() {
+                edge.cutoff = self.cutoffs.iter().copied().find(|c| edge.distance <= *c);
+            }
+        }
+        protein_graph
+    }
+}
+
+
+/// Keeps the atoms of `pdb` whose index in `pdb.atoms()` order `keep`
+/// accepts and drops what is left empty. Serial numbers restart in every
+/// model of NMR files, so they can't select atoms of multi-model structures.
+pub fn retain_atoms(pdb: &mut PDB, keep: impl Fn(usize) -> bool) {
+    let index = std::cell::Cell::new(0);
+    pdb.remove_atoms_by(|_| {
+        let i = index.replace(index.get() + 1);
+        !keep(i)
+    });
+    pdb.remove_empty();
+}
+
+
+/// 64-bit FNV-1a, used where a short hash must be stable across runs and
+/// platforms.
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, b| (h ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+
+/// Builds the graph of a structure with the default settings.
+pub fn build_graph(pdb: &PDB) -> Graph<AtomNode, EdgeData> {
+    GraphBuilder::new().build_graph(pdb)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_graphs_with_the_configured_settings() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        let pdb = pdbtbx::open(path, StrictnessLevel::Medium).unwrap().0;
+
+        let graph = build_graph(&pdb);
+        assert_eq!(graph.node_count(), pdb.atom_count());
+        assert!(graph.edge_weights().all(|e| e.distance <= DEFAULT_CUTOFF));
+        let bonds = graph.edge_weights().filter(|e| e.bond_type == BondType::Covalent).count();
+        assert!(bonds > 0 && bonds < graph.edge_count());
+        assert!(graph.edge_weights().filter(|e| e.bond_type == BondType::Covalent).all(|e| e.distance < 2.0));
+        assert!(graph.node_weights().all(|n| n.valence.is_some() && n.charge.is_some()));
+
+        let sparse = GraphBuilder::new().cutoff(6.0).max_neighbors(2).features(&[NodeFeature::Charge]).build_graph(&pdb);
+        assert!(sparse.node_indices().all(|n| sparse.neighbors(n).count() <= 4));
+        assert!(sparse.node_weights().all(|n| n.valence.is_none() && n.electronegativity.is_none() && n.charge.is_some()));
+
+        let named = GraphBuilder::new().features(&[NodeFeature::Identity]).build_graph(&pdb);
+        let identities: Vec<&residue::AtomIdentity> = named.node_weights().filter_map(|n| n.identity.as_ref()).collect();
+        assert_eq!(identities.len(), named.node_count());
+        assert!(identities.iter().any(|i| i.chain == "H" && i.insertion_code.as_deref() == Some("A")));
+
+        let knn = GraphBuilder::new().cutoff(1.0).knn(5).build_graph(&pdb);
+        assert!(knn.node_indices().all(|n| knn.edges_directed(n, petgraph::Direction::Outgoing).count() == 5));
+
+        let labeled = GraphBuilder::new().cutoffs(vec![5.0, DEFAULT_CUTOFF]).build_graph(&pdb);
+        assert_eq!(labeled.edge_count(), GraphBuilder::new().cutoff(5.0).build_graph(&pdb).edge_count());
+        assert_eq!(labeled.edge_weights().filter(|e| e.cutoff == Some(DEFAULT_CUTOFF)).count(), graph.edge_count());
+    }
+
+    #[test]
+    fn masks_partially_missing_features() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        let pdb = pdbtbx::open(path, StrictnessLevel::Medium).unwrap().0;
+        let (mut nodes, _) = build_graph(&pdb).into_nodes_edges();
+        nodes.truncate(3);
+        let mut nodes: Vec<AtomNode> = nodes.into_iter().map(|n| n.weight).collect();
+        assert!(feature_masks(&nodes).is_empty());
+
+        nodes[0].valence = None;
+        nodes[2].plddt = Some(90.0);
+        let masks = feature_masks(&nodes);
+        assert_eq!(masks["valence"], vec![false, true, true]);
+        assert_eq!(masks["plddt"], vec![false, false, true]);
+        assert_eq!(masks.len(), 2);
+        assert_eq!(missing_features(&nodes, &["valence", "plddt", "charge"]), vec![("plddt".to_string(), 2), ("valence".to_string(), 1)]);
+    }
+
+    #[test]
+    fn stable_hash_is_fnv1a() {
+        assert_eq!(stable_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(stable_hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(stable_hash(b"foobar"), 0x85944171f73967e8);
+    }
+}
@@ -1,205 +1,3184 @@
-use clap;
 use pdbtbx::*;
-use std::collections::HashMap;
-use petgraph::{graph::Graph, graph::NodeIndex};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use petgraph::graph::Graph;
 use serde::{Serialize, Deserialize};
 use std::fs::File;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use std::io::prelude::*;
-use anyhow::{Result, bail};
-use log::{debug, info, warn};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use anyhow::{Context, Result, bail};
+use log::{debug, error, info, warn};
 use glob::glob;
 use rayon::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
 
+use graphein::{
+    align, altloc, atomic, canonical, ccd, chunk, composition, config, conservation,
+    contact_map, contacts, correspond, degenerate, dot, dssp, embeddings, entity, external,
+    fetch, fingerprint, hbond, ident, index, input, inspect, interactions, interface,
+    invariants, isotope, labels, manifest, memory, mirror, packed, pairs, pdbqt, pointcloud,
+    prediction, pyg, quality, quantize, radius, residue_graph, sanity, sasa, schema, scores,
+    selection, sequence, sifts, stats, tables, temporal, thin, topology, trim, typing, voxel,
+    stable_hash, AtomNode, EdgeData, GraphBuilder, NodeAnnotations, NodeFeature,
+};
 
-fn van_der_waals_radius(element: &Element) -> f64 {
-    match element {
-        Element::C => 1.70,
-        Element::H => 1.20,
-        Element::N => 1.55,
-        Element::O => 1.52,
-        Element::P => 1.80,
-        Element::S => 1.80,
-        Element::Ca => 2.31,
-        Element::K => 2.75,
-        Element::Na => 2.27,
-        Element::Cl => 1.75,
-        Element::Mg => 1.73,
-        Element::I => 1.98,
-        Element::Se => 1.90,
-        Element::Cu => 1.40,
-        Element::F => 1.47,
-        Element::Br => 1.85,
-        _ => 1.8, 
-    }
-}
-
-fn atomic_number(element: &Element) -> u8 {
-    match element {
-        Element::H => 1,
-        Element::C => 6,
-        Element::N => 7,
-        Element::O => 8,
-        Element::F => 9,
-        Element::Na => 11,
-        Element::Mg => 12,
-        Element::P => 15,
-        Element::S => 16,
-        Element::Cl => 17,
-        Element::K => 19,
-        Element::Ca => 20,
-        Element::Cu => 29,
-        Element::Br => 35,
-        Element::Se => 34,
-        Element::I => 53,
-        _ => 0, // Let's assume that 0 means 'unknown'
-    }
-}
-
-fn valence_electrons(element: &Element) -> u8 {
-    match element {
-        Element::H => 1,
-        Element::C => 4,
-        Element::N => 5,
-        Element::O => 6,
-        Element::F => 7,
-        Element::Na => 1,
-        Element::Mg => 2,
-        Element::P => 5,
-        Element::S => 6,
-        Element::Cl => 7,
-        Element::K => 1,
-        Element::Ca => 2,
-        Element::Cu => 1, // Typically in a +2 oxidation state, it loses 2 electrons
-        Element::Br => 7,
-        Element::Se => 6,
-        Element::I => 7,
-        _ => 0, // Let's assume that 0 means 'unknown'
-    }
-}
-
-fn electronegativity(element: &Element) -> f64 {
-    match element {
-        Element::H => 2.20,
-        Element::C => 2.55,
-        Element::N => 3.04,
-        Element::O => 3.44,
-        Element::F => 3.98,
-        Element::Na => 0.93,
-        Element::Mg => 1.31,
-        Element::P => 2.19,
-        Element::S => 2.58,
-        Element::Cl => 3.16,
-        Element::K => 0.82,
-        Element::Ca => 1.00,
-        Element::Cu => 1.90,
-        Element::Br => 2.96,
-        Element::Se => 2.55,
-        Element::I => 2.66,
-        _ => 0.0, // Let's assume that 0 means 'unknown'
-    }
-}
-
-
-#[derive(Serialize, Deserialize, Debug)]
-struct AtomNode {
-    id: usize,
-    atom_number: u8,
-    valence: u8,
-    electronegativity: f64,
-    charge: isize,
-}
-
-
-fn process_pdb_file(fname: &str, edge_max_dist: &f64) -> Result<()> {
-    let (pdb, _errors) = match pdbtbx::open(
-        fname,
-        StrictnessLevel::Medium
-    ) {
-        Ok(pdb) => pdb,
-        Err(e) => bail!("Error parsing pdb file {} - {:?}", fname, e)
-    };
-
-    let tree = pdb.create_atom_rtree();
-    let mut protein_graph = Graph::<AtomNode, f64>::new();
-    let mut atom_sn_node_id: HashMap<usize, NodeIndex> = HashMap::new();
+use align::{AlignAtoms, AlignmentInfo, Reference};
+use altloc::AltlocPolicy;
+use chunk::ChunkInfo;
+use degenerate::Degeneracy;
+use entity::{ChainInfo, EntityType};
+use ident::StructureId;
+use manifest::{GraphSize, Join, JoinProvenance, Manifest, Shard, ShardInfo, SourceFile};
+use prediction::PredictionInfo;
+use sanity::{CoordinateCheck, CoordinateIssue};
+use thin::EdgeSamplingInfo;
+use trim::{TrimInfo, TrimStrategy};
+
+
+/// File format of the outputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    /// Graph with metadata as JSON
+    Json,
+    /// Gaussian density grid as npz, no graph
+    Voxel,
+    /// Atom coordinates and node features as npz, no edges
+    PointCloud,
+    /// Graphviz graph with element labels and edge distances, no features
+    Dot,
+    /// Node features, edge index and edge features as npz for PyTorch Geometric
+    Pyg,
+    /// Nodes and edges tables as Parquet files
+    Parquet,
+    /// Residue contact map or distance matrix as npy, no graph
+    ContactMap,
+    /// Residue contact map or distance matrix as CSV, no graph
+    ContactMapCsv,
+}
+
+impl OutputFormat {
+    /// Appended to the structure id of an input to form its output name,
+    /// unless `--out-template` names outputs.
+    fn suffix(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "_graph.json",
+            OutputFormat::Voxel => "_voxels.npz",
+            OutputFormat::PointCloud => "_points.npz",
+            OutputFormat::Dot => "_graph.dot",
+            OutputFormat::Pyg => "_graph.npz",
+            OutputFormat::Parquet => "_nodes.parquet",
+            OutputFormat::ContactMap => "_contact_map.npy",
+            OutputFormat::ContactMapCsv => "_contact_map.csv",
+        }
+    }
+}
+
+
+/// What the nodes of a graph represent.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum Granularity {
+    /// One node per atom
+    Atom,
+    /// One node per residue
+    Residue,
+}
+
+
+/// A graph variant requested with `--variant NAME=GRANULARITY[:CUTOFF]`.
+#[derive(Clone, Debug)]
+struct VariantSpec {
+    name: String,
+    granularity: Granularity,
+    cutoff: Option<f64>,
+}
+
+
+fn parse_variant(s: &str) -> std::result::Result<VariantSpec, String> {
+    let (name, spec) = s.split_once('=').ok_or("expected NAME=GRANULARITY[:CUTOFF]")?;
+    if name.is_empty() {
+        return Err("variant name is empty".to_string());
+    }
+    let (granularity, cutoff) = match spec.split_once(':') {
+        Some((granularity, cutoff)) => (granularity, Some(cutoff.parse::<f64>().map_err(|e| format!("invalid cutoff {}: {}", cutoff, e))?)),
+        None => (spec, None),
+    };
+    Ok(VariantSpec {
+        name: name.to_string(),
+        granularity: <Granularity as clap::ValueEnum>::from_str(granularity, true)?,
+        cutoff,
+    })
+}
+
+
+/// How a graph variant is built.
+enum VariantGraph {
+    Atom(Box<GraphBuilder>),
+    Residue(residue_graph::ResidueGraphOptions),
+}
+
+
+/// A named graph variant written into a container file.
+struct Variant {
+    name: String,
+    graph: VariantGraph,
+}
+
+
+/// Entry of the variant index in the metadata of a container file.
+#[derive(Serialize, Debug, Clone)]
+struct VariantInfo {
+    name: String,
+    granularity: Granularity,
+    cutoff: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    residue_edges: Option<residue_graph::ResidueEdges>,
+    nodes: usize,
+    edges: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    degenerate: Vec<Degeneracy>,
+}
+
+
+/// Metadata of one graph in a container file.
+#[derive(Serialize)]
+struct VariantMetadata<'a> {
+    name: &'a str,
+    schema: schema::GraphSchema,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    edge_sampling: Option<EdgeSamplingInfo>,
+}
+
+
+/// How atoms are connected by edges.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum EdgeMode {
+    /// Atoms within the cutoff
+    Radius,
+    /// Every atom to its k nearest neighbours
+    Knn,
+}
+
+
+/// How graphs are laid out in JSON outputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum Layout {
+    /// A serialized petgraph graph with an object per node and edge
+    Structs,
+    /// Column-major feature matrices of all nodes and edges
+    Packed,
+}
+
+
+/// Where outputs are written and what they are named.
+#[derive(Debug, Clone, Default)]
+struct OutputNaming {
+    /// Directory of all outputs, next to their inputs if not set
+    dir: Option<PathBuf>,
+    /// Template of output file names, named after the structure id if not set
+    template: Option<String>,
+    /// Edge cutoff filled into the template
+    cutoff: f64,
+}
+
+impl OutputNaming {
+    const PLACEHOLDERS: [&'static str; 4] = ["id", "stem", "parent", "cutoff"];
+
+    fn parse_template(s: &str) -> std::result::Result<String, String> {
+        if s.is_empty() || s.contains(['/', '\\']) {
+            return Err(format!("invalid output template {}, expected a file name", s));
+        }
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            let Some(end) = rest[start..].find('}') else {
+                return Err(format!("unclosed placeholder in output template {}", s));
+            };
+            let name = &rest[start + 1..start + end];
+            if !OutputNaming::PLACEHOLDERS.contains(&name) {
+                return Err(format!("unknown placeholder {{{}}} in output template {}, expected {{id}}, {{stem}}, {{parent}} or {{cutoff}}", name, s));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        Ok(s.to_string())
+    }
+
+    /// Output file name of the input `fname`.
+    fn file_name(&self, fname: &str, format: OutputFormat) -> String {
+        let Some(template) = &self.template else {
+            return format!("{}{}", StructureId::from_path(fname), format.suffix());
+        };
+        let path = Path::new(fname);
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or(fname);
+        let name = name.strip_suffix(".gz").unwrap_or(name);
+        let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+        let parent = path.parent().and_then(|p| p.file_name()).and_then(|n| n.to_str()).unwrap_or("");
+        template
+            .replace("{id}", &StructureId::from_path(fname).to_string())
+            .replace("{stem}", stem)
+            .replace("{parent}", parent)
+            .replace("{cutoff}", &self.cutoff.to_string())
+    }
+}
+
+
+/// Output of the input `fname`, in the output directory or next to the input.
+fn output_path(fname: &str, format: OutputFormat, naming: &OutputNaming) -> String {
+    let name = naming.file_name(fname, format);
+    match &naming.dir {
+        Some(dir) => dir.join(name),
+        None => Path::new(fname).with_file_name(name),
+    }.to_string_lossy().into_owned()
+}
+
+
+/// What to do when several inputs map to the same output path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum CollisionPolicy {
+    /// Refuse to start the run
+    Fail,
+    /// Suffix colliding outputs with a hash of their input path
+    Disambiguate,
+}
+
+
+/// Output paths claimed by more than one input, with the inputs claiming them.
+fn find_collisions<'a>(paths: &'a [String], format: OutputFormat, naming: &OutputNaming) -> BTreeMap<String, Vec<&'a str>> {
+    let mut planned: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for p in paths {
+        planned.entry(output_path(p, format, naming)).or_default().push(p);
+    }
+    planned.retain(|_, inputs| inputs.len() > 1);
+    planned
+}
+
+
+/// Resolves the output path of every input, applying `policy` to collisions.
+/// Disambiguated names only depend on the input path, so they don't change
+/// between runs over different subsets of files.
+fn plan_outputs(paths: &[String], policy: CollisionPolicy, format: OutputFormat, naming: &OutputNaming) -> Result<Vec<String>> {
+    let collisions = find_collisions(paths, format, naming);
+    if !collisions.is_empty() && policy == CollisionPolicy::Fail {
+        let report: Vec<String> = collisions.iter()
+            .map(|(out, inputs)| format!("{} <- {}", out, inputs.join(", ")))
+            .collect();
+        bail!("{} output paths are claimed by multiple inputs:\n{}", collisions.len(), report.join("\n"));
+    }
+    let outputs: Vec<String> = paths.iter().map(|p| {
+        let out = output_path(p, format, naming);
+        if collisions.contains_key(&out) {
+            let (stem, extension) = split_output_path(&out, format);
+            format!("{}_{:08x}{}", stem, stable_hash(p.as_bytes()) as u32, extension)
+        } else {
+            out
+        }
+    }).collect();
+    let inputs: HashSet<&Path> = paths.iter().map(Path::new).collect();
+    if let Some(out) = outputs.iter().find(|out| inputs.contains(Path::new(out.as_str()))) {
+        bail!("The output {} would overwrite an input, see --out-dir and --out-template", out);
+    }
+    Ok(outputs)
+}
+
+
+/// Settings shared by every structure in a run.
+struct Options {
+    graph: GraphBuilder,
+    strictness: StrictnessLevel,
+    retry_loose: bool,
+    coordinate_check: CoordinateCheck,
+    /// Score table columns attached to nodes, all if not set
+    score_columns: Option<Vec<String>>,
+    /// Inputs are structure prediction outputs with per-residue confidence
+    predictions: bool,
+    /// Directory of per-residue embeddings attached to nodes, if set
+    embeddings: Option<PathBuf>,
+    /// Directory of sequence profiles attached to nodes, if set
+    conservation: Option<PathBuf>,
+    /// GO terms and EC numbers joined onto structures by PDB id and chain, if set
+    labels: Option<labels::LabelTable>,
+    /// Directory of SIFTS residue mappings, if set
+    sifts: Option<PathBuf>,
+    /// Externally computed per-residue features, if set
+    residue_features: Option<external::ResidueFeatureTable>,
+    /// Assign secondary structure to the atoms of amino acids
+    secondary_structure: bool,
+    /// Scheme of the atom types attached to nodes, if set
+    atom_types: Option<typing::AtomTypeScheme>,
+    /// Chemical component dictionary resolving hetero residues, if set
+    ccd: Option<ccd::Dictionary>,
+    /// Van der Waals radius of elements without a tabulated one
+    unknown_radius: radius::UnknownRadius,
+    /// Settings read from a config file, recorded in the metadata
+    config: Option<config::RunConfig>,
+    /// Settings of `--preset`, recorded in the metadata
+    preset: Option<config::RunConfig>,
+    /// Read written graphs back and check them
+    verify: bool,
+    /// Check the builder's invariants on every graph before writing it
+    check_invariants: bool,
+    /// Elements and residues an input must contain to be converted
+    require: composition::Requirements,
+    /// Keep only residues of these polymer types, if set
+    polymer_only: Option<Vec<EntityType>>,
+    /// Build graphs of only some chains, if set
+    chains: Option<selection::ChainSelection>,
+    /// Kinds of atoms left out of graphs
+    exclude: selection::AtomExclusion,
+    /// Models of multi-model structures to build graphs of, all in one graph if not set
+    models: Option<selection::ModelSelection>,
+    altloc: AltlocPolicy,
+    /// Trim structures with more nodes than this
+    max_nodes: Option<usize>,
+    trim_strategy: TrimStrategy,
+    seed: u64,
+    /// Split structures into boxes of this edge length (in Å), if set
+    chunk_size: Option<f64>,
+    chunk_halo: f64,
+    /// Only chunk structures with more nodes than this
+    chunk_above: usize,
+    /// Write one interface graph per interacting pair of chains, if set
+    chain_pairs: Option<interface::InterfaceOptions>,
+    /// Keep each edge with this probability, if set
+    edge_sample_rate: Option<f64>,
+    /// Limits for flagging degenerate graphs
+    thresholds: degenerate::Thresholds,
+    /// Superpose every structure onto this reference, if set
+    align: Option<Reference>,
+    /// Structure added to the graph of every input with edges between them,
+    /// e.g. the receptor of docking poses
+    receptor: Option<Prepared>,
+    /// Write a temporal graph over all models instead of a single graph
+    temporal: bool,
+    /// Write a graph of residues instead of atoms, if set
+    residue_graph: Option<residue_graph::ResidueGraphOptions>,
+    /// Write these graph variants into one container file instead of a single graph, if any
+    variants: Vec<Variant>,
+    /// Also write a residue pair table of pairs with CB atoms up to this far apart, if set
+    residue_pairs: Option<f64>,
+    /// Also write a table of contacts and sampled negative residue pairs, if set
+    contacts: Option<contacts::ContactOptions>,
+    /// Fail structures with requested node features missing on some atoms
+    strict_output: bool,
+    /// Sort nodes and edges into canonical order before writing
+    canonical: bool,
+    /// Also write a table of hydrogen bonds, salt bridges and disulfides, if set
+    interactions: Option<interactions::InteractionOptions>,
+    /// Also write a per-residue protein–ligand interaction fingerprint, if set
+    fingerprint: Option<interactions::InteractionOptions>,
+    format: OutputFormat,
+    naming: OutputNaming,
+    layout: Layout,
+    /// Store edge distances as fixed-point integers, if set
+    quantize_distances: Option<quantize::DistanceQuantization>,
+    voxel: voxel::VoxelOptions,
+    /// Residue neighbour search and values of contact maps
+    contact_map: (residue_graph::ResidueGraphOptions, contact_map::ContactValues),
+}
+
+
+/// How a structure was parsed.
+#[derive(Serialize, Debug, Clone)]
+struct ParseInfo {
+    #[serde(serialize_with = "serialize_display")]
+    strictness: StrictnessLevel,
+    /// Parsing failed at the configured strictness and succeeded on a retry at `Loose`
+    recovered: bool,
+    warnings: Vec<String>,
+}
+
+
+fn serialize_display<T: std::fmt::Display, S: serde::Serializer>(value: &T, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+
+/// Information about the source structure stored next to its graph.
+#[derive(Serialize, Debug, Clone)]
+struct GraphMetadata {
+    source: String,
+    structure_id: StructureId,
+    /// PDB id from the file name, or from the header if the name has none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pdb_id: Option<String>,
+    parse: ParseInfo,
+    /// Number of the model the graph was built of, with `--models`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<usize>,
+    chains: Vec<ChainInfo>,
+    /// Contact order and related fold topology metrics
+    #[serde(skip_serializing_if = "Option::is_none")]
+    topology: Option<topology::TopologyMetrics>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    coordinate_issues: Vec<CoordinateIssue>,
+    /// Anomalies of the structure, for filtering after a run
+    quality: quality::QualityFlags,
+    /// Structure id of the receptor added to the graph
+    #[serde(skip_serializing_if = "Option::is_none")]
+    receptor: Option<StructureId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scores: Option<scores::ScoreInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prediction: Option<PredictionInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embeddings: Option<embeddings::EmbeddingInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    conservation: Option<conservation::ConservationInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    labels: Option<labels::StructureLabels>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sifts: Option<sifts::SiftsInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    residue_features: Option<external::ExternalInfo>,
+    /// Settings read from `--config`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config: Option<config::RunConfig>,
+    /// Settings of `--preset`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preset: Option<config::RunConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secondary_structure: Option<dssp::SecondaryStructureInfo>,
+    /// Scheme of the nodes' `chemical_type`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    atom_types: Option<typing::AtomTypeScheme>,
+    /// Hetero residues resolved with `--ccd`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ccd: Option<ccd::CcdInfo>,
+    /// Elements whose van der Waals radius had to be assumed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    radii: Option<radius::RadiusInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alignment: Option<AlignmentInfo>,
+    /// How residues were connected, for residue graphs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    residue_graph: Option<residue_graph::ResidueGraphOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trim: Option<TrimInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunk: Option<ChunkInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chain_pair: Option<interface::ChainPairInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    edge_sampling: Option<EdgeSamplingInfo>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    degenerate: Vec<Degeneracy>,
+    /// Layout of the node and edge features
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schema: Option<schema::GraphSchema>,
+    /// Index of the graphs of a container file
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    variants: Vec<VariantInfo>,
+}
+
+
+/// Layout of a `_graph.json` output file.
+#[derive(Serialize)]
+struct GraphFile {
+    metadata: GraphMetadata,
+    /// Masks of node features missing on some nodes, in node order
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    masks: BTreeMap<String, Vec<bool>>,
+    graph: Graph<AtomNode, EdgeData>,
+}
+
+
+fn open_structure(fname: &str, opts: &Options) -> Result<(PDB, ParseInfo)> {
+    let err = match input::open(fname, opts.strictness) {
+        Ok((pdb, errors)) => {
+            let warnings = errors.iter().map(|e| e.to_string()).collect();
+            return Ok((pdb, ParseInfo { strictness: opts.strictness, recovered: false, warnings }));
+        }
+        Err(e) => e,
+    };
+    if !opts.retry_loose || opts.strictness == StrictnessLevel::Loose {
+        bail!("Error parsing pdb file {} - {:?}", fname, err);
+    }
+    match input::open(fname, StrictnessLevel::Loose) {
+        Ok((pdb, errors)) => {
+            warn!("Recovered {} at loose strictness with {} warnings", fname, errors.len());
+            let warnings = errors.iter().map(|e| e.to_string()).collect();
+            Ok((pdb, ParseInfo { strictness: StrictnessLevel::Loose, recovered: true, warnings }))
+        }
+        Err(e) => bail!("Error parsing pdb file {} - {:?}", fname, e),
+    }
+}
+
+
+fn chain_info(pdb: &PDB) -> Vec<ChainInfo> {
+    pdb.model(0).map(|m| m.chains().map(entity::classify_chain).collect()).unwrap_or_default()
+}
+
+
+/// A parsed structure with all filters applied, ready to be turned into a graph.
+struct Prepared {
+    pdb: PDB,
+    annotations: NodeAnnotations,
+    metadata: GraphMetadata,
+}
+
+
+/// Parses `fname` and applies the atom selection and filters from `opts`.
+/// Fails for structures split into several models with `--models all`.
+fn prepare(fname: &str, opts: &Options) -> Result<Prepared> {
+    let mut models = prepare_models(fname, opts)?;
+    if models.len() > 1 {
+        bail!("{} has {} models, --models all only applies to the converted inputs", fname, models.len());
+    }
+    Ok(models.remove(0))
+}
+
+
+/// Parses `fname` and prepares each model selected with `--models`, or the
+/// whole structure without it.
+fn prepare_models(fname: &str, opts: &Options) -> Result<Vec<Prepared>> {
+    let (pdb, parse_info) = open_structure(fname, opts)?;
+    let models = match &opts.models {
+        Some(selection) => selection.select(pdb).with_context(|| format!("failed to select models of {}", fname))?
+            .into_iter().map(|(number, model)| (Some(number), model)).collect(),
+        None => vec![(None, pdb)],
+    };
+    models.into_iter().map(|(model, pdb)| prepare_structure(fname, pdb, parse_info.clone(), model, opts)).collect()
+}
+
+
+/// Applies the atom selection and filters from `opts` to the parsed `pdb`,
+/// which is the model numbered `model` of `fname` if set.
+fn prepare_structure(fname: &str, mut pdb: PDB, parse_info: ParseInfo, model: Option<usize>, opts: &Options) -> Result<Prepared> {
+    let isotopes = isotope::resolve_isotopes(&mut pdb);
+    let (ccd_annotations, ccd_info) = match &opts.ccd {
+        Some(dictionary) => {
+            let (annotations, info) = ccd::resolve(&mut pdb, dictionary);
+            if !info.missing.is_empty() {
+                debug!("{}: no CCD entry for {}", fname, info.missing.iter().cloned().collect::<Vec<_>>().join(", "));
+            }
+            (annotations, Some(info))
+        }
+        None => Default::default(),
+    };
+    if !opts.require.is_empty() {
+        let missing = composition::missing(&pdb, &opts.require);
+        if !missing.is_empty() {
+            return Err(composition::Excluded { missing }.into());
+        }
+    }
+    let coordinate_issues = match opts.coordinate_check {
+        CoordinateCheck::Off => Vec::new(),
+        check => {
+            let issues = sanity::check_coordinates(&pdb);
+            if !issues.is_empty() {
+                let report = issues.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ");
+                if check == CoordinateCheck::Reject {
+                    bail!("Implausible coordinates in {} - {}", fname, report);
+                }
+                warn!("Implausible coordinates in {} - {}", fname, report);
+            }
+            issues
+        }
+    };
+    let structure_id = StructureId::from_path(fname);
+    let pdb_id = structure_id.pdb_id.clone().or_else(|| pdb.identifier.as_ref().map(|id| id.to_ascii_lowercase()));
+    // Score tables refer to residues by their index in the file, so they are
+    // matched before any residues are filtered out
+    let score_table = if input::is_pdb_text(fname) { scores::parse_score_table(&input::read_text(fname)?) } else { None };
+    let (atom_scores, score_info) = match score_table {
+        Some(table) => {
+            let (atom_scores, info) = scores::atom_scores(&pdb, &table, opts.score_columns.as_deref());
+            (atom_scores, Some(info))
+        }
+        None => (HashMap::new(), None),
+    };
+    let (plddt, prediction) = if opts.predictions {
+        let (plddt, info) = prediction::confidence(fname, &pdb)?;
+        (plddt, Some(info))
+    } else {
+        (HashMap::new(), None)
+    };
+    let (atom_embeddings, embedding_info) = match &opts.embeddings {
+        Some(dir) => {
+            let (atom_embeddings, info) = embeddings::atom_embeddings(&pdb, dir, &structure_id.name)?;
+            (atom_embeddings, Some(info))
+        }
+        None => (HashMap::new(), None),
+    };
+    let (atom_conservation, conservation_info) = match &opts.conservation {
+        Some(dir) => {
+            let (atom_conservation, info) = conservation::atom_conservation(&pdb, dir, &structure_id.name)?;
+            (atom_conservation, Some(info))
+        }
+        None => (HashMap::new(), None),
+    };
+    let (uniprot, sifts_info) = match &opts.sifts {
+        Some(dir) => {
+            let pdb_id = pdb_id.as_deref().with_context(|| format!("no PDB id to look up the SIFTS mapping of {}", fname))?;
+            let (uniprot, info) = sifts::atom_residues(&pdb, dir, pdb_id)?;
+            (uniprot, Some(info))
+        }
+        None => (HashMap::new(), None),
+    };
+    let (residue_features, residue_feature_info) = match opts.residue_features.as_ref().and_then(|t| t.atom_features(&pdb, &structure_id)) {
+        Some((features, info)) => (features, Some(info)),
+        None => (HashMap::new(), None),
+    };
+    let (secondary_structure, secondary_structure_info) = if opts.secondary_structure {
+        let (secondary_structure, info) = dssp::atom_secondary_structure(&pdb);
+        (secondary_structure, Some(info))
+    } else {
+        (HashMap::new(), None)
+    };
+    if let Some(chains) = &opts.chains {
+        let missing = chains.apply(&mut pdb);
+        if pdb.atom_count() == 0 {
+            bail!("No atoms of {} are in the chains selected with --chains or --exclude-chains", fname);
+        }
+        if !missing.is_empty() {
+            debug!("{} has no chains {}", fname, missing.join(", "));
+        }
+    }
+    if !opts.exclude.is_empty() {
+        let removed = opts.exclude.apply(&mut pdb);
+        if pdb.atom_count() == 0 {
+            bail!("No atoms of {} are left after --no-water, --no-hetatm and --no-hydrogens", fname);
+        }
+        debug!("Left {} atoms out of {}", removed, fname);
+    }
+    if let Some(keep) = &opts.polymer_only {
+        entity::retain_entities(&mut pdb, keep);
+    }
+    // Counted before alternate locations are resolved
+    let quality = quality::QualityFlags {
+        parse_warnings: parse_info.warnings.len(),
+        coordinate_issues: coordinate_issues.len(),
+        ..quality::quality_flags(&pdb, opts.graph.bond_tolerance)
+    };
+    let docking = if pdbqt::is_pdbqt(fname) {
+        pdbqt::read(&input::read_text(fname)?)?.1
+    } else {
+        HashMap::new()
+    };
+    let mut annotations = NodeAnnotations {
+        altlocs: altloc::resolve_altlocs(&mut pdb, opts.altloc),
+        isotopes,
+        ccd: ccd_annotations,
+        docking,
+        scores: atom_scores,
+        plddt,
+        embeddings: atom_embeddings,
+        conservation: atom_conservation,
+        uniprot,
+        residue_features,
+        secondary_structure,
+        ..Default::default()
+    };
+    let mut radii = radius::Radii::new(opts.unknown_radius);
+    if opts.graph.features.contains(&NodeFeature::Sasa) {
+        annotations.sasa = sasa::atom_sasa(&pdb, &mut radii).with_context(|| format!("failed to compute the SASA of {}", fname))?;
+    }
+    if let Some(scheme) = opts.atom_types {
+        annotations.chemical_types = typing::atom_types(&pdb, scheme, opts.graph.bond_tolerance, &annotations.ccd);
+    }
+    let alignment = opts.align.as_ref().map(|r| align::align(&mut pdb, r)).transpose()?;
+    let trim = opts.max_nodes.and_then(|max_nodes| {
+        trim::trim(&mut pdb, max_nodes, opts.trim_strategy, opts.seed ^ stable_hash(fname.as_bytes()))
+    });
+    if let Some(t) = &trim {
+        debug!("Trimmed {} from {} to {} nodes", fname, t.original_nodes, t.kept_nodes);
+    }
+    debug!("Parsed {} at {} strictness with {} warnings", fname, parse_info.strictness, parse_info.warnings.len());
+    for w in &parse_info.warnings {
+        debug!("{}: {}", fname, w);
+    }
+    let chains = chain_info(&pdb);
+    let labels = match (&opts.labels, &pdb_id) {
+        (Some(table), Some(id)) => table.get(id, &chains.iter().map(|c| c.id.as_str()).collect::<Vec<_>>()),
+        _ => None,
+    };
+    if opts.labels.is_some() && labels.is_none() {
+        debug!("No function labels for {}", fname);
+    }
+    let metadata = GraphMetadata {
+        source: fname.to_string(),
+        pdb_id,
+        structure_id,
+        parse: parse_info,
+        model,
+        topology: topology::topology(&pdb),
+        chains,
+        coordinate_issues,
+        quality,
+        receptor: None,
+        scores: score_info,
+        prediction,
+        embeddings: embedding_info,
+        conservation: conservation_info,
+        labels,
+        sifts: sifts_info,
+        residue_features: residue_feature_info,
+        config: opts.config.clone(),
+        preset: opts.preset.clone(),
+        secondary_structure: secondary_structure_info,
+        atom_types: opts.atom_types,
+        ccd: ccd_info,
+        radii: radii.info(),
+        alignment,
+        residue_graph: None,
+        trim,
+        chunk: None,
+        chain_pair: None,
+        edge_sampling: None,
+        degenerate: Vec::new(),
+        schema: None,
+        variants: Vec::new(),
+    };
+    if opts.strict_output {
+        let missing = graphein::missing_features(&opts.graph.nodes(&pdb, &annotations), &required_features(opts));
+        if !missing.is_empty() {
+            let atoms = pdb.atoms().filter(|a| a.element().is_some()).count();
+            let report = missing.iter().map(|(name, count)| format!("{} missing on {} of {} atoms", name, count, atoms)).collect::<Vec<_>>();
+            bail!("Incomplete features in {} - {}", fname, report.join(", "));
+        }
+    }
+    Ok(Prepared { pdb, annotations, metadata })
+}
+
+
+/// Node features every atom must have with `--strict-output`: the computed
+/// features and the requested annotations.
+fn required_features(opts: &Options) -> Vec<&'static str> {
+    let mut required: Vec<&str> = opts.graph.features.iter().map(|f| f.name()).collect();
+    for (name, requested) in [
+        ("scores", opts.score_columns.is_some()),
+        ("plddt", opts.predictions),
+        ("embedding", opts.embeddings.is_some()),
+        ("conservation", opts.conservation.is_some()),
+        ("uniprot", opts.sifts.is_some()),
+        ("residue_features", opts.residue_features.is_some()),
+        ("secondary_structure", opts.secondary_structure),
+        ("chemical_type", opts.atom_types.is_some()),
+    ] {
+        if requested {
+            required.push(name);
+        }
+    }
+    required
+}
+
+
+/// Canonical form of a freshly built atom graph if `--canonical` is set.
+fn canonical_atoms(graph: Graph<AtomNode, EdgeData>, opts: &Options) -> Graph<AtomNode, EdgeData> {
+    if opts.canonical { canonical::canonicalize(graph) } else { graph }
+}
+
+
+/// Canonical form of a freshly built residue graph if `--canonical` is set.
+fn canonical_residues<N, E>(graph: Graph<N, E>, opts: &Options) -> Graph<N, E> {
+    if opts.canonical { canonical::sort_edges(graph) } else { graph }
+}
+
+
+/// Randomly thins the edges of `graph` if `--edge-sample-rate` is set. The
+/// seed is derived from `--seed` and the input path.
+fn thin_edges<N, E>(graph: &mut Graph<N, E>, fname: &str, opts: &Options) -> Option<EdgeSamplingInfo> {
+    opts.edge_sample_rate.map(|rate| thin::sample_edges(graph, rate, opts.seed ^ stable_hash(fname.as_bytes())))
+}
+
+
+/// A graph output as written, borrowing the parts of one of the output
+/// structs.
+#[derive(Serialize)]
+struct GraphOutput<'a, M, G> {
+    metadata: &'a M,
+    #[serde(skip_serializing_if = "no_masks")]
+    masks: &'a BTreeMap<String, Vec<bool>>,
+    graph: G,
+}
+
+
+fn no_masks(masks: &&BTreeMap<String, Vec<bool>>) -> bool {
+    masks.is_empty()
+}
+
+
+fn layout_json<M: Serialize, N: Serialize, E: Serialize>(
+    metadata: &M,
+    masks: &BTreeMap<String, Vec<bool>>,
+    graph: &Graph<N, E>,
+    schema: Option<&schema::GraphSchema>,
+    layout: Layout,
+) -> Result<String> {
+    Ok(match layout {
+        Layout::Structs => serde_json::to_string(&GraphOutput { metadata, masks, graph })?,
+        Layout::Packed => {
+            let graph = packed::pack_graph(graph, schema.context("graph has no schema to pack")?)?;
+            serde_json::to_string(&GraphOutput { metadata, masks, graph })?
+        }
+    })
+}
+
+
+/// Serializes a graph output in the configured layout, with quantized edge
+/// distances if set.
+fn graph_json<M: Serialize, N: Serialize, E: Serialize + quantize::Quantize>(
+    metadata: &M,
+    masks: &BTreeMap<String, Vec<bool>>,
+    graph: &Graph<N, E>,
+    schema: Option<&schema::GraphSchema>,
+    opts: &Options,
+) -> Result<String> {
+    match &opts.quantize_distances {
+        Some(quantization) => layout_json(metadata, masks, &quantize::quantize_graph(graph, quantization), schema, opts.layout),
+        None => layout_json(metadata, masks, graph, schema, opts.layout),
+    }
+}
+
+
+/// Builds the graph of a prepared structure together with the output
+/// metadata. Chunked structures produce one graph per chunk, and with chain
+/// pairing one graph per interacting pair of chains.
+fn convert(fname: &str, prepared: Prepared, opts: &Options) -> Vec<GraphFile> {
+    let Prepared { pdb, annotations, mut metadata } = prepared;
+
+    if let Some(interface_opts) = &opts.chain_pairs {
+        let pairs = interface::chain_pairs(&pdb, interface_opts);
+        debug!("Found {} interacting chain pairs in {}", pairs.len(), fname);
+        return pairs.into_iter().map(|pair| {
+            let mut pair_pdb = pdb.clone();
+            pair_pdb.remove_atoms_by(|a| !pair.atoms.contains(&a.serial_number()));
+            pair_pdb.remove_empty();
+            let mut graph = canonical_atoms(opts.graph.build_annotated_graph(&pair_pdb, &annotations), opts);
+            let edge_sampling = thin_edges(&mut graph, &format!("{}_{}", fname, pair.info.suffix()), opts);
+            GraphFile {
+                metadata: GraphMetadata {
+                    chain_pair: Some(pair.info),
+                    edge_sampling,
+                    schema: Some(schema::atom_graph_schema(&graph).quantized(opts.quantize_distances.as_ref())),
+                    ..metadata.clone()
+                },
+                masks: graphein::feature_masks(graph.node_weights()),
+                graph,
+            }
+        }).collect();
+    }
+
+    let node_count = pdb.atoms().filter(|a| a.element().is_some()).count();
+    let chunks = match opts.chunk_size {
+        Some(size) if node_count > opts.chunk_above => chunk::partition(&pdb, size, opts.chunk_halo),
+        _ => {
+            let protein_graph = match &opts.receptor {
+                Some(receptor) => {
+                    metadata.receptor = Some(receptor.metadata.structure_id.clone());
+                    let receptor_id = receptor.metadata.structure_id.to_string();
+                    let ligand_id = metadata.structure_id.to_string();
+                    opts.graph.build_merged_graph(
+                        &[(&receptor.pdb, &receptor.annotations, Some(&receptor_id)), (&pdb, &annotations, Some(&ligand_id))],
+                        true,
+                    )
+                }
+                None => opts.graph.build_annotated_graph(&pdb, &annotations),
+            };
+            let mut protein_graph = canonical_atoms(protein_graph, opts);
+            metadata.edge_sampling = thin_edges(&mut protein_graph, fname, opts);
+            metadata.schema = Some(schema::atom_graph_schema(&protein_graph).quantized(opts.quantize_distances.as_ref()));
+            debug!("Parsing protein {}, node couunt {}. edge count {}", fname, protein_graph.node_count(), protein_graph.edge_count());
+            return vec![GraphFile { metadata, masks: graphein::feature_masks(protein_graph.node_weights()), graph: protein_graph }];
+        }
+    };
+    debug!("Split {} with {} nodes into {} chunks", fname, node_count, chunks.len());
+    chunks.into_iter().map(|c| {
+        let mut chunk_pdb = pdb.clone();
+        graphein::retain_atoms(&mut chunk_pdb, |i| c.core.contains(&i) || c.halo.contains(&i));
+        // Nodes are built from the kept atoms with an element, in order
+        let halo: Vec<bool> = pdb.atoms().enumerate()
+            .filter(|(i, a)| a.element().is_some() && (c.core.contains(i) || c.halo.contains(i)))
+            .map(|(i, _)| c.halo.contains(&i))
+            .collect();
+        let mut graph = opts.graph.build_annotated_graph(&chunk_pdb, &annotations);
+        for (node, halo) in graph.node_weights_mut().zip(halo) {
+            node.halo = halo;
+        }
+        let mut graph = canonical_atoms(graph, opts);
+        let edge_sampling = thin_edges(&mut graph, &format!("{}_{}", fname, c.info.suffix()), opts);
+        GraphFile {
+            metadata: GraphMetadata {
+                chunk: Some(c.info),
+                edge_sampling,
+                schema: Some(schema::atom_graph_schema(&graph).quantized(opts.quantize_distances.as_ref())),
+                ..metadata.clone()
+            },
+            masks: graphein::feature_masks(graph.node_weights()),
+            graph,
+        }
+    }).collect()
+}
+
+
+#[derive(Serialize)]
+struct MergedMetadata {
+    inter_structure_edges: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    edge_sampling: Option<EdgeSamplingInfo>,
+    schema: schema::GraphSchema,
+    structures: Vec<GraphMetadata>,
+}
+
+
+/// Layout of the output file of `--merge-into`.
+#[derive(Serialize)]
+struct MergedGraphFile {
+    metadata: MergedMetadata,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    masks: BTreeMap<String, Vec<bool>>,
+    graph: Graph<AtomNode, EdgeData>,
+}
+
+
+/// Merges all `paths` into a single graph written to `save_fname`. Inputs
+/// that fail to parse are left out and returned with their errors.
+fn merge_pdb_files(paths: &[String], save_fname: &Path, opts: &Options, inter_structure_edges: bool) -> Result<Vec<(String, anyhow::Error)>> {
+    let prepared: Vec<(&String, Result<Prepared>)> = paths.par_iter().map(|p| (p, prepare(p, opts))).collect();
+    let mut failures = Vec::new();
+    let mut structures = Vec::new();
+    for (p, res) in prepared {
+        match res {
+            Ok(prepared) => structures.push((StructureId::from_path(p).to_string(), prepared)),
+            Err(e) => failures.push((p.clone(), e)),
+        }
+    }
+    if structures.is_empty() {
+        bail!("None of the {} inputs could be parsed", paths.len());
+    }
+    let mut graph = canonical_atoms(opts.graph.build_merged_graph(
+        &structures.iter().map(|(id, p)| (&p.pdb, &p.annotations, Some(id.as_str()))).collect::<Vec<_>>(),
+        inter_structure_edges,
+    ), opts);
+    let edge_sampling = thin_edges(&mut graph, &save_fname.to_string_lossy(), opts);
+    if opts.check_invariants {
+        ensure_invariants(&save_fname.to_string_lossy(), invariants::check(&graph, &opts.graph))?;
+    }
+    info!("Merged {} structures into a graph with {} nodes and {} edges", structures.len(), graph.node_count(), graph.edge_count());
+    let output = MergedGraphFile {
+        metadata: MergedMetadata {
+            inter_structure_edges,
+            edge_sampling,
+            schema: schema::atom_graph_schema(&graph).quantized(opts.quantize_distances.as_ref()),
+            structures: structures.into_iter().map(|(_, p)| p.metadata).collect(),
+        },
+        masks: graphein::feature_masks(graph.node_weights()),
+        graph,
+    };
+    let json = graph_json(&output.metadata, &output.masks, &output.graph, Some(&output.metadata.schema), opts)?;
+    atomic::write(save_fname, json.as_bytes())?;
+    Ok(failures)
+}
+
+
+/// Writes the node correspondence between the graphs of `a` and `b`, built
+/// with the same options, to `save_fname`.
+fn write_correspondence(a: &str, b: &str, alignment: Option<&str>, save_fname: &Path, opts: &Options) -> Result<()> {
+    let prepared_a = prepare(a, opts)?;
+    let prepared_b = prepare(b, opts)?;
+    let mapping = correspond::correspond(a, &prepared_a.pdb, b, &prepared_b.pdb, alignment)?;
+    info!("Aligned {} residues ({:.0}% identity), {} corresponding nodes", mapping.aligned_residues, mapping.identity * 100.0, mapping.nodes.len());
+    atomic::write(save_fname, serde_json::to_string(&mapping)?.as_bytes())?;
+    Ok(())
+}
+
+
+/// Writes a graph of every record of a FASTA file next to it, as
+/// `<record id>_graph.json`.
+fn write_sequence_graphs(fasta: &str, window: usize) -> Result<()> {
+    let text = std::fs::read_to_string(fasta)?;
+    let records = sequence::parse_fasta(&text)?;
+    if records.is_empty() {
+        bail!("no FASTA records");
+    }
+    let dir = Path::new(fasta).parent().unwrap_or(Path::new(""));
+    for record in records {
+        let stem = sequence::file_stem(&record.id);
+        let metadata = sequence::SequenceMetadata {
+            source: fasta.to_string(),
+            structure_id: stem.clone(),
+            description: record.description,
+            length: record.sequence.len(),
+            window,
+        };
+        let output = sequence::SequenceGraphFile { graph: sequence::sequence_graph(&record.sequence, window), metadata };
+        let save_fname = dir.join(format!("{}_graph.json", stem));
+        atomic::write(&save_fname, serde_json::to_string(&output)?.as_bytes())?;
+        info!("Wrote {} ({} residues)", save_fname.display(), output.metadata.length);
+    }
+    Ok(())
+}
+
+
+/// Splits an output path into its stem and the suffix of `format`, or the
+/// extension of its file name for outputs named with `--out-template`.
+fn split_output_path(save_fname: &str, format: OutputFormat) -> (&str, &str) {
+    if let Some(stem) = save_fname.strip_suffix(format.suffix()) {
+        return (stem, format.suffix());
+    }
+    let name_start = save_fname.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    match save_fname[name_start..].rfind('.') {
+        Some(dot) => save_fname.split_at(name_start + dot),
+        None => (save_fname, ""),
+    }
+}
+
+
+/// Output path of one part of a structure written to `save_fname`, e.g. a
+/// chunk, with the suffix of the part.
+fn part_output_path(save_fname: &str, suffix: &str, format: OutputFormat) -> String {
+    let (stem, extension) = split_output_path(save_fname, format);
+    format!("{}_{}{}", stem, suffix, extension)
+}
+
+
+/// Output path of a table written next to the output `save_fname`, e.g.
+/// `<filename>_pairs.csv` for `name` `pairs.csv`.
+fn table_output_path(save_fname: &str, format: OutputFormat, name: &str) -> String {
+    let (stem, _) = split_output_path(save_fname, format);
+    format!("{}_{}", stem, name)
+}
+
+
+/// Layout of the output file in `--temporal` mode.
+#[derive(Serialize)]
+struct TemporalGraphFile {
+    metadata: GraphMetadata,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    masks: BTreeMap<String, Vec<bool>>,
+    temporal_graph: temporal::TemporalGraph,
+}
+
+
+/// Layout of the output file with `--granularity residue`.
+#[derive(Serialize)]
+struct ResidueGraphFile {
+    metadata: GraphMetadata,
+    graph: Graph<residue_graph::ResidueGraphNode, f64>,
+}
+
+
+/// Outcome of converting one input.
+#[derive(Debug, Default)]
+struct FileReport {
+    /// The structure had to be recovered at loose strictness
+    recovered: bool,
+    /// The structure was left out by the composition filter
+    excluded: bool,
+    /// Files written
+    outputs: Vec<String>,
+    /// Sizes of the graphs written
+    graphs: Vec<GraphSize>,
+    /// The run was interrupted before the input was converted
+    pending: bool,
+    /// Outputs whose graphs were flagged as degenerate, with the reasons
+    degenerate: Vec<(String, Vec<Degeneracy>)>,
+    /// Joins of external data that found nothing for the structure
+    unmatched_joins: Vec<Join>,
+    /// Atoms of each element without a tabulated van der Waals radius
+    unknown_elements: BTreeMap<String, usize>,
+}
+
+impl FileReport {
+    /// Adds the report of another model of the same input.
+    fn add(&mut self, other: FileReport) {
+        self.recovered |= other.recovered;
+        self.excluded |= other.excluded;
+        self.outputs.extend(other.outputs);
+        self.graphs.extend(other.graphs);
+        self.pending |= other.pending;
+        self.degenerate.extend(other.degenerate);
+        for join in other.unmatched_joins {
+            if !self.unmatched_joins.contains(&join) {
+                self.unmatched_joins.push(join);
+            }
+        }
+        for (element, atoms) in other.unknown_elements {
+            *self.unknown_elements.entry(element).or_default() += atoms;
+        }
+    }
+}
+
+
+/// A `_graph.json` output as read back for verification.
+#[derive(Deserialize)]
+struct WrittenGraphFile<G> {
+    metadata: serde_json::Map<String, serde_json::Value>,
+    #[serde(alias = "graphs")]
+    graph: G,
+}
+
+
+fn read_graph_file<G: serde::de::DeserializeOwned>(path: &str) -> Result<WrittenGraphFile<G>> {
+    let file = File::open(path)?;
+    let written: WrittenGraphFile<G> = serde_json::from_reader(std::io::BufReader::new(file))
+        .map_err(|e| anyhow::anyhow!("Verification of {} failed - not a valid graph file: {}", path, e))?;
+    if !written.metadata.contains_key("structure_id") {
+        bail!("Verification of {} failed - metadata has no structure id", path);
+    }
+    Ok(written)
+}
+
+
+/// Fails `path` with the invariant violations of its graph, if any.
+fn ensure_invariants(path: &str, violations: Vec<invariants::Violation>) -> Result<()> {
+    const SHOWN: usize = 5;
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let shown = violations.iter().take(SHOWN).map(|v| v.to_string()).collect::<Vec<_>>().join(", ");
+    let more = if violations.len() > SHOWN { format!(" and {} more", violations.len() - SHOWN) } else { String::new() };
+    bail!("Invariant violations in {} - {}{}", path, shown, more);
+}
+
+
+/// Reads a written graph back and checks that it parses as a graph output
+/// in `layout` with the node and edge counts of `expected`.
+fn verify_graph_file<N: serde::de::DeserializeOwned, E: serde::de::DeserializeOwned>(path: &str, expected: &Graph<N, E>, layout: Layout) -> Result<()> {
+    let (nodes, edges) = match layout {
+        Layout::Structs => {
+            let graph = read_graph_file::<Graph<N, E>>(path)?.graph;
+            (graph.node_count(), graph.edge_count())
+        }
+        Layout::Packed => {
+            let graph = read_graph_file::<packed::PackedGraph>(path)?.graph;
+            (graph.nodes.shape[0], graph.edge_index[0].len())
+        }
+    };
+    if (nodes, edges) != (expected.node_count(), expected.edge_count()) {
+        bail!(
+            "Verification of {} failed - read {} nodes and {} edges, wrote {} and {}",
+            path, nodes, edges, expected.node_count(), expected.edge_count()
+        );
+    }
+    Ok(())
+}
+
+
+/// Builds every variant of a prepared structure and writes them into one
+/// container file at `save_fname`, with an index of the variants in its
+/// metadata and the graphs under `graphs` by name.
+fn write_variants(fname: &str, prepared: Prepared, save_fname: &str, opts: &Options, report: &mut FileReport) -> Result<()> {
+    let Prepared { pdb, annotations, mut metadata } = prepared;
+    let mut entries = Vec::new();
+    for variant in &opts.variants {
+        let seed_name = format!("{}#{}", fname, variant.name);
+        let (info, json) = match &variant.graph {
+            VariantGraph::Atom(builder) => {
+                let mut graph = canonical_atoms(builder.build_annotated_graph(&pdb, &annotations), opts);
+                let edge_sampling = thin_edges(&mut graph, &seed_name, opts);
+                if opts.check_invariants {
+                    ensure_invariants(&seed_name, invariants::check(&graph, builder))?;
+                }
+                let info = VariantInfo {
+                    name: variant.name.clone(),
+                    granularity: Granularity::Atom,
+                    cutoff: builder.cutoff,
+                    residue_edges: None,
+                    nodes: graph.node_count(),
+                    edges: graph.edge_count(),
+                    degenerate: degenerate::check(&graph, &opts.thresholds),
+                };
+                let schema = schema::atom_graph_schema(&graph).quantized(opts.quantize_distances.as_ref());
+                let variant_metadata = VariantMetadata { name: &variant.name, schema: schema.clone(), edge_sampling };
+                (info, graph_json(&variant_metadata, &graphein::feature_masks(graph.node_weights()), &graph, Some(&schema), opts)?)
+            }
+            VariantGraph::Residue(residue_opts) => {
+                let mut graph = canonical_residues(residue_graph::build_residue_graph(&pdb, residue_opts), opts);
+                let edge_sampling = thin_edges(&mut graph, &seed_name, opts);
+                if opts.check_invariants {
+                    ensure_invariants(&seed_name, invariants::check_residues(&graph, residue_opts))?;
+                }
+                let info = VariantInfo {
+                    name: variant.name.clone(),
+                    granularity: Granularity::Residue,
+                    cutoff: residue_opts.cutoff,
+                    residue_edges: Some(residue_opts.edges),
+                    nodes: graph.node_count(),
+                    edges: graph.edge_count(),
+                    degenerate: degenerate::check(&graph, &opts.thresholds),
+                };
+                let schema = schema::residue_graph_schema(graph.node_weights()).quantized(opts.quantize_distances.as_ref());
+                let variant_metadata = VariantMetadata { name: &variant.name, schema: schema.clone(), edge_sampling };
+                (info, graph_json(&variant_metadata, &BTreeMap::new(), &graph, Some(&schema), opts)?)
+            }
+        };
+        let path = format!("{}#{}", save_fname, info.name);
+        if !info.degenerate.is_empty() {
+            report.degenerate.push((path.clone(), info.degenerate.clone()));
+        }
+        report.graphs.push(GraphSize { path, nodes: info.nodes, edges: info.edges });
+        entries.push(format!("{}:{}", serde_json::to_string(&info.name)?, json));
+        metadata.variants.push(info);
+    }
+    let json = format!("{{\"metadata\":{},\"graphs\":{{{}}}}}", serde_json::to_string(&metadata)?, entries.join(","));
+    atomic::write(save_fname, json.as_bytes())?;
+    debug!("Saved {} graph variants {}", metadata.variants.len(), save_fname);
+    if opts.verify {
+        let written = read_graph_file::<BTreeMap<String, serde_json::Value>>(save_fname)?;
+        if written.graph.len() != metadata.variants.len() {
+            bail!("Verification of {} failed - read {} graph variants, wrote {}", save_fname, written.graph.len(), metadata.variants.len());
+        }
+    }
+    report.outputs.push(save_fname.to_string());
+    Ok(())
+}
+
+
+/// Joins of external data that were set up but found nothing for a
+/// structure: no labels, no residue with residue features or a UniProt
+/// position, or no chain with embeddings or aligned profiles.
+fn unmatched_joins(metadata: &GraphMetadata, opts: &Options) -> Vec<Join> {
+    [
+        (Join::Labels, opts.labels.is_some(), metadata.labels.is_some()),
+        (Join::ResidueFeatures, opts.residue_features.is_some(), metadata.residue_features.as_ref().is_some_and(|i| i.residues > 0)),
+        (Join::Embeddings, opts.embeddings.is_some(), metadata.embeddings.as_ref().is_some_and(|i| !i.chains.is_empty())),
+        (Join::Conservation, opts.conservation.is_some(), metadata.conservation.as_ref().is_some_and(|i| i.chains.iter().any(|c| c.aligned > 0))),
+        (Join::Sifts, opts.sifts.is_some(), metadata.sifts.as_ref().is_some_and(|i| i.mapped_residues > 0)),
+    ]
+    .into_iter()
+    .filter(|(_, joined, matched)| *joined && !matched)
+    .map(|(join, _, _)| join)
+    .collect()
+}
+
+
+/// Converts `fname` and writes the result to `save_fname`.
+fn process_pdb_file(fname: &str, save_fname: &str, opts: &Options) -> Result<FileReport> {
+    let models = match prepare_models(fname, opts) {
+        Ok(models) => models,
+        Err(e) if e.is::<composition::Excluded>() => {
+            debug!("Skipped {} - {}", fname, e);
+            return Ok(FileReport { excluded: true, ..Default::default() });
+        }
+        Err(e) => return Err(e),
+    };
+    let mut report = FileReport::default();
+    for prepared in models {
+        let mut model_report = FileReport {
+            recovered: prepared.metadata.parse.recovered,
+            unmatched_joins: unmatched_joins(&prepared.metadata, opts),
+            unknown_elements: prepared.metadata.radii.as_ref().map(|r| r.unknown_elements.clone()).unwrap_or_default(),
+            ..Default::default()
+        };
+        // Every model of `--models all` is written to its own output
+        let path = match (opts.models, prepared.metadata.model) {
+            (Some(selection::ModelSelection::All), Some(model)) => part_output_path(save_fname, &format!("model{}", model), opts.format),
+            _ => save_fname.to_string(),
+        };
+        process_prepared(fname, prepared, &path, opts, &mut model_report)?;
+        report.add(model_report);
+    }
+    Ok(report)
+}
+
+
+/// Writes the outputs of one prepared structure of `fname` to `save_fname`.
+fn process_prepared(fname: &str, prepared: Prepared, save_fname: &str, opts: &Options, report: &mut FileReport) -> Result<()> {
+    if let Some(max_distance) = opts.residue_pairs {
+        let pairs = pairs::residue_pairs(&prepared.pdb, max_distance, opts.graph.cutoff);
+        let path = table_output_path(save_fname, opts.format, "pairs.csv");
+        pairs::write_pairs(&pairs, Path::new(&path))?;
+        debug!("Saved {} residue pairs {}", pairs.len(), path);
+        report.outputs.push(path);
+    }
+    if let Some(contact_opts) = &opts.contacts {
+        let pairs = contacts::contact_pairs(&prepared.pdb, contact_opts, opts.seed ^ stable_hash(fname.as_bytes()));
+        let path = table_output_path(save_fname, opts.format, "contacts.csv");
+        contacts::write_contact_pairs(&pairs, Path::new(&path))?;
+        let positives = pairs.iter().filter(|p| p.contact).count();
+        debug!("Saved {} contacts and {} sampled negatives {}", positives, pairs.len() - positives, path);
+        report.outputs.push(path);
+    }
+    if let Some(interaction_opts) = &opts.interactions {
+        let found = interactions::interactions(&prepared.pdb, interaction_opts, opts.graph.bond_tolerance);
+        let path = table_output_path(save_fname, opts.format, "interactions.csv");
+        interactions::write_interactions(&found, Path::new(&path))?;
+        debug!("Saved {} interactions {}", found.len(), path);
+        report.outputs.push(path);
+    }
+    if let Some(interaction_opts) = &opts.fingerprint {
+        let found = match &opts.receptor {
+            Some(receptor) => fingerprint::fingerprint(&receptor.pdb, &prepared.pdb, interaction_opts, opts.graph.bond_tolerance),
+            None => {
+                let (protein, ligands) = fingerprint::split_ligands(&prepared.pdb);
+                fingerprint::fingerprint(&protein, &ligands, interaction_opts, opts.graph.bond_tolerance)
+            }
+        };
+        let path = table_output_path(save_fname, opts.format, "fingerprint.csv");
+        fingerprint::write_fingerprint(&found, Path::new(&path))?;
+        debug!("Saved interaction fingerprint of {} residues {}", found.len(), path);
+        report.outputs.push(path);
+    }
+    if opts.format == OutputFormat::Voxel {
+        let mut radii = radius::Radii::new(opts.unknown_radius);
+        voxel::write_voxels(&prepared.pdb, &opts.voxel, &mut radii, Path::new(save_fname))
+            .with_context(|| format!("failed to write the voxel grid of {}", fname))?;
+        report.unknown_elements = radii.unknown_elements();
+        debug!("Saved voxel grid {}", save_fname);
+        report.outputs.push(save_fname.to_string());
+        return Ok(());
+    }
+    if matches!(opts.format, OutputFormat::ContactMap | OutputFormat::ContactMapCsv) {
+        let (residue_opts, values) = &opts.contact_map;
+        let map = contact_map::contact_map(&prepared.pdb, residue_opts, *values);
+        if opts.format == OutputFormat::ContactMap {
+            contact_map::write_npy(&map, Path::new(save_fname))?;
+            let path = table_output_path(save_fname, opts.format, "contact_map_residues.csv");
+            contact_map::write_residues(&map, Path::new(&path))?;
+            report.outputs.push(path);
+        } else {
+            contact_map::write_csv(&map, Path::new(save_fname))?;
+        }
+        debug!("Saved contact map of {} residues {}", map.residues.len(), save_fname);
+        report.outputs.push(save_fname.to_string());
+        return Ok(());
+    }
+    if opts.format == OutputFormat::PointCloud {
+        let points = pointcloud::write_point_cloud(&prepared.pdb, &opts.graph, &prepared.annotations, Path::new(save_fname))?;
+        debug!("Saved point cloud of {} points {}", points, save_fname);
+        report.outputs.push(save_fname.to_string());
+        return Ok(());
+    }
+    if opts.temporal {
+        let temporal_graph = temporal::build_temporal_graph(&prepared.pdb, &opts.graph, &prepared.annotations);
+        let graph_schema = schema::GraphSchema {
+            nodes: schema::atom_node_schema(&temporal_graph.nodes),
+            edges: schema::temporal_edge_schema(),
+        };
+        let output = TemporalGraphFile {
+            masks: graphein::feature_masks(&temporal_graph.nodes),
+            temporal_graph,
+            metadata: GraphMetadata { schema: Some(graph_schema), ..prepared.metadata },
+        };
+        debug!("Built temporal graph of {} over {} frames", fname, output.temporal_graph.frame_count);
+        atomic::write(save_fname, serde_json::to_string(&output)?.as_bytes())?;
+        report.outputs.push(save_fname.to_string());
+        return Ok(());
+    }
+    if !opts.variants.is_empty() {
+        write_variants(fname, prepared, save_fname, opts, report)?;
+        return Ok(());
+    }
+    if let Some(residue_opts) = &opts.residue_graph {
+        let mut output = ResidueGraphFile {
+            graph: canonical_residues(residue_graph::build_residue_graph(&prepared.pdb, residue_opts), opts),
+            metadata: GraphMetadata { residue_graph: Some(residue_opts.clone()), ..prepared.metadata },
+        };
+        output.metadata.edge_sampling = thin_edges(&mut output.graph, fname, opts);
+        if opts.check_invariants {
+            ensure_invariants(save_fname, invariants::check_residues(&output.graph, residue_opts))?;
+        }
+        output.metadata.schema = Some(schema::residue_graph_schema(output.graph.node_weights()).quantized(opts.quantize_distances.as_ref()));
+        output.metadata.degenerate = degenerate::check(&output.graph, &opts.thresholds);
+        if !output.metadata.degenerate.is_empty() {
+            report.degenerate.push((save_fname.to_string(), output.metadata.degenerate.clone()));
+        }
+        if opts.format == OutputFormat::Pyg {
+            let graph_schema = output.metadata.schema.as_ref().expect("residue graphs have a schema");
+            match &opts.quantize_distances {
+                Some(quantization) => pyg::write_pyg(&quantize::quantize_graph(&output.graph, quantization), graph_schema, Path::new(save_fname))?,
+                None => pyg::write_pyg(&output.graph, graph_schema, Path::new(save_fname))?,
+            }
+            debug!("Saved residue PyG arrays {}", save_fname);
+        } else {
+            let json = graph_json(&output.metadata, &BTreeMap::new(), &output.graph, output.metadata.schema.as_ref(), opts)?;
+            atomic::write(save_fname, json.as_bytes())?;
+            debug!("Saved residue graph file {}", save_fname);
+        }
+        if opts.verify && opts.format == OutputFormat::Json {
+            verify_graph_file(save_fname, &output.graph, opts.layout)?;
+        }
+        report.graphs.push(GraphSize { path: save_fname.to_string(), nodes: output.graph.node_count(), edges: output.graph.edge_count() });
+        report.outputs.push(save_fname.to_string());
+        return Ok(());
+    }
+    let outputs = convert(fname, prepared, opts);
+    for mut output in outputs {
+        let path = match (&output.metadata.chunk, &output.metadata.chain_pair) {
+            (Some(chunk), _) => part_output_path(save_fname, &chunk.suffix(), opts.format),
+            (_, Some(pair)) => part_output_path(save_fname, &pair.suffix(), opts.format),
+            _ => save_fname.to_string(),
+        };
+        if opts.check_invariants {
+            ensure_invariants(&path, invariants::check(&output.graph, &opts.graph))?;
+        }
+        output.metadata.degenerate = degenerate::check(&output.graph, &opts.thresholds);
+        if !output.metadata.degenerate.is_empty() {
+            report.degenerate.push((path.clone(), output.metadata.degenerate.clone()));
+        }
+        match opts.format {
+            OutputFormat::Dot => {
+                let name = output.metadata.structure_id.to_string();
+                atomic::write(&path, dot::atom_graph_dot(&output.graph, &name).as_bytes())?;
+                debug!("Saved Graphviz file {}", path);
+            }
+            OutputFormat::Pyg => {
+                let graph_schema = output.metadata.schema.as_ref().expect("atom graphs have a schema");
+                match &opts.quantize_distances {
+                    Some(quantization) => pyg::write_pyg(&quantize::quantize_graph(&output.graph, quantization), graph_schema, Path::new(&path))?,
+                    None => pyg::write_pyg(&output.graph, graph_schema, Path::new(&path))?,
+                }
+                debug!("Saved PyG arrays {}", path);
+            }
+            OutputFormat::Parquet => {
+                let graph_schema = output.metadata.schema.as_ref().expect("atom graphs have a schema");
+                let edges_path = table_output_path(&path, opts.format, "edges.parquet");
+                let structure_id = output.metadata.structure_id.to_string();
+                match &opts.quantize_distances {
+                    Some(quantization) => {
+                        let graph = quantize::quantize_graph(&output.graph, quantization);
+                        tables::write_graph_tables(&graph, graph_schema, &structure_id, &output.metadata, Path::new(&path), Path::new(&edges_path))?
+                    }
+                    None => tables::write_graph_tables(&output.graph, graph_schema, &structure_id, &output.metadata, Path::new(&path), Path::new(&edges_path))?,
+                }
+                debug!("Saved Parquet tables {} and {}", path, edges_path);
+                report.outputs.push(edges_path);
+            }
+            _ => {
+                let json = graph_json(&output.metadata, &output.masks, &output.graph, output.metadata.schema.as_ref(), opts)?;
+                atomic::write(&path, json.as_bytes())?;
+                debug!("Saved graph file {}", path);
+            }
+        }
+        if opts.verify && opts.format == OutputFormat::Json {
+            verify_graph_file(&path, &output.graph, opts.layout)?;
+        }
+        report.graphs.push(GraphSize { path: path.clone(), nodes: output.graph.node_count(), edges: output.graph.edge_count() });
+        report.outputs.push(path);
+    }
+
+    Ok(())
+}
+
+
+/// Order in which inputs are converted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum ScheduleOrder {
+    /// Largest files first, so that no huge structure is left running alone at the end
+    LargestFirst,
+    /// Smallest files first, to get many outputs early
+    SmallestFirst,
+    /// Order in which the inputs were matched
+    Input,
+}
+
+
+/// Set on Ctrl-C: inputs in flight are finished, no new ones are started.
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+
+/// Converts every input in parallel, picking inputs in `order` from a
+/// shared queue. Inputs not started when the run is cancelled are reported
+/// as pending. With a memory limit, structures reserve their estimated
+/// memory before starting, and those estimated above the limit are deferred
+/// and converted one at a time at the end.
+fn convert_all(paths: &[String], outputs: &[String], opts: &Options, order: ScheduleOrder, max_memory: Option<u64>) -> Vec<Result<FileReport>> {
+    let sizes: Vec<u64> = paths.iter().map(|p| std::fs::metadata(p).map(|m| m.len()).unwrap_or(0)).collect();
+    let mut queue: Vec<usize> = (0..paths.len()).collect();
+    match order {
+        ScheduleOrder::LargestFirst => queue.sort_by_key(|&i| std::cmp::Reverse(sizes[i])),
+        ScheduleOrder::SmallestFirst => queue.sort_by_key(|&i| sizes[i]),
+        ScheduleOrder::Input => {}
+    }
+    let budget = max_memory.map(memory::Budget::new);
+    let estimates: Vec<u64> = paths.iter().map(|p| memory::estimate(p)).collect();
+    let (deferred, parallel): (Vec<usize>, Vec<usize>) = queue.into_iter()
+        .partition(|&i| budget.as_ref().is_some_and(|b| estimates[i] > b.limit));
+
+    let results: Mutex<Vec<Option<Result<FileReport>>>> = Mutex::new((0..paths.len()).map(|_| None).collect());
+    let next = AtomicUsize::new(0);
+    (0..rayon::current_num_threads()).into_par_iter().for_each(|_| {
+        while let Some(&i) = parallel.get(next.fetch_add(1, Ordering::Relaxed)).filter(|_| !CANCELLED.load(Ordering::SeqCst)) {
+            let _reservation = budget.as_ref().map(|b| {
+                debug!("Converting {} with an estimated {}", paths[i], memory::format_size(estimates[i]));
+                b.reserve(estimates[i])
+            });
+            let result = process_pdb_file(&paths[i], &outputs[i], opts);
+            results.lock().unwrap()[i] = Some(result);
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    if let Some(limit) = budget.as_ref().map(|b| b.limit).filter(|_| !deferred.is_empty()) {
+        info!("Converting {} structures estimated above {} one at a time", deferred.len(), memory::format_size(limit));
+    }
+    for i in deferred {
+        if CANCELLED.load(Ordering::SeqCst) {
+            break;
+        }
+        debug!("Converting deferred {} with an estimated {}", paths[i], memory::format_size(estimates[i]));
+        results[i] = Some(process_pdb_file(&paths[i], &outputs[i], opts));
+    }
+    results.into_iter().map(|r| r.unwrap_or_else(|| Ok(FileReport { pending: true, ..Default::default() }))).collect()
+}
+
+
+/// What to do when joins of external data miss too many structures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum JoinCheck {
+    /// Log a warning
+    Warn,
+    /// Fail the run once all inputs are converted
+    Fail,
+}
+
+
+/// How failing inputs are placed in the quarantine directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum QuarantineMode {
+    Copy,
+    Symlink,
+}
+
+
+/// Puts a failing input into `dir` next to a `<name>.error.txt` holding the
+/// error, so a batch's failures can be inspected or re-run on their own.
+fn quarantine(fname: &str, err: &anyhow::Error, dir: &Path, mode: QuarantineMode) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let src = Path::new(fname);
+    let name = src.file_name().and_then(|n| n.to_str()).unwrap_or(fname);
+    let taken = |target: &Path| target.exists() || target.is_symlink();
+    // Whether `target` holds this input from an earlier run, by the first line of its error file
+    let earlier = |target: &Path| {
+        std::fs::read_to_string(format!("{}.error.txt", target.display())).is_ok_and(|text| text.lines().next() == Some(fname))
+    };
+    // Inputs from different directories may share a file name; the hashed
+    // name is only ever taken by this input
+    let mut target = dir.join(name);
+    if taken(&target) && !earlier(&target) {
+        target = dir.join(format!("{:08x}_{}", stable_hash(fname.as_bytes()) as u32, name));
+    }
+    if taken(&target) {
+        std::fs::remove_file(&target)?;
+    }
+    match mode {
+        QuarantineMode::Copy => {
+            std::fs::copy(src, &target)?;
+        }
+        QuarantineMode::Symlink => {
+            symlink(&src.canonicalize()?, &target)?;
+        }
+    }
+    atomic::write(format!("{}.error.txt", target.display()), format!("{}\n{:#}\n", fname, err).as_bytes())?;
+    Ok(())
+}
+
+
+#[cfg(unix)]
+fn symlink(src: &Path, target: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(src, target)
+}
+
+#[cfg(windows)]
+fn symlink(src: &Path, target: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(src, target)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_src: &Path, _target: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "--quarantine-mode symlink isn't supported on this platform"))
+}
+
+
+/// Writes flagged graphs as tab separated input, output and reasons.
+fn write_review_list(degenerate: &[(&String, &String, &Vec<Degeneracy>)], path: &Path) -> Result<()> {
+    let mut file = atomic::AtomicFile::create(path)?;
+    for (input, output, issues) in degenerate {
+        let reasons: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+        writeln!(file, "{}\t{}\t{}", input, output, reasons.join(", "))?;
+    }
+    file.commit()?;
+    Ok(())
+}
+
+
+/// Number of files converted by `--dry-run` to estimate output size.
+const DRY_RUN_ESTIMATE_FILES: usize = 5;
+
+/// Reports what a run would do without writing any outputs: matched inputs,
+/// planned outputs, output paths claimed by more than one input and an
+/// estimate of disk usage extrapolated from converting a few files into a
+/// scratch directory.
+fn dry_run(paths: &[String], opts: &Options, policy: CollisionPolicy) {
+    println!("Dry run: {} input files matched", paths.len());
+    let collisions = find_collisions(paths, opts.format, &opts.naming);
+    for (out, inputs) in &collisions {
+        println!("Collision: {} would be written by {} inputs: {}", out, inputs.len(), inputs.join(", "));
+    }
+    let outputs = match plan_outputs(paths, CollisionPolicy::Disambiguate, opts.format, &opts.naming) {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            println!("Run would fail: {}", e);
+            return;
+        }
+    };
+    for (p, out) in paths.iter().zip(&outputs) {
+        println!("{} -> {}", p, out);
+    }
+    if policy == CollisionPolicy::Fail && !collisions.is_empty() {
+        println!("Run would fail: {} output paths collide, see --on-collision", collisions.len());
+    }
+
+    let input_bytes = |p: &str| std::fs::metadata(p).map(|m| m.len()).unwrap_or(0);
+    let total_input: u64 = paths.iter().map(|p| input_bytes(p)).sum();
+    let scratch = std::env::temp_dir().join(format!("graphein-dry-run-{}", std::process::id()));
+    let (mut sampled_input, mut sampled_output) = (0u64, 0u64);
+    for (i, p) in paths.iter().take(DRY_RUN_ESTIMATE_FILES).enumerate() {
+        let dir = scratch.join(i.to_string());
+        let save_fname = dir.join(format!("sample{}", opts.format.suffix()));
+        let written = std::fs::create_dir_all(&dir)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| process_pdb_file(p, save_fname.to_str().unwrap(), opts))
+            .and_then(|_| Ok(std::fs::read_dir(&dir)?.flatten().filter_map(|e| e.metadata().ok()).map(|m| m.len()).sum::<u64>()));
+        match written {
+            Ok(size) => {
+                sampled_input += input_bytes(p);
+                sampled_output += size;
+            }
+            Err(e) => warn!("{:?}", e),
+        }
+    }
+    let _ = std::fs::remove_dir_all(&scratch);
+    if sampled_input > 0 {
+        let estimate = total_input as f64 * sampled_output as f64 / sampled_input as f64;
+        println!("Estimated output size: {:.1} MiB (from {:.1} MiB of input)", estimate / 1048576.0, total_input as f64 / 1048576.0);
+    } else {
+        warn!("Could not estimate output size, no sample file converted successfully");
+    }
+}
+
+
+fn parse_strictness(s: &str) -> std::result::Result<StrictnessLevel, String> {
+    match s {
+        "strict" => Ok(StrictnessLevel::Strict),
+        "medium" => Ok(StrictnessLevel::Medium),
+        "loose" => Ok(StrictnessLevel::Loose),
+        _ => Err(format!("unknown strictness {}, expected strict, medium or loose", s)),
+    }
+}
+
+
+fn parse_polymer(s: &str) -> std::result::Result<EntityType, String> {
+    match s {
+        "protein" => Ok(EntityType::Protein),
+        "dna" => Ok(EntityType::Dna),
+        "rna" => Ok(EntityType::Rna),
+        _ => Err(format!("unknown polymer type {}, expected protein, dna or rna", s)),
+    }
+}
+
+
+fn parse_positive(s: &str) -> std::result::Result<f64, String> {
+    let v: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if v > 0.0 && v.is_finite() {
+        Ok(v)
+    } else {
+        Err(format!("{} is not a positive number", v))
+    }
+}
+
+fn parse_fraction(s: &str) -> std::result::Result<f64, String> {
+    let f: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if f > 0.0 && f <= 1.0 {
+        Ok(f)
+    } else {
+        Err(format!("{} is not in range (0, 1]", f))
+    }
+}
+
+/// Whether an input belongs to `shard`. Inputs are assigned by a hash of
+/// their path so that every job of a sharded run picks a disjoint part
+/// without coordination, and inputs keep their shard when others are added
+/// or removed.
+fn in_shard(path: &str, shard: Shard) -> bool {
+    stable_hash(path.as_bytes()) % shard.count as u64 == shard.index as u64
+}
+
+
+/// Manifest entries of a run: the status and outputs of every input.
+/// Entries of a resumed run's manifest are carried over.
+fn manifest_entries(paths: &[String], results: &[Result<FileReport>], resumed: Vec<manifest::Entry>) -> Vec<manifest::Entry> {
+    resumed.into_iter().chain(paths.iter().zip(results).map(|(p, r)| match r {
+        Ok(report) => manifest::Entry {
+            input: p.clone(),
+            status: if report.pending {
+                manifest::Status::Pending
+            } else if report.excluded {
+                manifest::Status::Excluded
+            } else {
+                manifest::Status::Converted
+            },
+            outputs: report.outputs.clone(),
+            graphs: report.graphs.clone(),
+            error: None,
+            unmatched_joins: report.unmatched_joins.clone(),
+        },
+        Err(e) => manifest::Entry {
+            input: p.clone(),
+            status: manifest::Status::Failed,
+            outputs: Vec::new(),
+            graphs: Vec::new(),
+            error: Some(format!("{:#}", e)),
+            unmatched_joins: Vec::new(),
+        },
+    })).collect()
+}
+
+
+/// Writes the node and edge counts of every graph as CSV, sorted by node
+/// count, for size-based bucketing without opening the graphs.
+fn write_size_index(entries: &[manifest::Entry], out: &Path) -> Result<()> {
+    let mut graphs: Vec<&GraphSize> = entries.iter().flat_map(|e| &e.graphs).collect();
+    graphs.sort_by(|a, b| (a.nodes, a.edges, &a.path).cmp(&(b.nodes, b.edges, &b.path)));
+    let mut csv = String::from("path,nodes,edges\n");
+    for g in graphs {
+        csv.push_str(&format!("{},{},{}\n", g.path, g.nodes, g.edges));
+    }
+    atomic::write(out, csv.as_bytes())?;
+    Ok(())
+}
+
+
+fn write_manifest(manifest: &Manifest, out: &Path) -> Result<()> {
+    atomic::write(out, serde_json::to_string_pretty(manifest)?.as_bytes())?;
+    Ok(())
+}
+
+
+fn read_manifest(path: &Path) -> Result<Manifest> {
+    let file = File::open(path).map_err(|e| anyhow::anyhow!("Failed to open manifest {} - {}", path.display(), e))?;
+    Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+}
+
+
+/// Merges manifests into `out`, returning the number of inputs.
+fn merge_manifests(inputs: &[&PathBuf], out: &Path) -> Result<usize> {
+    let manifests = inputs.iter()
+        .map(|p| Ok((p.display().to_string(), read_manifest(p)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let merged = manifest::merge(&manifests)?;
+    write_manifest(&merged, out)?;
+    Ok(merged.entries.len())
+}
+
+
+/// Replaces matched prediction output directories by the models to convert.
+fn prediction_models(paths: Vec<String>) -> Result<Vec<String>> {
+    let mut models = Vec::new();
+    for p in paths {
+        if Path::new(&p).is_dir() {
+            models.extend(prediction::models(Path::new(&p))?.into_iter().map(|m| m.to_string_lossy().into_owned()));
+        } else {
+            models.push(p);
+        }
+    }
+    Ok(models)
+}
+
+
+/// Picks a seeded random subset of the matched files, either `sample` files or
+/// `fraction` of them (rounded up, at least one). Input order is preserved.
+fn sample_paths(paths: Vec<String>, sample: Option<usize>, fraction: Option<f64>, seed: u64) -> Vec<String> {
+    let count = match (sample, fraction) {
+        (Some(n), _) => n,
+        (None, Some(f)) => ((paths.len() as f64 * f).ceil() as usize).max(1),
+        (None, None) => return paths,
+    };
+    if count >= paths.len() {
+        return paths;
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut picked = rand::seq::index::sample(&mut rng, paths.len(), count).into_vec();
+    picked.sort_unstable();
+    info!("Sampling {} of {} matched files (seed {})", count, paths.len(), seed);
+    picked.into_iter().map(|i| paths[i].clone()).collect()
+}
+
+
+/// Files matching a glob pattern.
+fn glob_paths(pattern: &Path) -> Vec<String> {
+    let pdb_glob = glob(pattern.to_str().unwrap()).expect("Failed to read glob pattern");
+    pdb_glob.map(|p| String::from(p.unwrap().to_str().unwrap())).collect()
+}
+
+
+/// PDB ids of a file with one id per line, skipping empty lines and lines
+/// starting with `#`.
+fn read_id_file(path: &Path) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(text.lines().map(|l| l.trim().to_string()).filter(|l| !l.is_empty() && !l.starts_with('#')).collect())
+}
+
+
+/// Short descriptions of the parse errors that fail at `strictness`.
+fn failing_errors(errors: &[PDBError], strictness: StrictnessLevel) -> String {
+    errors.iter().filter(|e| e.fails(strictness)).map(|e| e.short_description()).collect::<Vec<_>>().join("; ")
+}
+
+
+/// Summary of the graph of a structure, built in memory.
+fn summarize_structure(fname: &str, builder: &GraphBuilder, strictness: StrictnessLevel) -> Result<stats::GraphSummary> {
+    let (pdb, _) = input::open(fname, strictness)
+        .map_err(|errors| anyhow::anyhow!("Error parsing pdb file {} - {}", fname, failing_errors(&errors, strictness)))?;
+    Ok(stats::GraphSummary::of_graph(fname, &StructureId::from_path(fname).to_string(), &builder.build_graph(&pdb)))
+}
+
+
+/// Report of `graphein inspect` on the structure `fname`.
+fn inspect_structure(fname: &str, cutoffs: &[f64], residues: &residue_graph::ResidueGraphOptions, strictness: StrictnessLevel) -> Result<inspect::Report> {
+    let (pdb, warnings) = input::open(fname, strictness)
+        .map_err(|errors| anyhow::anyhow!("Error parsing pdb file {} - {}", fname, failing_errors(&errors, strictness)))?;
+    let lower = fname.to_ascii_lowercase();
+    // pdbtbx keeps the remarks of PDB files, but not the header of mmCIF files
+    let text = [".cif", ".cif.gz", ".mmcif", ".mmcif.gz"].iter().any(|e| lower.ends_with(e)).then(|| input::read_text(fname).ok()).flatten();
+    let resolution = inspect::resolution(&pdb, text.as_deref());
+    Ok(inspect::Report::new(fname, &StructureId::from_path(fname).to_string(), &pdb, warnings.len(), resolution, cutoffs, residues))
+}
+
+
+/// Parses every file at `strictness`, printing one line per file with its
+/// warnings or error. Returns whether all files parsed.
+fn validate(paths: &[String], strictness: StrictnessLevel) -> bool {
+    let results: Vec<_> = paths.par_iter().map(|p| input::open(p, strictness).map(|(_, warnings)| warnings.len())).collect();
+    let mut failed = 0;
+    for (p, result) in paths.iter().zip(results) {
+        match result {
+            Ok(warnings) => println!("{}\tok\t{} warnings", p, warnings),
+            Err(errors) => {
+                println!("{}\tfailed\t{}", p, failing_errors(&errors, strictness));
+                failed += 1;
+            }
+        }
+    }
+    info!("{} of {} files parsed", paths.len() - failed, paths.len());
+    failed == 0
+}
+
+
+/// Options of `graphein convert`, which config files can set.
+fn convert_command() -> clap::Command {
+    clap::Command::new("convert")
+        .about("Convert structures into graphs")
+        .arg(
+            clap::arg!(--"pdb-glob" <PATH> "Glob pattern for protein files")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::arg!(--"preset" <PRESET> "Start from a named bundle of settings, which the command line and --config override")
+                .value_parser(clap::value_parser!(config::Preset)),
+        )
+        .arg(
+            clap::arg!(--"config" <FILE> "TOML file setting options by their long names, e.g. cutoff = 4.5, optionally grouped into tables; options on the command line take precedence")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::arg!(--"mirror-root" <DIR> "Local mirror of the PDB in the divided layout, used to resolve --ids")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::arg!(--"ids" <IDS> "PDB ids of structures to convert from the mirror")
+                .value_delimiter(',').requires("mirror-root"),
+        )
+        .arg(
+            clap::arg!(--"id-file" <FILE> "File with a PDB id per line to convert from the mirror")
+                .value_parser(clap::value_parser!(PathBuf)).requires("mirror-root"),
+        )
+        .arg(
+            clap::arg!(--"cutoff" <f64> "Cutoff distance for graph edges; several, e.g. 3.5,5,8, label each edge with the smallest it is within")
+                .value_parser(clap::value_parser!(f64)).value_delimiter(',').default_value("3.5"),
+        )
+        .arg(
+            clap::arg!(--"features" <FEATURES> "Optional node features to compute")
+                .value_parser(clap::value_parser!(NodeFeature))
+                .value_delimiter(',')
+                .default_value("valence,electronegativity,charge,coordinates"),
+        )
+        .arg(
+            clap::arg!(--"strict-output" "Fail structures where a requested node feature or annotation is missing on some atoms instead of masking it"),
+        )
+        .arg(
+            clap::arg!(--"canonical" "Sort nodes by structure and serial number and edges by their endpoints, so equal graphs are written byte for byte identically")
+                .conflicts_with("temporal"),
+        )
+        .arg(
+            clap::arg!(--"bond-tolerance" <ANGSTROM> "Label edges between atoms closer than the sum of their covalent radii plus this as covalent bonds")
+                .value_parser(clap::value_parser!(f64)).default_value("0.45"),
+        )
+        .arg(
+            clap::arg!(--"ccd" <FILE> "Resolve residues in this Chemical Component Dictionary (components.cif, may be gzipped) to normalize their atom names and take the elements, formal charges, aromaticity and bonds of hetero residues from it")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::arg!(--"bond-orders" "Label covalent edges with their bond order (single, double, triple or aromatic), from templates for standard residues and from valences for other residues with hydrogens"),
+        )
+        .arg(
+            clap::arg!(--"hbonds" "Detect hydrogen bonds and tag their edges with edge_kind hbond"),
+        )
+        .arg(
+            clap::arg!(--"hbond-distance" <ANGSTROM> "Largest donor-acceptor distance of hydrogen bonds")
+                .value_parser(clap::value_parser!(f64)).default_value("3.5"),
+        )
+        .arg(
+            clap::arg!(--"hbond-angle" <DEGREES> "Smallest donor-hydrogen-acceptor angle of hydrogen bonds")
+                .value_parser(clap::value_parser!(f64)).default_value("120"),
+        )
+        .arg(
+            clap::arg!(--"disulfide-distance" <ANGSTROM> "Largest SG-SG distance of disulfide bridges")
+                .value_parser(clap::value_parser!(f64)).default_value("2.05"),
+        )
+        .arg(
+            clap::arg!(--"no-disulfides" "Don't detect disulfide bridges"),
+        )
+        .arg(
+            clap::arg!(--"edge-mode" <MODE> "Connect atoms within the cutoff (radius) or each atom to its --k nearest neighbours (knn)")
+                .value_parser(clap::value_parser!(EdgeMode)).default_value("radius"),
+        )
+        .arg(
+            clap::arg!(--"k" <K> "Number of neighbours of every atom with --edge-mode knn")
+                .value_parser(clap::value_parser!(usize)).default_value("10"),
+        )
+        .arg(
+            clap::arg!(--"max-neighbors" <K> "Connect each node only to its K nearest neighbours within the cutoff")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            clap::arg!(--"strictness" <LEVEL> "PDB parsing strictness: strict, medium or loose")
+                .value_parser(parse_strictness).default_value("medium"),
+        )
+        .arg(
+            clap::arg!(--"retry-loose" "Retry files that fail to parse at loose strictness"),
+        )
+        .arg(
+            clap::arg!(--"coordinate-check" <ACTION> "What to do with structures whose coordinates look corrupted or not in Ångströms")
+                .value_parser(clap::value_parser!(CoordinateCheck)).default_value("flag"),
+        )
+        .arg(
+            clap::arg!(--"altloc" <POLICY> "How atoms with alternate locations become nodes")
+                .value_parser(clap::value_parser!(AltlocPolicy)).default_value("all"),
+        )
+        .arg(
+            clap::arg!(--"max-nodes" <N> "Trim structures with more nodes than this")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            clap::arg!(--"trim-strategy" <STRATEGY> "Which atoms to keep when trimming to --max-nodes")
+                .value_parser(clap::value_parser!(TrimStrategy)).default_value("center"),
+        )
+        .arg(
+            clap::arg!(--"chunk-size" <ANGSTROM> "Split structures into overlapping boxes of this edge length, one graph per box")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            clap::arg!(--"chunk-halo" <ANGSTROM> "Width of the halo around each chunk [default: cutoff]")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            clap::arg!(--"chunk-above" <N> "Only chunk structures with more nodes than this")
+                .value_parser(clap::value_parser!(usize)).default_value("0"),
+        )
+        .arg(
+            clap::arg!(--"edge-sample-rate" <RATE> "Keep each edge with this probability (0-1], seeded with --seed")
+                .value_parser(parse_fraction),
+        )
+        .arg(
+            clap::arg!(--"max-mean-degree" <DEGREE> "Flag graphs with a higher mean node degree as degenerate")
+                .value_parser(clap::value_parser!(f64)).default_value("100"),
+        )
+        .arg(
+            clap::arg!(--"max-isolated" <FRACTION> "Flag graphs with a larger fraction of nodes without edges as degenerate")
+                .value_parser(clap::value_parser!(f64)).default_value("0.5"),
+        )
+        .arg(
+            clap::arg!(--"review-list" <FILE> "Write inputs whose graphs were flagged as degenerate to FILE")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::arg!(--"merge-into" <FILE> "Merge all matched structures into a single graph written to FILE")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::arg!(--"inter-file-edges" "With --merge-into, add edges between atoms of different structures sharing a coordinate frame"),
+        )
+        .arg(
+            clap::arg!(--"align-to" <REFERENCE> "Superpose every structure onto this reference structure before building graphs"),
+        )
+        .arg(
+            clap::arg!(--"chain-pairs" "Write one interface graph per pair of protein chains in contact instead of one graph per structure")
+                .conflicts_with_all(["chunk-size", "receptor", "temporal", "merge-into"]),
+        )
+        .arg(
+            clap::arg!(--"interface-distance" <ANGSTROM> "Largest heavy atom distance of residues in contact across chains")
+                .value_parser(clap::value_parser!(f64)).default_value("5.0"),
+        )
+        .arg(
+            clap::arg!(--"min-interface-contacts" <N> "Skip chain pairs with fewer residue contacts than this")
+                .value_parser(clap::value_parser!(usize)).default_value("10"),
+        )
+        .arg(
+            clap::arg!(--"keep-symmetric-pairs" "With --chain-pairs, also write symmetric copies of an interface"),
+        )
+        .arg(
+            clap::arg!(--"receptor" <STRUCTURE> "Add this structure, e.g. the receptor of docking poses, to the graph of every input with edges between them")
+                .conflicts_with("chunk-size"),
+        )
+        .arg(
+            clap::arg!(--"align-atoms" <ATOMS> "Atoms paired with the reference to compute the superposition")
+                .value_parser(clap::value_parser!(AlignAtoms)).default_value("ca"),
+        )
+        .arg(
+            clap::arg!(--"correspond" <STRUCTURE> "Write the node correspondence between the graphs of two homologous structures instead of converting")
+                .num_args(2),
+        )
+        .arg(
+            clap::arg!(--"correspondence-alignment" <FASTA> "Pairwise sequence alignment to use for --correspond instead of Needleman-Wunsch"),
+        )
+        .arg(
+            clap::arg!(--"correspondence-out" <FILE> "Output file of --correspond")
+                .value_parser(clap::value_parser!(PathBuf)).default_value("correspondence.json"),
+        )
+        .arg(
+            clap::arg!(--"fasta" <FILE> "Write a sequence graph of every record of this FASTA file instead of converting structures")
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            clap::arg!(--"sequence-window" <RESIDUES> "Connect residues of --fasta sequences at most this far apart")
+                .value_parser(clap::value_parser!(usize)).default_value("2"),
+        )
+        .arg(
+            clap::arg!(--"temporal" "Treat the models of each file as frames and write a temporal graph with per-frame edge occurrences"),
+        )
+        .arg(
+            clap::arg!(--"granularity" <LEVEL> "Build graphs of atoms or of residues")
+                .value_parser(clap::value_parser!(Granularity)).default_value("atom"),
+        )
+        .arg(
+            clap::arg!(--"residue-edges" <DISTANCE> "Distance residues are connected by with --granularity residue and in contact maps [default cutoff: 8 for ca, 5 for min-distance]")
+                .value_parser(clap::value_parser!(residue_graph::ResidueEdges)).default_value("ca"),
+        )
+        .arg(
+            clap::arg!(--"variant" <VARIANT> "Write a graph variant NAME=GRANULARITY[:CUTOFF], e.g. atoms=atom:4.5; several variants go into one container file")
+                .value_parser(parse_variant)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            clap::arg!(--"residue-pairs" "Also write a table of residue pair features (distance, orientations, contact) to <filename>_pairs.csv"),
+        )
+        .arg(
+            clap::arg!(--"pair-max-dist" <ANGSTROM> "Largest CB-CB distance of residue pairs in the table")
+                .value_parser(clap::value_parser!(f64)).default_value("20.0"),
+        )
+        .arg(
+            clap::arg!(--"contact-pairs" "Also write contacting and sampled non-contacting residue pairs to <filename>_contacts.csv"),
+        )
+        .arg(
+            clap::arg!(--"negative-ratio" <RATIO> "Non-contacting pairs sampled per contact, matching sequence separation")
+                .value_parser(clap::value_parser!(f64)).default_value("1.0"),
+        )
+        .arg(
+            clap::arg!(--"interface-only" "Only export residue pairs across chains with --contact-pairs"),
+        )
+        .arg(
+            clap::arg!(--"interactions" "Also write the hydrogen bonds, salt bridges and disulfide bridges to <filename>_interactions.csv"),
+        )
+        .arg(
+            clap::arg!(--"ligand-fingerprint" "Also write the interactions of every protein residue with the ligands to <filename>_fingerprint.csv"),
+        )
+        .arg(
+            clap::arg!(--"salt-bridge-distance" <ANGSTROM> "Largest distance between oppositely charged atoms of salt bridges")
+                .value_parser(clap::value_parser!(f64)).default_value("4.0"),
+        )
+        .arg(
+            clap::arg!(--"format" <FORMAT> "Output format")
+                .value_parser(clap::value_parser!(OutputFormat)).default_value("json"),
+        )
+        .arg(
+            clap::arg!(--"quantize-distances" <SCALE> "Store edge distances as 16 bit integers in units of 1/SCALE Å, e.g. 100 for centi-Ångström")
+                .value_parser(parse_positive),
+        )
+        .arg(
+            clap::arg!(--"layout" <LAYOUT> "Layout of graphs in JSON outputs")
+                .value_parser(clap::value_parser!(Layout)).default_value("structs"),
+        )
+        .arg(
+            clap::arg!(--"voxel-size" <ANGSTROM> "Voxel edge length for --format voxel")
+                .value_parser(parse_positive).default_value("1.0"),
+        )
+        .arg(
+            clap::arg!(--"voxel-dim" <N> "Fixed number of voxels per axis, centered on the structure [default: fit the structure]")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            clap::arg!(--"voxel-padding" <ANGSTROM> "Padding around the structure when the grid is fit to it")
+                .value_parser(clap::value_parser!(f64)).default_value("2.0"),
+        )
+        .arg(
+            clap::arg!(--"voxel-channels" <CHANNELS> "Grid channels: element symbols, other, all, charge or electronegativity")
+                .value_parser(voxel::Channel::parse).value_delimiter(',').default_value("C,N,O,S,other"),
+        )
+        .arg(
+            clap::arg!(--"contact-values" <VALUES> "Values of --format contact-map and contact-map-csv")
+                .value_parser(clap::value_parser!(contact_map::ContactValues)).default_value("contacts"),
+        )
+        .arg(
+            clap::arg!(--"score-columns" <COLUMNS> "Columns of per-residue score tables (e.g. Rosetta's pose energies) attached to nodes [default: all]")
+                .value_delimiter(','),
+        )
+        .arg(
+            clap::arg!(--"predictions" "Read inputs as structure prediction outputs (ColabFold, AlphaFold, ESMFold): matched directories are replaced by their top-ranked models and pLDDT is attached to nodes"),
+        )
+        .arg(
+            clap::arg!(--"embeddings" <DIR> "Attach precomputed per-residue embeddings (<name>.safetensors with a tensor per chain, or <name>_<chain>.npy) from this directory to nodes")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::arg!(--"conservation" <DIR> "Attach conservation and profiles from PSI-BLAST PSSMs or HH-suite HMMs (<name>_<chain>.pssm or .hhm) in this directory to nodes")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::arg!(--"labels" <TABLE> "Join GO terms and EC numbers from this annotation table (e.g. SIFTS pdb_chain_go.tsv) onto structures by PDB id and chain")
+                .value_parser(clap::value_parser!(PathBuf))
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            clap::arg!(--"sifts" <DIR> "Attach the UniProt accession and position of every residue from SIFTS residue mappings (<pdb id>.xml.gz) in this directory to nodes")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::arg!(--"residue-features" <TABLE> "Attach externally computed per-residue values from this TSV (id, chain, resnum, value, name) to nodes as named features")
+                .value_parser(clap::value_parser!(PathBuf))
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            clap::arg!(--"secondary-structure" "Assign DSSP secondary structure to amino acids and attach it to their atoms as helix, sheet or coil with the eight state code"),
+        )
+        .arg(
+            clap::arg!(--"atom-types" <SCHEME> "Attach the chemical type of every atom under this scheme to its node as chemical_type")
+                .value_parser(clap::value_parser!(typing::AtomTypeScheme)),
+        )
+        .arg(
+            clap::arg!(--"unknown-radius" <POLICY> "Van der Waals radius of elements without a tabulated one, for SASA and voxel grids: a radius in Å, group to estimate it from the periodic table, or error to fail the structure")
+                .value_parser(radius::UnknownRadius::parse)
+                .default_value("1.8"),
+        )
+        .arg(
+            clap::arg!(--"require-elements" <ELEMENTS> "Convert only structures containing all of these elements, e.g. Zn")
+                .value_delimiter(','),
+        )
+        .arg(
+            clap::arg!(--"require-residue" <RESIDUES> "Convert only structures containing all of these residues, e.g. HEM")
+                .value_delimiter(','),
+        )
+        .arg(
+            clap::arg!(--"polymer-only" <TYPES> "Keep only residues of these polymer types (protein, dna, rna), dropping waters, ions and ligands")
+                .value_parser(parse_polymer).value_delimiter(','),
+        )
+        .arg(
+            clap::arg!(--"chains" <IDS> "Build graphs of only these chains, e.g. A,B")
+                .value_delimiter(',')
+                .conflicts_with("exclude-chains"),
+        )
+        .arg(
+            clap::arg!(--"exclude-chains" <IDS> "Build graphs of every chain but these")
+                .value_delimiter(','),
+        )
+        .arg(
+            clap::arg!(--"models" <MODELS> "Models of multi-model structures such as NMR ensembles to build graphs of: all for one graph per model, written with a _model<N> suffix, first, or a model number; without it all models go into one graph")
+                .value_parser(selection::ModelSelection::parse)
+                .conflicts_with("temporal"),
+        )
+        .arg(clap::arg!(--"no-water" "Leave waters out of the graph").overrides_with("water"))
+        .arg(clap::arg!(--"water" "Keep waters in the graph (default)").overrides_with("no-water"))
+        .arg(clap::arg!(--"no-hetatm" "Leave the atoms of HETATM records out of the graph, i.e. waters, ions, ligands and modified residues").overrides_with("hetatm"))
+        .arg(clap::arg!(--"hetatm" "Keep the atoms of HETATM records in the graph (default)").overrides_with("no-hetatm"))
+        .arg(clap::arg!(--"no-hydrogens" "Leave hydrogens, deuterium and tritium out of the graph").overrides_with("hydrogens"))
+        .arg(clap::arg!(--"hydrogens" "Keep hydrogens in the graph (default)").overrides_with("no-hydrogens"))
+        .arg(
+            clap::arg!(--"sample" <N> "Process only a random subset of N matched files")
+                .value_parser(clap::value_parser!(usize))
+                .conflicts_with("sample-fraction"),
+        )
+        .arg(
+            clap::arg!(--"sample-fraction" <FRACTION> "Process only a random fraction (0-1] of matched files")
+                .value_parser(parse_fraction),
+        )
+        .arg(
+            clap::arg!(--"seed" <SEED> "Seed for random sampling, trimming, negative pairs and edge thinning")
+                .value_parser(clap::value_parser!(u64)).default_value("0"),
+        )
+        .arg(
+            clap::arg!(--"order" <ORDER> "Order in which inputs are converted")
+                .value_parser(clap::value_parser!(ScheduleOrder)).default_value("largest-first"),
+        )
+        .arg(
+            clap::arg!(--"max-memory" <SIZE> "Limit the estimated memory of structures converted at once, e.g. 16G; larger structures are converted alone at the end")
+                .value_parser(memory::parse_size),
+        )
+        .arg(
+            clap::arg!(--"shard" <SHARD> "Process only shard i/n of the inputs (i from 0), to split a run across n jobs")
+                .value_parser(clap::value_parser!(Shard)),
+        )
+        .arg(
+            clap::arg!(--"max-unmatched" <FRACTION> "Largest fraction of converted structures a join of labels, residue features, embeddings, profiles or SIFTS mappings may find nothing for")
+                .value_parser(clap::value_parser!(f64)).default_value("0.05"),
+        )
+        .arg(
+            clap::arg!(--"join-check" <ACTION> "What to do when a join misses more structures than --max-unmatched")
+                .value_parser(clap::value_parser!(JoinCheck)).default_value("warn"),
+        )
+        .arg(
+            clap::arg!(--"manifest" <FILE> "Write the status and outputs of every input, and the shard, to this JSON file")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::arg!(--"verify" "Read every written graph back and check that it parses with the expected node and edge counts"),
+        )
+        .arg(
+            clap::arg!(--"check-invariants" "Check every graph for self-loops, duplicate or one-way edges, distances beyond the cutoff and inconsistent feature lengths before writing it, failing structures with violations")
+                .conflicts_with("temporal"),
+        )
+        .arg(
+            clap::arg!(--"size-index" <FILE> "Write the node and edge counts of every graph to this CSV file, for size-based batching")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::arg!(--"resume" <MANIFEST> "Skip the inputs a previous run's manifest records as converted or excluded")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::arg!(--"dry-run" "Report matched inputs, planned outputs and estimated disk usage without converting"),
+        )
+        .arg(
+            clap::arg!(--"on-collision" <POLICY> "What to do when several inputs map to the same output file")
+                .value_parser(clap::value_parser!(CollisionPolicy)).default_value("fail"),
+        )
+        .arg(
+            clap::arg!(--"out-dir" <DIR> "Write outputs into this directory instead of next to their inputs")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::arg!(--"out-template" <TEMPLATE> "Name outputs after this template of {id}, {stem}, {parent} and {cutoff}, e.g. \"{stem}_{cutoff}.json\"")
+                .value_parser(OutputNaming::parse_template),
+        )
+        .arg(
+            clap::arg!(--"quarantine-dir" <DIR> "Copy or link failing inputs and their errors into this directory")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            clap::arg!(--"quarantine-mode" <MODE> "How failing inputs are placed in the quarantine directory")
+                .value_parser(clap::value_parser!(QuarantineMode)).default_value("copy"),
+        )
+}
+
+
+fn main() {
+    env_logger::init();
+    let cmd = clap::Command::new("graphein")
+        .bin_name("graphein")
+        .subcommand_required(true)
+        .subcommand(convert_command())
+        .subcommand(
+            clap::Command::new("validate")
+                .about("Parse structures without converting them and report the ones that fail")
+                .arg(clap::arg!(--"pdb-glob" <PATH> "Glob pattern for protein files").value_parser(clap::value_parser!(PathBuf)).required(true))
+                .arg(clap::arg!(--"strictness" <LEVEL> "Which pdbtbx warnings abort parsing").value_parser(parse_strictness).default_value("medium")),
+        )
+        .subcommand(
+            clap::Command::new("stats")
+                .about("Print the size, degrees, connectivity and composition of graphs, built in memory or written")
+                .arg(clap::arg!([GRAPHS] ... "Graph outputs (_graph.json)").value_parser(clap::value_parser!(PathBuf)))
+                .arg(clap::arg!(--"pdb-glob" <PATH> "Build the graphs of these structures in memory instead").value_parser(clap::value_parser!(PathBuf)))
+                .group(clap::ArgGroup::new("inputs").args(["GRAPHS", "pdb-glob"]).required(true))
+                .arg(clap::arg!(--"cutoff" <f64> "Cutoff distance for graph edges built with --pdb-glob").value_parser(clap::value_parser!(f64)).default_value("3.5"))
+                .arg(clap::arg!(--"strictness" <LEVEL> "Which pdbtbx warnings abort parsing").value_parser(parse_strictness).default_value("medium"))
+                .arg(clap::arg!(--"format" <FORMAT> "Report format").value_parser(clap::value_parser!(stats::StatsFormat)).default_value("table")),
+        )
+        .subcommand(
+            clap::Command::new("inspect")
+                .about("Report the chains, hetero content and resolution of a structure and the size of its graphs under some parameters")
+                .arg(clap::arg!(<FILE> "Structure file"))
+                .arg(clap::arg!(--"cutoff" <f64> "Cutoff distances of the atom graphs to preview").value_parser(clap::value_parser!(f64)).value_delimiter(',').default_value("3.5"))
+                .arg(clap::arg!(--"residue-edges" <DISTANCE> "Distance residues of the residue graph preview are connected by").value_parser(clap::value_parser!(residue_graph::ResidueEdges)).default_value("ca"))
+                .arg(clap::arg!(--"residue-cutoff" <f64> "Cutoff distance of the residue graph preview [default: 8 for ca, 5 for min-distance]").value_parser(clap::value_parser!(f64)))
+                .arg(clap::arg!(--"strictness" <LEVEL> "Which pdbtbx warnings abort parsing").value_parser(parse_strictness).default_value("medium"))
+                .arg(clap::arg!(--"format" <FORMAT> "Report format").value_parser(clap::value_parser!(inspect::InspectFormat)).default_value("text")),
+        )
+        .subcommand(
+            clap::Command::new("fetch")
+                .about("Download structures from the PDB")
+                .arg(clap::arg!([IDS] ... "PDB ids of the structures"))
+                .arg(clap::arg!(--"id-file" <FILE> "File with a PDB id per line").value_parser(clap::value_parser!(PathBuf)))
+                .arg(clap::arg!(--"out-dir" <DIR> "Directory the structures are downloaded to").value_parser(clap::value_parser!(PathBuf)).default_value("."))
+                .arg(clap::arg!(--"format" <FORMAT> "File format to download").value_parser(clap::value_parser!(fetch::FetchFormat)).default_value("cif"))
+                .arg(clap::arg!(--"base-url" <URL> "Server to download from").default_value(fetch::DEFAULT_BASE_URL))
+                .arg(clap::arg!(--"jobs" <N> "Downloads running at the same time").value_parser(clap::value_parser!(usize)).default_value("4"))
+                .arg(clap::arg!(--"retries" <N> "Retries of downloads failing with a transient error").value_parser(clap::value_parser!(usize)).default_value("3"))
+                .arg(clap::arg!(--"cache-dir" <DIR> "Directory keeping downloads across runs, linked into --out-dir").value_parser(clap::value_parser!(PathBuf))),
+        )
+        .subcommand(
+            clap::Command::new("merge")
+                .about("Combine the manifests of several runs or shards into one, checking for collisions and missing shards")
+                .arg(clap::arg!(<MANIFESTS> ... "Manifests written with --manifest").value_parser(clap::value_parser!(PathBuf)))
+                .arg(clap::arg!(--"out" <FILE> "Merged manifest").value_parser(clap::value_parser!(PathBuf)).required(true)),
+        )
+        .subcommand(
+            clap::Command::new("index")
+                .about("Index the graph outputs in a directory into a SQLite database")
+                .arg(clap::arg!(<DIR> "Directory searched recursively for graph outputs").value_parser(clap::value_parser!(PathBuf)))
+                .arg(clap::arg!(--"db" <FILE> "Index database, updated if it exists").value_parser(clap::value_parser!(PathBuf)).default_value("graphein_index.sqlite")),
+        )
+        .subcommand(
+            clap::Command::new("query")
+                .about("List the indexed graph outputs matching all given conditions")
+                .arg(clap::arg!(--"db" <FILE> "Index database").value_parser(clap::value_parser!(PathBuf)).default_value("graphein_index.sqlite"))
+                .arg(clap::arg!(--"where" <SQL> "SQL condition on the graphs table, e.g. \"chain_count > 1\""))
+                .arg(clap::arg!(--"ligand" <NAME> "Graphs containing this ligand residue, e.g. HEM").action(clap::ArgAction::Append))
+                .arg(clap::arg!(--"min-nodes" <N> "Graphs with at least N nodes").value_parser(clap::value_parser!(usize)))
+                .arg(clap::arg!(--"max-nodes" <N> "Graphs with at most N nodes").value_parser(clap::value_parser!(usize))),
+        );
     
-    for atom in pdb.atoms() {
-        let ele = match atom.element() {
-            Some(e) => e,
-            None => continue
-        };
-        let an = AtomNode {
-            id: atom.serial_number(),
-            atom_number: atomic_number(ele),
-            valence: valence_electrons(ele),
-            electronegativity: electronegativity(ele),
-            charge: atom.charge(),
-        };
-        let node_id = protein_graph.add_node(an);
-        atom_sn_node_id.insert(atom.serial_number(), node_id);
 
+    // Before subcommands, graphein only converted: `graphein --pdb-glob ...`
+    let mut argv: Vec<OsString> = std::env::args_os().collect();
+    if argv.get(1).and_then(|a| a.to_str()).is_some_and(|a| a.starts_with('-') && a != "-h" && a != "--help") {
+        warn!("Options without a subcommand are deprecated, use graphein convert");
+        argv.insert(1, "convert".into());
     }
+    let matches = cmd.get_matches_from(&argv);
 
-    for atom in pdb.atoms() {
-        let atom_node_id = match atom_sn_node_id.get(&atom.serial_number()) {
-            Some(an) => an,
-            None => continue
-        };
-        for neighbor_atom in tree.locate_within_distance(atom.pos(), edge_max_dist * edge_max_dist) {
-            let neigh_sn = neighbor_atom.serial_number();
-            if atom.pos() == neighbor_atom.pos() {  // Same atom
-                continue;
+    match matches.subcommand() {
+        Some(("index", sub)) => {
+            let dir = sub.get_one::<PathBuf>("DIR").unwrap();
+            let db = sub.get_one::<PathBuf>("db").unwrap();
+            match index::build_index(dir, db) {
+                Ok(report) => info!(
+                    "Indexed {} graphs into {} ({} unchanged, {} removed)",
+                    report.added, db.display(), report.unchanged, report.removed
+                ),
+                Err(e) => {
+                    error!("{:?}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(("merge", sub)) => {
+            let inputs: Vec<&PathBuf> = sub.get_many::<PathBuf>("MANIFESTS").unwrap().collect();
+            let out = sub.get_one::<PathBuf>("out").unwrap();
+            match merge_manifests(&inputs, out) {
+                Ok(count) => info!("Merged {} manifests with {} inputs into {}", inputs.len(), count, out.display()),
+                Err(e) => {
+                    error!("{:?}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(("query", sub)) => {
+            let query = index::Query {
+                condition: sub.get_one::<String>("where").cloned(),
+                ligands: sub.get_many::<String>("ligand").map(|v| v.cloned().collect()).unwrap_or_default(),
+                min_nodes: sub.get_one::<usize>("min-nodes").copied(),
+                max_nodes: sub.get_one::<usize>("max-nodes").copied(),
+            };
+            match index::query(sub.get_one::<PathBuf>("db").unwrap(), &query) {
+                Ok(paths) => paths.iter().for_each(|p| println!("{}", p)),
+                Err(e) => {
+                    error!("{:?}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(("validate", sub)) => {
+            let paths = glob_paths(sub.get_one::<PathBuf>("pdb-glob").unwrap());
+            if !validate(&paths, *sub.get_one::<StrictnessLevel>("strictness").unwrap()) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(("stats", sub)) => {
+            let results: Vec<Result<stats::GraphSummary>> = match sub.get_one::<PathBuf>("pdb-glob") {
+                Some(pattern) => {
+                    let builder = GraphBuilder::new().cutoff(*sub.get_one::<f64>("cutoff").unwrap());
+                    let strictness = *sub.get_one::<StrictnessLevel>("strictness").unwrap();
+                    glob_paths(pattern).par_iter().map(|p| summarize_structure(p, &builder, strictness)).collect()
+                }
+                None => {
+                    let paths: Vec<&PathBuf> = sub.get_many::<PathBuf>("GRAPHS").unwrap().collect();
+                    paths.par_iter().map(|p| stats::summarize_file(p)).collect()
+                }
             };
-            let node_id =  match atom_sn_node_id.get(&neigh_sn){
-                Some(ni) => ni,
-                None => continue
+            let (total, mut summaries) = (results.len(), Vec::new());
+            for result in results {
+                match result {
+                    Ok(summary) => summaries.push(summary),
+                    Err(e) => error!("{:?}", e),
+                }
+            }
+            match sub.get_one::<stats::StatsFormat>("format").unwrap() {
+                stats::StatsFormat::Table => {
+                    println!("{}", stats::GraphSummary::HEADER);
+                    summaries.iter().for_each(|s| println!("{}", s.row()));
+                }
+                stats::StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&summaries).unwrap()),
+            }
+            if summaries.len() < total {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(("inspect", sub)) => {
+            let fname = sub.get_one::<String>("FILE").unwrap();
+            let edges = *sub.get_one::<residue_graph::ResidueEdges>("residue-edges").unwrap();
+            let residues = residue_graph::ResidueGraphOptions { edges, cutoff: sub.get_one::<f64>("residue-cutoff").copied().unwrap_or(edges.default_cutoff()) };
+            let cutoffs: Vec<f64> = sub.get_many::<f64>("cutoff").unwrap().copied().collect();
+            match inspect_structure(fname, &cutoffs, &residues, *sub.get_one::<StrictnessLevel>("strictness").unwrap()) {
+                Ok(report) => match sub.get_one::<inspect::InspectFormat>("format").unwrap() {
+                    inspect::InspectFormat::Text => print!("{}", report.text()),
+                    inspect::InspectFormat::Json => println!("{}", serde_json::to_string_pretty(&report).unwrap()),
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(("fetch", sub)) => {
+            let mut ids: Vec<String> = sub.get_many::<String>("IDS").map(|v| v.cloned().collect()).unwrap_or_default();
+            if let Some(id_file) = sub.get_one::<PathBuf>("id-file") {
+                match read_id_file(id_file) {
+                    Ok(file_ids) => ids.extend(file_ids),
+                    Err(e) => {
+                        error!("{:?}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            // Concurrent downloads of one id would write the same file
+            let mut seen = HashSet::new();
+            ids.retain(|id| seen.insert(fetch::normalize_id(id).unwrap_or_else(|_| id.clone())));
+            let dir = sub.get_one::<PathBuf>("out-dir").unwrap();
+            if let Err(e) = std::fs::create_dir_all(dir) {
+                error!("Cannot create the output directory {}: {}", dir.display(), e);
+                std::process::exit(1);
+            }
+            let (format, base_url) = (*sub.get_one::<fetch::FetchFormat>("format").unwrap(), sub.get_one::<String>("base-url").unwrap());
+            let (jobs, retries) = (*sub.get_one::<usize>("jobs").unwrap(), *sub.get_one::<usize>("retries").unwrap());
+            // Downloads go to the cache, if any, and are linked into the output directory
+            let cache = sub.get_one::<PathBuf>("cache-dir");
+            let store = cache.unwrap_or(dir);
+            if let Err(e) = std::fs::create_dir_all(store) {
+                error!("Cannot create the cache directory {}: {}", store.display(), e);
+                std::process::exit(1);
+            }
+            let (mut manifest, mut cache_manifest) = match (fetch::Manifest::load(dir), fetch::Manifest::load(store)) {
+                (Ok(manifest), Ok(cache_manifest)) => (manifest, cache_manifest),
+                (Err(e), _) | (_, Err(e)) => {
+                    error!("{:?}", e);
+                    std::process::exit(1);
+                }
             };
-            protein_graph.update_edge(*atom_node_id, *node_id, atom.distance(&neighbor_atom));
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs.max(1)).build().unwrap();
+            let results: Vec<Result<(PathBuf, fetch::ManifestEntry, bool)>> = pool.install(|| {
+                ids.par_iter().map(|id| {
+                    let expected = fetch::fetched_path(store, id, format).ok().and_then(|path| cache_manifest.get(&path).cloned());
+                    let (path, entry, fresh) = fetch::fetch(id, format, store, base_url, expected.as_ref(), retries)?;
+                    let path = if cache.is_some() { fetch::place(&path, dir)? } else { path };
+                    Ok((path, entry, fresh))
+                }).collect()
+            });
+            let (mut downloaded, mut failed) = (0, 0);
+            for (id, result) in ids.iter().zip(results) {
+                match result {
+                    Ok((path, entry, fresh)) => {
+                        downloaded += fresh as usize;
+                        debug!("{} -> {}", id, path.display());
+                        if cache.is_some() {
+                            cache_manifest.insert(&path, entry.clone());
+                        }
+                        manifest.insert(&path, entry);
+                    }
+                    Err(e) => {
+                        warn!("Failed to fetch {} - {:?}", id, e);
+                        failed += 1;
+                    }
+                }
+            }
+            info!("Downloaded {} of {} structures into {} ({} already there, {} failed)", downloaded, ids.len(), dir.display(), ids.len() - downloaded - failed, failed);
+            let saved = manifest.save(dir).and_then(|_| if cache.is_some() { cache_manifest.save(store) } else { Ok(()) });
+            if let Err(e) = saved {
+                error!("Failed to write the download manifest - {:?}", e);
+                std::process::exit(1);
+            }
+            if failed > 0 {
+                std::process::exit(1);
+            }
+            return;
         }
+        _ => {}
     }
-    let save_fname = fname.replace(".pdb", "_graph.json");
-    debug!("Parsing protein {}, node couunt {}. edge count {}", fname, protein_graph.node_count(), protein_graph.edge_count());
 
-    let json = serde_json::to_string(&protein_graph)?;
-    let mut file = File::create(&save_fname)?;
-    debug!("Saved graph file {}", &save_fname);
-    file.write_all(&json.as_bytes())?;
+    // Settings of the config are passed as if given before the command line arguments
+    let matches = matches.subcommand_matches("convert").unwrap();
+    let run_config = matches.get_one::<PathBuf>("config").map(|path| {
+        let args = config::RunConfig::read(path).and_then(|mut config| {
+            let args = config.args(&convert_command(), |id| matches.value_source(id) == Some(clap::parser::ValueSource::CommandLine))?;
+            Ok((config, args))
+        });
+        args.unwrap_or_else(|e| {
+            error!("{:?}", e);
+            std::process::exit(1);
+        })
+    });
+    let matches = match &run_config {
+        Some((_, args)) => {
+            let argv = argv[1..2].iter().cloned().chain(args.iter().map(Into::into)).chain(argv[2..].iter().cloned());
+            convert_command().bin_name("graphein convert").get_matches_from(argv)
+        }
+        None => matches.clone(),
+    };
+    // The preset fills in what neither the command line nor the config sets
+    let preset = matches.get_one::<config::Preset>("preset").map(|preset| {
+        let mut config = preset.config();
+        match config.args(&convert_command(), |id| matches.value_source(id) == Some(clap::parser::ValueSource::CommandLine)) {
+            Ok(args) => (config, args),
+            Err(e) => {
+                error!("{:?}", e);
+                std::process::exit(1);
+            }
+        }
+    });
+    let matches = match &preset {
+        Some((_, args)) => {
+            let config_args = run_config.iter().flat_map(|(_, args)| args);
+            let argv = argv[1..2].iter().cloned()
+                .chain(args.iter().map(Into::into))
+                .chain(config_args.map(Into::into))
+                .chain(argv[2..].iter().cloned());
+            convert_command().bin_name("graphein convert").get_matches_from(argv)
+        }
+        None => matches,
+    };
 
-    Ok(())
-}
+    let cutoffs: Vec<f64> = matches.get_many::<f64>("cutoff").unwrap().copied().collect();
+    let edge_max_dist = cutoffs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let residue_opts = {
+        let edges = *matches.get_one::<residue_graph::ResidueEdges>("residue-edges").unwrap();
+        let cutoff = match matches.value_source("cutoff") {
+            Some(clap::parser::ValueSource::DefaultValue) => edges.default_cutoff(),
+            _ => edge_max_dist,
+        };
+        residue_graph::ResidueGraphOptions { edges, cutoff }
+    };
+    let labels = matches.get_many::<PathBuf>("labels").map(|tables| {
+        let mut table = labels::LabelTable::default();
+        for path in tables {
+            if let Err(e) = table.read(path) {
+                error!("{:?}", e);
+                std::process::exit(1);
+            }
+        }
+        info!("Loaded function labels of {} structures", table.structures());
+        table
+    });
+    let residue_features = matches.get_many::<PathBuf>("residue-features").map(|tables| {
+        let mut table = external::ResidueFeatureTable::default();
+        for path in tables {
+            if let Err(e) = table.read(path) {
+                error!("{:?}", e);
+                std::process::exit(1);
+            }
+        }
+        info!("Loaded residue features of {} structures", table.structures());
+        table
+    });
+    let ccd = matches.get_one::<PathBuf>("ccd").map(|path| match ccd::Dictionary::read(path) {
+        Ok(dictionary) => {
+            info!("Loaded {} chemical components from {}", dictionary.len(), dictionary.path);
+            dictionary
+        }
+        Err(e) => {
+            error!("{:?}", e);
+            std::process::exit(1);
+        }
+    });
+    // Sources of the joined data, recorded in the manifest
+    let mut joins: BTreeMap<Join, JoinProvenance> = BTreeMap::new();
+    for (join, arg) in [(Join::Labels, "labels"), (Join::ResidueFeatures, "residue-features")] {
+        let Some(tables) = matches.get_many::<PathBuf>(arg) else { continue };
+        match tables.map(|p| SourceFile::read(p)).collect::<Result<Vec<_>>>() {
+            Ok(sources) => {
+                joins.insert(join, JoinProvenance { sources, ..Default::default() });
+            }
+            Err(e) => {
+                error!("{:?}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    for (join, arg) in [(Join::Embeddings, "embeddings"), (Join::Conservation, "conservation"), (Join::Sifts, "sifts")] {
+        if let Some(dir) = matches.get_one::<PathBuf>(arg) {
+            joins.insert(join, JoinProvenance { directory: Some(dir.display().to_string()), ..Default::default() });
+        }
+    }
+    let graph = GraphBuilder {
+        cutoff: edge_max_dist,
+        max_neighbors: matches.get_one::<usize>("max-neighbors").copied(),
+        knn: (*matches.get_one::<EdgeMode>("edge-mode").unwrap() == EdgeMode::Knn).then(|| *matches.get_one::<usize>("k").unwrap()),
+        features: matches.get_many::<NodeFeature>("features").unwrap().copied().collect(),
+        bond_tolerance: *matches.get_one::<f64>("bond-tolerance").unwrap(),
+        hbonds: matches.get_flag("hbonds").then(|| hbond::HbondCriteria {
+            max_distance: *matches.get_one::<f64>("hbond-distance").unwrap(),
+            min_angle: *matches.get_one::<f64>("hbond-angle").unwrap(),
+        }),
+        disulfide_distance: (!matches.get_flag("no-disulfides")).then(|| *matches.get_one::<f64>("disulfide-distance").unwrap()),
+        bond_orders: matches.get_flag("bond-orders"),
+        constants: run_config.as_ref().map(|(config, _)| config.constants.clone()).unwrap_or_default(),
+        edge_filter: run_config.as_ref().and_then(|(config, _)| config.edge_filter.clone()),
+        cutoffs: Vec::new(),
+    };
+    let graph = if cutoffs.len() > 1 { graph.cutoffs(cutoffs.clone()) } else { graph };
+    let interaction_opts = interactions::InteractionOptions {
+        hbonds: hbond::HbondCriteria {
+            max_distance: *matches.get_one::<f64>("hbond-distance").unwrap(),
+            min_angle: *matches.get_one::<f64>("hbond-angle").unwrap(),
+        },
+        salt_bridge_distance: *matches.get_one::<f64>("salt-bridge-distance").unwrap(),
+        disulfide_distance: graph.disulfide_distance,
+    };
+    let mut opts = Options {
+        graph: graph.clone(),
+        strictness: *matches.get_one::<StrictnessLevel>("strictness").unwrap(),
+        retry_loose: matches.get_flag("retry-loose"),
+        coordinate_check: *matches.get_one::<CoordinateCheck>("coordinate-check").unwrap(),
+        score_columns: matches.get_many::<String>("score-columns").map(|v| v.cloned().collect()),
+        predictions: matches.get_flag("predictions"),
+        embeddings: matches.get_one::<PathBuf>("embeddings").cloned(),
+        conservation: matches.get_one::<PathBuf>("conservation").cloned(),
+        labels,
+        sifts: matches.get_one::<PathBuf>("sifts").cloned(),
+        residue_features,
+        secondary_structure: matches.get_flag("secondary-structure"),
+        atom_types: matches.get_one::<typing::AtomTypeScheme>("atom-types").copied(),
+        ccd,
+        unknown_radius: *matches.get_one::<radius::UnknownRadius>("unknown-radius").unwrap(),
+        config: run_config.map(|(config, _)| config),
+        preset: preset.map(|(preset, _)| preset),
+        verify: matches.get_flag("verify"),
+        check_invariants: matches.get_flag("check-invariants"),
+        require: composition::Requirements::default(),
+        polymer_only: matches.get_many::<EntityType>("polymer-only").map(|v| v.copied().collect()),
+        chains: match (matches.get_many::<String>("chains"), matches.get_many::<String>("exclude-chains")) {
+            (Some(ids), _) => Some(selection::ChainSelection::Only(ids.cloned().collect())),
+            (None, Some(ids)) => Some(selection::ChainSelection::Except(ids.cloned().collect())),
+            (None, None) => None,
+        },
+        exclude: selection::AtomExclusion {
+            water: matches.get_flag("no-water"),
+            hetatm: matches.get_flag("no-hetatm"),
+            hydrogens: matches.get_flag("no-hydrogens"),
+        },
+        models: matches.get_one::<selection::ModelSelection>("models").copied(),
+        altloc: *matches.get_one::<AltlocPolicy>("altloc").unwrap(),
+        max_nodes: matches.get_one::<usize>("max-nodes").copied(),
+        trim_strategy: *matches.get_one::<TrimStrategy>("trim-strategy").unwrap(),
+        seed: *matches.get_one::<u64>("seed").unwrap(),
+        chunk_size: matches.get_one::<f64>("chunk-size").copied(),
+        chunk_halo: matches.get_one::<f64>("chunk-halo").copied().unwrap_or(edge_max_dist),
+        chunk_above: *matches.get_one::<usize>("chunk-above").unwrap(),
+        chain_pairs: matches.get_flag("chain-pairs").then(|| interface::InterfaceOptions {
+            distance: *matches.get_one::<f64>("interface-distance").unwrap(),
+            min_contacts: *matches.get_one::<usize>("min-interface-contacts").unwrap(),
+            keep_symmetric: matches.get_flag("keep-symmetric-pairs"),
+        }),
+        edge_sample_rate: matches.get_one::<f64>("edge-sample-rate").copied(),
+        thresholds: degenerate::Thresholds {
+            max_mean_degree: *matches.get_one::<f64>("max-mean-degree").unwrap(),
+            max_isolated_fraction: *matches.get_one::<f64>("max-isolated").unwrap(),
+        },
+        align: match matches.get_one::<String>("align-to") {
+            Some(reference) => match Reference::load(reference, *matches.get_one::<AlignAtoms>("align-atoms").unwrap()) {
+                Ok(reference) => Some(reference),
+                Err(e) => {
+                    error!("{:?}", e);
+                    std::process::exit(1);
+                }
+            },
+            None => None,
+        },
+        receptor: None,
+        temporal: matches.get_flag("temporal"),
+        residue_graph: (*matches.get_one::<Granularity>("granularity").unwrap() == Granularity::Residue).then(|| residue_opts.clone()),
+        variants: matches.get_many::<VariantSpec>("variant").unwrap_or_default().map(|spec| {
+            let graph = match spec.granularity {
+                Granularity::Atom => VariantGraph::Atom(Box::new(GraphBuilder { cutoff: spec.cutoff.unwrap_or(edge_max_dist), ..graph.clone() })),
+                Granularity::Residue => {
+                    let edges = *matches.get_one::<residue_graph::ResidueEdges>("residue-edges").unwrap();
+                    VariantGraph::Residue(residue_graph::ResidueGraphOptions { edges, cutoff: spec.cutoff.unwrap_or(edges.default_cutoff()) })
+                }
+            };
+            Variant { name: spec.name.clone(), graph }
+        }).collect(),
+        residue_pairs: matches.get_flag("residue-pairs").then(|| *matches.get_one::<f64>("pair-max-dist").unwrap()),
+        contacts: matches.get_flag("contact-pairs").then(|| contacts::ContactOptions {
+            contact_distance: edge_max_dist,
+            negative_ratio: *matches.get_one::<f64>("negative-ratio").unwrap(),
+            interface_only: matches.get_flag("interface-only"),
+        }),
+        strict_output: matches.get_flag("strict-output"),
+        canonical: matches.get_flag("canonical"),
+        interactions: matches.get_flag("interactions").then_some(interaction_opts),
+        fingerprint: matches.get_flag("ligand-fingerprint").then_some(interaction_opts),
+        format: *matches.get_one::<OutputFormat>("format").unwrap(),
+        naming: OutputNaming {
+            dir: matches.get_one::<PathBuf>("out-dir").cloned(),
+            template: matches.get_one::<String>("out-template").cloned(),
+            cutoff: edge_max_dist,
+        },
+        layout: *matches.get_one::<Layout>("layout").unwrap(),
+        quantize_distances: matches.get_one::<f64>("quantize-distances").map(|scale| quantize::DistanceQuantization { scale: *scale }),
+        voxel: voxel::VoxelOptions {
+            size: *matches.get_one::<f64>("voxel-size").unwrap(),
+            dim: matches.get_one::<usize>("voxel-dim").copied(),
+            padding: *matches.get_one::<f64>("voxel-padding").unwrap(),
+            channels: matches.get_many::<voxel::Channel>("voxel-channels").unwrap().cloned().collect(),
+        },
+        contact_map: (residue_opts, *matches.get_one::<contact_map::ContactValues>("contact-values").unwrap()),
+    };
+    if cutoffs.len() > 1 {
+        let unsupported = [
+            ("--temporal", opts.temporal),
+            ("--granularity residue", opts.residue_graph.is_some()),
+            ("--variant", !opts.variants.is_empty()),
+            ("--format contact-map", matches!(opts.format, OutputFormat::ContactMap | OutputFormat::ContactMapCsv)),
+        ];
+        if let Some((mode, _)) = unsupported.iter().find(|(_, enabled)| *enabled) {
+            error!("Several --cutoff values can't be combined with {}", mode);
+            std::process::exit(1);
+        }
+    }
+    if opts.temporal && opts.layout == Layout::Packed {
+        error!("--layout packed can't be combined with --temporal");
+        std::process::exit(1);
+    }
+    if opts.temporal && matches!(opts.format, OutputFormat::Dot | OutputFormat::Pyg | OutputFormat::Parquet | OutputFormat::ContactMap | OutputFormat::ContactMapCsv) {
+        error!("--format {} can't be combined with --temporal", clap::ValueEnum::to_possible_value(&opts.format).unwrap().get_name());
+        std::process::exit(1);
+    }
+    if opts.temporal && opts.graph.knn.is_some() {
+        error!("--edge-mode knn can't be combined with --temporal");
+        std::process::exit(1);
+    }
+    if opts.temporal && opts.graph.edge_filter.is_some() {
+        error!("An [edge-filter] of the config can't be combined with --temporal");
+        std::process::exit(1);
+    }
+    if opts.temporal && opts.graph.max_neighbors.is_some() {
+        error!("--max-neighbors can't be combined with --temporal");
+        std::process::exit(1);
+    }
+    if opts.temporal && opts.quantize_distances.is_some() {
+        error!("--quantize-distances can't be combined with --temporal");
+        std::process::exit(1);
+    }
+    let names: HashSet<&str> = opts.variants.iter().map(|v| v.name.as_str()).collect();
+    if names.len() < opts.variants.len() {
+        error!("--variant names must be unique");
+        std::process::exit(1);
+    }
+    for (mode, enabled, formats) in [
+        ("--granularity residue", opts.residue_graph.is_some(), &[OutputFormat::Json, OutputFormat::Pyg, OutputFormat::ContactMap, OutputFormat::ContactMapCsv][..]),
+        ("--variant", !opts.variants.is_empty(), &[OutputFormat::Json][..]),
+    ] {
+        if !enabled {
+            continue;
+        }
+        let unsupported = ["temporal", "chunk-size", "chain-pairs", "receptor", "merge-into", "strict-output"]
+            .into_iter()
+            .filter(|a| matches.value_source(a) == Some(clap::parser::ValueSource::CommandLine))
+            .map(|a| format!("--{}", a))
+            .chain((!formats.contains(&opts.format)).then(|| format!("--format {}", clap::ValueEnum::to_possible_value(&opts.format).unwrap().get_name())))
+            .collect::<Vec<_>>();
+        if !unsupported.is_empty() {
+            error!("{} can't be combined with {}", mode, unsupported.join(", "));
+            std::process::exit(1);
+        }
+    }
+    if let Some(receptor) = matches.get_one::<String>("receptor") {
+        match prepare(receptor, &opts) {
+            Ok(prepared) => opts.receptor = Some(prepared),
+            Err(e) => {
+                error!("{:?}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    // The receptor is exempt from the composition filter
+    opts.require = composition::Requirements {
+        elements: matches.get_many::<String>("require-elements").map(|v| v.cloned().collect()).unwrap_or_default(),
+        residues: matches.get_many::<String>("require-residue").map(|v| v.cloned().collect()).unwrap_or_default(),
+    };
+    if let Some(pair) = matches.get_many::<String>("correspond") {
+        let pair: Vec<&String> = pair.collect();
+        let alignment = matches.get_one::<String>("correspondence-alignment").map(|s| s.as_str());
+        let out = matches.get_one::<PathBuf>("correspondence-out").unwrap();
+        if let Err(e) = write_correspondence(pair[0], pair[1], alignment, out, &opts) {
+            error!("{:?}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
+    if let Some(fastas) = matches.get_many::<String>("fasta") {
+        let window = *matches.get_one::<usize>("sequence-window").unwrap();
+        for fasta in fastas {
+            if let Err(e) = write_sequence_graphs(fasta, window) {
+                error!("Failed to convert {} - {:?}", fasta, e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-fn main() {
-    env_logger::init();
-    let cmd = clap::Command::new("graphein")
-        .bin_name("graphein")
-        .arg(
-            clap::arg!(--"pdb-glob" <PATH> "Glob pattern for protein files")
-                .value_parser(clap::value_parser!(std::path::PathBuf)),
-        )
-        .arg(
-            clap::arg!(--"cutoff" <f64> "Cutoff distance for graph edges")
-                .value_parser(clap::value_parser!(f64)).default_value("3.5"),
-        );
-    
+    let mut paths: Vec<String> = matches.get_one::<PathBuf>("pdb-glob").map(|pattern| glob_paths(pattern)).unwrap_or_default();
+    let mut ids: Vec<String> = matches.get_many::<String>("ids").map(|v| v.cloned().collect()).unwrap_or_default();
+    if let Some(id_file) = matches.get_one::<PathBuf>("id-file") {
+        match read_id_file(id_file) {
+            Ok(file_ids) => ids.extend(file_ids),
+            Err(e) => {
+                error!("{:?}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if !ids.is_empty() {
+        let root = matches.get_one::<PathBuf>("mirror-root").unwrap();
+        let mut missing = 0;
+        for id in &ids {
+            match mirror::resolve(root, id) {
+                Some(path) => paths.push(path.to_string_lossy().into_owned()),
+                None => {
+                    warn!("{} not found in mirror {}", id, root.display());
+                    missing += 1;
+                }
+            }
+        }
+        info!("Resolved {} of {} ids in mirror {}", ids.len() - missing, ids.len(), root.display());
+    }
+    let paths = if opts.predictions {
+        match prediction_models(paths) {
+            Ok(paths) => paths,
+            Err(e) => {
+                error!("{:?}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        paths
+    };
+    let paths = sample_paths(
+        paths,
+        matches.get_one::<usize>("sample").copied(),
+        matches.get_one::<f64>("sample-fraction").copied(),
+        opts.seed,
+    );
+
+    // Outputs are planned over all inputs, so that collisions between
+    // shards are found and disambiguated the same way by every job
+    let all_paths = paths;
+    let shard = matches.get_one::<Shard>("shard").copied();
+    let paths: Vec<String> = all_paths.iter().filter(|p| shard.is_none_or(|s| in_shard(p, s))).cloned().collect();
+    if let Some(shard) = shard {
+        info!("Shard {} has {} of {} inputs", shard, paths.len(), all_paths.len());
+    }
+
+    let collision_policy = *matches.get_one::<CollisionPolicy>("on-collision").unwrap();
+
+    if matches.get_flag("dry-run") {
+        dry_run(&paths, &opts, collision_policy);
+        return;
+    }
 
-    let matches = cmd.get_matches();
+    if let Some(merged) = matches.get_one::<PathBuf>("merge-into") {
+        match merge_pdb_files(&paths, merged, &opts, matches.get_flag("inter-file-edges")) {
+            Ok(failures) => {
+                for (p, e) in failures {
+                    warn!("Left {} out of the merged graph - {:?}", p, e);
+                }
+            }
+            Err(e) => {
+                error!("{:?}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let outputs: Vec<String> = match plan_outputs(&all_paths, collision_policy, opts.format, &opts.naming) {
+        Ok(outputs) => all_paths.iter().zip(outputs).filter(|(p, _)| shard.is_none_or(|s| in_shard(p, s))).map(|(_, o)| o).collect(),
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(dir) = matches.get_one::<PathBuf>("out-dir") {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            error!("Cannot create the output directory {}: {}", dir.display(), e);
+            std::process::exit(1);
+        }
+    }
 
-    let edge_max_dist = matches.get_one::<f64>("cutoff").unwrap();
-    let pdb_glob = glob(matches.get_one::<std::path::PathBuf>("pdb-glob").unwrap().to_str().unwrap()).expect("Failed to read glob pattern");
+    // Inputs a previous run converted or excluded are skipped
+    let resumed: Vec<manifest::Entry> = match matches.get_one::<PathBuf>("resume") {
+        Some(previous) => match read_manifest(previous) {
+            Ok(manifest) => {
+                let planned: HashSet<&String> = paths.iter().collect();
+                manifest.entries.into_iter().filter(|e| e.status.is_done() && planned.contains(&e.input)).collect()
+            }
+            Err(e) => {
+                error!("{:?}", e);
+                std::process::exit(1);
+            }
+        },
+        None => Vec::new(),
+    };
+    let (paths, outputs): (Vec<String>, Vec<String>) = if resumed.is_empty() {
+        (paths, outputs)
+    } else {
+        let done: HashSet<&String> = resumed.iter().map(|e| &e.input).collect();
+        info!("Resuming with {} inputs already done", done.len());
+        paths.into_iter().zip(outputs).filter(|(p, _)| !done.contains(p)).unzip()
+    };
 
-    let paths: Vec<String> = pdb_glob.map(|p| String::from(p.unwrap().to_str().unwrap())).collect();
+    if let Err(e) = ctrlc::set_handler(|| {
+        if CANCELLED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        warn!("Interrupted, finishing the inputs in progress (press Ctrl-C again to stop immediately)");
+    }) {
+        warn!("Failed to install Ctrl-C handler - {:?}", e);
+    }
 
-    let results: Vec<Result<()>> = paths.par_iter().map(|p| process_pdb_file(p, edge_max_dist)).collect();
+    let max_memory = matches.get_one::<u64>("max-memory").copied();
+    let order = *matches.get_one::<ScheduleOrder>("order").unwrap();
+    let results = convert_all(&paths, &outputs, &opts, order, max_memory);
 
-    let ok_res = results.iter().filter(|r| r.is_ok()).count();
+    let excluded = results.iter().filter(|r| matches!(r, Ok(report) if report.excluded)).count();
+    let pending = results.iter().filter(|r| matches!(r, Ok(report) if report.pending)).count();
+    let ok_res = results.iter().filter(|r| r.is_ok()).count() - excluded - pending;
     let err_res = results.iter().filter(|r| r.is_err()).count();
+    let recovered = results.iter().filter(|r| matches!(r, Ok(report) if report.recovered)).count();
 
-    info!("Processed {} proteins, failed {} times", ok_res, err_res);
+    info!("Processed {} proteins ({} recovered at loose strictness), failed {} times", ok_res, recovered, err_res);
+    if excluded > 0 {
+        info!("Skipped {} structures lacking required elements or residues", excluded);
+    }
     for e in results.iter().filter(|r| r.is_err()) {
         warn!("{:?}", e);
     }
+    // Atoms and structures of every element without a tabulated radius
+    let mut unknown_elements: BTreeMap<&String, (usize, usize)> = BTreeMap::new();
+    for report in results.iter().filter_map(|r| r.as_ref().ok()) {
+        for (element, atoms) in &report.unknown_elements {
+            let counts = unknown_elements.entry(element).or_default();
+            *counts = (counts.0 + atoms, counts.1 + 1);
+        }
+    }
+    if !unknown_elements.is_empty() {
+        let report = unknown_elements.iter()
+            .map(|(element, (atoms, structures))| format!("{} ({} atoms in {} structures)", element, atoms, structures))
+            .collect::<Vec<_>>();
+        info!("Van der Waals radius from --unknown-radius {} used for {}", opts.unknown_radius, report.join(", "));
+    }
+
+    let degenerate: Vec<(&String, &String, &Vec<Degeneracy>)> = paths.iter().zip(&results)
+        .filter_map(|(p, r)| r.as_ref().ok().map(|report| (p, report)))
+        .flat_map(|(p, report)| report.degenerate.iter().map(move |(out, issues)| (p, out, issues)))
+        .collect();
+    for (_, out, issues) in &degenerate {
+        warn!("Degenerate graph {}: {}", out, issues.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", "));
+    }
+    if let Some(review) = matches.get_one::<PathBuf>("review-list") {
+        if let Err(e) = write_review_list(&degenerate, review) {
+            warn!("Failed to write review list {} - {:?}", review.display(), e);
+        } else if !degenerate.is_empty() {
+            info!("Listed {} degenerate graphs for review in {}", degenerate.len(), review.display());
+        }
+    }
+
+    let entries = manifest_entries(&paths, &results, resumed);
+    if let Some(out) = matches.get_one::<PathBuf>("size-index") {
+        if let Err(e) = write_size_index(&entries, out) {
+            warn!("Failed to write size index {} - {:?}", out.display(), e);
+        }
+    }
+    let shard = shard.map(|shard| ShardInfo { shard, total_inputs: all_paths.len() });
+    let mut manifest = Manifest { shard, joins, entries };
+    manifest.count_joins();
+    let max_unmatched = *matches.get_one::<f64>("max-unmatched").unwrap();
+    let unmatched = manifest.unmatched_joins(max_unmatched);
+    for (join, provenance) in &unmatched {
+        warn!(
+            "No {} found for {} of {} structures ({:.1}%, more than --max-unmatched {})",
+            join,
+            provenance.unmatched,
+            provenance.matched + provenance.unmatched,
+            100.0 * provenance.unmatched_fraction(),
+            max_unmatched
+        );
+    }
+    if let Some(out) = matches.get_one::<PathBuf>("manifest") {
+        if let Err(e) = write_manifest(&manifest, out) {
+            warn!("Failed to write manifest {} - {:?}", out.display(), e);
+        }
+    }
 
+    if let Some(dir) = matches.get_one::<PathBuf>("quarantine-dir") {
+        let mode = *matches.get_one::<QuarantineMode>("quarantine-mode").unwrap();
+        for (p, res) in paths.iter().zip(&results) {
+            if let Err(e) = res {
+                if let Err(qe) = quarantine(p, e, dir, mode) {
+                    warn!("Failed to quarantine {} - {:?}", p, qe);
+                }
+            }
+        }
+        info!("Quarantined {} failed inputs in {}", err_res, dir.display());
+    }
 
-    
-}
\ No newline at end of file
+    if pending > 0 {
+        match matches.get_one::<PathBuf>("manifest") {
+            Some(out) => warn!("Interrupted with {} inputs left, continue with --resume {}", pending, out.display()),
+            None => warn!("Interrupted with {} inputs left", pending),
+        }
+        std::process::exit(130);
+    }
+    if !unmatched.is_empty() && *matches.get_one::<JoinCheck>("join-check").unwrap() == JoinCheck::Fail {
+        error!("Joins of external data missed too many structures, see --max-unmatched");
+        std::process::exit(1);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Options of a `convert` run without any flags.
+    fn default_options() -> Options {
+        let residue_opts = residue_graph::ResidueGraphOptions { edges: residue_graph::ResidueEdges::Ca, cutoff: 8.0 };
+        Options {
+            graph: GraphBuilder::new(),
+            strictness: StrictnessLevel::Medium,
+            retry_loose: false,
+            coordinate_check: CoordinateCheck::Flag,
+            score_columns: None,
+            predictions: false,
+            embeddings: None,
+            conservation: None,
+            labels: None,
+            sifts: None,
+            residue_features: None,
+            secondary_structure: false,
+            atom_types: None,
+            ccd: None,
+            unknown_radius: radius::UnknownRadius::default(),
+            config: None,
+            preset: None,
+            verify: false,
+            check_invariants: true,
+            require: composition::Requirements::default(),
+            polymer_only: None,
+            chains: None,
+            exclude: selection::AtomExclusion::default(),
+            models: None,
+            altloc: AltlocPolicy::All,
+            max_nodes: None,
+            trim_strategy: TrimStrategy::Center,
+            seed: 0,
+            chunk_size: None,
+            chunk_halo: 3.5,
+            chunk_above: 0,
+            chain_pairs: None,
+            edge_sample_rate: None,
+            thresholds: degenerate::Thresholds { max_mean_degree: f64::INFINITY, max_isolated_fraction: 1.0 },
+            align: None,
+            receptor: None,
+            temporal: false,
+            residue_graph: None,
+            variants: Vec::new(),
+            residue_pairs: None,
+            contacts: None,
+            strict_output: false,
+            canonical: false,
+            interactions: None,
+            fingerprint: None,
+            format: OutputFormat::Json,
+            naming: OutputNaming::default(),
+            layout: Layout::Structs,
+            quantize_distances: None,
+            voxel: voxel::VoxelOptions { size: 1.0, dim: None, padding: 0.0, channels: Vec::new() },
+            contact_map: (residue_opts, contact_map::ContactValues::Contacts),
+        }
+    }
+
+    #[test]
+    fn samples_are_seeded() {
+        let paths: Vec<String> = (0..50).map(|i| format!("in/{}.pdb", i)).collect();
+        let sampled = sample_paths(paths.clone(), Some(10), None, 3);
+        assert_eq!(sampled.len(), 10);
+        assert_eq!(sampled, sample_paths(paths.clone(), Some(10), None, 3));
+        assert_ne!(sampled, sample_paths(paths.clone(), Some(10), None, 4));
+        assert!(sampled.windows(2).all(|w| paths.iter().position(|p| *p == w[0]) < paths.iter().position(|p| *p == w[1])));
+        assert_eq!(sample_paths(paths.clone(), Some(100), None, 3), paths);
+
+        assert_eq!(sample_paths(paths.clone(), None, Some(0.25), 3).len(), 13);
+        assert_eq!(sample_paths(paths.clone(), None, Some(1.0), 3), paths);
+        // A fraction of 0 is rejected, a tiny one still converts a file
+        assert!(parse_fraction("0").is_err());
+        assert_eq!(sample_paths(paths.clone(), None, Some(0.001), 3).len(), 1);
+        assert_eq!(sample_paths(paths.clone(), None, None, 3), paths);
+    }
+
+    #[test]
+    fn collisions_fail_or_get_stable_names() {
+        let naming = OutputNaming { dir: Some(PathBuf::from("out")), ..Default::default() };
+        let paths = vec!["a/1abc.pdb".to_string(), "b/1abc.pdb".to_string(), "a/2xyz.pdb".to_string()];
+        let err = plan_outputs(&paths, CollisionPolicy::Fail, OutputFormat::Json, &naming).unwrap_err();
+        assert!(err.to_string().contains("a/1abc.pdb, b/1abc.pdb"));
+
+        let outputs = plan_outputs(&paths, CollisionPolicy::Disambiguate, OutputFormat::Json, &naming).unwrap();
+        assert_ne!(outputs[0], outputs[1]);
+        assert!(outputs[0].starts_with("out/1abc_") && outputs[0].ends_with("_graph.json"));
+        assert_eq!(outputs[2], "out/2xyz_graph.json");
+        // Names only depend on the input, not on the other inputs of the run
+        let reordered = vec![paths[1].clone(), paths[2].clone(), paths[0].clone()];
+        let again = plan_outputs(&reordered, CollisionPolicy::Disambiguate, OutputFormat::Json, &naming).unwrap();
+        assert_eq!((&again[2], &again[0]), (&outputs[0], &outputs[1]));
+    }
+
+    #[test]
+    fn merges_structures() {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+        let paths = vec![format!("{}/antibody_insertion_codes.pdb", fixtures), format!("{}/benzoate_stacking.pdb", fixtures)];
+        let save_fname = std::env::temp_dir().join(format!("graphein_merge_{}.json", std::process::id()));
+        let opts = default_options();
+        for inter_structure_edges in [false, true] {
+            assert!(merge_pdb_files(&paths, &save_fname, &opts, inter_structure_edges).unwrap().is_empty());
+            let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&save_fname).unwrap()).unwrap();
+            let structure_ids: Vec<&str> = json["graph"]["nodes"].as_array().unwrap().iter()
+                .map(|n| n["structure_id"].as_str().unwrap())
+                .collect();
+            assert_eq!(structure_ids.iter().filter(|id| **id == "antibody_insertion_codes").count(), 60);
+            assert_eq!(structure_ids.iter().filter(|id| **id == "benzoate_stacking").count(), 17);
+            let inter_edges = json["graph"]["edges"].as_array().unwrap().iter()
+                .filter(|e| structure_ids[e[0].as_u64().unwrap() as usize] != structure_ids[e[1].as_u64().unwrap() as usize])
+                .count();
+            // The fixtures overlap in space, so only the flag keeps them apart
+            assert_eq!(inter_edges > 0, inter_structure_edges);
+            assert_eq!(json["metadata"]["structures"].as_array().unwrap().len(), 2);
+        }
+        std::fs::remove_file(&save_fname).unwrap();
+    }
+}
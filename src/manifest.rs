@@ -0,0 +1,293 @@
+use crate::stable_hash;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+
+/// Part `index` of `count` of the inputs, e.g. `--shard 2/8` for the third
+/// of eight cluster jobs.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, count) = s.split_once('/').ok_or_else(|| format!("{} is not of the form i/n", s))?;
+        let index: usize = index.trim().parse().map_err(|e| format!("invalid shard index: {}", e))?;
+        let count: usize = count.trim().parse().map_err(|e| format!("invalid shard count: {}", e))?;
+        if index >= count {
+            return Err(format!("shard index {} is not in range 0..{}", index, count));
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+
+/// Shard of a run recorded in its manifest.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShardInfo {
+    #[serde(flatten)]
+    pub shard: Shard,
+    /// Inputs of the whole run, before sharding
+    pub total_inputs: usize,
+}
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Converted,
+    /// Left out by the composition filter
+    Excluded,
+    Failed,
+    /// Not converted because the run was interrupted
+    Pending,
+}
+
+impl Status {
+    /// Whether a resumed run can skip the input.
+    pub fn is_done(self) -> bool {
+        matches!(self, Status::Converted | Status::Excluded)
+    }
+}
+
+
+/// Size of a written graph.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GraphSize {
+    pub path: String,
+    pub nodes: usize,
+    pub edges: usize,
+}
+
+
+/// Outcome of one input.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    pub input: String,
+    pub status: Status,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<String>,
+    /// Node and edge counts of the graphs among the outputs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub graphs: Vec<GraphSize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Joins of external data that found nothing for the input
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unmatched_joins: Vec<Join>,
+}
+
+
+/// External data joined onto structures.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Join {
+    Labels,
+    ResidueFeatures,
+    Embeddings,
+    Conservation,
+    Sifts,
+}
+
+impl fmt::Display for Join {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Join::Labels => "labels",
+            Join::ResidueFeatures => "residue features",
+            Join::Embeddings => "embeddings",
+            Join::Conservation => "conservation",
+            Join::Sifts => "SIFTS mappings",
+        })
+    }
+}
+
+
+/// A table read for a join, with a fingerprint of its contents.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourceFile {
+    pub path: String,
+    pub bytes: u64,
+    /// 64-bit FNV-1a hash of the contents in hex
+    pub fnv1a: String,
+}
+
+impl SourceFile {
+    pub fn read(path: &Path) -> Result<SourceFile> {
+        let contents = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        Ok(SourceFile { path: path.display().to_string(), bytes: contents.len() as u64, fnv1a: format!("{:016x}", stable_hash(&contents)) })
+    }
+}
+
+
+/// Where the data of a join came from and how many converted inputs it
+/// found data for.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct JoinProvenance {
+    /// Tables the data was read from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<SourceFile>,
+    /// Directory of per-structure files the data was read from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory: Option<String>,
+    #[serde(default)]
+    pub matched: usize,
+    #[serde(default)]
+    pub unmatched: usize,
+}
+
+impl JoinProvenance {
+    pub fn unmatched_fraction(&self) -> f64 {
+        self.unmatched as f64 / (self.matched + self.unmatched).max(1) as f64
+    }
+}
+
+
+/// Record of a run: what every input became.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Manifest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard: Option<ShardInfo>,
+    /// Joins of external data of the run
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub joins: BTreeMap<Join, JoinProvenance>,
+    pub entries: Vec<Entry>,
+}
+
+impl Manifest {
+    /// Counts the converted inputs every join matched and missed.
+    pub fn count_joins(&mut self) {
+        for (join, provenance) in self.joins.iter_mut() {
+            let converted = self.entries.iter().filter(|e| e.status == Status::Converted);
+            let (unmatched, matched): (Vec<&Entry>, Vec<&Entry>) = converted.partition(|e| e.unmatched_joins.contains(join));
+            (provenance.matched, provenance.unmatched) = (matched.len(), unmatched.len());
+        }
+    }
+
+    /// Joins missing data for more than `max_fraction` of the converted inputs.
+    pub fn unmatched_joins(&self, max_fraction: f64) -> Vec<(Join, &JoinProvenance)> {
+        self.joins.iter().filter(|(_, p)| p.unmatched_fraction() > max_fraction).map(|(j, p)| (*j, p)).collect()
+    }
+}
+
+
+/// Combines the manifests of several runs or of the shards of one run.
+/// Fails if an input or an output appears in more than one manifest, if
+/// shards are missing or come from runs over different inputs, or if the
+/// runs joined data from different sources.
+pub fn merge(manifests: &[(String, Manifest)]) -> Result<Manifest> {
+    let mut problems = Vec::new();
+    let mut inputs: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    let mut outputs: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (name, manifest) in manifests {
+        for entry in &manifest.entries {
+            inputs.entry(&entry.input).or_default().push(name);
+            for output in &entry.outputs {
+                outputs.entry(output).or_default().push(&entry.input);
+            }
+        }
+    }
+    for (input, names) in inputs.iter().filter(|(_, names)| names.len() > 1) {
+        problems.push(format!("input {} is in {}", input, names.join(", ")));
+    }
+    for (output, claimed_by) in outputs.iter().filter(|(_, inputs)| inputs.len() > 1) {
+        problems.push(format!("output {} is written by {}", output, claimed_by.join(", ")));
+    }
+
+    let shards: Vec<&ShardInfo> = manifests.iter().filter_map(|(_, m)| m.shard.as_ref()).collect();
+    if let Some(first) = shards.first() {
+        if shards.len() < manifests.len() {
+            problems.push("sharded and unsharded manifests are mixed".to_string());
+        }
+        if shards.iter().any(|s| s.shard.count != first.shard.count || s.total_inputs != first.total_inputs) {
+            problems.push("shards come from runs with different shard counts or inputs".to_string());
+        } else {
+            let present: BTreeSet<usize> = shards.iter().map(|s| s.shard.index).collect();
+            let missing: Vec<String> = (0..first.shard.count).filter(|i| !present.contains(i)).map(|i| i.to_string()).collect();
+            if !missing.is_empty() {
+                problems.push(format!("shards {} of {} are missing", missing.join(", "), first.shard.count));
+            } else if inputs.len() != first.total_inputs {
+                problems.push(format!("shards cover {} of {} inputs", inputs.len(), first.total_inputs));
+            }
+        }
+    }
+
+    let mut joins: BTreeMap<Join, JoinProvenance> = BTreeMap::new();
+    for (name, manifest) in manifests {
+        for (join, provenance) in &manifest.joins {
+            match joins.get(join) {
+                Some(first) if (&first.sources, &first.directory) != (&provenance.sources, &provenance.directory) => {
+                    problems.push(format!("{} of {} come from different sources than in the other manifests", join, name));
+                }
+                Some(_) => {}
+                None => {
+                    joins.insert(*join, provenance.clone());
+                }
+            }
+        }
+    }
+    if !problems.is_empty() {
+        bail!("Manifests can't be merged:\n{}", problems.join("\n"));
+    }
+    let mut entries: Vec<Entry> = manifests.iter().flat_map(|(_, m)| m.entries.iter().cloned()).collect();
+    entries.sort_by(|a, b| a.input.cmp(&b.input));
+    let mut merged = Manifest { shard: None, joins, entries };
+    merged.count_joins();
+    Ok(merged)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_shards() {
+        assert_eq!("2/8".parse(), Ok(Shard { index: 2, count: 8 }));
+        assert!("8/8".parse::<Shard>().is_err());
+        assert!("2".parse::<Shard>().is_err());
+    }
+
+    fn shard(index: usize, inputs: &[&str]) -> (String, Manifest) {
+        let entries = inputs.iter().map(|i| Entry {
+            input: i.to_string(),
+            status: Status::Converted,
+            outputs: vec![format!("{}_graph.json", i)],
+            graphs: Vec::new(),
+            error: None,
+            unmatched_joins: if *i == "b" { vec![Join::Labels] } else { Vec::new() },
+        }).collect();
+        let shard = ShardInfo { shard: Shard { index, count: 2 }, total_inputs: 3 };
+        let joins = BTreeMap::from([(Join::Labels, JoinProvenance { directory: Some("labels".to_string()), ..Default::default() })]);
+        let mut manifest = Manifest { shard: Some(shard), joins, entries };
+        manifest.count_joins();
+        (format!("manifest_{}.json", index), manifest)
+    }
+
+    #[test]
+    fn merges_complete_shards_only() {
+        let merged = merge(&[shard(1, &["b"]), shard(0, &["c", "a"])]).unwrap();
+        assert_eq!(merged.entries.iter().map(|e| e.input.as_str()).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+        assert_eq!((merged.joins[&Join::Labels].matched, merged.joins[&Join::Labels].unmatched), (2, 1));
+        assert_eq!(merged.unmatched_joins(0.5).len(), 0);
+        assert_eq!(merged.unmatched_joins(0.2).len(), 1);
+        assert!(merge(&[shard(0, &["c", "a"])]).is_err());
+        assert!(merge(&[shard(0, &["c", "a"]), shard(1, &["a"])]).is_err());
+        let (name, mut other) = shard(1, &["b"]);
+        other.joins.get_mut(&Join::Labels).unwrap().directory = Some("other".to_string());
+        assert!(merge(&[(name, other), shard(0, &["c", "a"])]).is_err());
+    }
+}
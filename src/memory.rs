@@ -0,0 +1,111 @@
+use std::sync::{Condvar, Mutex};
+
+
+/// Approximate memory held per atom while a structure is converted: the
+/// parsed hierarchy, the graph with its neighbour edges and the serialized
+/// output.
+const BYTES_PER_ATOM: u64 = 2048;
+
+
+/// Approximate input bytes per atom of each file type, used to guess the
+/// atom count from the file size before parsing.
+fn input_bytes_per_atom(fname: &str) -> u64 {
+    let lower = fname.to_ascii_lowercase();
+    if lower.ends_with(".mmtf") || lower.ends_with(".bcif") || lower.ends_with(".fcz") {
+        8
+    } else if lower.ends_with(".gz") {
+        20
+    } else {
+        // PDB lines are 81 bytes, mmCIF atom_site rows a bit longer
+        80
+    }
+}
+
+
+/// Estimated memory needed to convert `fname`, from its size.
+pub fn estimate(fname: &str) -> u64 {
+    let size = std::fs::metadata(fname).map(|m| m.len()).unwrap_or(0);
+    size / input_bytes_per_atom(fname) * BYTES_PER_ATOM
+}
+
+
+/// Parses a size like `512M`, `8G` or `1.5GiB` into bytes.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("invalid size {}", s))?;
+    let factor: u64 = match unit.trim().to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("invalid size unit in {}", s)),
+    };
+    Ok((number * factor as f64) as u64)
+}
+
+
+pub fn format_size(bytes: u64) -> String {
+    if bytes >= 1 << 30 {
+        format!("{:.1} GiB", bytes as f64 / (1u64 << 30) as f64)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / (1u64 << 20) as f64)
+    }
+}
+
+
+/// Memory shared by the structures converted in parallel. Each structure
+/// reserves its estimate before starting and waits while the reservations
+/// of the structures in flight would exceed the limit.
+pub struct Budget {
+    pub limit: u64,
+    in_flight: Mutex<u64>,
+    released: Condvar,
+}
+
+impl Budget {
+    pub fn new(limit: u64) -> Self {
+        Budget { limit, in_flight: Mutex::new(0), released: Condvar::new() }
+    }
+
+    /// Reserves `bytes` (at most the limit) until the returned guard is
+    /// dropped.
+    pub fn reserve(&self, bytes: u64) -> Reservation<'_> {
+        let bytes = bytes.min(self.limit);
+        let mut in_flight = self.in_flight.lock().unwrap();
+        while *in_flight > 0 && *in_flight + bytes > self.limit {
+            in_flight = self.released.wait(in_flight).unwrap();
+        }
+        *in_flight += bytes;
+        Reservation { budget: self, bytes }
+    }
+}
+
+
+pub struct Reservation<'a> {
+    budget: &'a Budget,
+    bytes: u64,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        *self.budget.in_flight.lock().unwrap() -= self.bytes;
+        self.budget.released.notify_all();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("512M"), Ok(512 << 20));
+        assert_eq!(parse_size("1.5GiB"), Ok(3 << 29));
+        assert_eq!(parse_size("100"), Ok(100));
+        assert!(parse_size("8X").is_err());
+    }
+}
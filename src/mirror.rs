@@ -0,0 +1,73 @@
+use std::path::{Path, PathBuf};
+
+
+/// Directories of a divided mirror that hold structures, relative to the
+/// mirror root, in order of preference. mmCIF comes first as the PDB format
+/// doesn't exist for large structures. The root can be the top of an rsync
+/// mirror, its `divided` directory, or a single format's directory.
+const LAYOUTS: &[(&str, Format)] = &[
+    ("data/structures/divided/mmCIF", Format::MmCif),
+    ("data/structures/divided/pdb", Format::Pdb),
+    ("divided/mmCIF", Format::MmCif),
+    ("divided/pdb", Format::Pdb),
+    ("mmCIF", Format::MmCif),
+    ("pdb", Format::Pdb),
+    ("", Format::MmCif),
+    ("", Format::Pdb),
+];
+
+
+#[derive(Clone, Copy)]
+enum Format {
+    MmCif,
+    Pdb,
+}
+
+impl Format {
+    /// File names of a structure, compressed as distributed or unpacked.
+    fn file_names(self, id: &str) -> [String; 2] {
+        match self {
+            Format::MmCif => [format!("{}.cif.gz", id), format!("{}.cif", id)],
+            Format::Pdb => [format!("pdb{}.ent.gz", id), format!("pdb{}.ent", id)],
+        }
+    }
+}
+
+
+/// Path of the structure `id` in the divided mirror at `root`, where files
+/// are grouped into directories by the middle two characters of the id,
+/// e.g. `mmCIF/ab/1abc.cif.gz`.
+pub fn resolve(root: &Path, id: &str) -> Option<PathBuf> {
+    let id = id.trim().to_ascii_lowercase();
+    if id.len() != 4 || !id.is_ascii() {
+        return None;
+    }
+    let hash = &id[1..3];
+    LAYOUTS.iter()
+        .flat_map(|(dir, format)| format.file_names(&id).map(|name| root.join(dir).join(hash).join(name)))
+        .find(|p| p.is_file())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_divided_layouts() {
+        let root = std::env::temp_dir().join(format!("graphein-mirror-test-{}", std::process::id()));
+        let cif = root.join("data/structures/divided/mmCIF/ab");
+        let pdb = root.join("data/structures/divided/pdb/xy");
+        std::fs::create_dir_all(&cif).unwrap();
+        std::fs::create_dir_all(&pdb).unwrap();
+        std::fs::write(cif.join("1abc.cif.gz"), "").unwrap();
+        std::fs::write(pdb.join("pdb1xyz.ent.gz"), "").unwrap();
+
+        assert_eq!(resolve(&root, "1ABC"), Some(cif.join("1abc.cif.gz")));
+        assert_eq!(resolve(&root, "1xyz"), Some(pdb.join("pdb1xyz.ent.gz")));
+        assert_eq!(resolve(&root.join("data/structures/divided/pdb"), "1xyz"), Some(pdb.join("pdb1xyz.ent.gz")));
+        assert_eq!(resolve(&root, "2abc"), None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
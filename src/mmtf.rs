@@ -0,0 +1,278 @@
+use crate::input::{build_pdb, AtomRecord};
+use crate::msgpack::{self, Value};
+use anyhow::{anyhow, bail, Context, Result};
+use pdbtbx::PDB;
+
+
+/// Splits `data` into big-endian integers of `width` bytes.
+fn be_ints(data: &[u8], width: usize) -> Vec<i32> {
+    data.chunks_exact(width).map(|c| match width {
+        1 => c[0] as i8 as i32,
+        2 => i16::from_be_bytes([c[0], c[1]]) as i32,
+        _ => i32::from_be_bytes([c[0], c[1], c[2], c[3]]),
+    }).collect()
+}
+
+
+fn run_length(values: &[i32]) -> Vec<i32> {
+    values.chunks_exact(2).flat_map(|p| std::iter::repeat_n(p[0], p[1].max(0) as usize)).collect()
+}
+
+
+fn delta(values: Vec<i32>) -> Vec<i32> {
+    values.into_iter().scan(0i32, |acc, v| {
+        *acc = acc.wrapping_add(v);
+        Some(*acc)
+    }).collect()
+}
+
+
+/// Sums runs of values at the limits of the packed integer type.
+fn recursive_index(values: &[i32], min: i32, max: i32) -> Vec<i32> {
+    let mut out = Vec::new();
+    let mut acc = 0;
+    for &v in values {
+        acc += v;
+        if v != min && v != max {
+            out.push(acc);
+            acc = 0;
+        }
+    }
+    out
+}
+
+
+/// An array decoded from MMTF's binary encoding.
+enum Array {
+    Ints(Vec<i32>),
+    Floats(Vec<f64>),
+    Strings(Vec<String>),
+}
+
+
+/// Decodes a binary MMTF array: a 12 byte header of codec, length and
+/// parameter, followed by the encoded data.
+fn decode_array(data: &[u8]) -> Result<Array> {
+    if data.len() < 12 {
+        bail!("encoded array is shorter than its header");
+    }
+    let header = be_ints(&data[..12], 4);
+    let (codec, param, body) = (header[0], header[2], &data[12..]);
+    let divide = |ints: Vec<i32>| ints.into_iter().map(|i| i as f64 / param as f64).collect();
+    Ok(match codec {
+        1 => Array::Floats(body.chunks_exact(4).map(|c| f32::from_be_bytes([c[0], c[1], c[2], c[3]]) as f64).collect()),
+        2 => Array::Ints(be_ints(body, 1)),
+        3 => Array::Ints(be_ints(body, 2)),
+        4 => Array::Ints(be_ints(body, 4)),
+        5 => Array::Strings(body.chunks(param.max(1) as usize)
+            .map(|c| String::from_utf8_lossy(c).trim_end_matches('\0').to_string())
+            .collect()),
+        6 => Array::Strings(run_length(&be_ints(body, 4)).into_iter()
+            .map(|c| char::from_u32(c as u32).filter(|c| *c != '\0').map(String::from).unwrap_or_default())
+            .collect()),
+        7 => Array::Ints(run_length(&be_ints(body, 4))),
+        8 => Array::Ints(delta(run_length(&be_ints(body, 4)))),
+        9 => Array::Floats(divide(run_length(&be_ints(body, 4)))),
+        10 => Array::Floats(divide(delta(recursive_index(&be_ints(body, 2), i16::MIN as i32, i16::MAX as i32)))),
+        11 => Array::Floats(divide(be_ints(body, 2))),
+        12 => Array::Floats(divide(recursive_index(&be_ints(body, 2), i16::MIN as i32, i16::MAX as i32))),
+        13 => Array::Floats(divide(recursive_index(&be_ints(body, 1), i8::MIN as i32, i8::MAX as i32))),
+        14 => Array::Ints(recursive_index(&be_ints(body, 2), i16::MIN as i32, i16::MAX as i32)),
+        15 => Array::Ints(recursive_index(&be_ints(body, 1), i8::MIN as i32, i8::MAX as i32)),
+        _ => bail!("unsupported codec {}", codec),
+    })
+}
+
+
+/// Field of the MMTF map, either a plain MessagePack array or a binary
+/// encoded one.
+struct Fields<'a>(&'a Value);
+
+impl Fields<'_> {
+    fn array(&self, key: &str) -> Result<Option<Array>> {
+        match self.0.get(key) {
+            None | Some(Value::Nil) => Ok(None),
+            Some(Value::Bin(data)) => decode_array(data).map(Some).with_context(|| format!("decoding {}", key)),
+            Some(Value::Array(values)) => {
+                if let Some(ints) = values.iter().map(|v| v.as_int().map(|i| i as i32)).collect::<Option<Vec<_>>>() {
+                    Ok(Some(Array::Ints(ints)))
+                } else if let Some(floats) = values.iter().map(|v| v.as_float()).collect::<Option<Vec<_>>>() {
+                    Ok(Some(Array::Floats(floats)))
+                } else {
+                    Ok(Some(Array::Strings(values.iter().map(|v| v.as_str().unwrap_or_default().to_string()).collect())))
+                }
+            }
+            Some(_) => bail!("{} is not an array", key),
+        }
+    }
+
+    fn ints(&self, key: &str) -> Result<Option<Vec<i32>>> {
+        match self.array(key)? {
+            None => Ok(None),
+            Some(Array::Ints(v)) => Ok(Some(v)),
+            Some(_) => bail!("{} is not an integer array", key),
+        }
+    }
+
+    fn floats(&self, key: &str) -> Result<Option<Vec<f64>>> {
+        match self.array(key)? {
+            None => Ok(None),
+            Some(Array::Floats(v)) => Ok(Some(v)),
+            Some(Array::Ints(v)) => Ok(Some(v.into_iter().map(|i| i as f64).collect())),
+            Some(_) => bail!("{} is not a float array", key),
+        }
+    }
+
+    fn strings(&self, key: &str) -> Result<Option<Vec<String>>> {
+        match self.array(key)? {
+            None => Ok(None),
+            Some(Array::Strings(v)) => Ok(Some(v)),
+            Some(Array::Ints(v)) if v.is_empty() => Ok(Some(Vec::new())),
+            Some(_) => bail!("{} is not a string array", key),
+        }
+    }
+
+    fn required<T>(&self, key: &str, value: Option<T>) -> Result<T> {
+        value.ok_or_else(|| anyhow!("missing field {}", key))
+    }
+}
+
+
+/// Reads a structure from MMTF data (uncompressed MessagePack).
+pub fn read(data: &[u8]) -> Result<PDB> {
+    let root = msgpack::decode(data)?;
+    let f = Fields(&root);
+    let chains_per_model = f.required("chainsPerModel", f.ints("chainsPerModel")?)?;
+    let groups_per_chain = f.required("groupsPerChain", f.ints("groupsPerChain")?)?;
+    let chain_names = match f.strings("chainNameList")? {
+        Some(names) => names,
+        None => f.required("chainIdList", f.strings("chainIdList")?)?,
+    };
+    let group_types = f.required("groupTypeList", f.ints("groupTypeList")?)?;
+    let group_ids = f.required("groupIdList", f.ints("groupIdList")?)?;
+    let ins_codes = f.strings("insCodeList")?;
+    let x = f.required("xCoordList", f.floats("xCoordList")?)?;
+    let y = f.required("yCoordList", f.floats("yCoordList")?)?;
+    let z = f.required("zCoordList", f.floats("zCoordList")?)?;
+    let b_factors = f.floats("bFactorList")?;
+    let occupancies = f.floats("occupancyList")?;
+    let atom_ids = f.ints("atomIdList")?;
+    let alt_locs = f.strings("altLocList")?;
+    let (atom_count, group_count) = (x.len(), group_types.len());
+    for (key, len, expected) in [
+        ("yCoordList", Some(y.len()), atom_count),
+        ("zCoordList", Some(z.len()), atom_count),
+        ("bFactorList", b_factors.as_ref().map(|v| v.len()), atom_count),
+        ("occupancyList", occupancies.as_ref().map(|v| v.len()), atom_count),
+        ("atomIdList", atom_ids.as_ref().map(|v| v.len()), atom_count),
+        ("altLocList", alt_locs.as_ref().map(|v| v.len()), atom_count),
+        ("groupIdList", Some(group_ids.len()), group_count),
+        ("insCodeList", ins_codes.as_ref().map(|v| v.len()), group_count),
+    ] {
+        if len.is_some_and(|len| len != expected) {
+            bail!("{} has {} entries, expected {}", key, len.unwrap(), expected);
+        }
+    }
+    let group_list = root.get("groupList").and_then(|g| g.as_array()).ok_or_else(|| anyhow!("missing field groupList"))?;
+
+    let mut atoms = Vec::new();
+    let (mut chain_index, mut group_index, mut atom_index) = (0, 0, 0);
+    for (model, &chain_count) in chains_per_model.iter().enumerate() {
+        for _ in 0..chain_count {
+            let chain = chain_names.get(chain_index).ok_or_else(|| anyhow!("chain {} out of range", chain_index))?;
+            let group_count = *groups_per_chain.get(chain_index).ok_or_else(|| anyhow!("chain {} out of range", chain_index))?;
+            for _ in 0..group_count {
+                let group_type = *group_types.get(group_index).ok_or_else(|| anyhow!("group {} out of range", group_index))?;
+                let group = group_list.get(group_type as usize).ok_or_else(|| anyhow!("group type {} out of range", group_type))?;
+                let resname = group.get("groupName").and_then(|v| v.as_str()).unwrap_or("UNK");
+                // Polymer components are typed as e.g. "L-PEPTIDE LINKING", everything else is a HETATM
+                let hetero = !group.get("chemCompType").and_then(|v| v.as_str()).unwrap_or("").to_ascii_uppercase().contains("LINKING");
+                let names = group.get("atomNameList").and_then(|v| v.as_array()).unwrap_or(&[]);
+                let elements = group.get("elementList").and_then(|v| v.as_array()).unwrap_or(&[]);
+                let charges = group.get("formalChargeList").and_then(|v| v.as_array()).unwrap_or(&[]);
+                for (i, name) in names.iter().enumerate() {
+                    if atom_index >= x.len() {
+                        bail!("atom {} out of range", atom_index);
+                    }
+                    atoms.push(AtomRecord {
+                        model: model + 1,
+                        chain: chain.clone(),
+                        resseq: group_ids[group_index] as isize,
+                        icode: ins_codes.as_ref().map(|c| c[group_index].clone()).filter(|c| !c.is_empty()),
+                        resname: resname.to_string(),
+                        name: name.as_str().unwrap_or_default().to_string(),
+                        altloc: alt_locs.as_ref().map(|a| a[atom_index].clone()).filter(|a| !a.is_empty()),
+                        element: elements.get(i).and_then(|e| e.as_str()).unwrap_or_default().to_string(),
+                        pos: (x[atom_index], y[atom_index], z[atom_index]),
+                        occupancy: occupancies.as_ref().map_or(1.0, |o| o[atom_index]),
+                        b_factor: b_factors.as_ref().map_or(0.0, |b| b[atom_index]),
+                        charge: charges.get(i).and_then(|c| c.as_int()).unwrap_or(0) as isize,
+                        hetero,
+                        serial_number: atom_ids.as_ref().map_or(atom_index + 1, |a| a[atom_index] as usize),
+                    });
+                    atom_index += 1;
+                }
+                group_index += 1;
+            }
+            chain_index += 1;
+        }
+    }
+    let identifier = root.get("structureId").and_then(|v| v.as_str()).map(|s| s.to_string());
+    build_pdb(identifier, atoms)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn str(s: &str) -> Value {
+        Value::Str(s.to_string())
+    }
+
+    /// Binary array with an MMTF header.
+    fn encoded(codec: i32, length: i32, param: i32, ints: &[i32], width: usize) -> Value {
+        let mut out = Vec::new();
+        for v in [codec, length, param] {
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        for v in ints {
+            out.extend_from_slice(&v.to_be_bytes()[4 - width..]);
+        }
+        Value::Bin(out)
+    }
+
+    #[test]
+    fn reads_encoded_structure() {
+        let group = Value::Map(vec![
+            (str("groupName"), str("GLY")),
+            (str("atomNameList"), Value::Array(vec![str("N"), str("CA")])),
+            (str("elementList"), Value::Array(vec![str("N"), str("C")])),
+            (str("formalChargeList"), Value::Array(vec![Value::Int(0), Value::Int(0)])),
+            (str("chemCompType"), str("PEPTIDE LINKING")),
+        ]);
+        let root = Value::Map(vec![
+            (str("structureId"), str("1ABC")),
+            (str("chainsPerModel"), Value::Array(vec![Value::Int(1)])),
+            (str("groupsPerChain"), Value::Array(vec![Value::Int(2)])),
+            (str("chainNameList"), encoded(5, 1, 4, &[0x41000000], 4)),
+            (str("groupTypeList"), encoded(4, 2, 0, &[0, 0], 4)),
+            // Run-length and delta encoded 10, 11
+            (str("groupIdList"), encoded(8, 2, 0, &[10, 1, 1, 1], 4)),
+            (str("insCodeList"), encoded(6, 2, 0, &[0, 2], 4)),
+            // 40000 needs recursive indexing: 32767 + 7233, then deltas
+            (str("xCoordList"), encoded(10, 4, 1000, &[1000, 32767, 7233, -1000, 500], 2)),
+            (str("yCoordList"), encoded(10, 4, 1000, &[0, 0, 0, 0], 2)),
+            (str("zCoordList"), encoded(10, 4, 1000, &[0, 0, 0, 0], 2)),
+            (str("groupList"), Value::Array(vec![group])),
+        ]);
+        let pdb = read(&msgpack::encode(&root)).unwrap();
+        assert_eq!(pdb.identifier.as_deref(), Some("1ABC"));
+        let xs: Vec<f64> = pdb.atoms().map(|a| a.x()).collect();
+        assert_eq!(xs, vec![1.0, 41.0, 40.0, 40.5]);
+        let residues: Vec<isize> = pdb.residues().map(|r| r.serial_number()).collect();
+        assert_eq!(residues, vec![10, 11]);
+        assert_eq!(pdb.chains().next().unwrap().id(), "A");
+        assert!(pdb.atoms().all(|a| !a.hetero()));
+    }
+}
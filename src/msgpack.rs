@@ -0,0 +1,213 @@
+use anyhow::{bail, Result};
+
+
+/// A decoded MessagePack value. Extension types are not used by MMTF or
+/// BinaryCIF and are decoded as `Nil`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bin(Vec<u8>),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+}
+
+impl Value {
+    /// Value of `key` in a map with string keys.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| matches!(k, Value::Str(s) if s == key)).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            Value::Float(f) if f.fract() == 0.0 => Some(*f as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bin(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bin(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+}
+
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.pos + n > self.data.len() {
+            bail!("MessagePack data ends unexpectedly at byte {}", self.pos);
+        }
+        let bytes = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn uint(&mut self, n: usize) -> Result<u64> {
+        Ok(self.take(n)?.iter().fold(0, |acc, b| acc << 8 | *b as u64))
+    }
+
+    fn int(&mut self, n: usize) -> Result<i64> {
+        let bits = 64 - 8 * n as u32;
+        Ok(((self.uint(n)? << bits) as i64) >> bits)
+    }
+
+    fn str(&mut self, n: usize) -> Result<Value> {
+        Ok(Value::Str(String::from_utf8_lossy(self.take(n)?).into_owned()))
+    }
+
+    fn array(&mut self, n: usize) -> Result<Value> {
+        (0..n).map(|_| self.value()).collect::<Result<_>>().map(Value::Array)
+    }
+
+    fn map(&mut self, n: usize) -> Result<Value> {
+        (0..n).map(|_| Ok((self.value()?, self.value()?))).collect::<Result<_>>().map(Value::Map)
+    }
+
+    fn ext(&mut self, n: usize) -> Result<Value> {
+        self.take(1 + n)?;
+        Ok(Value::Nil)
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let marker = self.take(1)?[0];
+        match marker {
+            0x00..=0x7f => Ok(Value::Int(marker as i64)),
+            0x80..=0x8f => self.map((marker & 0x0f) as usize),
+            0x90..=0x9f => self.array((marker & 0x0f) as usize),
+            0xa0..=0xbf => self.str((marker & 0x1f) as usize),
+            0xc0 => Ok(Value::Nil),
+            0xc2 => Ok(Value::Bool(false)),
+            0xc3 => Ok(Value::Bool(true)),
+            0xc4..=0xc6 => {
+                let n = self.uint(1 << (marker - 0xc4))? as usize;
+                Ok(Value::Bin(self.take(n)?.to_vec()))
+            }
+            0xc7..=0xc9 => {
+                let n = self.uint(1 << (marker - 0xc7))? as usize;
+                self.ext(n)
+            }
+            0xca => Ok(Value::Float(f32::from_bits(self.uint(4)? as u32) as f64)),
+            0xcb => Ok(Value::Float(f64::from_bits(self.uint(8)?))),
+            0xcc..=0xcf => Ok(Value::Int(self.uint(1 << (marker - 0xcc))? as i64)),
+            0xd0..=0xd3 => Ok(Value::Int(self.int(1 << (marker - 0xd0))?)),
+            0xd4..=0xd8 => self.ext(1 << (marker - 0xd4)),
+            0xd9..=0xdb => {
+                let n = self.uint(1 << (marker - 0xd9))? as usize;
+                self.str(n)
+            }
+            0xdc | 0xdd => {
+                let n = self.uint(2 << (marker - 0xdc))? as usize;
+                self.array(n)
+            }
+            0xde | 0xdf => {
+                let n = self.uint(2 << (marker - 0xde))? as usize;
+                self.map(n)
+            }
+            0xe0..=0xff => Ok(Value::Int(marker as i8 as i64)),
+            _ => bail!("Invalid MessagePack marker {:#x} at byte {}", marker, self.pos - 1),
+        }
+    }
+}
+
+
+/// Decodes a single MessagePack value.
+pub fn decode(data: &[u8]) -> Result<Value> {
+    Reader { data, pos: 0 }.value()
+}
+
+
+/// Encodes a value, for building test inputs.
+#[cfg(test)]
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    match value {
+        Value::Nil => out.push(0xc0),
+        Value::Bool(b) => out.push(if *b { 0xc3 } else { 0xc2 }),
+        Value::Int(i) => {
+            out.push(0xd3);
+            out.extend_from_slice(&i.to_be_bytes());
+        }
+        Value::Float(f) => {
+            out.push(0xcb);
+            out.extend_from_slice(&f.to_be_bytes());
+        }
+        Value::Str(s) => {
+            out.push(0xdb);
+            out.extend_from_slice(&(s.len() as u32).to_be_bytes());
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Bin(b) => {
+            out.push(0xc6);
+            out.extend_from_slice(&(b.len() as u32).to_be_bytes());
+            out.extend_from_slice(b);
+        }
+        Value::Array(a) => {
+            out.push(0xdd);
+            out.extend_from_slice(&(a.len() as u32).to_be_bytes());
+            a.iter().for_each(|v| out.extend(encode(v)));
+        }
+        Value::Map(m) => {
+            out.push(0xdf);
+            out.extend_from_slice(&(m.len() as u32).to_be_bytes());
+            for (k, v) in m {
+                out.extend(encode(k));
+                out.extend(encode(v));
+            }
+        }
+    }
+    out
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_compact_and_wide_forms() {
+        // {"a": [1, -1, 300, "xy"], "b": nil}
+        let data = [0x82, 0xa1, b'a', 0x94, 0x01, 0xff, 0xcd, 0x01, 0x2c, 0xa2, b'x', b'y', 0xa1, b'b', 0xc0];
+        let value = decode(&data).unwrap();
+        let a = value.get("a").unwrap().as_array().unwrap();
+        assert_eq!(a[..3].iter().map(|v| v.as_int().unwrap()).collect::<Vec<_>>(), vec![1, -1, 300]);
+        assert_eq!(a[3].as_str(), Some("xy"));
+        assert_eq!(value.get("b"), Some(&Value::Nil));
+        assert_eq!(decode(&encode(&value)).unwrap(), value);
+    }
+}
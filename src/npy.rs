@@ -0,0 +1,140 @@
+use crate::atomic::AtomicFile;
+use anyhow::Result;
+use std::io::Write;
+use std::path::Path;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+
+/// Element types that can be stored in `.npy` arrays.
+pub trait NpyElement {
+    /// numpy dtype description, e.g. `<f4`
+    const DESCR: &'static str;
+    fn write_le(&self, out: &mut Vec<u8>);
+}
+
+macro_rules! npy_element {
+    ($t:ty, $descr:expr) => {
+        impl NpyElement for $t {
+            const DESCR: &'static str = $descr;
+            fn write_le(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+        }
+    };
+}
+
+npy_element!(f32, "<f4");
+npy_element!(f64, "<f8");
+npy_element!(i64, "<i8");
+npy_element!(u16, "<u2");
+npy_element!(u8, "|u1");
+
+impl NpyElement for bool {
+    const DESCR: &'static str = "|b1";
+    fn write_le(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+
+fn header(descr: &str, shape: &[usize]) -> Vec<u8> {
+    let shape = match shape {
+        [n] => format!("({},)", n),
+        _ => format!("({})", shape.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut dict = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+    // Magic, version and header length take 10 bytes, the data must start 64-byte aligned
+    let padding = 64 - (10 + dict.len() + 1) % 64;
+    dict.push_str(&" ".repeat(padding % 64));
+    dict.push('\n');
+    let mut out = b"\x93NUMPY\x01\x00".to_vec();
+    out.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+    out.extend_from_slice(dict.as_bytes());
+    out
+}
+
+
+/// Serializes `data` in C order as a `.npy` array of the given shape.
+pub fn npy_bytes<T: NpyElement>(data: &[T], shape: &[usize]) -> Vec<u8> {
+    debug_assert_eq!(data.len(), shape.iter().product::<usize>());
+    let mut out = header(T::DESCR, shape);
+    out.reserve(std::mem::size_of_val(data));
+    for v in data {
+        v.write_le(&mut out);
+    }
+    out
+}
+
+
+/// Serializes strings as a 1D `.npy` unicode array.
+pub fn npy_strings(data: &[String]) -> Vec<u8> {
+    let width = data.iter().map(|s| s.chars().count()).max().unwrap_or(1).max(1);
+    let mut out = header(&format!("<U{}", width), &[data.len()]);
+    for s in data {
+        let mut chars = s.chars();
+        for _ in 0..width {
+            out.extend_from_slice(&(chars.next().map(|c| c as u32).unwrap_or(0)).to_le_bytes());
+        }
+    }
+    out
+}
+
+
+/// Writes named arrays into a compressed `.npz` archive, as `numpy.savez_compressed` does.
+pub struct NpzWriter {
+    zip: ZipWriter<AtomicFile>,
+}
+
+impl NpzWriter {
+    pub fn create(path: impl AsRef<Path>) -> Result<NpzWriter> {
+        Ok(NpzWriter { zip: ZipWriter::new(AtomicFile::create(path)?) })
+    }
+
+    /// Adds an already serialized `.npy` array.
+    pub fn add_npy(&mut self, name: &str, npy: &[u8]) -> Result<()> {
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        self.zip.start_file(format!("{}.npy", name), options)?;
+        self.zip.write_all(npy)?;
+        Ok(())
+    }
+
+    pub fn add<T: NpyElement>(&mut self, name: &str, data: &[T], shape: &[usize]) -> Result<()> {
+        self.add_npy(name, &npy_bytes(data, shape))
+    }
+
+    pub fn add_strings(&mut self, name: &str, data: &[String]) -> Result<()> {
+        self.add_npy(name, &npy_strings(data))
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.zip.finish()?.commit()?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_aligned_headers() {
+        for (shape, descr) in [(vec![3], "<f4"), (vec![2, 3, 4], "<f4"), (vec![], "<f4")] {
+            let n: usize = shape.iter().product();
+            let bytes = npy_bytes(&vec![1.5f32; n], &shape);
+            assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+            let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+            assert_eq!((10 + header_len) % 64, 0);
+            assert_eq!(bytes.len(), 10 + header_len + 4 * n);
+            let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+            assert!(header.ends_with('\n'));
+            assert!(header.contains(&format!("'descr': '{}'", descr)));
+            let expected = match shape.as_slice() {
+                [n] => format!("({},)", n),
+                _ => format!("({})", shape.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", ")),
+            };
+            assert!(header.contains(&format!("'shape': {}", expected)), "{}", header);
+            assert_eq!(&bytes[10 + header_len..10 + header_len + 4], &1.5f32.to_le_bytes());
+        }
+    }
+}
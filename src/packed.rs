@@ -0,0 +1,155 @@
+use crate::schema::{DType, FeatureSchema, GraphSchema};
+use anyhow::{bail, Context, Result};
+use petgraph::graph::Graph;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+
+/// Features of all nodes or edges as one matrix. `data` holds the columns one
+/// after another (column-major), so `data[column * rows + row]` is the value
+/// of a row. Missing values are NaN, which JSON stores as `null`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PackedMatrix {
+    pub columns: Vec<String>,
+    /// Rows and columns
+    pub shape: [usize; 2],
+    #[serde(deserialize_with = "nan_as_null")]
+    pub data: Vec<f64>,
+}
+
+
+fn nan_as_null<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<f64>, D::Error> {
+    let values: Vec<Option<f64>> = Deserialize::deserialize(deserializer)?;
+    Ok(values.into_iter().map(|v| v.unwrap_or(f64::NAN)).collect())
+}
+
+
+/// A graph with packed node and edge features.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PackedGraph {
+    pub nodes: PackedMatrix,
+    /// Source and target node of every edge
+    pub edge_index: [Vec<usize>; 2],
+    pub edges: PackedMatrix,
+}
+
+
+/// Columns of a feature: vectors get one per value (`embedding[0]`...),
+/// objects one per key (`scores.total`...).
+pub(crate) fn columns(feature: &FeatureSchema) -> Result<Vec<String>> {
+    let Some(dim) = feature.dim else {
+        bail!("{} has no fixed size and can't be packed", feature.name);
+    };
+    if feature.dtype == DType::Str && feature.vocabulary.is_none() {
+        bail!("{} is text without a vocabulary and can't be packed", feature.name);
+    }
+    Ok(match (&feature.components, dim) {
+        (Some(components), _) => components.iter().map(|c| format!("{}.{}", feature.name, c)).collect(),
+        (None, 1) => vec![feature.name.clone()],
+        (None, _) => (0..dim).map(|i| format!("{}[{}]", feature.name, i)).collect(),
+    })
+}
+
+
+/// The value of a feature of a serialized node or edge, following `.` into
+/// nested objects. Flattened features are found at the top level, and plain
+/// edge weights are their only feature.
+fn lookup<'a>(item: &'a Value, name: &str) -> Option<&'a Value> {
+    if !item.is_object() {
+        return Some(item);
+    }
+    item.get(name).or_else(|| name.split('.').try_fold(item, |v, key| v.get(key)))
+}
+
+
+/// A column name with its value of each item, `None` where missing.
+pub(crate) type ColumnValues<'a> = (String, Vec<Option<&'a Value>>);
+
+
+/// Values of every column of a feature for each serialized item.
+pub(crate) fn column_values<'a>(items: &'a [Value], feature: &FeatureSchema) -> Result<Vec<ColumnValues<'a>>> {
+    Ok(columns(feature)?.into_iter().enumerate().map(|(i, name)| {
+        let values = items.iter().map(|item| {
+            let value = lookup(item, &feature.name);
+            match (&feature.components, value) {
+                (Some(components), Some(value)) => value.get(&components[i]),
+                (None, Some(Value::Array(values))) => values.get(i),
+                _ => value,
+            }
+        });
+        (name, values.map(|v| v.filter(|v| !v.is_null())).collect())
+    }).collect())
+}
+
+
+/// Numeric value of a scalar: categories become their index in the vocabulary.
+fn number(value: Option<&Value>, feature: &FeatureSchema) -> f64 {
+    match (value, &feature.vocabulary) {
+        (Some(Value::String(s)), Some(vocabulary)) => vocabulary.iter().position(|v| v == s).map_or(f64::NAN, |i| i as f64),
+        (Some(Value::Bool(b)), _) => *b as u8 as f64,
+        (Some(v), _) => v.as_f64().unwrap_or(f64::NAN),
+        (None, _) => f64::NAN,
+    }
+}
+
+
+/// Packs the features listed in `schema` of every item into a matrix.
+pub fn pack<'a, T: Serialize + 'a>(items: impl IntoIterator<Item = &'a T>, schema: &[FeatureSchema]) -> Result<PackedMatrix> {
+    let items: Vec<Value> = items.into_iter().map(serde_json::to_value).collect::<Result<_, _>>()?;
+    let mut matrix = PackedMatrix { columns: Vec::new(), shape: [items.len(), 0], data: Vec::new() };
+    for feature in schema {
+        for (name, values) in column_values(&items, feature)? {
+            matrix.data.extend(values.into_iter().map(|v| number(v, feature)));
+            matrix.columns.push(name);
+        }
+    }
+    matrix.shape[1] = matrix.columns.len();
+    Ok(matrix)
+}
+
+
+/// Packs a graph whose node and edge features are described by `schema`.
+pub fn pack_graph<N: Serialize, E: Serialize>(graph: &Graph<N, E>, schema: &GraphSchema) -> Result<PackedGraph> {
+    let mut edge_index = [Vec::with_capacity(graph.edge_count()), Vec::with_capacity(graph.edge_count())];
+    for edge in graph.raw_edges() {
+        edge_index[0].push(edge.source().index());
+        edge_index[1].push(edge.target().index());
+    }
+    Ok(PackedGraph {
+        nodes: pack(graph.node_weights(), &schema.nodes).context("failed to pack node features")?,
+        edge_index,
+        edges: pack(graph.edge_weights(), &schema.edges).context("failed to pack edge features")?,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::atom_graph_schema;
+    use crate::{build_graph, BondType};
+    use pdbtbx::StrictnessLevel;
+
+    #[test]
+    fn packs_features_column_major() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        let pdb = pdbtbx::open(path, StrictnessLevel::Medium).unwrap().0;
+        let mut graph = build_graph(&pdb);
+        graph[petgraph::graph::NodeIndex::new(1)].embedding = Some(vec![0.5, 1.5]);
+        let packed = pack_graph(&graph, &atom_graph_schema(&graph)).unwrap();
+
+        let n = graph.node_count();
+        assert_eq!(packed.nodes.shape, [n, packed.nodes.columns.len()]);
+        assert_eq!(packed.nodes.data.len(), n * packed.nodes.columns.len());
+        let embedding = packed.nodes.columns.iter().position(|c| c == "embedding[1]").unwrap();
+        assert_eq!(packed.nodes.data[embedding * n + 1], 1.5);
+        assert!(packed.nodes.data[embedding * n].is_nan());
+
+        assert_eq!(packed.edges.columns, vec!["distance", "bond_type"]);
+        let e = graph.edge_count();
+        let first = &graph.raw_edges()[0].weight;
+        assert_eq!(packed.edges.data[0], first.distance);
+        assert_eq!(packed.edges.data[e], if first.bond_type == BondType::Covalent { 0.0 } else { 1.0 });
+        assert_eq!(packed.edge_index[0].len(), e);
+    }
+}
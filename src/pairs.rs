@@ -0,0 +1,182 @@
+use crate::atomic::AtomicFile;
+use crate::residue::{protein_residues, ResidueKey};
+use crate::residue_graph::neighbour_pairs;
+use anyhow::Result;
+use nalgebra::Vector3;
+use pdbtbx::{Residue, PDB};
+use rstar::primitives::GeomWithData;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+
+/// Backbone frame of a residue used for the orientation features.
+struct Frame {
+    n: Vector3<f64>,
+    ca: Vector3<f64>,
+    cb: Vector3<f64>,
+    /// Heavy atom positions, for the contact flag
+    atoms: Vec<Vector3<f64>>,
+}
+
+
+fn position(residue: &Residue, name: &str) -> Option<Vector3<f64>> {
+    residue.atoms().find(|a| a.name() == name).map(|a| {
+        let (x, y, z) = a.pos();
+        Vector3::new(x, y, z)
+    })
+}
+
+
+/// Frame of a residue, `None` if its backbone is incomplete. Glycines and
+/// residues without a modelled CB get an ideal virtual CB placed from the
+/// backbone.
+fn frame(residue: &Residue) -> Option<Frame> {
+    let n = position(residue, "N")?;
+    let ca = position(residue, "CA")?;
+    let c = position(residue, "C")?;
+    let cb = position(residue, "CB").unwrap_or_else(|| {
+        let b = ca - n;
+        let c = c - ca;
+        -0.58273431 * b.cross(&c) + 0.56802827 * b - 0.54067466 * c + ca
+    });
+    let atoms = residue.atoms()
+        .filter(|a| a.element().is_some_and(|e| e.symbol() != "H" && e.symbol() != "D"))
+        .map(|a| {
+            let (x, y, z) = a.pos();
+            Vector3::new(x, y, z)
+        })
+        .collect();
+    Some(Frame { n, ca, cb, atoms })
+}
+
+
+fn angle(a: Vector3<f64>, b: Vector3<f64>, c: Vector3<f64>) -> f64 {
+    (a - b).angle(&(c - b)).to_degrees()
+}
+
+
+fn dihedral(a: Vector3<f64>, b: Vector3<f64>, c: Vector3<f64>, d: Vector3<f64>) -> f64 {
+    let (b0, b1, b2) = (a - b, c - b, d - c);
+    let v = b0 - b1 * b0.dot(&b1) / b1.norm_squared();
+    let w = b2 - b1 * b2.dot(&b1) / b1.norm_squared();
+    let x = v.dot(&w);
+    let y = b1.normalize().cross(&v).dot(&w);
+    y.atan2(x).to_degrees()
+}
+
+
+/// Features of an ordered residue pair, with the trRosetta inter-residue
+/// orientations (in degrees).
+pub struct ResiduePair {
+    pub a: ResidueKey,
+    pub b: ResidueKey,
+    /// CB-CB distance in Å
+    pub distance: f64,
+    /// Shortest heavy atom distance in Å
+    pub min_distance: f64,
+    /// Dihedral CA(a), CB(a), CB(b), CA(b)
+    pub omega: f64,
+    /// Dihedral N(a), CA(a), CB(a), CB(b)
+    pub theta: f64,
+    /// Angle CA(a), CB(a), CB(b)
+    pub phi: f64,
+    /// Some pair of heavy atoms is within the graph edge cutoff
+    pub contact: bool,
+}
+
+
+/// Features of every ordered pair of distinct protein residues of the first
+/// model whose CB atoms are at most `max_distance` apart, in residue order.
+/// The pairs are found in a pass of their own over the CB positions, with
+/// the neighbour search residue graphs use, whatever the graph granularity.
+/// Residues with an incomplete backbone are skipped.
+pub fn residue_pairs(pdb: &PDB, max_distance: f64, contact_distance: f64) -> Vec<ResiduePair> {
+    let frames: Vec<(ResidueKey, Frame)> = protein_residues(pdb).into_iter()
+        .filter_map(|(key, r)| frame(r).map(|f| (key, f)))
+        .collect();
+    let points = frames.iter().enumerate().map(|(i, (_, f))| GeomWithData::new([f.cb.x, f.cb.y, f.cb.z], i)).collect();
+    let mut ordered: Vec<(usize, usize, f64)> = neighbour_pairs(points, max_distance).into_iter()
+        .flat_map(|((i, j), d)| [(i, j, d), (j, i, d)])
+        .collect();
+    ordered.sort_by_key(|(i, j, _)| (*i, *j));
+    ordered.into_iter().map(|(i, j, distance)| {
+        let ((key_a, a), (key_b, b)) = (&frames[i], &frames[j]);
+        let min_distance = a.atoms.iter()
+            .flat_map(|p| b.atoms.iter().map(move |q| (p - q).norm()))
+            .fold(f64::INFINITY, f64::min);
+        ResiduePair {
+            a: key_a.clone(),
+            b: key_b.clone(),
+            distance,
+            min_distance,
+            omega: dihedral(a.ca, a.cb, b.cb, b.ca),
+            theta: dihedral(a.n, a.ca, a.cb, b.cb),
+            phi: angle(a.ca, a.cb, b.cb),
+            contact: min_distance <= contact_distance,
+        }
+    }).collect()
+}
+
+
+/// Writes residue pairs as a flat CSV table with one row per ordered pair.
+pub fn write_pairs(pairs: &[ResiduePair], save_fname: &Path) -> Result<()> {
+    let mut out = BufWriter::new(AtomicFile::create(save_fname)?);
+    writeln!(out, "residue_a,residue_b,distance,min_distance,omega,theta,phi,contact")?;
+    for p in pairs {
+        writeln!(
+            out,
+            "{},{},{:.3},{:.3},{:.2},{:.2},{:.2},{}",
+            p.a, p.b, p.distance, p.min_distance, p.omega, p.theta, p.phi, p.contact as u8
+        )?;
+    }
+    out.into_inner().map_err(|e| e.into_error())?.commit()?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn structure(atoms: &[(&str, usize, [f64; 3])]) -> PDB {
+        let mut text = String::new();
+        for (i, (name, resseq, [x, y, z])) in atoms.iter().enumerate() {
+            text += &format!("ATOM  {:>5}  {:<3} ALA A{:>4}    {:>8.3}{:>8.3}{:>8.3}  1.00  0.00           {}\n", i + 1, name, resseq, x, y, z, &name[..1]);
+        }
+        text += "END\n";
+        pdbtbx::open_pdb_raw(std::io::BufReader::new(text.as_bytes()), pdbtbx::Context::None, pdbtbx::StrictnessLevel::Loose).unwrap().0
+    }
+
+    #[test]
+    fn computes_orientations() {
+        // CB(a) at the origin, CB(b) 4 Å along x, CA(a) bent back by 45°,
+        // N(a) and CA(b) set off perpendicular to the CB-CB axis
+        let pdb = structure(&[
+            ("N", 1, [-1.0, 1.0, 1.0]), ("CA", 1, [-1.0, 1.0, 0.0]), ("C", 1, [-2.0, 1.0, 0.0]), ("CB", 1, [0.0, 0.0, 0.0]),
+            ("N", 2, [5.0, 0.0, 1.0]), ("CA", 2, [4.0, 0.0, 1.0]), ("C", 2, [4.0, 0.0, 2.0]), ("CB", 2, [4.0, 0.0, 0.0]),
+        ]);
+        let pairs = residue_pairs(&pdb, 20.0, 3.0);
+        assert_eq!(pairs.len(), 2);
+        let p = &pairs[0];
+        assert_eq!((p.a.to_string(), p.b.to_string()), ("A:1".to_string(), "A:2".to_string()));
+        assert!((p.distance - 4.0).abs() < 1e-9);
+        assert!((p.omega - 90.0).abs() < 1e-9, "{}", p.omega);
+        assert!((p.theta + 90.0).abs() < 1e-9, "{}", p.theta);
+        assert!((p.phi - 135.0).abs() < 1e-9, "{}", p.phi);
+        assert!((p.min_distance - 4.0).abs() < 1e-9);
+        assert!(!p.contact);
+        assert!(residue_pairs(&pdb, 20.0, 4.0)[0].contact);
+
+        assert!(residue_pairs(&pdb, 3.9, 3.0).is_empty());
+    }
+
+    #[test]
+    fn places_virtual_cb() {
+        // Ideal alanine of the chemical component dictionary
+        let ala = structure(&[("N", 1, [-0.966, 0.493, 1.500]), ("CA", 1, [0.257, 0.418, 0.692]), ("C", 1, [-0.094, 0.017, -0.716])]);
+        let f = frame(ala.residues().next().unwrap()).unwrap();
+        assert!((f.cb - Vector3::new(1.204, -0.620, 1.296)).norm() < 0.1, "{}", f.cb);
+        assert!(((f.cb - f.ca).norm() - 1.53).abs() < 0.03);
+        assert!((angle(f.n, f.ca, f.cb) - 110.5).abs() < 2.0);
+    }
+}
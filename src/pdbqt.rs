@@ -0,0 +1,114 @@
+use crate::input::{build_pdb, AtomRecord};
+use anyhow::{anyhow, Result};
+use pdbtbx::PDB;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+
+/// Docking-assigned properties of a PDBQT atom.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DockingAtom {
+    /// Partial charge assigned by the docking preparation (e.g. Gasteiger)
+    pub partial_charge: f64,
+    /// AutoDock atom type, e.g. `OA` or `HD`
+    pub atom_type: String,
+}
+
+
+pub fn is_pdbqt(fname: &str) -> bool {
+    fname.to_ascii_lowercase().ends_with(".pdbqt")
+}
+
+
+/// Element of an AutoDock atom type. Types also encode hydrogen bonding and
+/// aromaticity (`OA` is an acceptor oxygen, `A` an aromatic carbon), so the
+/// element column of PDBQT files can't be read as an element directly.
+fn element(atom_type: &str) -> &str {
+    match atom_type.to_ascii_uppercase().as_str() {
+        "A" | "C" | "G0" | "G1" | "G2" | "G3" | "CG0" | "CG1" | "CG2" | "CG3" => "C",
+        "N" | "NA" | "NS" => "N",
+        "O" | "OA" | "OS" => "O",
+        "S" | "SA" => "S",
+        "H" | "HD" | "HS" => "H",
+        "W" => "O",
+        _ => atom_type,
+    }
+}
+
+
+fn field(line: &str, range: std::ops::Range<usize>) -> &str {
+    line.get(range.start..range.end.min(line.len())).unwrap_or("").trim()
+}
+
+
+fn number<T: std::str::FromStr>(line: &str, range: std::ops::Range<usize>, name: &str, line_number: usize) -> Result<T> {
+    field(line, range).parse().map_err(|_| anyhow!("invalid {} on line {}", name, line_number))
+}
+
+
+/// Reads a PDBQT file: PDB atom records followed by a partial charge and an
+/// AutoDock atom type. Torsion tree records (`ROOT`, `BRANCH`...) are
+/// skipped, `MODEL` records (docking poses) start new models. Returns the
+/// structure and the docking properties of its atoms by serial number.
+pub fn read(text: &str) -> Result<(PDB, HashMap<usize, DockingAtom>)> {
+    let mut atoms = Vec::new();
+    let mut docking = HashMap::new();
+    let mut model = 1;
+    for (i, line) in text.lines().enumerate() {
+        let n = i + 1;
+        if let Some(number) = line.strip_prefix("MODEL") {
+            // Vina writes `MODEL 1` rather than the fixed PDB columns
+            model = number.trim().parse().unwrap_or(model);
+            continue;
+        }
+        if !line.starts_with("ATOM") && !line.starts_with("HETATM") {
+            continue;
+        }
+        let atom_type = field(line, 76..line.len()).to_string();
+        let serial_number = number(line, 6..11, "serial number", n)?;
+        atoms.push(AtomRecord {
+            model,
+            chain: field(line, 21..22).to_string(),
+            resseq: number(line, 22..26, "residue number", n).unwrap_or(0),
+            icode: Some(field(line, 26..27).to_string()).filter(|c| !c.is_empty()),
+            resname: field(line, 17..21).to_string(),
+            name: field(line, 12..16).to_string(),
+            altloc: Some(field(line, 16..17).to_string()).filter(|a| !a.is_empty()),
+            element: element(&atom_type).to_string(),
+            pos: (number(line, 30..38, "x", n)?, number(line, 38..46, "y", n)?, number(line, 46..54, "z", n)?),
+            occupancy: number(line, 54..60, "occupancy", n).unwrap_or(1.0),
+            b_factor: number(line, 60..66, "b-factor", n).unwrap_or(0.0),
+            charge: 0,
+            hetero: line.starts_with("HETATM"),
+            serial_number,
+        });
+        docking.insert(serial_number, DockingAtom {
+            partial_charge: number(line, 66..76, "partial charge", n)?,
+            atom_type,
+        });
+    }
+    Ok((build_pdb(None, atoms)?, docking))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_charges_and_types() {
+        let text = "\
+MODEL 2
+ROOT
+ATOM      1  C1  LIG     1      10.000  10.000  10.000  0.00  0.00    +0.123 A 
+ATOM      2  N1  LIG     1      11.200  10.000  10.000  0.00  0.00    -0.456 NA
+ENDROOT
+ENDMDL
+";
+        let (pdb, docking) = read(text).unwrap();
+        assert_eq!(pdb.model(0).unwrap().serial_number(), 2);
+        let elements: Vec<&str> = pdb.atoms().map(|a| a.element().unwrap().symbol()).collect();
+        assert_eq!(elements, vec!["C", "N"]);
+        assert_eq!(docking[&2], DockingAtom { partial_charge: -0.456, atom_type: "NA".to_string() });
+    }
+}
@@ -0,0 +1,135 @@
+use crate::npy::NpzWriter;
+use crate::residue::AtomIdentity;
+use crate::{feature_masks, GraphBuilder, NodeAnnotations, NodeFeature};
+use clap::ValueEnum;
+use anyhow::Result;
+use pdbtbx::PDB;
+use std::path::Path;
+
+
+/// Writes the featurized atoms of `pdb` as a point cloud to an `.npz`
+/// archive: `coords` `[N, 3]` next to one array per node feature of
+/// `builder`, in the same order. No neighbour search is done, so this is
+/// much cheaper than building the graph. Features missing on some atoms are
+/// zero there, with a boolean `<feature>_mask` array marking the atoms that
+/// have them.
+pub fn write_point_cloud(pdb: &PDB, builder: &GraphBuilder, annotations: &NodeAnnotations, save_fname: &Path) -> Result<usize> {
+    let mut coords = Vec::new();
+    let mut nodes = Vec::new();
+    for chain in pdb.models().flat_map(|m| m.chains()) {
+        for residue in chain.residues() {
+            for atom in residue.atoms() {
+                if let Some(node) = builder.node(chain, residue, atom, annotations, None) {
+                    let (x, y, z) = atom.pos();
+                    coords.extend([x, y, z]);
+                    nodes.push(node);
+                }
+            }
+        }
+    }
+    let n = nodes.len();
+    let mut npz = NpzWriter::create(save_fname)?;
+    npz.add("coords", &coords, &[n, 3])?;
+    npz.add("id", &nodes.iter().map(|a| a.id as i64).collect::<Vec<_>>(), &[n])?;
+    npz.add("atom_number", &nodes.iter().map(|a| a.atom_number).collect::<Vec<_>>(), &[n])?;
+    for feature in &builder.features {
+        match feature {
+            NodeFeature::Valence => npz.add("valence", &nodes.iter().map(|a| a.valence.unwrap_or(0)).collect::<Vec<_>>(), &[n])?,
+            NodeFeature::Electronegativity => npz.add("electronegativity", &nodes.iter().map(|a| a.electronegativity.unwrap_or(0.0)).collect::<Vec<_>>(), &[n])?,
+            NodeFeature::Charge => npz.add("charge", &nodes.iter().map(|a| a.charge.unwrap_or(0) as i64).collect::<Vec<_>>(), &[n])?,
+            // Always written as `coords`
+            NodeFeature::Coordinates => {}
+            NodeFeature::Bfactor => npz.add("bfactor", &nodes.iter().map(|a| a.bfactor.unwrap_or(0.0)).collect::<Vec<_>>(), &[n])?,
+            NodeFeature::Occupancy => npz.add("occupancy", &nodes.iter().map(|a| a.occupancy.unwrap_or(0.0)).collect::<Vec<_>>(), &[n])?,
+            NodeFeature::Identity => {
+                let identities: Vec<&AtomIdentity> = nodes.iter().filter_map(|a| a.identity.as_ref()).collect();
+                let text = |field: fn(&AtomIdentity) -> String| identities.iter().map(|i| field(i)).collect::<Vec<_>>();
+                npz.add_strings("chain", &text(|i| i.chain.clone()))?;
+                npz.add_strings("residue_name", &text(|i| i.residue_name.clone()))?;
+                npz.add("residue_number", &identities.iter().map(|i| i.residue_number as i64).collect::<Vec<_>>(), &[n])?;
+                npz.add_strings("insertion_code", &text(|i| i.insertion_code.clone().unwrap_or_default()))?;
+                npz.add_strings("atom_name", &text(|i| i.atom_name.clone()))?;
+            }
+            NodeFeature::Sasa => {
+                npz.add("sasa", &nodes.iter().map(|a| a.sasa.map_or(0.0, |s| s.sasa)).collect::<Vec<_>>(), &[n])?;
+                npz.add("residue_sasa", &nodes.iter().map(|a| a.sasa.map_or(0.0, |s| s.residue_sasa)).collect::<Vec<_>>(), &[n])?;
+                npz.add("relative_sasa", &nodes.iter().map(|a| a.sasa.and_then(|s| s.relative_sasa).unwrap_or(0.0)).collect::<Vec<_>>(), &[n])?;
+            }
+        }
+    }
+    for (name, mask) in feature_masks(&nodes) {
+        if builder.features.iter().any(|f| f.to_possible_value().is_some_and(|v| v.get_name() == name)) {
+            npz.add(&format!("{}_mask", name), &mask, &[n])?;
+        }
+    }
+    npz.finish()?;
+    Ok(n)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sasa::AtomSasa;
+    use std::io::Read;
+
+    /// Header and data of every array of an `.npz` archive.
+    fn read_npz(path: &Path) -> std::collections::HashMap<String, (String, Vec<u8>)> {
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(path).unwrap()).unwrap();
+        (0..zip.len()).map(|i| {
+            let mut entry = zip.by_index(i).unwrap();
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes).unwrap();
+            let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+            let header = String::from_utf8(bytes[10..10 + header_len].to_vec()).unwrap();
+            let name = entry.name().trim_end_matches(".npy").to_string();
+            (name, (header, bytes[10 + header_len..].to_vec()))
+        }).collect()
+    }
+
+    fn f64s(data: &[u8]) -> Vec<f64> {
+        data.chunks(8).map(|c| f64::from_le_bytes(c.try_into().unwrap())).collect()
+    }
+
+    #[test]
+    fn aligns_arrays_per_atom() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        let pdb = pdbtbx::open(path, pdbtbx::StrictnessLevel::Medium).unwrap().0;
+        let atoms: Vec<_> = pdb.atoms().collect();
+        let n = atoms.len();
+        let builder = GraphBuilder::new().features(&[NodeFeature::Bfactor, NodeFeature::Identity, NodeFeature::Sasa]);
+        // Only one atom has a SASA, so the others are masked
+        let mut annotations = NodeAnnotations::default();
+        annotations.sasa.insert(atoms[5].serial_number(), AtomSasa { sasa: 7.5, residue_sasa: 20.0, relative_sasa: None });
+
+        let save_fname = std::env::temp_dir().join(format!("graphein_pointcloud_{}.npz", std::process::id()));
+        assert_eq!(write_point_cloud(&pdb, &builder, &annotations, &save_fname).unwrap(), n);
+        let arrays = read_npz(&save_fname);
+        std::fs::remove_file(&save_fname).unwrap();
+
+        let (header, coords) = &arrays["coords"];
+        assert!(header.contains(&format!("'shape': ({}, 3)", n)), "{}", header);
+        let coords = f64s(coords);
+        let bfactors = f64s(&arrays["bfactor"].1);
+        let sasa = f64s(&arrays["sasa"].1);
+        for (i, atom) in atoms.iter().enumerate() {
+            let (x, y, z) = atom.pos();
+            assert_eq!(&coords[3 * i..3 * i + 3], &[x, y, z]);
+            assert_eq!(bfactors[i], atom.b_factor());
+            assert_eq!(sasa[i], if i == 5 { 7.5 } else { 0.0 });
+        }
+        let mask: Vec<bool> = arrays["sasa_mask"].1.iter().map(|b| *b == 1).collect();
+        assert_eq!(mask, (0..n).map(|i| i == 5).collect::<Vec<_>>());
+
+        for name in ["id", "atom_number", "bfactor", "sasa", "sasa_mask", "chain", "residue_name", "residue_number", "insertion_code", "atom_name"] {
+            assert!(arrays[name].0.contains(&format!("'shape': ({},)", n)), "{}: {}", name, arrays[name].0);
+        }
+        // Identity strings are fixed-width UTF-32
+        let (header, data) = &arrays["atom_name"];
+        let width: usize = header.split("'<U").nth(1).unwrap().split('\'').next().unwrap().parse().unwrap();
+        let names: Vec<String> = data.chunks(4 * width)
+            .map(|c| c.chunks(4).map(|u| u32::from_le_bytes(u.try_into().unwrap())).filter(|u| *u != 0).filter_map(char::from_u32).collect())
+            .collect();
+        assert_eq!(names, atoms.iter().map(|a| a.name().to_string()).collect::<Vec<_>>());
+    }
+}
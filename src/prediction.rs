@@ -0,0 +1,170 @@
+use anyhow::{bail, Context, Result};
+use pdbtbx::PDB;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+
+/// Where the per-residue confidence of a predicted model was read from.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfidenceSource {
+    /// ColabFold's `_scores` JSON next to the model
+    ScoresJson,
+    /// The b-factor column, where AlphaFold and ESMFold write pLDDT
+    BFactor,
+}
+
+
+/// Summary of a predicted model, stored in the graph metadata.
+#[derive(Serialize, Debug, Clone)]
+pub struct PredictionInfo {
+    /// Job name of the prediction (the ColabFold query or output directory)
+    pub job: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rank: Option<usize>,
+    pub confidence: ConfidenceSource,
+    pub mean_plddt: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ptm: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iptm: Option<f64>,
+}
+
+
+/// ColabFold per-model scores.
+#[derive(Deserialize)]
+struct Scores {
+    plddt: Vec<f64>,
+    ptm: Option<f64>,
+    iptm: Option<f64>,
+}
+
+
+fn is_model(path: &Path) -> bool {
+    matches!(path.extension().and_then(|e| e.to_str()), Some("pdb" | "cif"))
+}
+
+
+/// ColabFold model names are `<job>_[un]relaxed_rank_<rank>_<model>`,
+/// returns the job, the rank and whether the model is relaxed.
+fn colabfold_rank(stem: &str) -> Option<(&str, usize, bool)> {
+    let (prefix, rest) = stem.split_once("_rank_")?;
+    let rank = rest.split('_').next()?.parse().ok()?;
+    if let Some(job) = prefix.strip_suffix("_unrelaxed") {
+        Some((job, rank, false))
+    } else {
+        prefix.strip_suffix("_relaxed").map(|job| (job, rank, true))
+    }
+}
+
+
+fn stem(path: &Path) -> &str {
+    path.file_stem().and_then(|s| s.to_str()).unwrap_or("")
+}
+
+
+/// Models to convert from a structure prediction output directory:
+/// the top-ranked model of every ColabFold job (relaxed if available),
+/// AlphaFold's `ranked_0.pdb`, or else every model, as ESMFold writes one
+/// file per sequence.
+pub fn models(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("failed to list {}", dir.display()))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && is_model(p))
+        .collect();
+    files.sort();
+    let mut top: BTreeMap<&str, (usize, bool, &PathBuf)> = BTreeMap::new();
+    for file in &files {
+        if let Some((job, rank, relaxed)) = colabfold_rank(stem(file)) {
+            let best = top.entry(job).or_insert((rank, relaxed, file));
+            if (rank, !relaxed) < (best.0, !best.1) {
+                *best = (rank, relaxed, file);
+            }
+        }
+    }
+    let models: Vec<PathBuf> = if !top.is_empty() {
+        top.into_values().map(|(_, _, file)| file.clone()).collect()
+    } else if let Some(ranked) = files.iter().find(|f| stem(f) == "ranked_0") {
+        vec![ranked.clone()]
+    } else {
+        files
+    };
+    if models.is_empty() {
+        bail!("no predicted models in {}", dir.display());
+    }
+    Ok(models)
+}
+
+
+/// ColabFold scores file of a model: `<job>_scores_rank_<...>.json` for
+/// `<job>_unrelaxed_rank_<...>.pdb`, or `<model>_scores.json` in releases
+/// before 1.5.
+fn scores_path(model: &Path) -> Option<PathBuf> {
+    let stem = stem(model);
+    let (prefix, rest) = stem.split_once("_rank_")?;
+    let job = prefix.strip_suffix("_unrelaxed").or_else(|| prefix.strip_suffix("_relaxed"))?;
+    [format!("{}_scores_rank_{}.json", job, rest), format!("{}_unrelaxed_rank_{}_scores.json", job, rest)]
+        .into_iter()
+        .map(|name| model.with_file_name(name))
+        .find(|p| p.is_file())
+}
+
+
+/// pLDDT of every atom of the first model by serial number, from the
+/// ColabFold scores next to `fname` or else from the b-factors. Scores
+/// list residues in file order; b-factors on a 0-1 scale (some ESMFold
+/// versions) are rescaled to 0-100.
+pub fn confidence(fname: &str, pdb: &PDB) -> Result<(HashMap<usize, f64>, PredictionInfo)> {
+    let path = Path::new(fname);
+    let model = pdb.model(0).context("structure has no models")?;
+    let mut plddt = HashMap::new();
+    let (source, ptm, iptm) = match scores_path(path) {
+        Some(scores_path) => {
+            let text = std::fs::read_to_string(&scores_path)?;
+            let scores: Scores = serde_json::from_str(&text)
+                .with_context(|| format!("invalid ColabFold scores {}", scores_path.display()))?;
+            if scores.plddt.len() != model.residue_count() {
+                bail!("{} has {} pLDDT values for {} residues", scores_path.display(), scores.plddt.len(), model.residue_count());
+            }
+            for (residue, value) in model.residues().zip(&scores.plddt) {
+                plddt.extend(residue.atoms().map(|a| (a.serial_number(), *value)));
+            }
+            (ConfidenceSource::ScoresJson, scores.ptm, scores.iptm)
+        }
+        None => {
+            let scale = if model.atoms().all(|a| a.b_factor() <= 1.0) { 100.0 } else { 1.0 };
+            plddt.extend(model.atoms().map(|a| (a.serial_number(), a.b_factor() * scale)));
+            (ConfidenceSource::BFactor, None, None)
+        }
+    };
+    let stem = stem(path);
+    let (job, rank) = match colabfold_rank(stem) {
+        Some((job, rank, _)) => (job.to_string(), Some(rank)),
+        None if stem == "ranked_0" => {
+            let dir = path.parent().and_then(|d| d.file_name()).and_then(|d| d.to_str());
+            (dir.unwrap_or(stem).to_string(), Some(1))
+        }
+        None => (stem.to_string(), None),
+    };
+    let residue_plddt: Vec<f64> = model.residues()
+        .filter_map(|r| r.atoms().next().and_then(|a| plddt.get(&a.serial_number())))
+        .copied()
+        .collect();
+    let mean_plddt = residue_plddt.iter().sum::<f64>() / residue_plddt.len().max(1) as f64;
+    Ok((plddt, PredictionInfo { job, rank, confidence: source, mean_plddt, ptm, iptm }))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_colabfold_names() {
+        assert_eq!(colabfold_rank("q1_unrelaxed_rank_001_alphafold2_ptm_model_3_seed_000"), Some(("q1", 1, false)));
+        assert_eq!(colabfold_rank("q1_relaxed_rank_2_model_5"), Some(("q1", 2, true)));
+        assert_eq!(colabfold_rank("ranked_0"), None);
+    }
+}
@@ -0,0 +1,73 @@
+use crate::npy::NpzWriter;
+use crate::packed::{pack, pack_graph, PackedMatrix};
+use crate::schema::GraphSchema;
+use anyhow::Result;
+use petgraph::graph::Graph;
+use serde::Serialize;
+use std::path::Path;
+
+
+/// Row-major float32 values of a packed matrix, with missing values zeroed,
+/// and whether each value was present.
+fn rows(matrix: &PackedMatrix) -> (Vec<f32>, Vec<bool>) {
+    let [n, columns] = matrix.shape;
+    let mut values = Vec::with_capacity(n * columns);
+    let mut present = Vec::with_capacity(n * columns);
+    for row in 0..n {
+        for column in 0..columns {
+            let value = matrix.data[column * n + row];
+            values.push(if value.is_nan() { 0.0 } else { value as f32 });
+            present.push(!value.is_nan());
+        }
+    }
+    (values, present)
+}
+
+
+/// Writes a graph as an `.npz` archive laid out as a PyTorch Geometric
+/// `Data` object: `node_features` `[N, F]`, `edge_index` `[2, E]` and
+/// `edge_attr` `[E, D]`, with the column names in `node_feature_names` and
+/// `edge_attr_names` and the node ids in `node_id`. Atom coordinates are
+/// written as `pos` `[N, 3]` rather than as node features. Features are
+/// packed as described by `schema`, categories as their index in the
+/// vocabulary. Missing values are zero, and a boolean `<array>_mask` of the
+/// same shape marks the present ones if any are missing.
+pub fn write_pyg<N: Serialize, E: Serialize>(graph: &Graph<N, E>, schema: &GraphSchema, save_fname: &Path) -> Result<()> {
+    let (pos, nodes): (Vec<_>, Vec<_>) = schema.nodes.iter().filter(|f| f.name != "id").cloned().partition(|f| f.name == "pos");
+    let packed = pack_graph(graph, &GraphSchema { nodes, edges: schema.edges.clone() })?;
+    let ids = serde_json::to_value(graph.node_weights().collect::<Vec<_>>())?;
+    let ids: Vec<i64> = ids.as_array().into_iter().flatten().map(|n| n.get("id").and_then(|id| id.as_i64()).unwrap_or(-1)).collect();
+
+    let mut npz = NpzWriter::create(save_fname)?;
+    npz.add("node_id", &ids, &[ids.len()])?;
+    if !pos.is_empty() {
+        let pos = pack(graph.node_weights(), &pos)?;
+        npz.add("pos", &rows(&pos).0, &pos.shape)?;
+    }
+    for (name, matrix) in [("node_features", &packed.nodes), ("edge_attr", &packed.edges)] {
+        let (values, present) = rows(matrix);
+        npz.add(name, &values, &matrix.shape)?;
+        if present.contains(&false) {
+            npz.add(&format!("{}_mask", name), &present, &matrix.shape)?;
+        }
+    }
+    npz.add_strings("node_feature_names", &packed.nodes.columns)?;
+    npz.add_strings("edge_attr_names", &packed.edges.columns)?;
+    let edge_index: Vec<i64> = packed.edge_index.iter().flatten().map(|i| *i as i64).collect();
+    npz.add("edge_index", &edge_index, &[2, graph.edge_count()])?;
+    npz.finish()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transposes_to_rows() {
+        let matrix = PackedMatrix { columns: vec!["a".into(), "b".into()], shape: [3, 2], data: vec![1.0, 2.0, 3.0, 4.0, f64::NAN, 6.0] };
+        let (values, present) = rows(&matrix);
+        assert_eq!(values, vec![1.0, 4.0, 2.0, 0.0, 3.0, 6.0]);
+        assert_eq!(present, vec![true, true, true, false, true, true]);
+    }
+}
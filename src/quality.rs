@@ -0,0 +1,160 @@
+use crate::dssp::PEPTIDE_BOND;
+use crate::entity::{classify_residue, EntityType};
+use crate::hbond::bonded;
+use crate::van_der_waals_radius;
+use pdbtbx::{Atom, Element, Residue, PDB};
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+use serde::{Deserialize, Serialize};
+
+
+/// Overlap (in Å) of the van der Waals spheres of two non-bonded atoms above
+/// which they clash, MolProbity's threshold for serious clashes.
+pub const CLASH_OVERLAP: f64 = 0.4;
+
+/// Atoms at most this many bonds apart don't clash, as in Probe.
+const BONDS_APART: usize = 3;
+
+
+/// Counts of the anomalies found in a structure, stored in the graph metadata
+/// so that low-quality structures can be filtered after a run.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct QualityFlags {
+    /// Pairs of heavy atoms of different residues that clash
+    pub clashes: usize,
+    /// Atoms with an alternate location, before `--altloc` resolves them
+    pub altloc_atoms: usize,
+    /// Consecutive amino acids of a chain whose C and N atoms are too far
+    /// apart for a peptide bond
+    pub chain_breaks: usize,
+    /// Atoms whose element isn't recognized
+    pub unknown_elements: usize,
+    pub parse_warnings: usize,
+    /// Implausible coordinates found by the coordinate check
+    pub coordinate_issues: usize,
+}
+
+
+/// Flags of the first model of `pdb`. Covalent bonds are inferred with
+/// `bond_tolerance` as for edges. Parse warnings and coordinate issues are found elsewhere and
+/// filled in by the caller.
+pub fn quality_flags(pdb: &PDB, bond_tolerance: f64) -> QualityFlags {
+    let Some(model) = pdb.model(0) else {
+        return QualityFlags::default();
+    };
+    let mut flags = QualityFlags::default();
+    let mut heavy: Vec<HeavyAtom> = Vec::new();
+    for (residue_index, residue) in model.residues().enumerate() {
+        for conformer in residue.conformers() {
+            let altloc = conformer.alternative_location();
+            for atom in conformer.atoms() {
+                flags.altloc_atoms += altloc.is_some() as usize;
+                match atom.element() {
+                    None => flags.unknown_elements += 1,
+                    Some(Element::H) => {}
+                    Some(element) => {
+                        if let Some(radius) = van_der_waals_radius(element) {
+                            heavy.push(HeavyAtom { residue: residue_index, altloc, radius, atom });
+                        }
+                    }
+                }
+            }
+        }
+    }
+    flags.clashes = clashes(&heavy, bond_tolerance);
+
+    for chain in model.chains() {
+        let amino_acids: Vec<&Residue> = chain.residues().filter(|r| classify_residue(r) == EntityType::Protein).collect();
+        for pair in amino_acids.windows(2) {
+            if let (Some(c), Some(n)) = (named(pair[0], "C"), named(pair[1], "N")) {
+                flags.chain_breaks += (c.distance(n) > PEPTIDE_BOND) as usize;
+            }
+        }
+    }
+    flags
+}
+
+
+/// A heavy atom with a van der Waals radius, and the index of its residue.
+struct HeavyAtom<'a> {
+    residue: usize,
+    altloc: Option<&'a str>,
+    radius: f64,
+    atom: &'a Atom,
+}
+
+
+/// Clashing pairs of atoms of different residues. Atoms at most
+/// `BONDS_APART` bonds apart are meant to be close and left out, as are
+/// atoms of different alternate locations.
+fn clashes(heavy: &[HeavyAtom], bond_tolerance: f64) -> usize {
+    let points = heavy.iter().enumerate().map(|(i, h)| GeomWithData::new([h.atom.x(), h.atom.y(), h.atom.z()], i)).collect();
+    let tree: RTree<GeomWithData<[f64; 3], usize>> = RTree::bulk_load(points);
+    let covalent = heavy.iter().filter_map(|h| h.atom.element()).map(|e| e.atomic_radius().covalent_single).fold(0.0, f64::max);
+    let bond_reach = 2.0 * covalent + bond_tolerance;
+    let bonds: Vec<Vec<usize>> = heavy.iter().enumerate().map(|(i, h)| {
+        tree.locate_within_distance([h.atom.x(), h.atom.y(), h.atom.z()], bond_reach * bond_reach)
+            .map(|p| p.data)
+            .filter(|j| *j != i && bonded(h.atom, heavy[*j].atom, bond_tolerance))
+            .collect()
+    }).collect();
+    let within_bonds = |from: usize, to: usize| {
+        let mut frontier = vec![from];
+        for _ in 0..BONDS_APART {
+            frontier = frontier.iter().flat_map(|i| bonds[*i].iter().copied()).collect();
+            if frontier.contains(&to) {
+                return true;
+            }
+        }
+        false
+    };
+
+    let largest = heavy.iter().map(|h| h.radius).fold(0.0, f64::max);
+    let mut clashes = 0;
+    for (i, a) in heavy.iter().enumerate() {
+        let reach = a.radius + largest - CLASH_OVERLAP;
+        for j in tree.locate_within_distance([a.atom.x(), a.atom.y(), a.atom.z()], reach * reach).map(|p| p.data) {
+            let b = &heavy[j];
+            // Each pair once, and only between residues
+            if b.residue <= a.residue || (a.altloc.is_some() && b.altloc.is_some() && a.altloc != b.altloc) {
+                continue;
+            }
+            if a.atom.distance(b.atom) < a.radius + b.radius - CLASH_OVERLAP && !within_bonds(i, j) {
+                clashes += 1;
+            }
+        }
+    }
+    clashes
+}
+
+
+fn named<'a>(residue: &'a Residue, name: &str) -> Option<&'a Atom> {
+    residue.atoms().find(|a| a.name() == name)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_clashes_and_breaks() {
+        let pdb = crate::golden::Fixture::get("benzoate_stacking").unwrap().structure().unwrap();
+        assert_eq!(quality_flags(&pdb, crate::DEFAULT_BOND_TOLERANCE), QualityFlags::default());
+
+        let pdb_text = "\
+ATOM      1  N   GLY A   1       0.000   0.000   0.000  1.00  0.00           N
+ATOM      2  CA  GLY A   1       1.450   0.000   0.000  1.00  0.00           C
+ATOM      3  C   GLY A   1       2.000   1.400   0.000  1.00  0.00           C
+ATOM      4  N   GLY A   2       9.000   1.400   0.000  1.00  0.00           N
+ATOM      5  CA  GLY A   2      10.450   1.400   0.000  1.00  0.00           C
+ATOM      6  C   GLY A   2      11.000   2.800   0.000  1.00  0.00           C
+HETATM    7  O   HOH A   3       1.450   0.000   2.000  1.00  0.00           O
+END
+";
+        let pdb = pdbtbx::open_pdb_raw(std::io::BufReader::new(pdb_text.as_bytes()), pdbtbx::Context::None, pdbtbx::StrictnessLevel::Loose).unwrap().0;
+        let flags = quality_flags(&pdb, crate::DEFAULT_BOND_TOLERANCE);
+        assert_eq!(flags.chain_breaks, 1);
+        assert!(flags.clashes > 0);
+    }
+}
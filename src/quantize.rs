@@ -0,0 +1,85 @@
+use crate::bond_order::BondOrder;
+use crate::{BondType, EdgeData, EdgeKind};
+use petgraph::graph::Graph;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+
+/// Fixed-point storage of edge distances: a distance `d` in Å is stored as
+/// the unsigned 16 bit integer `round(d * scale)`, saturating at 65535.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct DistanceQuantization {
+    /// Stored units per Å, e.g. 100 for centi-Ångström
+    pub scale: f64,
+}
+
+impl DistanceQuantization {
+    pub fn quantize(&self, distance: f64) -> u16 {
+        (distance * self.scale).round().clamp(0.0, u16::MAX as f64) as u16
+    }
+
+    pub fn dequantize(&self, value: u16) -> f64 {
+        value as f64 / self.scale
+    }
+}
+
+
+/// Edge data of atom graphs with a quantized distance.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QuantizedEdgeData {
+    pub distance: u16,
+    pub bond_type: BondType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bond_order: Option<BondOrder>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edge_kind: Option<EdgeKind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constants: Option<BTreeMap<String, f64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cutoff: Option<f64>,
+}
+
+
+/// Edge weights whose distance can be quantized.
+pub trait Quantize {
+    type Quantized: Serialize;
+    fn quantize(&self, quantization: &DistanceQuantization) -> Self::Quantized;
+}
+
+impl Quantize for EdgeData {
+    type Quantized = QuantizedEdgeData;
+    fn quantize(&self, quantization: &DistanceQuantization) -> QuantizedEdgeData {
+        QuantizedEdgeData { distance: quantization.quantize(self.distance), bond_type: self.bond_type, bond_order: self.bond_order, edge_kind: self.edge_kind, constants: self.constants.clone(), cutoff: self.cutoff }
+    }
+}
+
+/// Plain distance weights, as in residue graphs.
+impl Quantize for f64 {
+    type Quantized = u16;
+    fn quantize(&self, quantization: &DistanceQuantization) -> u16 {
+        quantization.quantize(*self)
+    }
+}
+
+
+/// View of `graph` with quantized edge distances, borrowing its nodes.
+pub fn quantize_graph<'a, N, E: Quantize>(graph: &'a Graph<N, E>, quantization: &DistanceQuantization) -> Graph<&'a N, E::Quantized> {
+    graph.map(|_, node| node, |_, edge| edge.quantize(quantization))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantizes_to_centi_angstrom() {
+        let centi = DistanceQuantization { scale: 100.0 };
+        assert_eq!(centi.quantize(2.8737), 287);
+        assert_eq!(centi.dequantize(287), 2.87);
+        assert_eq!(centi.quantize(1000.0), u16::MAX);
+
+        let edge = EdgeData { distance: 1.3945, bond_type: BondType::Covalent, bond_order: None, edge_kind: None, constants: None, cutoff: None };
+        assert_eq!(edge.quantize(&centi).distance, 139);
+    }
+}
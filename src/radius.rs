@@ -0,0 +1,160 @@
+use crate::van_der_waals_radius;
+use anyhow::{bail, Result};
+use pdbtbx::Element;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+
+/// Radius in Å given to elements without a tabulated van der Waals radius
+/// by default.
+pub const DEFAULT_UNKNOWN_RADIUS: f64 = 1.8;
+
+
+/// How elements without a tabulated van der Waals radius are handled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnknownRadius {
+    /// Give them this radius in Å
+    Fixed(f64),
+    /// Estimate their radius from their group and period
+    Group,
+    /// Fail the structure
+    Error,
+}
+
+impl Default for UnknownRadius {
+    fn default() -> UnknownRadius {
+        UnknownRadius::Fixed(DEFAULT_UNKNOWN_RADIUS)
+    }
+}
+
+impl UnknownRadius {
+    pub fn parse(s: &str) -> std::result::Result<UnknownRadius, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "group" => Ok(UnknownRadius::Group),
+            "error" => Ok(UnknownRadius::Error),
+            _ => match s.parse::<f64>() {
+                Ok(radius) if radius > 0.0 && radius.is_finite() => Ok(UnknownRadius::Fixed(radius)),
+                _ => Err(format!("invalid unknown element radius {}, expected a radius in Å, group or error", s)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for UnknownRadius {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnknownRadius::Fixed(radius) => write!(f, "{}", radius),
+            UnknownRadius::Group => write!(f, "group"),
+            UnknownRadius::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl Serialize for UnknownRadius {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+
+/// Rough van der Waals radius in Å from the position of an element in the
+/// periodic table: radii grow down a group, alkali and alkaline earth
+/// metals are large, transition metals small and lanthanides and actinides
+/// in between. Within a few tenths of an Å of the Bondi and Mantina radii
+/// for most elements.
+pub fn group_radius(element: &Element) -> f64 {
+    let z = element.atomic_number();
+    // Atomic numbers of the noble gases closing each period
+    let period = [2, 10, 18, 36, 54, 86].iter().take_while(|&&last| z > last).count() + 1;
+    let offset = z - [0, 2, 10, 18, 36, 54, 86][period - 1] - 1;
+    let p = period as f64;
+    match (period, offset) {
+        (1, _) => 1.3,
+        (_, 0) => 1.82 + 0.4 * (p - 2.0),
+        (_, 1) => 1.53 + 0.3 * (p - 2.0),
+        (4 | 5, 2..=11) | (6 | 7, 17..=25) => 1.6 + 0.1 * (p - 4.0),
+        (6 | 7, 2..=16) => 2.4,
+        _ => 1.5 + 0.15 * p,
+    }
+}
+
+
+/// Unknown elements encountered in a structure, reported in its metadata.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RadiusInfo {
+    pub unknown_radius: UnknownRadius,
+    /// Atoms of each element without a tabulated radius, by symbol
+    pub unknown_elements: BTreeMap<String, usize>,
+}
+
+
+/// Van der Waals radii of the atoms of a structure under an unknown element
+/// policy, remembering the atoms that needed it.
+#[derive(Debug, Clone, Default)]
+pub struct Radii {
+    pub policy: UnknownRadius,
+    /// Serial numbers of the atoms of every unknown element
+    unknown: BTreeMap<&'static str, BTreeSet<usize>>,
+}
+
+impl Radii {
+    pub fn new(policy: UnknownRadius) -> Radii {
+        Radii { policy, unknown: BTreeMap::new() }
+    }
+
+    /// Radius of the atom `serial` of `element`.
+    pub fn radius(&mut self, element: &Element, serial: usize) -> Result<f64> {
+        if let Some(radius) = van_der_waals_radius(element) {
+            return Ok(radius);
+        }
+        let radius = match self.policy {
+            UnknownRadius::Fixed(radius) => radius,
+            UnknownRadius::Group => group_radius(element),
+            UnknownRadius::Error => bail!("No van der Waals radius for {} atom {}, see --unknown-radius", element.symbol(), serial),
+        };
+        self.unknown.entry(element.symbol()).or_default().insert(serial);
+        Ok(radius)
+    }
+
+    /// Number of atoms of every unknown element encountered so far.
+    pub fn unknown_elements(&self) -> BTreeMap<String, usize> {
+        self.unknown.iter().map(|(symbol, atoms)| (symbol.to_string(), atoms.len())).collect()
+    }
+
+    /// The report for the metadata, `None` if every element was known.
+    pub fn info(&self) -> Option<RadiusInfo> {
+        (!self.unknown.is_empty()).then(|| RadiusInfo { unknown_radius: self.policy, unknown_elements: self.unknown_elements() })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_elements_follow_the_policy() {
+        assert_eq!(UnknownRadius::parse("2.1"), Ok(UnknownRadius::Fixed(2.1)));
+        assert_eq!(UnknownRadius::parse("Group"), Ok(UnknownRadius::Group));
+        assert!(UnknownRadius::parse("-1").is_err());
+
+        let mut radii = Radii::default();
+        assert_eq!(radii.radius(&Element::C, 1).unwrap(), 1.70);
+        assert_eq!(radii.radius(&Element::Zn, 2).unwrap(), DEFAULT_UNKNOWN_RADIUS);
+        radii.radius(&Element::Zn, 2).unwrap();
+        radii.radius(&Element::Zn, 3).unwrap();
+        assert_eq!(radii.unknown_elements(), BTreeMap::from([(Element::Zn.symbol().to_string(), 2)]));
+
+        assert!(Radii::new(UnknownRadius::Error).radius(&Element::Zn, 2).is_err());
+        assert!(Radii::new(UnknownRadius::Error).info().is_none());
+
+        // Alkali metals grow down the group and dwarf the transition metals
+        let (li, rb, cs) = (group_radius(&Element::Li), group_radius(&Element::Rb), group_radius(&Element::Cs));
+        assert!(li < rb && rb < cs);
+        assert!(group_radius(&Element::Zn) < group_radius(&Element::Rb));
+        for (element, bondi) in [(Element::Li, 1.82), (Element::Rb, 3.03), (Element::Zn, 1.39), (Element::Xe, 2.16), (Element::Ga, 1.87)] {
+            assert!((group_radius(&element) - bondi).abs() < 0.35, "{:?}", element);
+        }
+    }
+}
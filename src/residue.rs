@@ -0,0 +1,145 @@
+use crate::entity::{classify_residue, EntityType};
+use pdbtbx::{Atom, Chain, Residue, PDB};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+
+
+/// Identifies a residue within a model. Antibody numbering schemes and many
+/// loop insertions reuse the same sequence number with different insertion
+/// codes (52, 52A, 52B...), so the insertion code is part of the key and all
+/// residue-level logic must use this rather than the sequence number alone.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ResidueKey {
+    pub chain: String,
+    pub resseq: isize,
+    pub icode: Option<String>,
+}
+
+impl ResidueKey {
+    pub fn new(chain: &Chain, residue: &Residue) -> ResidueKey {
+        let (resseq, icode) = residue.id();
+        ResidueKey {
+            chain: chain.id().to_string(),
+            resseq,
+            icode: icode.map(|c| c.to_string()),
+        }
+    }
+}
+
+/// Formats as `chain:resseq[icode]`, e.g. `H:52A`.
+impl fmt::Display for ResidueKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}{}", self.chain, self.resseq, self.icode.as_deref().unwrap_or(""))
+    }
+}
+
+impl Serialize for ResidueKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+
+/// Where an atom sits in its structure, so graph nodes can be mapped back
+/// to it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AtomIdentity {
+    pub chain: String,
+    /// Three-letter residue name
+    pub residue_name: String,
+    pub residue_number: isize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insertion_code: Option<String>,
+    pub atom_name: String,
+}
+
+impl AtomIdentity {
+    pub fn new(chain: &Chain, residue: &Residue, atom: &Atom) -> AtomIdentity {
+        let (residue_number, insertion_code) = residue.id();
+        AtomIdentity {
+            chain: chain.id().to_string(),
+            residue_name: residue.name().unwrap_or("").to_string(),
+            residue_number,
+            insertion_code: insertion_code.map(|c| c.to_string()),
+            atom_name: atom.name().to_string(),
+        }
+    }
+}
+
+
+/// Amino acid residues of the first model in chain order, with their keys.
+pub fn protein_residues(pdb: &PDB) -> Vec<(ResidueKey, &Residue)> {
+    let Some(model) = pdb.model(0) else {
+        return Vec::new();
+    };
+    model.chains()
+        .flat_map(|c| c.residues().map(move |r| (ResidueKey::new(c, r), r)))
+        .filter(|(_, r)| classify_residue(r) == EntityType::Protein)
+        .collect()
+}
+
+
+/// One-letter code of a standard amino acid, `X` for anything else.
+pub fn one_letter_code(name: &str) -> char {
+    match name {
+        "ALA" => 'A', "ARG" => 'R', "ASN" => 'N', "ASP" => 'D', "CYS" => 'C',
+        "GLN" => 'Q', "GLU" => 'E', "GLY" => 'G', "HIS" => 'H', "ILE" => 'I',
+        "LEU" => 'L', "LYS" => 'K', "MET" => 'M', "PHE" => 'F', "PRO" => 'P',
+        "SER" => 'S', "THR" => 'T', "TRP" => 'W', "TYR" => 'Y', "VAL" => 'V',
+        "MSE" => 'M', "SEC" => 'U', "PYL" => 'O',
+        _ => 'X',
+    }
+}
+
+
+/// Residue name of a one-letter amino acid code, `UNK` if unknown.
+pub fn three_letter_code(code: char) -> &'static str {
+    match code.to_ascii_uppercase() {
+        'A' => "ALA", 'R' => "ARG", 'N' => "ASN", 'D' => "ASP", 'C' => "CYS",
+        'Q' => "GLN", 'E' => "GLU", 'G' => "GLY", 'H' => "HIS", 'I' => "ILE",
+        'L' => "LEU", 'K' => "LYS", 'M' => "MET", 'F' => "PHE", 'P' => "PRO",
+        'S' => "SER", 'T' => "THR", 'W' => "TRP", 'Y' => "TYR", 'V' => "VAL",
+        'U' => "SEC", 'O' => "PYL",
+        _ => "UNK",
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdbtbx::StrictnessLevel;
+    use std::collections::HashSet;
+
+    fn antibody() -> PDB {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        pdbtbx::open(path, StrictnessLevel::Medium).unwrap().0
+    }
+
+    fn keys(pdb: &PDB) -> Vec<ResidueKey> {
+        pdb.chains().flat_map(|c| c.residues().map(move |r| ResidueKey::new(c, r))).collect()
+    }
+
+    #[test]
+    fn insertion_codes_give_distinct_keys() {
+        let pdb = antibody();
+        let keys = keys(&pdb);
+        assert_eq!(keys.len(), 15);
+        assert_eq!(keys.iter().collect::<HashSet<_>>().len(), keys.len());
+        let h52: Vec<String> = keys.iter()
+            .filter(|k| k.chain == "H" && k.resseq == 52)
+            .map(|k| k.to_string())
+            .collect();
+        assert_eq!(h52, vec!["H:52", "H:52A", "H:52B", "H:52C"]);
+    }
+
+    #[test]
+    fn keys_order_by_chain_then_sequence_then_insertion_code() {
+        let pdb = antibody();
+        let mut keys = keys(&pdb);
+        keys.reverse();
+        keys.sort();
+        let light: Vec<String> = keys.iter().filter(|k| k.chain == "L").map(|k| k.to_string()).collect();
+        assert_eq!(light, vec!["L:26", "L:27", "L:27A", "L:27B", "L:27C", "L:28", "L:29"]);
+    }
+}
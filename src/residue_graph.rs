@@ -0,0 +1,161 @@
+use crate::residue::ResidueKey;
+use petgraph::graph::{Graph, NodeIndex};
+use pdbtbx::{Atom, PDB};
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+
+/// Default CA–CA cutoff of residue graphs in Å.
+pub const DEFAULT_CA_CUTOFF: f64 = 8.0;
+
+/// Default cutoff of residue graphs on the closest atom pair in Å.
+pub const DEFAULT_MIN_DISTANCE_CUTOFF: f64 = 5.0;
+
+
+/// Distance residues of a residue graph are connected by.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ResidueEdges {
+    /// Distance between the CA atoms, or the centroids of residues without one
+    Ca,
+    /// Distance between the closest atoms of the two residues
+    MinDistance,
+}
+
+impl ResidueEdges {
+    pub fn default_cutoff(&self) -> f64 {
+        match self {
+            ResidueEdges::Ca => DEFAULT_CA_CUTOFF,
+            ResidueEdges::MinDistance => DEFAULT_MIN_DISTANCE_CUTOFF,
+        }
+    }
+}
+
+
+/// How a residue graph is built, stored in the graph metadata.
+#[derive(Serialize, Debug, Clone)]
+pub struct ResidueGraphOptions {
+    pub edges: ResidueEdges,
+    /// Residues at most this far apart (in Å) are connected
+    pub cutoff: f64,
+}
+
+
+/// A residue node of a structure graph.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResidueGraphNode {
+    pub id: usize,
+    pub chain: String,
+    pub resseq: isize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icode: Option<String>,
+    pub residue_name: String,
+    /// Mean position of the residue's atoms
+    pub centroid: [f64; 3],
+    pub atoms: usize,
+}
+
+
+fn centroid(atoms: &[&Atom]) -> [f64; 3] {
+    let n = atoms.len() as f64;
+    let (x, y, z) = atoms.iter().fold((0.0, 0.0, 0.0), |(x, y, z), a| (x + a.x(), y + a.y(), z + a.z()));
+    [x / n, y / n, z / n]
+}
+
+
+fn distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+
+/// Builds a graph with one node per residue of the first model, numbered in
+/// chain order. Edges are weighted with the distance (in Å) they were
+/// selected by and added in both directions, as in atom graphs.
+pub fn build_residue_graph(pdb: &PDB, options: &ResidueGraphOptions) -> Graph<ResidueGraphNode, f64> {
+    let mut graph = Graph::new();
+    let mut points: Vec<GeomWithData<[f64; 3], NodeIndex>> = Vec::new();
+    if let Some(model) = pdb.model(0) {
+        for chain in model.chains() {
+            for residue in chain.residues() {
+                let atoms: Vec<&Atom> = residue.atoms().collect();
+                if atoms.is_empty() {
+                    continue;
+                }
+                let key = ResidueKey::new(chain, residue);
+                let center = centroid(&atoms);
+                let node = graph.add_node(ResidueGraphNode {
+                    id: graph.node_count(),
+                    chain: key.chain,
+                    resseq: key.resseq,
+                    icode: key.icode,
+                    residue_name: residue.name().unwrap_or("UNK").to_string(),
+                    centroid: center,
+                    atoms: atoms.len(),
+                });
+                match options.edges {
+                    ResidueEdges::Ca => {
+                        let ca = atoms.iter().find(|a| a.name() == "CA").map(|a| [a.x(), a.y(), a.z()]);
+                        points.push(GeomWithData::new(ca.unwrap_or(center), node));
+                    }
+                    ResidueEdges::MinDistance => {
+                        points.extend(atoms.iter().map(|a| GeomWithData::new([a.x(), a.y(), a.z()], node)));
+                    }
+                }
+            }
+        }
+    }
+
+    for ((a, b), d) in neighbour_pairs(points, options.cutoff) {
+        graph.add_edge(a, b, d);
+        graph.add_edge(b, a, d);
+    }
+    graph
+}
+
+
+/// Pairs `(a, b)` with `a < b` of the items labelling `points` that have
+/// points at most `cutoff` apart, with the shortest distance between their
+/// points. An item may have several points, e.g. every atom of a residue.
+pub(crate) fn neighbour_pairs<T: Copy + Ord>(points: Vec<GeomWithData<[f64; 3], T>>, cutoff: f64) -> BTreeMap<(T, T), f64> {
+    let tree = RTree::bulk_load(points);
+    let mut distances: BTreeMap<(T, T), f64> = BTreeMap::new();
+    for point in tree.iter() {
+        for neighbor in tree.locate_within_distance(*point.geom(), cutoff * cutoff) {
+            if neighbor.data <= point.data {
+                continue;
+            }
+            let d = distance(point.geom(), neighbor.geom());
+            let entry = distances.entry((point.data, neighbor.data)).or_insert(d);
+            *entry = entry.min(d);
+        }
+    }
+    distances
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdbtbx::StrictnessLevel;
+
+    #[test]
+    fn builds_residue_graphs() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        let pdb = pdbtbx::open(path, StrictnessLevel::Medium).unwrap().0;
+
+        let ca = build_residue_graph(&pdb, &ResidueGraphOptions { edges: ResidueEdges::Ca, cutoff: DEFAULT_CA_CUTOFF });
+        assert_eq!(ca.node_count(), 15);
+        assert_eq!(ca.node_weights().map(|n| n.atoms).sum::<usize>(), pdb.atom_count());
+        assert!(ca.node_weights().any(|n| n.chain == "H" && n.resseq == 52 && n.icode.as_deref() == Some("B")));
+        assert!(ca.edge_weights().all(|d| *d <= DEFAULT_CA_CUTOFF));
+        assert_eq!(ca.edge_count() % 2, 0);
+
+        let contacts = build_residue_graph(&pdb, &ResidueGraphOptions { edges: ResidueEdges::MinDistance, cutoff: 2.0 });
+        assert_eq!(contacts.node_count(), 15);
+        // Only covalently linked neighbours are this close
+        assert!(contacts.edge_count() < ca.edge_count());
+        assert!(contacts.edge_weights().all(|d| *d <= 2.0));
+    }
+}
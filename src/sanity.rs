@@ -0,0 +1,145 @@
+use pdbtbx::PDB;
+use serde::Serialize;
+use std::fmt;
+
+
+/// What to do with structures whose coordinates look corrupted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum CoordinateCheck {
+    /// Don't check coordinates
+    Off,
+    /// Convert anyway and record the issues in the metadata
+    Flag,
+    /// Fail the input
+    Reject,
+}
+
+
+/// Largest plausible extent of a structure in Å.
+const MAX_EXTENT: f64 = 2000.0;
+
+/// Plausible range of the median nearest neighbour distance in Å. Bonded
+/// atoms are 1-1.6 Å apart, CA-only models about 3.8 Å. Coordinates in
+/// nanometers end up well below the range.
+const NEAREST_NEIGHBOUR_RANGE: (f64, f64) = (0.5, 5.0);
+
+/// Atoms sampled to estimate the median nearest neighbour distance.
+const NEAREST_NEIGHBOUR_SAMPLE: usize = 1000;
+
+
+/// Sign that coordinates are corrupted or not in Ångströms, stored in the
+/// graph metadata.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CoordinateIssue {
+    /// Coordinates that are NaN or infinite
+    NonFinite { atoms: usize },
+    /// Several atoms placed exactly at the origin, typical of unset coordinates
+    AtOrigin { atoms: usize },
+    /// Largest side of the bounding box
+    Extent { size: f64 },
+    /// Median distance of an atom to its nearest neighbour
+    Spacing { median: f64 },
+}
+
+impl fmt::Display for CoordinateIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoordinateIssue::NonFinite { atoms } => write!(f, "{} atoms with NaN or infinite coordinates", atoms),
+            CoordinateIssue::AtOrigin { atoms } => write!(f, "{} atoms at the origin", atoms),
+            CoordinateIssue::Extent { size } => write!(f, "bounding box of {:.0} Å", size),
+            CoordinateIssue::Spacing { median } => write!(f, "median nearest neighbour distance of {:.2} Å, not in Ångströms?", median),
+        }
+    }
+}
+
+
+/// Checks that the coordinates of `pdb` look like a molecule in Ångströms.
+pub fn check_coordinates(pdb: &PDB) -> Vec<CoordinateIssue> {
+    check_positions(pdb.atoms().map(|a| {
+        let (x, y, z) = a.pos();
+        [x, y, z]
+    }))
+}
+
+
+/// Checks atom positions, which pdbtbx only holds finite.
+fn check_positions(positions: impl Iterator<Item = [f64; 3]>) -> Vec<CoordinateIssue> {
+    let mut issues = Vec::new();
+    let (finite, non_finite): (Vec<[f64; 3]>, Vec<[f64; 3]>) = positions.partition(|p| p.iter().all(|c| c.is_finite()));
+    if !non_finite.is_empty() {
+        issues.push(CoordinateIssue::NonFinite { atoms: non_finite.len() });
+    }
+    let at_origin = finite.iter().filter(|p| **p == [0.0; 3]).count();
+    if at_origin > 1 {
+        issues.push(CoordinateIssue::AtOrigin { atoms: at_origin });
+    }
+    if finite.len() < 2 {
+        return issues;
+    }
+
+    let size = (0..3).map(|i| {
+        let (min, max) = finite.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| (lo.min(p[i]), hi.max(p[i])));
+        max - min
+    }).fold(0.0, f64::max);
+    if size > MAX_EXTENT {
+        issues.push(CoordinateIssue::Extent { size });
+    }
+
+    let tree = rstar::RTree::bulk_load(finite.clone());
+    let step = finite.len().div_ceil(NEAREST_NEIGHBOUR_SAMPLE);
+    let mut nearest: Vec<f64> = finite.iter().step_by(step)
+        .filter_map(|p| {
+            // Atoms sharing a position (e.g. altlocs) don't tell anything about units
+            tree.nearest_neighbor_iter(p)
+                .map(|q| ((p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2) + (p[2] - q[2]).powi(2)).sqrt())
+                .find(|d| *d > 0.0)
+        })
+        .collect();
+    if !nearest.is_empty() {
+        nearest.sort_by(f64::total_cmp);
+        let median = nearest[nearest.len() / 2];
+        if median < NEAREST_NEIGHBOUR_RANGE.0 || median > NEAREST_NEIGHBOUR_RANGE.1 {
+            issues.push(CoordinateIssue::Spacing { median });
+        }
+    }
+    issues
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn structure() -> PDB {
+        crate::golden::Fixture::get("benzoate_stacking").unwrap().structure().unwrap()
+    }
+
+    fn moved(f: impl Fn(usize, (f64, f64, f64)) -> (f64, f64, f64)) -> PDB {
+        let mut pdb = structure();
+        for (i, atom) in pdb.atoms_mut().enumerate() {
+            atom.set_pos(f(i, atom.pos())).unwrap();
+        }
+        pdb
+    }
+
+    #[test]
+    fn flags_implausible_coordinates() {
+        assert!(check_coordinates(&structure()).is_empty());
+
+        let unset = moved(|i, p| if i < 3 { (0.0, 0.0, 0.0) } else { p });
+        assert!(matches!(check_coordinates(&unset)[..], [CoordinateIssue::AtOrigin { atoms: 3 }]));
+        let one_at_origin = moved(|i, p| if i == 0 { (0.0, 0.0, 0.0) } else { p });
+        assert!(check_coordinates(&one_at_origin).is_empty());
+
+        let far = moved(|i, p| if i == 0 { (p.0 + 3000.0, p.1, p.2) } else { p });
+        assert!(matches!(check_coordinates(&far)[..], [CoordinateIssue::Extent { .. }]));
+
+        let nanometers = moved(|_, p| (p.0 / 10.0, p.1 / 10.0, p.2 / 10.0));
+        assert!(matches!(check_coordinates(&nanometers)[..], [CoordinateIssue::Spacing { median }] if median < 0.5));
+
+        let positions = [[f64::NAN, 0.0, 0.0], [1.0, 0.0, 0.0], [2.5, 0.0, 0.0], [4.0, 0.0, 0.0]];
+        assert!(matches!(check_positions(positions.into_iter())[..], [CoordinateIssue::NonFinite { atoms: 1 }]));
+        assert!(check_positions(positions[1..].iter().copied()).is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::entity::{classify_residue, EntityType};
+use crate::residue::one_letter_code;
+use crate::radius::Radii;
+use anyhow::Result;
+use pdbtbx::PDB;
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+
+/// Radius of the water probe in Å.
+pub const PROBE_RADIUS: f64 = 1.4;
+
+/// Test points on the sphere of every atom.
+const SPHERE_POINTS: usize = 100;
+
+
+/// Solvent accessible surface area of an atom and of its residue, in Å².
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct AtomSasa {
+    pub sasa: f64,
+    pub residue_sasa: f64,
+    /// Residue SASA divided by the largest SASA of its amino acid, for
+    /// standard amino acids
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_sasa: Option<f64>,
+}
+
+
+/// Largest SASA (in Å²) of an amino acid in a Gly-X-Gly tripeptide, the
+/// theoretical values of Tien et al. (2013).
+fn max_sasa(code: char) -> Option<f64> {
+    let max = match code {
+        'A' => 129.0, 'R' => 274.0, 'N' => 195.0, 'D' => 193.0, 'C' => 167.0,
+        'E' => 223.0, 'Q' => 225.0, 'G' => 104.0, 'H' => 224.0, 'I' => 197.0,
+        'L' => 201.0, 'K' => 236.0, 'M' => 224.0, 'F' => 240.0, 'P' => 159.0,
+        'S' => 155.0, 'T' => 172.0, 'W' => 285.0, 'Y' => 263.0, 'V' => 174.0,
+        _ => return None,
+    };
+    Some(max)
+}
+
+
+/// Points evenly spread over the unit sphere, on a golden section spiral.
+fn unit_sphere(n: usize) -> Vec<[f64; 3]> {
+    let increment = std::f64::consts::PI * (3.0 - 5f64.sqrt());
+    (0..n).map(|i| {
+        let y = 1.0 - 2.0 * (i as f64 + 0.5) / n as f64;
+        let r = (1.0 - y * y).sqrt();
+        let phi = i as f64 * increment;
+        [r * phi.cos(), y, r * phi.sin()]
+    }).collect()
+}
+
+
+/// Shrake–Rupley solvent accessible surface area of the heavy atoms of the
+/// first model of `pdb`, with their van der Waals radii from `radii` and a
+/// 1.4 Å probe, by serial number. Every atom is tested against the atoms whose expanded
+/// spheres can overlap its own, found with an r-tree. Hydrogens and waters
+/// are left out of the surface and get no SASA.
+pub fn atom_sasa(pdb: &PDB, radii: &mut Radii) -> Result<HashMap<usize, AtomSasa>> {
+    let Some(model) = pdb.model(0) else {
+        return Ok(HashMap::new());
+    };
+    // (serial, residue index, position, expanded radius)
+    let mut atoms: Vec<(usize, usize, [f64; 3], f64)> = Vec::new();
+    let mut residues: Vec<Option<f64>> = Vec::new();
+    for residue in model.chains().flat_map(|c| c.residues()) {
+        if classify_residue(residue) == EntityType::Water {
+            continue;
+        }
+        let max = residue.name().and_then(|name| max_sasa(one_letter_code(name)));
+        for atom in residue.atoms() {
+            let Some(element) = atom.element().filter(|e| e.symbol() != "H" && e.symbol() != "D") else { continue };
+            let radius = radii.radius(element, atom.serial_number())?;
+            atoms.push((atom.serial_number(), residues.len(), [atom.x(), atom.y(), atom.z()], radius + PROBE_RADIUS));
+        }
+        residues.push(max);
+    }
+
+    let largest = atoms.iter().map(|a| a.3).fold(0.0, f64::max);
+    let tree = RTree::bulk_load(atoms.iter().enumerate().map(|(i, a)| GeomWithData::new(a.2, i)).collect());
+    let sphere = unit_sphere(SPHERE_POINTS);
+    let areas: Vec<f64> = atoms.iter().enumerate().map(|(i, &(_, _, center, radius))| {
+        let neighbors: Vec<([f64; 3], f64)> = tree.locate_within_distance(center, (radius + largest).powi(2))
+            .filter(|n| n.data != i)
+            .map(|n| (atoms[n.data].2, atoms[n.data].3))
+            .filter(|(pos, r)| distance_squared(center, *pos) < (radius + r).powi(2))
+            .collect();
+        let accessible = sphere.iter()
+            .map(|p| [center[0] + radius * p[0], center[1] + radius * p[1], center[2] + radius * p[2]])
+            .filter(|point| neighbors.iter().all(|(pos, r)| distance_squared(*point, *pos) >= r * r))
+            .count();
+        4.0 * std::f64::consts::PI * radius * radius * accessible as f64 / SPHERE_POINTS as f64
+    }).collect();
+
+    let mut residue_sasa = vec![0.0; residues.len()];
+    for (atom, area) in atoms.iter().zip(&areas) {
+        residue_sasa[atom.1] += area;
+    }
+    Ok(atoms.iter().zip(areas).map(|(&(serial, residue, _, _), sasa)| {
+        let relative_sasa = residues[residue].map(|max| residue_sasa[residue] / max);
+        (serial, AtomSasa { sasa, residue_sasa: residue_sasa[residue], relative_sasa })
+    }).collect())
+}
+
+
+fn distance_squared(a: [f64; 3], b: [f64; 3]) -> f64 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdbtbx::StrictnessLevel;
+
+    #[test]
+    fn buried_atoms_have_less_surface() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/benzoate_stacking.pdb");
+        let (mut pdb, _) = pdbtbx::open(path, StrictnessLevel::Medium).unwrap();
+        let sasa = atom_sasa(&pdb, &mut Radii::default()).unwrap();
+        assert_eq!(sasa.len(), 17);
+        // The lysine amine is bonded on one side only, the stacked ring atoms on
+        // both sides and from above
+        let exposed = 4.0 * std::f64::consts::PI * (1.55 + PROBE_RADIUS).powi(2);
+        assert!(sasa[&8].sasa > 0.25 * exposed && sasa[&8].sasa < exposed);
+        assert!(sasa[&4].sasa < sasa[&8].sasa);
+        assert_eq!(sasa[&8].relative_sasa, Some(sasa[&8].residue_sasa / 236.0));
+        assert_eq!(sasa[&9].relative_sasa, None);
+
+        pdb.remove_atoms_by(|a| a.serial_number() != 8);
+        let alone = atom_sasa(&pdb, &mut Radii::default()).unwrap()[&8].sasa;
+        assert!((alone - exposed).abs() < 1e-9, "{} != {}", alone, exposed);
+    }
+}
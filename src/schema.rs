@@ -0,0 +1,309 @@
+use crate::altloc::AltlocResolution;
+use crate::bond_order::BOND_ORDERS;
+use crate::dssp::DSSP_CODES;
+use crate::isotope::Isotope;
+use crate::quantize::DistanceQuantization;
+use crate::residue_graph::ResidueGraphNode;
+use crate::{AtomNode, EdgeData};
+use petgraph::graph::Graph;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+
+/// Element type of a feature, named as in numpy.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DType {
+    Bool,
+    Uint8,
+    Uint16,
+    Int64,
+    Float32,
+    Float64,
+    Str,
+}
+
+
+/// Layout of one node or edge feature, for loaders building tensors without
+/// hardcoding the output format.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FeatureSchema {
+    /// Key of the feature on nodes or edges, with `.` separating the keys of
+    /// nested objects
+    pub name: String,
+    pub dtype: DType,
+    /// Number of values, 1 for scalars and `null` for lists of varying length
+    pub dim: Option<usize>,
+    /// Keys of the values of features stored as objects, in order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub components: Option<Vec<String>>,
+    /// Categories of a categorical feature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vocabulary: Option<Vec<String>>,
+    /// The feature is missing on some nodes or edges
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+    /// Integer values are the feature multiplied by this
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f64>,
+}
+
+impl FeatureSchema {
+    pub fn scalar(name: &str, dtype: DType) -> FeatureSchema {
+        FeatureSchema { name: name.to_string(), dtype, dim: Some(1), components: None, vocabulary: None, optional: false, scale: None }
+    }
+
+    pub fn vector(name: &str, dtype: DType, dim: usize) -> FeatureSchema {
+        FeatureSchema { dim: Some(dim), ..FeatureSchema::scalar(name, dtype) }
+    }
+
+    pub fn categorical<S: ToString>(name: &str, vocabulary: impl IntoIterator<Item = S>) -> FeatureSchema {
+        FeatureSchema { vocabulary: Some(vocabulary.into_iter().map(|v| v.to_string()).collect()), ..FeatureSchema::scalar(name, DType::Str) }
+    }
+
+    fn named(name: &str, dtype: DType, components: BTreeSet<String>) -> FeatureSchema {
+        FeatureSchema { dim: Some(components.len()), components: Some(components.into_iter().collect()), ..FeatureSchema::scalar(name, dtype) }
+    }
+
+    fn optional(self, count: usize, total: usize) -> FeatureSchema {
+        FeatureSchema { optional: count < total, ..self }
+    }
+}
+
+
+/// Features of the nodes and edges of a graph, stored in the graph metadata.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GraphSchema {
+    pub nodes: Vec<FeatureSchema>,
+    pub edges: Vec<FeatureSchema>,
+}
+
+
+impl GraphSchema {
+    /// Schema of the graph written with quantized edge distances, if set.
+    pub fn quantized(mut self, quantization: Option<&DistanceQuantization>) -> GraphSchema {
+        if let Some(quantization) = quantization {
+            for feature in self.edges.iter_mut().filter(|f| f.name == "distance") {
+                feature.dtype = DType::Uint16;
+                feature.scale = Some(quantization.scale);
+            }
+        }
+        self
+    }
+}
+
+
+/// Features of the given atom graph edges.
+pub fn atom_edge_schema<'a>(edges: impl IntoIterator<Item = &'a EdgeData>) -> Vec<FeatureSchema> {
+    let mut schema = vec![
+        FeatureSchema::scalar("distance", DType::Float64),
+        FeatureSchema::categorical("bond_type", ["covalent", "contact"]),
+    ];
+    let (mut total, mut orders, mut kinds, mut constant, mut labeled) = (0, 0, 0, 0, 0);
+    let mut constants = BTreeSet::new();
+    for edge in edges {
+        total += 1;
+        orders += edge.bond_order.is_some() as usize;
+        kinds += edge.edge_kind.is_some() as usize;
+        constant += edge.constants.is_some() as usize;
+        labeled += edge.cutoff.is_some() as usize;
+        constants.extend(edge.constants.iter().flat_map(|c| c.keys().cloned()));
+    }
+    if orders > 0 {
+        schema.push(FeatureSchema::categorical("bond_order", BOND_ORDERS).optional(orders, total));
+    }
+    if kinds > 0 {
+        schema.push(FeatureSchema::categorical("edge_kind", ["hbond", "disulfide"]).optional(kinds, total));
+    }
+    if constant > 0 {
+        schema.push(FeatureSchema::named("constants", DType::Float64, constants).optional(constant, total));
+    }
+    if labeled > 0 {
+        schema.push(FeatureSchema::scalar("cutoff", DType::Float64).optional(labeled, total));
+    }
+    schema
+}
+
+
+/// Features of the edges of temporal graphs, which are stored as a list.
+pub fn temporal_edge_schema() -> Vec<FeatureSchema> {
+    vec![
+        FeatureSchema::scalar("source", DType::Int64),
+        FeatureSchema::scalar("target", DType::Int64),
+        FeatureSchema { dim: None, ..FeatureSchema::scalar("frames", DType::Int64) },
+        FeatureSchema::scalar("mean_distance", DType::Float64),
+        FeatureSchema::categorical("bond_type", ["covalent", "contact"]),
+    ]
+}
+
+
+/// Features of the given atom nodes. Only features present on at least one
+/// node are listed; vector sizes and vocabularies are taken from the nodes.
+pub fn atom_node_schema<'a>(nodes: impl IntoIterator<Item = &'a AtomNode>) -> Vec<FeatureSchema> {
+    let mut total = 0;
+    let mut counts = [0; 25];
+    let mut embedding_dim = 0;
+    let mut profile_dim = 0;
+    let (mut scores, mut residue_features) = (BTreeSet::new(), BTreeSet::new());
+    let (mut atom_types, mut structures, mut accessions) = (BTreeSet::new(), BTreeSet::new(), BTreeSet::new());
+    let (mut chemical_types, mut constants) = (BTreeSet::new(), BTreeSet::new());
+    let (mut chains, mut residue_names, mut insertion_codes, mut atom_names) = (BTreeSet::new(), BTreeSet::new(), BTreeSet::new(), BTreeSet::new());
+    for node in nodes {
+        total += 1;
+        for (count, present) in counts.iter_mut().zip([
+            node.valence.is_some(),
+            node.electronegativity.is_some(),
+            node.charge.is_some(),
+            node.pos.is_some(),
+            node.bfactor.is_some(),
+            node.occupancy.is_some(),
+            node.identity.is_some(),
+            node.identity.as_ref().is_some_and(|i| i.insertion_code.is_some()),
+            node.sasa.is_some(),
+            node.sasa.is_some_and(|s| s.relative_sasa.is_some()),
+            node.altloc.is_some(),
+            node.isotope.is_some(),
+            node.ccd.is_some(),
+            node.halo,
+            node.structure_id.is_some(),
+            node.docking.is_some(),
+            node.scores.is_some(),
+            node.plddt.is_some(),
+            node.embedding.is_some(),
+            node.conservation.is_some(),
+            node.uniprot.is_some(),
+            node.residue_features.is_some(),
+            node.secondary_structure.is_some(),
+            node.chemical_type.is_some(),
+            node.constants.is_some(),
+        ]) {
+            *count += present as usize;
+        }
+        if let Some(docking) = &node.docking {
+            atom_types.insert(docking.atom_type.clone());
+        }
+        if let Some(identity) = &node.identity {
+            chains.insert(identity.chain.clone());
+            residue_names.insert(identity.residue_name.clone());
+            insertion_codes.extend(identity.insertion_code.clone());
+            atom_names.insert(identity.atom_name.clone());
+        }
+        if let Some(id) = &node.structure_id {
+            structures.insert(id.clone());
+        }
+        scores.extend(node.scores.iter().flat_map(|s| s.keys().cloned()));
+        embedding_dim = embedding_dim.max(node.embedding.as_ref().map_or(0, |e| e.len()));
+        profile_dim = profile_dim.max(node.conservation.as_ref().map_or(0, |c| c.profile.len()));
+        if let Some(uniprot) = &node.uniprot {
+            accessions.insert(uniprot.accession.clone());
+        }
+        residue_features.extend(node.residue_features.iter().flat_map(|f| f.keys().cloned()));
+        chemical_types.extend(node.chemical_type.clone());
+        constants.extend(node.constants.iter().flat_map(|c| c.keys().cloned()));
+    }
+
+    let [valence, electronegativity, charge, pos, bfactor, occupancy, identity, insertion_code, sasa, relative_sasa, altloc, isotope, ccd, halo, structure_id, docking, score, plddt, embedding, conservation, uniprot, residue_feature, secondary_structure, chemical_type, constant] = counts;
+    let mut schema = vec![FeatureSchema::scalar("id", DType::Int64), FeatureSchema::scalar("atom_number", DType::Uint8)];
+    let mut add = |count: usize, feature: FeatureSchema| {
+        if count > 0 {
+            schema.push(feature.optional(count, total));
+        }
+    };
+    add(valence, FeatureSchema::scalar("valence", DType::Uint8));
+    add(electronegativity, FeatureSchema::scalar("electronegativity", DType::Float64));
+    add(charge, FeatureSchema::scalar("charge", DType::Int64));
+    add(pos, FeatureSchema::vector("pos", DType::Float64, 3));
+    add(bfactor, FeatureSchema::scalar("bfactor", DType::Float64));
+    add(occupancy, FeatureSchema::scalar("occupancy", DType::Float64));
+    add(identity, FeatureSchema::categorical("chain", chains));
+    add(identity, FeatureSchema::categorical("residue_name", residue_names));
+    add(identity, FeatureSchema::scalar("residue_number", DType::Int64));
+    add(insertion_code, FeatureSchema::categorical("insertion_code", insertion_codes));
+    add(identity, FeatureSchema::categorical("atom_name", atom_names));
+    add(sasa, FeatureSchema::scalar("sasa", DType::Float64));
+    add(sasa, FeatureSchema::scalar("residue_sasa", DType::Float64));
+    add(relative_sasa, FeatureSchema::scalar("relative_sasa", DType::Float64));
+    add(altloc, FeatureSchema::categorical("altloc", AltlocResolution::NAMES));
+    add(isotope, FeatureSchema::categorical("isotope", Isotope::SYMBOLS));
+    add(ccd, FeatureSchema::scalar("ccd.aromatic", DType::Bool));
+    add(halo, FeatureSchema::scalar("halo", DType::Bool));
+    add(structure_id, FeatureSchema::categorical("structure_id", structures));
+    add(docking, FeatureSchema::scalar("partial_charge", DType::Float64));
+    add(docking, FeatureSchema::categorical("atom_type", atom_types));
+    add(score, FeatureSchema::named("scores", DType::Float64, scores));
+    add(plddt, FeatureSchema::scalar("plddt", DType::Float64));
+    add(embedding, FeatureSchema::vector("embedding", DType::Float32, embedding_dim));
+    add(conservation, FeatureSchema::scalar("conservation", DType::Float64));
+    add(conservation, FeatureSchema::vector("profile", DType::Float64, profile_dim));
+    add(uniprot, FeatureSchema::categorical("uniprot.accession", accessions));
+    add(uniprot, FeatureSchema::scalar("uniprot.position", DType::Int64));
+    add(residue_feature, FeatureSchema::named("residue_features", DType::Float64, residue_features));
+    add(secondary_structure, FeatureSchema::categorical("secondary_structure", ["helix", "sheet", "coil"]));
+    add(secondary_structure, FeatureSchema::categorical("dssp", DSSP_CODES));
+    add(chemical_type, FeatureSchema::categorical("chemical_type", chemical_types));
+    add(constant, FeatureSchema::named("constants", DType::Float64, constants));
+    schema
+}
+
+
+/// Schema of an atom graph.
+pub fn atom_graph_schema(graph: &Graph<AtomNode, EdgeData>) -> GraphSchema {
+    GraphSchema { nodes: atom_node_schema(graph.node_weights()), edges: atom_edge_schema(graph.edge_weights()) }
+}
+
+
+/// Schema of a residue graph with the given nodes.
+pub fn residue_graph_schema<'a>(nodes: impl IntoIterator<Item = &'a ResidueGraphNode>) -> GraphSchema {
+    let mut total = 0;
+    let mut icodes = BTreeSet::new();
+    let mut with_icode = 0;
+    let (mut chains, mut residue_names) = (BTreeSet::new(), BTreeSet::new());
+    for node in nodes {
+        total += 1;
+        chains.insert(node.chain.clone());
+        if let Some(icode) = &node.icode {
+            icodes.insert(icode.clone());
+            with_icode += 1;
+        }
+        residue_names.insert(node.residue_name.clone());
+    }
+    let mut schema = vec![
+        FeatureSchema::scalar("id", DType::Int64),
+        FeatureSchema::categorical("chain", chains),
+        FeatureSchema::scalar("resseq", DType::Int64),
+    ];
+    if with_icode > 0 {
+        schema.push(FeatureSchema::categorical("icode", icodes).optional(with_icode, total));
+    }
+    schema.extend([
+        FeatureSchema::categorical("residue_name", residue_names),
+        FeatureSchema::vector("centroid", DType::Float64, 3),
+        FeatureSchema::scalar("atoms", DType::Int64),
+    ]);
+    GraphSchema { nodes: schema, edges: vec![FeatureSchema::scalar("distance", DType::Float64)] }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_graph;
+    use pdbtbx::StrictnessLevel;
+
+    #[test]
+    fn describes_node_features() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        let pdb = pdbtbx::open(path, StrictnessLevel::Medium).unwrap().0;
+        let mut graph = build_graph(&pdb);
+        graph[petgraph::graph::NodeIndex::new(0)].embedding = Some(vec![0.0; 8]);
+
+        let schema = atom_graph_schema(&graph);
+        let names: Vec<&str> = schema.nodes.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["id", "atom_number", "valence", "electronegativity", "charge", "pos", "embedding"]);
+        let embedding = schema.nodes.last().unwrap();
+        assert_eq!((embedding.dtype, embedding.dim, embedding.optional), (DType::Float32, Some(8), true));
+        assert!(!schema.nodes[2].optional);
+        assert_eq!(schema.edges[1].vocabulary.as_ref().unwrap(), &vec!["covalent", "contact"]);
+        assert_eq!(schema.edges.len(), 2);
+    }
+}
@@ -0,0 +1,109 @@
+use pdbtbx::PDB;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+
+/// Per-residue score table appended to model outputs, e.g. Rosetta's
+/// `#BEGIN_POSE_ENERGIES_TABLE` footer.
+pub struct ScoreTable {
+    pub columns: Vec<String>,
+    /// Scores of the whole model
+    pub pose: Vec<Option<f64>>,
+    /// Scores by 1-based residue index in file order
+    pub residues: HashMap<usize, Vec<Option<f64>>>,
+}
+
+
+/// Summary of the scores attached to a graph, stored in the graph metadata.
+#[derive(Serialize, Debug, Clone)]
+pub struct ScoreInfo {
+    /// Columns attached to nodes as `scores`
+    pub columns: Vec<String>,
+    /// Scores of the whole model
+    pub pose: BTreeMap<String, f64>,
+    pub residues: usize,
+}
+
+
+/// Parses the pose energies table of a Rosetta PDB output. Rows are labelled
+/// `<name>[:<variants>]_<index>` with the 1-based index of the residue in
+/// the pose, e.g. `ALA:NtermProteinFull_1`. Values that are not numbers
+/// (`NA`) are missing.
+pub fn parse_score_table(text: &str) -> Option<ScoreTable> {
+    let mut lines = text.lines()
+        .skip_while(|l| !l.starts_with("#BEGIN_POSE_ENERGIES_TABLE"))
+        .skip(1)
+        .take_while(|l| !l.starts_with("#END_POSE_ENERGIES_TABLE"));
+    let columns: Vec<String> = lines.next()?.split_whitespace().skip(1).map(|c| c.to_string()).collect();
+    let mut table = ScoreTable { columns, pose: Vec::new(), residues: HashMap::new() };
+    for line in lines {
+        let mut fields = line.split_whitespace();
+        let Some(label) = fields.next() else { continue };
+        let values: Vec<Option<f64>> = fields.map(|v| v.parse().ok()).collect();
+        if label == "pose" {
+            table.pose = values;
+        } else if let Some(index) = label.rsplit_once('_').and_then(|(_, i)| i.parse().ok()) {
+            table.residues.insert(index, values);
+        }
+    }
+    Some(table)
+}
+
+
+fn named(columns: &[String], values: &[Option<f64>], selected: Option<&[String]>) -> BTreeMap<String, f64> {
+    columns.iter().zip(values)
+        .filter(|(c, _)| selected.is_none_or(|s| s.contains(c)))
+        .filter_map(|(c, v)| v.map(|v| (c.clone(), v)))
+        .collect()
+}
+
+
+/// Scores of every atom of the first model by serial number, taken from the
+/// row of its residue. Only the `selected` columns are kept, if given.
+pub fn atom_scores(pdb: &PDB, table: &ScoreTable, selected: Option<&[String]>) -> (HashMap<usize, BTreeMap<String, f64>>, ScoreInfo) {
+    let mut scores = HashMap::new();
+    let mut residues = 0;
+    let Some(model) = pdb.model(0) else {
+        return (scores, ScoreInfo { columns: Vec::new(), pose: BTreeMap::new(), residues });
+    };
+    for (i, residue) in model.residues().enumerate() {
+        let Some(values) = table.residues.get(&(i + 1)) else { continue };
+        let residue_scores = named(&table.columns, values, selected);
+        residues += 1;
+        for atom in residue.atoms() {
+            scores.insert(atom.serial_number(), residue_scores.clone());
+        }
+    }
+    let info = ScoreInfo {
+        columns: table.columns.iter().filter(|c| selected.is_none_or(|s| s.contains(c))).cloned().collect(),
+        pose: named(&table.columns, &table.pose, None),
+        residues,
+    };
+    (scores, info)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pose_energies_table() {
+        let text = "\
+ATOM      1  N   ALA A   1      11.104   6.134  -6.504  1.00  0.00           N
+#BEGIN_POSE_ENERGIES_TABLE model.pdb
+label fa_atr fa_rep total
+weights 0.8 0.55 NA
+pose -10.5 2.0 -8.5
+ALA:NtermProteinFull_1 -1.5 0.2 NA
+GLY_2 -0.5 0.1 -0.4
+#END_POSE_ENERGIES_TABLE model.pdb
+";
+        let table = parse_score_table(text).unwrap();
+        assert_eq!(table.columns, vec!["fa_atr", "fa_rep", "total"]);
+        assert_eq!(table.pose, vec![Some(-10.5), Some(2.0), Some(-8.5)]);
+        assert_eq!(table.residues[&1], vec![Some(-1.5), Some(0.2), None]);
+        assert_eq!(table.residues.len(), 2);
+        assert!(parse_score_table("ATOM").is_none());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::entity::{classify_residue, EntityType};
+use anyhow::{bail, Result};
+use pdbtbx::{Element, PDB};
+use std::fmt;
+
+
+/// Chains to build graphs of, by chain id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainSelection {
+    /// Only these chains
+    Only(Vec<String>),
+    /// Every chain but these
+    Except(Vec<String>),
+}
+
+impl ChainSelection {
+    pub fn contains(&self, chain: &str) -> bool {
+        match self {
+            ChainSelection::Only(ids) => ids.iter().any(|id| id == chain),
+            ChainSelection::Except(ids) => !ids.iter().any(|id| id == chain),
+        }
+    }
+
+    /// Removes the chains of every model of `pdb` that aren't selected.
+    /// Returns the ids of `Only` chains that `pdb` doesn't have.
+    pub fn apply(&self, pdb: &mut PDB) -> Vec<String> {
+        let missing = match self {
+            ChainSelection::Only(ids) => ids.iter().filter(|id| pdb.chains().all(|c| c.id() != id.as_str())).cloned().collect(),
+            ChainSelection::Except(_) => Vec::new(),
+        };
+        pdb.remove_chains_by(|c| !self.contains(c.id()));
+        missing
+    }
+}
+
+
+/// Kinds of atoms left out of graphs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AtomExclusion {
+    /// Water molecules, by residue name
+    pub water: bool,
+    /// Atoms of HETATM records, which includes waters, ions and ligands
+    pub hetatm: bool,
+    /// Hydrogens, including deuterium and tritium
+    pub hydrogens: bool,
+}
+
+impl AtomExclusion {
+    pub fn is_empty(&self) -> bool {
+        !(self.water || self.hetatm || self.hydrogens)
+    }
+
+    /// Removes the excluded atoms from `pdb`. Returns how many were removed.
+    pub fn apply(&self, pdb: &mut PDB) -> usize {
+        let before = pdb.atom_count();
+        if self.water {
+            pdb.remove_residues_by(|r| classify_residue(r) == EntityType::Water);
+        }
+        pdb.remove_atoms_by(|a| (self.hetatm && a.hetero()) || (self.hydrogens && a.element() == Some(&Element::H)));
+        pdb.remove_empty();
+        before - pdb.atom_count()
+    }
+}
+
+
+/// Models of multi-model structures, e.g. NMR ensembles, to build graphs of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelSelection {
+    /// One graph per model
+    All,
+    /// Only the first model
+    First,
+    /// Only the model with this number
+    Number(usize),
+}
+
+impl ModelSelection {
+    pub fn parse(s: &str) -> std::result::Result<ModelSelection, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "all" => Ok(ModelSelection::All),
+            "first" => Ok(ModelSelection::First),
+            _ => s.parse().map(ModelSelection::Number).map_err(|_| format!("invalid model selection {}, expected all, first or a model number", s)),
+        }
+    }
+
+    /// Splits `pdb` into the selected models, each with its number: the
+    /// serial number of its MODEL record, or its position from 1 in files
+    /// without MODEL records.
+    pub fn select(&self, pdb: PDB) -> Result<Vec<(usize, PDB)>> {
+        let numbers: Vec<usize> = pdb.models().enumerate().map(|(i, m)| if m.serial_number() == 0 { i + 1 } else { m.serial_number() }).collect();
+        let indices: Vec<usize> = match self {
+            ModelSelection::All => (0..numbers.len()).collect(),
+            ModelSelection::First => (0..numbers.len().min(1)).collect(),
+            ModelSelection::Number(n) => match numbers.iter().position(|m| m == n) {
+                Some(index) => vec![index],
+                None => bail!("No model {} among the {} models", n, numbers.len()),
+            },
+        };
+        if let [index] = indices[..] {
+            let mut model = pdb;
+            model.remove_models_except(&[index]);
+            return Ok(vec![(numbers[index], model)]);
+        }
+        Ok(indices.into_iter().map(|index| {
+            let mut model = pdb.clone();
+            model.remove_models_except(&[index]);
+            (numbers[index], model)
+        }).collect())
+    }
+}
+
+impl fmt::Display for ModelSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelSelection::All => write!(f, "all"),
+            ModelSelection::First => write!(f, "first"),
+            ModelSelection::Number(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdbtbx::StrictnessLevel;
+
+    #[test]
+    fn keeps_selected_chains() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        let (pdb, _) = pdbtbx::open(path, StrictnessLevel::Medium).unwrap();
+        let chains = |pdb: &PDB| pdb.chains().map(|c| c.id().to_string()).collect::<Vec<_>>();
+        assert_eq!(chains(&pdb), ["H", "L"]);
+
+        let mut only = pdb.clone();
+        assert_eq!(ChainSelection::Only(vec!["L".into(), "X".into()]).apply(&mut only), ["X"]);
+        assert_eq!(chains(&only), ["L"]);
+        let mut except = pdb.clone();
+        assert!(ChainSelection::Except(vec!["L".into()]).apply(&mut except).is_empty());
+        assert_eq!(chains(&except), ["H"]);
+    }
+
+    #[test]
+    fn excludes_atoms() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/benzoate_stacking.pdb");
+        let (pdb, _) = pdbtbx::open(path, StrictnessLevel::Medium).unwrap();
+        let hetero = pdb.atoms().filter(|a| a.hetero()).count();
+        assert!(hetero > 0 && hetero < pdb.atom_count());
+
+        let mut kept = pdb.clone();
+        assert_eq!(AtomExclusion::default().apply(&mut kept), 0);
+        let mut polymer = pdb.clone();
+        assert_eq!(AtomExclusion { hetatm: true, ..Default::default() }.apply(&mut polymer), hetero);
+        assert!(polymer.atoms().all(|a| !a.hetero()));
+    }
+
+    #[test]
+    fn splits_models() {
+        let text = "\
+MODEL        1
+ATOM      1  CA  GLY A   1       0.000   0.000   0.000  1.00 10.00           C
+ENDMDL
+MODEL        2
+ATOM      1  CA  GLY A   1       0.500   0.000   0.000  1.00 10.00           C
+ENDMDL
+END
+";
+        let reader = std::io::BufReader::new(std::io::Cursor::new(text.as_bytes()));
+        let (pdb, _) = pdbtbx::open_pdb_raw(reader, pdbtbx::Context::none(), StrictnessLevel::Medium).unwrap();
+        let models = ModelSelection::All.select(pdb.clone()).unwrap();
+        assert_eq!(models.iter().map(|(n, m)| (*n, m.model_count(), m.atom(0).unwrap().x())).collect::<Vec<_>>(), [(1, 1, 0.0), (2, 1, 0.5)]);
+        assert_eq!(ModelSelection::Number(2).select(pdb.clone()).unwrap()[0].1.atom(0).unwrap().x(), 0.5);
+        assert!(ModelSelection::Number(3).select(pdb).is_err());
+        assert_eq!(ModelSelection::parse("First"), Ok(ModelSelection::First));
+
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/benzoate_stacking.pdb");
+        let (single, _) = pdbtbx::open(path, StrictnessLevel::Medium).unwrap();
+        assert_eq!(ModelSelection::All.select(single).unwrap()[0].0, 1);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::residue::three_letter_code;
+use anyhow::{bail, Result};
+use petgraph::graph::Graph;
+use serde::{Deserialize, Serialize};
+
+
+/// A record of a FASTA file.
+pub struct FastaRecord {
+    /// First word of the header
+    pub id: String,
+    /// Rest of the header
+    pub description: String,
+    pub sequence: String,
+}
+
+
+/// Parses FASTA text. Sequence lines are joined and whitespace, gaps and
+/// stop codons (`*`) are dropped.
+pub fn parse_fasta(text: &str) -> Result<Vec<FastaRecord>> {
+    let mut records: Vec<FastaRecord> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('>') {
+            let (id, description) = header.split_once(char::is_whitespace).unwrap_or((header, ""));
+            records.push(FastaRecord { id: id.to_string(), description: description.trim().to_string(), sequence: String::new() });
+        } else if !line.is_empty() && !line.starts_with(';') {
+            match records.last_mut() {
+                Some(record) => record.sequence.extend(line.chars().filter(|c| c.is_ascii_alphabetic())),
+                None => bail!("sequence on line {} before the first FASTA header", i + 1),
+            }
+        }
+    }
+    Ok(records)
+}
+
+
+/// File name friendly form of a record id, e.g. `sp_P69905_HBA_HUMAN` for
+/// `sp|P69905|HBA_HUMAN`.
+pub fn file_stem(id: &str) -> String {
+    id.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' }).collect()
+}
+
+
+/// A residue node of a sequence graph.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResidueNode {
+    /// 1-based position in the sequence
+    pub id: usize,
+    /// One-letter code
+    pub residue: char,
+    pub residue_name: String,
+}
+
+
+/// Information about the source sequence stored next to its graph.
+#[derive(Serialize, Debug, Clone)]
+pub struct SequenceMetadata {
+    pub source: String,
+    pub structure_id: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    pub length: usize,
+    /// Residues at most this far apart in the sequence are connected
+    pub window: usize,
+}
+
+
+/// Layout of a sequence `_graph.json` output file.
+#[derive(Serialize)]
+pub struct SequenceGraphFile {
+    pub metadata: SequenceMetadata,
+    pub graph: Graph<ResidueNode, f64>,
+}
+
+
+/// Builds the graph of a sequence: a node per residue, connected to the
+/// residues up to `window` positions away in both directions. Edges are
+/// weighted with the sequence separation.
+pub fn sequence_graph(sequence: &str, window: usize) -> Graph<ResidueNode, f64> {
+    let mut graph = Graph::new();
+    let nodes: Vec<_> = sequence.chars().enumerate().map(|(i, c)| {
+        graph.add_node(ResidueNode { id: i + 1, residue: c.to_ascii_uppercase(), residue_name: three_letter_code(c).to_string() })
+    }).collect();
+    for i in 0..nodes.len() {
+        for j in i.saturating_sub(window)..(i + window + 1).min(nodes.len()) {
+            if i != j {
+                graph.add_edge(nodes[i], nodes[j], i.abs_diff(j) as f64);
+            }
+        }
+    }
+    graph
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_windowed_graphs() {
+        let records = parse_fasta(">sp|P1|TEST_HUMAN Test protein\nMKV\nLA*\n>two\nGG\n").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].id.as_str(), records[0].description.as_str()), ("sp|P1|TEST_HUMAN", "Test protein"));
+        assert_eq!(records[0].sequence, "MKVLA");
+        assert_eq!(file_stem(&records[0].id), "sp_P1_TEST_HUMAN");
+
+        let graph = sequence_graph(&records[0].sequence, 2);
+        assert_eq!(graph.node_count(), 5);
+        // 2 * (4 pairs at separation 1 + 3 at separation 2)
+        assert_eq!(graph.edge_count(), 14);
+        assert_eq!(graph.node_weights().nth(1).unwrap().residue_name, "LYS");
+    }
+}
@@ -0,0 +1,176 @@
+use crate::residue::ResidueKey;
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use pdbtbx::PDB;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+
+/// UniProt residue a structure residue is mapped to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UniprotResidue {
+    pub accession: String,
+    /// 1-based position in the UniProt sequence
+    pub position: usize,
+}
+
+
+/// Summary of the SIFTS mapping of a graph, stored in the graph metadata.
+#[derive(Serialize, Debug, Clone)]
+pub struct SiftsInfo {
+    pub source: String,
+    pub accessions: BTreeSet<String>,
+    /// Residues of the structure with a UniProt position
+    pub mapped_residues: usize,
+}
+
+
+/// Attributes of an XML tag, unescaping only what SIFTS files use.
+fn attributes(tag: &str) -> HashMap<&str, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = tag;
+    while let Some((name, value)) = rest.split_once("=\"") {
+        let Some((value, after)) = value.split_once('"') else { break };
+        let name = name.rsplit(char::is_whitespace).next().unwrap_or(name);
+        attributes.insert(name, value.replace("&quot;", "\"").replace("&apos;", "'").replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&"));
+        rest = after;
+    }
+    attributes
+}
+
+
+/// Splits a PDB residue number such as `52A` into its sequence number and
+/// insertion code.
+fn residue_number(number: &str) -> Option<(isize, Option<String>)> {
+    let digits = number.find(|c: char| !c.is_ascii_digit() && c != '-').unwrap_or(number.len());
+    let resseq = number[..digits].parse().ok()?;
+    let icode = Some(number[digits..].to_string()).filter(|i| !i.is_empty());
+    Some((resseq, icode))
+}
+
+
+/// Parses a SIFTS residue-level XML file into the UniProt residue of every
+/// observed PDB residue, keyed by author chain, residue number and
+/// insertion code.
+pub fn parse(xml: &str) -> HashMap<ResidueKey, UniprotResidue> {
+    let mut mapping = HashMap::new();
+    let (mut pdb, mut uniprot) = (None, None);
+    for tag in xml.split('<').skip(1).filter_map(|t| t.split_once('>').map(|(tag, _)| tag)) {
+        let name = tag.split_whitespace().next().unwrap_or("");
+        match name {
+            "residue" => {
+                pdb = None;
+                uniprot = None;
+            }
+            "/residue" => {
+                if let (Some(key), Some(residue)) = (pdb.take(), uniprot.take()) {
+                    mapping.insert(key, residue);
+                }
+            }
+            "crossRefDb" => {
+                let attributes = attributes(tag);
+                let source = attributes.get("dbSource").map(|s| s.as_str());
+                let number = attributes.get("dbResNum");
+                match source {
+                    Some("PDB") => {
+                        pdb = number.and_then(|n| residue_number(n)).zip(attributes.get("dbChainId")).map(|((resseq, icode), chain)| {
+                            ResidueKey { chain: chain.clone(), resseq, icode }
+                        });
+                    }
+                    Some("UniProt") => {
+                        uniprot = number.and_then(|n| n.parse().ok()).zip(attributes.get("dbAccessionId")).map(|(position, accession)| {
+                            UniprotResidue { accession: accession.clone(), position }
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    mapping
+}
+
+
+/// SIFTS file of a PDB entry in `dir`, as downloaded from the split XML
+/// directory (`1abc.xml.gz`) or decompressed.
+fn find(dir: &Path, pdb_id: &str) -> Option<PathBuf> {
+    [format!("{}.xml.gz", pdb_id), format!("{}.xml", pdb_id)]
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|p| p.is_file())
+}
+
+
+fn read(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path)?;
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut text = String::new();
+        GzDecoder::new(&bytes[..]).read_to_string(&mut text)?;
+        Ok(text)
+    } else {
+        Ok(String::from_utf8(bytes)?)
+    }
+}
+
+
+/// UniProt residue of every atom of the first model by serial number, from
+/// the SIFTS file of `pdb_id` in `dir`. Residues without a mapping (ligands,
+/// expression tags) get none.
+pub fn atom_residues(pdb: &PDB, dir: &Path, pdb_id: &str) -> Result<(HashMap<usize, UniprotResidue>, SiftsInfo)> {
+    let path = find(dir, pdb_id).with_context(|| format!("no SIFTS mapping for {} in {}", pdb_id, dir.display()))?;
+    let mapping = parse(&read(&path).with_context(|| format!("failed to read {}", path.display()))?);
+    if mapping.is_empty() {
+        bail!("{} maps no residues to UniProt", path.display());
+    }
+    let model = pdb.model(0).context("structure has no models")?;
+    let mut atoms = HashMap::new();
+    let mut info = SiftsInfo { source: path.display().to_string(), accessions: BTreeSet::new(), mapped_residues: 0 };
+    for chain in model.chains() {
+        for residue in chain.residues() {
+            let Some(uniprot) = mapping.get(&ResidueKey::new(chain, residue)) else { continue };
+            atoms.extend(residue.atoms().map(|a| (a.serial_number(), uniprot.clone())));
+            info.accessions.insert(uniprot.accession.clone());
+            info.mapped_residues += 1;
+        }
+    }
+    Ok((atoms, info))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_residue_mapping() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<entry dbSource="PDBe" dbAccessionId="1abc">
+  <entity type="protein" entityId="A">
+    <segment segId="1abc_A_1_3" start="1" end="3">
+      <listResidue>
+        <residue dbSource="PDBe" dbCoordSys="PDBe" dbResNum="1" dbResName="GLY">
+          <crossRefDb dbSource="PDB" dbCoordSys="PDBresnum" dbAccessionId="1abc" dbResNum="null" dbResName="GLY" dbChainId="A"/>
+          <crossRefDb dbSource="UniProt" dbCoordSys="UniProt" dbAccessionId="P12345" dbResNum="24" dbResName="G"/>
+          <residueDetail dbSource="PDBe" property="Annotation">Not_Observed</residueDetail>
+        </residue>
+        <residue dbSource="PDBe" dbCoordSys="PDBe" dbResNum="2" dbResName="SER">
+          <crossRefDb dbSource="PDB" dbCoordSys="PDBresnum" dbAccessionId="1abc" dbResNum="52A" dbResName="SER" dbChainId="H"/>
+          <crossRefDb dbSource="UniProt" dbCoordSys="UniProt" dbAccessionId="P12345" dbResNum="25" dbResName="S"/>
+        </residue>
+        <residue dbSource="PDBe" dbCoordSys="PDBe" dbResNum="3" dbResName="HIS">
+          <crossRefDb dbSource="PDB" dbCoordSys="PDBresnum" dbAccessionId="1abc" dbResNum="-1" dbResName="HIS" dbChainId="H"/>
+        </residue>
+      </listResidue>
+    </segment>
+  </entity>
+</entry>"#;
+        let mapping = parse(xml);
+        assert_eq!(mapping.len(), 1);
+        let key = ResidueKey { chain: "H".to_string(), resseq: 52, icode: Some("A".to_string()) };
+        assert_eq!(mapping[&key], UniprotResidue { accession: "P12345".to_string(), position: 25 });
+        assert_eq!(residue_number("-1"), Some((-1, None)));
+    }
+}
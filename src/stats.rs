@@ -0,0 +1,178 @@
+use crate::{AtomNode, EdgeData};
+use anyhow::{Context, Result};
+use pdbtbx::Element;
+use petgraph::graph::Graph;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+
+/// Report format of `graphein stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StatsFormat {
+    /// Tab separated, one line per graph
+    Table,
+    /// A JSON array with an object per graph
+    Json,
+}
+
+
+/// Distribution of the out-degrees of a graph's nodes.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct DegreeSummary {
+    pub min: usize,
+    pub median: f64,
+    pub mean: f64,
+    pub max: usize,
+}
+
+impl DegreeSummary {
+    fn new(mut degrees: Vec<usize>) -> DegreeSummary {
+        if degrees.is_empty() {
+            return DegreeSummary::default();
+        }
+        degrees.sort_unstable();
+        let n = degrees.len();
+        DegreeSummary {
+            min: degrees[0],
+            median: (degrees[(n - 1) / 2] + degrees[n / 2]) as f64 / 2.0,
+            mean: degrees.iter().sum::<usize>() as f64 / n as f64,
+            max: degrees[n - 1],
+        }
+    }
+}
+
+
+/// Size, degrees, connectivity and composition of a graph. Edges are
+/// counted as stored, i.e. once per direction in atom graphs, and degrees
+/// are out-degrees.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GraphSummary {
+    /// Structure or graph output the summary is of
+    pub source: String,
+    pub structure_id: String,
+    pub nodes: usize,
+    pub edges: usize,
+    pub degree: DegreeSummary,
+    /// Nodes without edges
+    pub isolated: usize,
+    /// Connected components, counting isolated nodes as components
+    pub components: usize,
+    /// Nodes by element symbol, empty for graphs without atoms
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub elements: BTreeMap<String, usize>,
+}
+
+impl GraphSummary {
+    /// Columns of `row`, tab separated.
+    pub const HEADER: &'static str =
+        "source\tstructure_id\tnodes\tedges\tmin_degree\tmedian_degree\tmean_degree\tmax_degree\tisolated\tcomponents\telements";
+
+    /// Summary of a graph with a node per atomic number, `None` for nodes
+    /// that aren't atoms, and edges by source and target node index.
+    pub fn new(source: &str, structure_id: &str, atomic_numbers: &[Option<u8>], edges: &[(usize, usize)]) -> GraphSummary {
+        let nodes = atomic_numbers.len();
+        let mut degrees = vec![0; nodes];
+        // Union-find over the edges
+        let mut parent: Vec<usize> = (0..nodes).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        let mut components = nodes;
+        for &(source, target) in edges {
+            degrees[source] += 1;
+            let (a, b) = (root(&mut parent, source), root(&mut parent, target));
+            if a != b {
+                parent[a] = b;
+                components -= 1;
+            }
+        }
+        let mut elements = BTreeMap::new();
+        for element in atomic_numbers.iter().flatten().filter_map(|n| Element::new(*n as usize)) {
+            *elements.entry(element.symbol().to_string()).or_default() += 1;
+        }
+        GraphSummary {
+            source: source.to_string(),
+            structure_id: structure_id.to_string(),
+            nodes,
+            edges: edges.len(),
+            isolated: degrees.iter().filter(|d| **d == 0).count(),
+            degree: DegreeSummary::new(degrees),
+            components,
+            elements,
+        }
+    }
+
+    /// Summary of an atom graph.
+    pub fn of_graph(source: &str, structure_id: &str, graph: &Graph<AtomNode, EdgeData>) -> GraphSummary {
+        let atomic_numbers: Vec<Option<u8>> = graph.node_weights().map(|n| Some(n.atom_number)).collect();
+        let edges: Vec<(usize, usize)> = graph.raw_edges().iter().map(|e| (e.source().index(), e.target().index())).collect();
+        GraphSummary::new(source, structure_id, &atomic_numbers, &edges)
+    }
+
+    pub fn row(&self) -> String {
+        let elements: Vec<String> = self.elements.iter().map(|(symbol, count)| format!("{}:{}", symbol, count)).collect();
+        let DegreeSummary { min, median, mean, max } = self.degree;
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{:.1}\t{:.2}\t{}\t{}\t{}\t{}",
+            self.source, self.structure_id, self.nodes, self.edges, min, median, mean, max, self.isolated, self.components, elements.join(",")
+        )
+    }
+}
+
+
+/// The parts of a `_graph.json` output that are summarized.
+#[derive(Deserialize)]
+struct GraphFile {
+    metadata: GraphFileMetadata,
+    graph: SerializedGraph,
+}
+
+#[derive(Deserialize)]
+struct GraphFileMetadata {
+    structure_id: String,
+}
+
+#[derive(Deserialize)]
+struct SerializedGraph {
+    nodes: Vec<SerializedNode>,
+    edges: Vec<(usize, usize, IgnoredAny)>,
+}
+
+#[derive(Deserialize)]
+struct SerializedNode {
+    atom_number: Option<u8>,
+}
+
+
+/// Summary of a `_graph.json` output of a single structure.
+pub fn summarize_file(path: &Path) -> Result<GraphSummary> {
+    let reader = std::io::BufReader::new(std::fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?);
+    let file: GraphFile = serde_json::from_reader(reader).with_context(|| format!("{} is not a single structure graph", path.display()))?;
+    let atomic_numbers: Vec<Option<u8>> = file.graph.nodes.iter().map(|n| n.atom_number).collect();
+    let edges: Vec<(usize, usize)> = file.graph.edges.iter().map(|(source, target, _)| (*source, *target)).collect();
+    Ok(GraphSummary::new(&path.display().to_string(), &file.metadata.structure_id, &atomic_numbers, &edges))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_degrees() {
+        let summary = GraphSummary::new("1abc.pdb", "1abc", &[Some(6), Some(6), Some(8), Some(7)], &[(0, 1), (1, 0), (1, 2), (2, 1)]);
+        assert_eq!((summary.edges, summary.isolated, summary.components), (4, 1, 2));
+        assert_eq!(summary.degree, DegreeSummary { min: 0, median: 1.0, mean: 1.0, max: 2 });
+        assert_eq!(summary.row(), "1abc.pdb\t1abc\t4\t4\t0\t1.0\t1.00\t2\t1\t2\tC:2,N:1,O:1");
+
+        let pdb = crate::golden::Fixture::get("benzoate_stacking").unwrap().structure().unwrap();
+        let summary = GraphSummary::of_graph("benzoate_stacking.pdb", "benzoate_stacking", &crate::build_graph(&pdb));
+        assert_eq!(summary.elements, BTreeMap::from([("C".to_string(), 14), ("N".to_string(), 1), ("O".to_string(), 2)]));
+    }
+}
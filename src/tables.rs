@@ -0,0 +1,183 @@
+use crate::atomic::AtomicFile;
+use crate::packed::column_values;
+use crate::schema::{DType, FeatureSchema, GraphSchema};
+use anyhow::Result;
+use parquet::basic::{Compression, LogicalType, Repetition, Type as PhysicalType};
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, FloatType, Int64Type};
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use petgraph::graph::Graph;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+use std::sync::Arc;
+
+
+/// Values of one table column, `None` where missing.
+enum Column {
+    Bool(Vec<Option<bool>>),
+    Int(Vec<Option<i64>>),
+    Float(Vec<Option<f32>>),
+    Double(Vec<Option<f64>>),
+    Text(Vec<Option<String>>),
+}
+
+impl Column {
+    fn new(dtype: DType, values: &[Option<&Value>]) -> Column {
+        match dtype {
+            DType::Bool => Column::Bool(values.iter().map(|v| v.and_then(|v| v.as_bool())).collect()),
+            DType::Uint8 | DType::Uint16 | DType::Int64 => Column::Int(values.iter().map(|v| v.and_then(|v| v.as_i64())).collect()),
+            DType::Float32 => Column::Float(values.iter().map(|v| v.and_then(|v| v.as_f64()).map(|v| v as f32)).collect()),
+            DType::Float64 => Column::Double(values.iter().map(|v| v.and_then(|v| v.as_f64())).collect()),
+            DType::Str => Column::Text(values.iter().map(|v| v.map(|v| v.as_str().map_or_else(|| v.to_string(), |s| s.to_string()))).collect()),
+        }
+    }
+
+    fn field(&self, name: &str) -> Result<Arc<Type>> {
+        let (physical, logical) = match self {
+            Column::Bool(_) => (PhysicalType::BOOLEAN, None),
+            Column::Int(_) => (PhysicalType::INT64, None),
+            Column::Float(_) => (PhysicalType::FLOAT, None),
+            Column::Double(_) => (PhysicalType::DOUBLE, None),
+            Column::Text(_) => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+        };
+        Ok(Arc::new(Type::primitive_type_builder(name, physical)
+            .with_repetition(Repetition::OPTIONAL)
+            .with_logical_type(logical)
+            .build()?))
+    }
+}
+
+
+/// Present values and definition levels of an optional column.
+fn levels<T: Clone>(values: &[Option<T>]) -> (Vec<T>, Vec<i16>) {
+    (values.iter().flatten().cloned().collect(), values.iter().map(|v| v.is_some() as i16).collect())
+}
+
+
+/// Column name without the `.` and `[]` of nested features, which query
+/// engines read as paths: `scores.total` becomes `scores_total` and
+/// `embedding[0]` becomes `embedding_0`.
+fn column_name(name: &str) -> String {
+    name.replace(['.', '['], "_").replace(']', "")
+}
+
+
+/// Columns of the features of `schema` of every item.
+fn feature_columns<'a, T: Serialize + 'a>(items: impl IntoIterator<Item = &'a T>, schema: &[FeatureSchema]) -> Result<Vec<(String, Column)>> {
+    let items: Vec<Value> = items.into_iter().map(serde_json::to_value).collect::<Result<_, _>>()?;
+    let mut columns = Vec::new();
+    for feature in schema {
+        for (name, values) in column_values(&items, feature)? {
+            columns.push((column_name(&name), Column::new(feature.dtype, &values)));
+        }
+    }
+    Ok(columns)
+}
+
+
+/// Writes columns as a Parquet table, with `metadata` as JSON under the
+/// `graphein` key of the file metadata.
+fn write_table(path: &Path, columns: &[(String, Column)], metadata: &str) -> Result<()> {
+    let fields = columns.iter().map(|(name, column)| column.field(name)).collect::<Result<_>>()?;
+    let schema = Arc::new(Type::group_type_builder("graph").with_fields(fields).build()?);
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_key_value_metadata(Some(vec![KeyValue::new("graphein".to_string(), metadata.to_string())]))
+        .build();
+    let mut writer = SerializedFileWriter::new(AtomicFile::create(path)?, schema, Arc::new(properties))?;
+    let mut row_group = writer.next_row_group()?;
+    for (_, column) in columns {
+        let mut out = row_group.next_column()?.expect("a writer for every column");
+        match column {
+            Column::Bool(v) => {
+                let (values, defs) = levels(v);
+                out.typed::<BoolType>().write_batch(&values, Some(&defs), None)?;
+            }
+            Column::Int(v) => {
+                let (values, defs) = levels(v);
+                out.typed::<Int64Type>().write_batch(&values, Some(&defs), None)?;
+            }
+            Column::Float(v) => {
+                let (values, defs) = levels(v);
+                out.typed::<FloatType>().write_batch(&values, Some(&defs), None)?;
+            }
+            Column::Double(v) => {
+                let (values, defs) = levels(v);
+                out.typed::<DoubleType>().write_batch(&values, Some(&defs), None)?;
+            }
+            Column::Text(v) => {
+                let (values, defs) = levels(v);
+                let values: Vec<ByteArray> = values.into_iter().map(|s| ByteArray::from(s.into_bytes())).collect();
+                out.typed::<ByteArrayType>().write_batch(&values, Some(&defs), None)?;
+            }
+        }
+        out.close()?;
+    }
+    row_group.close()?;
+    writer.into_inner()?.commit()?;
+    Ok(())
+}
+
+
+/// Writes a graph as a Parquet nodes table and edges table. Both start with
+/// the `structure_id`, so the tables of many structures can be read as one
+/// dataset; nodes continue with their index `node` and edges with the
+/// `source` and `target` node, followed by the features described by
+/// `schema`. The metadata is stored as JSON in the file metadata of both.
+pub fn write_graph_tables<N: Serialize, E: Serialize, M: Serialize>(
+    graph: &Graph<N, E>,
+    schema: &GraphSchema,
+    structure_id: &str,
+    metadata: &M,
+    nodes_path: &Path,
+    edges_path: &Path,
+) -> Result<()> {
+    let metadata = serde_json::to_string(metadata)?;
+    let id = |n: usize| (String::from("structure_id"), Column::Text(vec![Some(structure_id.to_string()); n]));
+
+    let mut nodes = vec![id(graph.node_count()), (String::from("node"), Column::Int(graph.node_indices().map(|n| Some(n.index() as i64)).collect()))];
+    nodes.extend(feature_columns(graph.node_weights(), &schema.nodes)?);
+    write_table(nodes_path, &nodes, &metadata)?;
+
+    let mut edges = vec![
+        id(graph.edge_count()),
+        (String::from("source"), Column::Int(graph.raw_edges().iter().map(|e| Some(e.source().index() as i64)).collect())),
+        (String::from("target"), Column::Int(graph.raw_edges().iter().map(|e| Some(e.target().index() as i64)).collect())),
+    ];
+    edges.extend(feature_columns(graph.edge_weights(), &schema.edges)?);
+    write_table(edges_path, &edges, &metadata)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_graph;
+    use crate::schema::atom_graph_schema;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use pdbtbx::StrictnessLevel;
+
+    #[test]
+    fn writes_nodes_and_edges_tables() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        let pdb = pdbtbx::open(path, StrictnessLevel::Medium).unwrap().0;
+        let graph = build_graph(&pdb);
+        let dir = std::env::temp_dir().join(format!("graphein-tables-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (nodes, edges) = (dir.join("nodes.parquet"), dir.join("edges.parquet"));
+        write_graph_tables(&graph, &atom_graph_schema(&graph), "ab", &"{}", &nodes, &edges).unwrap();
+
+        let reader = SerializedFileReader::new(std::fs::File::open(&edges).unwrap()).unwrap();
+        let meta = reader.metadata().file_metadata();
+        assert_eq!(meta.num_rows() as usize, graph.edge_count());
+        let names: Vec<&str> = meta.schema_descr().columns().iter().map(|c| c.name()).collect();
+        assert_eq!(names, vec!["structure_id", "source", "target", "distance", "bond_type"]);
+        let reader = SerializedFileReader::new(std::fs::File::open(&nodes).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows() as usize, graph.node_count());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+use crate::residue::ResidueKey;
+use crate::{AtomNode, BondType, GraphBuilder, NodeAnnotations};
+use pdbtbx::{Atom, Chain, Model, Residue, PDB};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+
+/// Edge of a temporal graph with the frames it occurs in.
+#[derive(Serialize)]
+pub struct TemporalEdge {
+    pub source: usize,
+    pub target: usize,
+    pub frames: Vec<usize>,
+    pub mean_distance: f64,
+    /// Bond type in the first frame the edge occurs in
+    pub bond_type: BondType,
+}
+
+
+/// Graph over a fixed node set whose edges change between frames. Nodes are
+/// the atoms of the first model; atoms of later models are matched to them
+/// by residue and atom name.
+#[derive(Serialize)]
+pub struct TemporalGraph {
+    pub frame_count: usize,
+    pub nodes: Vec<AtomNode>,
+    pub edges: Vec<TemporalEdge>,
+}
+
+
+type AtomKey = (ResidueKey, String, Option<String>);
+
+
+fn keyed_atoms(model: &Model) -> Vec<(AtomKey, &Chain, &Residue, &Atom)> {
+    let mut atoms = Vec::new();
+    for chain in model.chains() {
+        for residue in chain.residues() {
+            for conformer in residue.conformers() {
+                for atom in conformer.atoms() {
+                    let key = (
+                        ResidueKey::new(chain, residue),
+                        atom.name().to_string(),
+                        conformer.alternative_location().map(|a| a.to_string()),
+                    );
+                    atoms.push((key, chain, residue, atom));
+                }
+            }
+        }
+    }
+    atoms
+}
+
+
+/// Builds a temporal graph treating every model of `pdb` as one frame.
+pub fn build_temporal_graph(pdb: &PDB, builder: &GraphBuilder, annotations: &NodeAnnotations) -> TemporalGraph {
+    let mut nodes = Vec::new();
+    let mut node_of: HashMap<AtomKey, usize> = HashMap::new();
+    if let Some(first) = pdb.model(0) {
+        for (key, chain, residue, atom) in keyed_atoms(first) {
+            if node_of.contains_key(&key) {
+                continue;
+            }
+            if let Some(node) = builder.node(chain, residue, atom, annotations, None) {
+                node_of.insert(key, nodes.len());
+                nodes.push(node);
+            }
+        }
+    }
+
+    // (source, target) -> (frames, summed distance, bond type)
+    let mut edges: BTreeMap<(usize, usize), (Vec<usize>, f64, BondType)> = BTreeMap::new();
+    for (frame, model) in pdb.models().enumerate() {
+        let atoms: Vec<(usize, &Atom)> = keyed_atoms(model).into_iter()
+            .filter_map(|(key, _, _, atom)| node_of.get(&key).map(|n| (*n, atom)))
+            .collect();
+        let node_at: HashMap<*const Atom, usize> = atoms.iter().map(|(n, a)| (*a as *const Atom, *n)).collect();
+        let tree = rstar::RTree::bulk_load(atoms.iter().map(|(_, a)| *a).collect());
+        for (source, atom) in &atoms {
+            for neighbor in tree.locate_within_distance(atom.pos(), builder.cutoff * builder.cutoff) {
+                let target = node_at[&(*neighbor as *const Atom)];
+                if target <= *source || atom.pos() == neighbor.pos() {
+                    continue;
+                }
+                let entry = edges.entry((*source, target)).or_insert_with(|| (Vec::new(), 0.0, builder.bond_type(atom, neighbor)));
+                if entry.0.last() != Some(&frame) {
+                    entry.0.push(frame);
+                    entry.1 += atom.distance(neighbor);
+                }
+            }
+        }
+    }
+
+    TemporalGraph {
+        frame_count: pdb.model_count(),
+        nodes,
+        edges: edges.into_iter().map(|((source, target), (frames, total, bond_type))| TemporalEdge {
+            source,
+            target,
+            mean_distance: total / frames.len() as f64,
+            frames,
+            bond_type,
+        }).collect(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One GLY residue per model, with the given atoms on the x axis.
+    fn trajectory(frames: &[&[(&str, f64)]]) -> PDB {
+        let mut text = String::new();
+        for (model, atoms) in frames.iter().enumerate() {
+            text += &format!("MODEL     {:>4}\n", model + 1);
+            for (i, (name, x)) in atoms.iter().enumerate() {
+                text += &format!("ATOM  {:>5}  {:<3} GLY A   1    {:>8.3}{:>8.3}{:>8.3}  1.00  0.00           {}\n", i + 1, name, x, 0.0, 0.0, &name[..1]);
+            }
+            text += "ENDMDL\n";
+        }
+        text += "END\n";
+        pdbtbx::open_pdb_raw(std::io::BufReader::new(text.as_bytes()), pdbtbx::Context::None, pdbtbx::StrictnessLevel::Loose).unwrap().0
+    }
+
+    #[test]
+    fn tracks_edges_across_frames() {
+        // The second frame lists its atoms in another order and lacks C
+        let pdb = trajectory(&[
+            &[("N", 0.0), ("CA", 1.5), ("C", 10.0), ("O", 12.0)],
+            &[("O", 5.0), ("CA", 3.0), ("N", 0.0)],
+        ]);
+        let graph = build_temporal_graph(&pdb, &GraphBuilder::new().cutoff(4.0), &NodeAnnotations::default());
+        assert_eq!(graph.frame_count, 2);
+        assert_eq!(graph.nodes.len(), 4);
+
+        let edges: Vec<_> = graph.edges.iter().map(|e| ((e.source, e.target), e.frames.clone(), e.mean_distance)).collect();
+        assert_eq!(edges.len(), 3);
+        // N–CA in both frames, CA–O only once O moved, C–O only while C exists
+        assert_eq!((edges[0].0, edges[0].1.as_slice()), ((0, 1), &[0, 1][..]));
+        assert!((edges[0].2 - 2.25).abs() < 1e-9);
+        assert_eq!((edges[1].0, edges[1].1.as_slice()), ((1, 3), &[1][..]));
+        assert!((edges[1].2 - 2.0).abs() < 1e-9);
+        assert_eq!((edges[2].0, edges[2].1.as_slice()), ((2, 3), &[0][..]));
+        assert!((edges[2].2 - 2.0).abs() < 1e-9);
+    }
+}
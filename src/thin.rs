@@ -0,0 +1,79 @@
+use petgraph::graph::{Graph, NodeIndex};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+use std::collections::HashMap;
+
+
+/// Record of randomly thinned edges, stored in the graph metadata.
+#[derive(Serialize, Debug, Clone)]
+pub struct EdgeSamplingInfo {
+    /// Requested probability of keeping an edge
+    pub rate: f64,
+    pub original_edges: usize,
+    pub kept_edges: usize,
+    /// Fraction of edges actually kept
+    pub effective_rate: f64,
+}
+
+
+/// Keeps every edge of `graph` with probability `rate`, drawn with `seed`.
+/// Both directions of an atom pair are kept or dropped together, so the
+/// graph stays symmetric.
+pub fn sample_edges<N, E>(graph: &mut Graph<N, E>, rate: f64, seed: u64) -> EdgeSamplingInfo {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let original_edges = graph.edge_count();
+    let mut keep: HashMap<(NodeIndex, NodeIndex), bool> = HashMap::new();
+    for e in graph.edge_indices() {
+        let (a, b) = graph.edge_endpoints(e).unwrap();
+        keep.entry((a.min(b), a.max(b))).or_insert_with(|| rng.gen_bool(rate));
+    }
+    graph.retain_edges(|g, e| {
+        let (a, b) = g.edge_endpoints(e).unwrap();
+        keep[&(a.min(b), a.max(b))]
+    });
+    let kept_edges = graph.edge_count();
+    EdgeSamplingInfo {
+        rate,
+        original_edges,
+        kept_edges,
+        effective_rate: if original_edges == 0 { 1.0 } else { kept_edges as f64 / original_edges as f64 },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(graph: &Graph<(), ()>) -> Vec<(usize, usize)> {
+        graph.raw_edges().iter().map(|e| (e.source().index(), e.target().index())).collect()
+    }
+
+    #[test]
+    fn keeps_both_directions_and_is_seeded() {
+        let full = || {
+            let mut graph: Graph<(), ()> = Graph::new();
+            let nodes: Vec<NodeIndex> = (0..20).map(|_| graph.add_node(())).collect();
+            for i in 0..20 {
+                for j in 0..20 {
+                    if i != j {
+                        graph.add_edge(nodes[i], nodes[j], ());
+                    }
+                }
+            }
+            graph
+        };
+        let thinned = |seed| {
+            let mut graph = full();
+            let info = sample_edges(&mut graph, 0.3, seed);
+            assert_eq!((info.original_edges, info.kept_edges), (380, graph.edge_count()));
+            pairs(&graph)
+        };
+
+        let kept = thinned(5);
+        assert!(!kept.is_empty() && kept.len() < 380);
+        assert!(kept.iter().all(|(a, b)| kept.contains(&(*b, *a))));
+        assert_eq!(kept, thinned(5));
+        assert_ne!(kept, thinned(6));
+    }
+}
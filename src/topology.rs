@@ -0,0 +1,112 @@
+use crate::residue::protein_residues;
+use pdbtbx::PDB;
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+use serde::{Deserialize, Serialize};
+
+
+/// Largest CA-CA distance of residues in contact, in Å.
+const CONTACT_DISTANCE: f64 = 8.0;
+
+/// Smallest sequence separation of a contact, so neighbours along the chain
+/// and within a helix turn don't count.
+const MIN_SEPARATION: usize = 3;
+
+/// Smallest sequence separation of a long-range contact.
+const LONG_RANGE_SEPARATION: usize = 12;
+
+
+/// Fold topology metrics of the protein chains of a structure, from the
+/// contacts between residues of the same chain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TopologyMetrics {
+    /// Protein residues with a CA atom
+    pub residues: usize,
+    pub contacts: usize,
+    /// Contacts per residue
+    pub contact_density: f64,
+    /// Mean sequence separation of the contacts
+    pub absolute_contact_order: f64,
+    /// Absolute contact order divided by the number of residues
+    pub relative_contact_order: f64,
+    /// Contacts more than 12 residues apart
+    pub long_range_contacts: usize,
+    /// Long-range contacts per residue
+    pub long_range_order: f64,
+}
+
+
+/// Computes the topology metrics of the first model, `None` without protein
+/// residues. Residues are in contact when their CA atoms are at most 8 Å
+/// apart and at least 3 positions apart in the same chain.
+pub fn topology(pdb: &PDB) -> Option<TopologyMetrics> {
+    let mut points = Vec::new();
+    let mut chain = None;
+    let (mut chain_index, mut position) = (0, 0);
+    for (key, residue) in protein_residues(pdb) {
+        if chain.as_ref() != Some(&key.chain) {
+            chain = Some(key.chain.clone());
+            chain_index += 1;
+            position = 0;
+        }
+        if let Some(ca) = residue.atoms().find(|a| a.name() == "CA") {
+            points.push(GeomWithData::new([ca.x(), ca.y(), ca.z()], (chain_index, position)));
+        }
+        position += 1;
+    }
+    if points.is_empty() {
+        return None;
+    }
+    let tree = RTree::bulk_load(points.clone());
+
+    let (mut contacts, mut long_range, mut separations) = (0, 0, 0);
+    for point in &points {
+        let (chain, i) = point.data;
+        for neighbor in tree.locate_within_distance(*point.geom(), CONTACT_DISTANCE * CONTACT_DISTANCE) {
+            let (other_chain, j) = neighbor.data;
+            if other_chain == chain && j >= i + MIN_SEPARATION {
+                contacts += 1;
+                separations += j - i;
+                long_range += (j - i > LONG_RANGE_SEPARATION) as usize;
+            }
+        }
+    }
+    let residues = points.len();
+    let absolute_contact_order = if contacts > 0 { separations as f64 / contacts as f64 } else { 0.0 };
+    Some(TopologyMetrics {
+        residues,
+        contacts,
+        contact_density: contacts as f64 / residues as f64,
+        absolute_contact_order,
+        relative_contact_order: absolute_contact_order / residues as f64,
+        long_range_contacts: long_range,
+        long_range_order: long_range as f64 / residues as f64,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdbtbx::{Atom, Model};
+
+    #[test]
+    fn measures_contact_order() {
+        // A hairpin: residues 0 and 19 face each other, the rest is stretched out
+        let mut model = Model::new(0);
+        for i in 0..20 {
+            let (x, y) = if i < 10 { (i as f64 * 10.0, 0.0) } else { ((19 - i) as f64 * 10.0, 5.0) };
+            let ca = Atom::new(false, i + 1, "CA", x, y, 0.0, 1.0, 20.0, "C", 0).unwrap();
+            model.add_atom(ca, "A", (i as isize + 1, None), ("ALA", None));
+        }
+        let mut pdb = PDB::new();
+        pdb.add_model(model);
+
+        let metrics = topology(&pdb).unwrap();
+        // Residue i pairs with 19 - i, separations 19, 17, ..., 3 for i = 0..8
+        assert_eq!((metrics.residues, metrics.contacts, metrics.long_range_contacts), (20, 9, 4));
+        assert_eq!(metrics.absolute_contact_order, 11.0);
+        assert_eq!(metrics.relative_contact_order, 0.55);
+        assert!(topology(&PDB::new()).is_none());
+    }
+}
@@ -0,0 +1,177 @@
+use pdbtbx::{Atom, PDB};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::Serialize;
+use std::collections::HashSet;
+
+
+/// Which atoms to keep when a structure has more nodes than allowed.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TrimStrategy {
+    /// Atoms closest to the geometric center of the structure
+    Center,
+    /// A seeded random subset of atoms
+    Random,
+    /// Whole chains in file order, cropping the first chain that doesn't fit around its center
+    Chain,
+}
+
+
+/// Record of a trimmed structure, stored in the graph metadata.
+#[derive(Serialize, Debug, Clone)]
+pub struct TrimInfo {
+    pub strategy: TrimStrategy,
+    pub original_nodes: usize,
+    pub kept_nodes: usize,
+}
+
+
+fn centroid<'a>(atoms: impl Iterator<Item = &'a Atom>) -> (f64, f64, f64) {
+    let (mut sum, mut n) = ((0.0, 0.0, 0.0), 0.0);
+    for a in atoms {
+        let p = a.pos();
+        sum = (sum.0 + p.0, sum.1 + p.1, sum.2 + p.2);
+        n += 1.0;
+    }
+    if n == 0.0 { sum } else { (sum.0 / n, sum.1 / n, sum.2 / n) }
+}
+
+
+/// Indices of up to `count` of the indexed atoms closest to their center.
+fn closest_to_center(atoms: &[(usize, &Atom)], count: usize) -> Vec<usize> {
+    let c = centroid(atoms.iter().map(|(_, a)| *a));
+    let dist = |a: &Atom| {
+        let p = a.pos();
+        (p.0 - c.0).powi(2) + (p.1 - c.1).powi(2) + (p.2 - c.2).powi(2)
+    };
+    let mut sorted = atoms.to_vec();
+    sorted.sort_by(|(_, a), (_, b)| dist(a).total_cmp(&dist(b)));
+    sorted.iter().take(count).map(|(i, _)| *i).collect()
+}
+
+
+/// Removes atoms from `pdb` until at most `max_nodes` atoms that become graph
+/// nodes (atoms with a known element) are left, counting the atoms of every
+/// model. Returns `None` if the structure already fits.
+pub fn trim(pdb: &mut PDB, max_nodes: usize, strategy: TrimStrategy, seed: u64) -> Option<TrimInfo> {
+    // Atoms are identified by their index in `pdb.atoms()` order
+    let nodes: Vec<(usize, &Atom)> = pdb.atoms().enumerate().filter(|(_, a)| a.element().is_some()).collect();
+    let original_nodes = nodes.len();
+    if original_nodes <= max_nodes {
+        return None;
+    }
+    let keep: HashSet<usize> = match strategy {
+        TrimStrategy::Center => closest_to_center(&nodes, max_nodes).into_iter().collect(),
+        TrimStrategy::Random => {
+            let mut rng = StdRng::seed_from_u64(seed);
+            rand::seq::index::sample(&mut rng, nodes.len(), max_nodes)
+                .into_iter()
+                .map(|i| nodes[i].0)
+                .collect()
+        }
+        TrimStrategy::Chain => {
+            let mut keep = HashSet::new();
+            let mut offset = 0;
+            for chain in pdb.chains() {
+                let atoms: Vec<(usize, &Atom)> = chain.atoms().enumerate()
+                    .map(|(i, a)| (offset + i, a))
+                    .filter(|(_, a)| a.element().is_some())
+                    .collect();
+                offset += chain.atom_count();
+                let budget = max_nodes - keep.len();
+                if atoms.len() <= budget {
+                    keep.extend(atoms.iter().map(|(i, _)| *i));
+                } else {
+                    keep.extend(closest_to_center(&atoms, budget));
+                    break;
+                }
+            }
+            keep
+        }
+    };
+    let is_node: Vec<bool> = pdb.atoms().map(|a| a.element().is_some()).collect();
+    crate::retain_atoms(pdb, |i| !is_node[i] || keep.contains(&i));
+    let kept_nodes = pdb.atoms().filter(|a| a.element().is_some()).count();
+    Some(TrimInfo { strategy, original_nodes, kept_nodes })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pdbtbx::StrictnessLevel;
+
+    fn antibody() -> PDB {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/antibody_insertion_codes.pdb");
+        pdbtbx::open(path, StrictnessLevel::Medium).unwrap().0
+    }
+
+    fn serials(pdb: &PDB) -> Vec<usize> {
+        pdb.atoms().map(|a| a.serial_number()).collect()
+    }
+
+    /// The antibody as an NMR-like ensemble of `n` models, with serial
+    /// numbers restarting in every model.
+    fn ensemble(n: usize) -> PDB {
+        let mut pdb = antibody();
+        let model = pdb.model(0).unwrap().clone();
+        for i in 1..n {
+            let mut copy = model.clone();
+            copy.set_serial_number(i + 1);
+            pdb.add_model(copy);
+        }
+        pdb
+    }
+
+    #[test]
+    fn strategies_keep_at_most_max_nodes() {
+        let original = antibody().atoms().filter(|a| a.element().is_some()).count();
+        for strategy in [TrimStrategy::Center, TrimStrategy::Random, TrimStrategy::Chain] {
+            let mut pdb = antibody();
+            assert!(trim(&mut pdb, original, strategy, 0).is_none());
+            let info = trim(&mut pdb, 40, strategy, 0).unwrap();
+            assert_eq!((info.original_nodes, info.kept_nodes), (original, 40));
+            assert_eq!(pdb.atoms().filter(|a| a.element().is_some()).count(), 40);
+        }
+    }
+
+    #[test]
+    fn counts_the_nodes_of_every_model() {
+        let models = 5;
+        let original = ensemble(models).atoms().count();
+        for strategy in [TrimStrategy::Center, TrimStrategy::Random, TrimStrategy::Chain] {
+            let mut pdb = ensemble(models);
+            let info = trim(&mut pdb, 50, strategy, 0).unwrap();
+            assert_eq!(info.original_nodes, original);
+            assert!(info.kept_nodes <= 50);
+            assert_eq!(pdb.atoms().count(), info.kept_nodes);
+        }
+    }
+
+    #[test]
+    fn random_is_seeded() {
+        let trimmed = |seed| {
+            let mut pdb = antibody();
+            trim(&mut pdb, 20, TrimStrategy::Random, seed);
+            serials(&pdb)
+        };
+        assert_eq!(trimmed(7), trimmed(7));
+        assert_ne!(trimmed(7), trimmed(8));
+    }
+
+    #[test]
+    fn chain_keeps_the_first_chain_whole() {
+        let first: Vec<usize> = antibody().chains().next().unwrap().atoms().map(|a| a.serial_number()).collect();
+        let mut pdb = antibody();
+        let info = trim(&mut pdb, first.len() + 10, TrimStrategy::Chain, 0).unwrap();
+        assert_eq!(info.kept_nodes, first.len() + 10);
+        let chains: Vec<usize> = pdb.chains().map(|c| c.atom_count()).collect();
+        assert_eq!(chains, vec![first.len(), 10]);
+        assert_eq!(serials(&pdb)[..first.len()], first[..]);
+
+        // A budget smaller than the first chain crops it and drops the rest
+        let mut pdb = antibody();
+        trim(&mut pdb, 5, TrimStrategy::Chain, 0).unwrap();
+        assert_eq!(pdb.chains().map(|c| c.atom_count()).collect::<Vec<_>>(), vec![5]);
+    }
+}
@@ -0,0 +1,378 @@
+use crate::bond_order::{residue_bond_orders, BondOrder};
+use crate::ccd::CcdAnnotations;
+use crate::hbond::bonded;
+use pdbtbx::{Atom, Element, Residue, PDB};
+use serde::Serialize;
+use std::collections::HashMap;
+
+
+/// Scheme of the chemical atom types of nodes.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum AtomTypeScheme {
+    /// Tripos Sybyl types, e.g. C.ar, N.am or O.co2
+    Sybyl,
+    /// Simplified GAFF types, e.g. ca, n or oh
+    Gaff,
+    /// Rosetta-style types of standard amino acid atoms, e.g. CAbb, aroC or Hpol
+    Protein,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Hybridization {
+    Sp,
+    Sp2,
+    Sp3,
+    Aromatic,
+}
+
+
+/// Atoms of a model with their residues and covalent bonds. The order of a
+/// bond is `None` where it is unknown.
+struct Bonds<'a> {
+    atoms: Vec<(&'a Residue, &'a Atom)>,
+    bonds: Vec<Vec<(usize, Option<BondOrder>)>>,
+    hybridization: Vec<Hybridization>,
+}
+
+impl<'a> Bonds<'a> {
+    fn new(residues: impl Iterator<Item = &'a Residue>, tolerance: f64, ccd: &CcdAnnotations) -> Bonds<'a> {
+        let residues: Vec<&Residue> = residues.collect();
+        let atoms: Vec<(&Residue, &Atom)> = residues.iter().flat_map(|&r| r.atoms().map(move |a| (r, a))).collect();
+        let mut orders: HashMap<(*const Atom, *const Atom), BondOrder> = HashMap::new();
+        for residue in &residues {
+            for (a, b, order) in residue_bond_orders(residue, tolerance) {
+                orders.insert((a, b), order);
+                orders.insert((b, a), order);
+            }
+        }
+        let index: HashMap<*const Atom, usize> = atoms.iter().enumerate().map(|(i, (_, a))| (*a as *const Atom, i)).collect();
+        let tree = rstar::RTree::bulk_load(atoms.iter().map(|(_, a)| *a).collect());
+        let bonds: Vec<Vec<(usize, Option<BondOrder>)>> = atoms.iter().map(|&(residue, atom)| {
+            tree.locate_within_distance(atom.pos(), 3.0 * 3.0)
+                .filter(|other| !std::ptr::eq(**other, atom) && bonded(atom, other, tolerance))
+                .map(|other| {
+                    let (j, other_residue) = (index[&(*other as *const Atom)], atoms[index[&(*other as *const Atom)]].0);
+                    let serials = (atom.serial_number(), other.serial_number());
+                    let order = match (ccd.atoms.get(&serials.0), ccd.atoms.get(&serials.1)) {
+                        (Some(x), Some(y)) if x.residue == y.residue => ccd.bonds.get(&serials).copied(),
+                        _ => orders.get(&(atom as *const Atom, *other as *const Atom)).copied(),
+                    };
+                    // Bonds between residues, such as peptide bonds, are single
+                    (j, order.or((!std::ptr::eq(residue, other_residue)).then_some(BondOrder::Single)))
+                })
+                .collect()
+        }).collect();
+        let mut typed = Bonds { atoms, bonds, hybridization: Vec::new() };
+        typed.hybridization = (0..typed.atoms.len()).map(|i| typed.hybridization_of(i)).collect();
+        // Geometry can't tell aromatic rings, those are rings of planar atoms
+        let planar: Vec<bool> = typed.hybridization.iter().zip(&typed.bonds)
+            .map(|(h, bonds)| *h == Hybridization::Sp2 && bonds.iter().any(|b| b.1.is_none()))
+            .collect();
+        for i in 0..typed.atoms.len() {
+            if planar[i] && typed.in_ring(i, &planar) {
+                typed.hybridization[i] = Hybridization::Aromatic;
+            }
+        }
+        typed
+    }
+
+    fn element(&self, i: usize) -> Option<&Element> {
+        self.atoms[i].1.element()
+    }
+
+    fn is(&self, i: usize, element: Element) -> bool {
+        self.element(i) == Some(&element)
+    }
+
+    fn neighbors(&self, i: usize) -> impl Iterator<Item = usize> + '_ {
+        self.bonds[i].iter().map(|b| b.0)
+    }
+
+    fn heavy_degree(&self, i: usize) -> usize {
+        self.neighbors(i).filter(|&j| !self.is(j, Element::H)).count()
+    }
+
+    fn has_hydrogen(&self, i: usize) -> bool {
+        self.neighbors(i).any(|j| self.is(j, Element::H))
+    }
+
+    /// Oxygens bonded to nothing but atom `i`.
+    fn terminal_oxygens(&self, i: usize) -> usize {
+        self.neighbors(i).filter(|&j| self.is(j, Element::O) && self.bonds[j].len() == 1).count()
+    }
+
+    /// Carbon double bonded to an oxygen or sulfur, or planar with a terminal one.
+    fn carbonyl(&self, i: usize) -> bool {
+        self.is(i, Element::C) && self.bonds[i].iter().any(|&(j, order)| {
+            (self.is(j, Element::O) || self.is(j, Element::S))
+                && (order == Some(BondOrder::Double) || (order.is_none() && self.bonds[j].len() == 1 && self.hybridization[i] == Hybridization::Sp2))
+        })
+    }
+
+    /// Planar carbon bonded to three nitrogens, as in guanidinium.
+    fn cation_carbon(&self, i: usize) -> bool {
+        self.is(i, Element::C) && self.hybridization[i] == Hybridization::Sp2 && self.neighbors(i).filter(|&j| self.is(j, Element::N)).count() == 3
+    }
+
+    /// Hybridization from the orders of the bonds of atom `i`, or from its
+    /// geometry if some are unknown: the mean angle between its bonds, or for
+    /// terminal atoms the length of their bond.
+    fn hybridization_of(&self, i: usize) -> Hybridization {
+        let orders: Vec<Option<BondOrder>> = self.bonds[i].iter().map(|b| b.1).collect();
+        let count = |order: BondOrder| orders.iter().filter(|o| **o == Some(order)).count();
+        if count(BondOrder::Aromatic) > 0 {
+            return Hybridization::Aromatic;
+        }
+        if count(BondOrder::Triple) > 0 || count(BondOrder::Double) > 1 {
+            return Hybridization::Sp;
+        }
+        if count(BondOrder::Double) > 0 {
+            return Hybridization::Sp2;
+        }
+        if orders.iter().all(Option::is_some) {
+            return Hybridization::Sp3;
+        }
+        let atom = self.atoms[i].1;
+        let neighbors: Vec<&Atom> = self.neighbors(i).map(|j| self.atoms[j].1).collect();
+        match neighbors[..] {
+            [other] => {
+                let single = atom.element().zip(other.element())
+                    .map_or(f64::INFINITY, |(x, y)| x.atomic_radius().covalent_single + y.atomic_radius().covalent_single);
+                if atom.distance(other) < single - 0.1 { Hybridization::Sp2 } else { Hybridization::Sp3 }
+            }
+            [] => Hybridization::Sp3,
+            _ => {
+                let mut angles = Vec::new();
+                for (k, a) in neighbors.iter().enumerate() {
+                    for b in &neighbors[k + 1..] {
+                        let u = (a.x() - atom.x(), a.y() - atom.y(), a.z() - atom.z());
+                        let v = (b.x() - atom.x(), b.y() - atom.y(), b.z() - atom.z());
+                        let cos = (u.0 * v.0 + u.1 * v.1 + u.2 * v.2) / (a.distance(atom) * b.distance(atom));
+                        angles.push(cos.clamp(-1.0, 1.0).acos().to_degrees());
+                    }
+                }
+                match angles.iter().sum::<f64>() / angles.len() as f64 {
+                    mean if mean >= 155.0 => Hybridization::Sp,
+                    mean if mean >= 115.0 => Hybridization::Sp2,
+                    _ => Hybridization::Sp3,
+                }
+            }
+        }
+    }
+
+    /// Whether atom `i` closes a ring of five or six `allowed` atoms.
+    fn in_ring(&self, i: usize, allowed: &[bool]) -> bool {
+        fn search(bonds: &Bonds, start: usize, at: usize, path: &mut Vec<usize>, allowed: &[bool]) -> bool {
+            for j in bonds.neighbors(at) {
+                if j == start && path.len() >= 5 {
+                    return true;
+                }
+                if allowed[j] && !path.contains(&j) && path.len() < 6 {
+                    path.push(j);
+                    if search(bonds, start, j, path, allowed) {
+                        return true;
+                    }
+                    path.pop();
+                }
+            }
+            false
+        }
+        search(self, i, i, &mut vec![i], allowed)
+    }
+
+    fn sybyl(&self, i: usize) -> String {
+        let hybridization = self.hybridization[i];
+        let bonded_to = |test: &dyn Fn(usize) -> bool| self.neighbors(i).any(test);
+        let sybyl = match self.element(i) {
+            Some(Element::C) => match hybridization {
+                Hybridization::Aromatic => "C.ar",
+                Hybridization::Sp => "C.1",
+                Hybridization::Sp2 if self.cation_carbon(i) => "C.cat",
+                Hybridization::Sp2 => "C.2",
+                Hybridization::Sp3 => "C.3",
+            },
+            Some(Element::N) => match hybridization {
+                Hybridization::Aromatic => "N.ar",
+                Hybridization::Sp => "N.1",
+                _ if bonded_to(&|j| self.cation_carbon(j)) => "N.pl3",
+                _ if bonded_to(&|j| self.carbonyl(j)) => "N.am",
+                Hybridization::Sp2 => "N.2",
+                Hybridization::Sp3 if self.bonds[i].len() == 4 || self.atoms[i].1.charge() > 0 => "N.4",
+                Hybridization::Sp3 if bonded_to(&|j| self.hybridization[j] != Hybridization::Sp3) => "N.pl3",
+                Hybridization::Sp3 => "N.3",
+            },
+            Some(Element::O) => match hybridization {
+                _ if self.bonds[i].len() == 1 && bonded_to(&|j| (self.is(j, Element::C) || self.is(j, Element::P)) && self.terminal_oxygens(j) >= 2) => "O.co2",
+                Hybridization::Sp3 => "O.3",
+                _ => "O.2",
+            },
+            Some(Element::S) => match self.terminal_oxygens(i) {
+                0 if hybridization == Hybridization::Sp2 => "S.2",
+                0 => "S.3",
+                1 => "S.O",
+                _ => "S.O2",
+            },
+            Some(Element::P) => "P.3",
+            Some(Element::H) => "H",
+            Some(element) => {
+                let symbol = element.symbol();
+                return format!("{}{}", &symbol[..1], symbol[1..].to_ascii_lowercase());
+            }
+            None => "Du",
+        };
+        sybyl.to_string()
+    }
+
+    fn gaff(&self, i: usize) -> String {
+        let gaff = match self.sybyl(i).as_str() {
+            "C.ar" => "ca",
+            "C.1" => "c1",
+            "C.2" | "C.cat" if self.carbonyl(i) => "c",
+            "C.2" | "C.cat" => "c2",
+            "C.3" => "c3",
+            "N.ar" if self.bonds[i].len() >= 3 => "na",
+            "N.ar" => "nb",
+            "N.1" => "n1",
+            "N.am" => "n",
+            "N.pl3" => "na",
+            "N.2" => "n2",
+            "N.4" => "n4",
+            "N.3" => "n3",
+            "O.co2" | "O.2" => "o",
+            "O.3" if self.has_hydrogen(i) || self.heavy_degree(i) < 2 => "oh",
+            "O.3" => "os",
+            "S.O2" => "s6",
+            "S.O" => "s4",
+            "S.2" => "s",
+            "S.3" if self.has_hydrogen(i) || self.heavy_degree(i) < 2 => "sh",
+            "S.3" => "ss",
+            "P.3" => "p5",
+            "H" => match self.neighbors(i).next() {
+                Some(j) if self.is(j, Element::C) && self.hybridization[j] == Hybridization::Aromatic => "ha",
+                Some(j) if self.is(j, Element::C) => "hc",
+                Some(j) if self.is(j, Element::N) => "hn",
+                Some(j) if self.is(j, Element::O) => "ho",
+                Some(j) if self.is(j, Element::S) => "hs",
+                _ => "h",
+            },
+            other => return other.to_ascii_lowercase(),
+        };
+        gaff.to_string()
+    }
+
+    /// Rosetta-style type of an atom of a standard amino acid.
+    fn protein(&self, i: usize) -> Option<String> {
+        let (residue, atom) = self.atoms[i];
+        let name = residue.name()?;
+        if self.is(i, Element::H) {
+            let j = self.neighbors(i).next()?;
+            return Some(match self.element(j) {
+                Some(Element::N | Element::O | Element::S) => "Hpol",
+                _ if self.hybridization[j] == Hybridization::Aromatic => "Haro",
+                _ => "Hapo",
+            }.to_string());
+        }
+        protein_type(name, atom.name()).map(str::to_string)
+    }
+}
+
+
+/// Rosetta-style type of the heavy atom `atom` of the amino acid `residue`.
+fn protein_type(residue: &str, atom: &str) -> Option<&'static str> {
+    let aromatic = ["CG", "CD1", "CD2", "CE1", "CE2", "CE3", "CZ", "CZ2", "CZ3", "CH2"];
+    let kind = match (residue, atom) {
+        ("PRO", "N") => "Npro",
+        (_, "N") => "Nbb",
+        (_, "CA") => "CAbb",
+        (_, "C") => "CObb",
+        (_, "O" | "OXT") => "OCbb",
+        ("ALA", "CB") => "CH3",
+        ("ILE" | "THR" | "VAL", "CB") | ("LEU", "CG") => "CH1",
+        ("ILE", "CG2" | "CD1") | ("LEU", "CD1" | "CD2") | ("MET", "CE") | ("THR", "CG2") | ("VAL", "CG1" | "CG2") => "CH3",
+        ("ARG", "NE") => "NtrR",
+        ("ARG", "CZ") => "aroC",
+        ("ARG", "NH1" | "NH2") => "Narg",
+        ("ASN", "CG") | ("GLN", "CD") => "CNH2",
+        ("ASN", "OD1") | ("GLN", "OE1") => "ONH2",
+        ("ASN", "ND2") | ("GLN", "NE2") => "NH2O",
+        ("ASP", "CG") | ("GLU", "CD") => "COO",
+        ("ASP", "OD1" | "OD2") | ("GLU", "OE1" | "OE2") => "OOC",
+        ("CYS", "SG") => "SH1",
+        ("MET", "SD") => "S",
+        ("HIS", "ND1") => "Nhis",
+        ("HIS", "NE2") | ("TRP", "NE1") => "Ntrp",
+        ("LYS", "NZ") => "Nlys",
+        ("SER", "OG") | ("THR", "OG1") | ("TYR", "OH") => "OH",
+        ("HIS" | "PHE" | "TRP" | "TYR", name) if aromatic.contains(&name) => "aroC",
+        ("ARG" | "ASN" | "ASP" | "CYS" | "GLN" | "GLU" | "HIS" | "LEU" | "LYS" | "MET" | "PHE" | "PRO" | "SER" | "TRP" | "TYR", "CB")
+        | ("ARG" | "GLN" | "GLU" | "LYS" | "MET" | "PRO", "CG")
+        | ("ARG" | "LYS" | "PRO", "CD")
+        | ("ILE", "CG1")
+        | ("LYS", "CE") => "CH2",
+        _ => return None,
+    };
+    Some(kind)
+}
+
+
+/// Atom types of the atoms of `pdb` under `scheme`, keyed by serial number.
+/// Hybridization comes from the bond orders of `bond_order` templates and
+/// valences and of the dictionary for residues resolved with `ccd`, and
+/// from bond angles where those are unknown; bonds are detected as for
+/// edges with `tolerance`. The protein scheme only types the atoms of
+/// standard amino acids.
+pub fn atom_types(pdb: &PDB, scheme: AtomTypeScheme, tolerance: f64, ccd: &CcdAnnotations) -> HashMap<usize, String> {
+    let mut types = HashMap::new();
+    for model in pdb.models() {
+        let bonds = Bonds::new(model.residues(), tolerance, ccd);
+        for (i, (_, atom)) in bonds.atoms.iter().enumerate() {
+            let kind = match scheme {
+                AtomTypeScheme::Sybyl => Some(bonds.sybyl(i)),
+                AtomTypeScheme::Gaff => Some(bonds.gaff(i)),
+                AtomTypeScheme::Protein => bonds.protein(i),
+            };
+            if let Some(kind) = kind {
+                types.insert(atom.serial_number(), kind);
+            }
+        }
+    }
+    types
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::golden::Fixture;
+
+    #[test]
+    fn types_by_scheme() {
+        let pdb = Fixture::get("benzoate_stacking").unwrap().structure().unwrap();
+        let name = |serial: usize| pdb.atoms().find(|a| a.serial_number() == serial).unwrap().name().to_string();
+        let typed = |scheme| {
+            let types = atom_types(&pdb, scheme, 0.45, &CcdAnnotations::default());
+            let mut named: Vec<(String, String)> = types.into_iter().map(|(serial, kind)| (name(serial), kind)).collect();
+            named.sort();
+            named
+        };
+        let sybyl = typed(AtomTypeScheme::Sybyl);
+        let get = |types: &[(String, String)], atom: &str| types.iter().filter(|(n, _)| n == atom).map(|(_, t)| t.clone()).collect::<Vec<_>>();
+        // The Phe ring from its template, the benzoate ring and carboxylate from their geometry
+        assert_eq!(get(&sybyl, "CZ"), ["C.ar"]);
+        assert_eq!(get(&sybyl, "C4"), ["C.ar"]);
+        assert_eq!(get(&sybyl, "O1"), ["O.co2"]);
+        assert_eq!(get(&sybyl, "C7"), ["C.2"]);
+        assert_eq!(get(&sybyl, "NZ"), ["N.3"]);
+
+        let gaff = typed(AtomTypeScheme::Gaff);
+        assert_eq!(get(&gaff, "C7"), ["c"]);
+        assert_eq!(get(&gaff, "CE"), ["c3"]);
+
+        let protein = typed(AtomTypeScheme::Protein);
+        assert_eq!(protein.len(), 8);
+        assert_eq!(get(&protein, "NZ"), ["Nlys"]);
+        assert_eq!(get(&protein, "CD1"), ["aroC"]);
+    }
+}
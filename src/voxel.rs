@@ -0,0 +1,178 @@
+use crate::npy::NpzWriter;
+use crate::electronegativity;
+use crate::radius::Radii;
+use anyhow::Result;
+use pdbtbx::{Atom, Element, PDB};
+use std::path::Path;
+
+
+/// What a channel of the density grid accumulates.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Channel {
+    /// Density of atoms of one element
+    Element(Element),
+    /// Density of atoms of elements without a channel of their own
+    Other,
+    /// Density of all atoms
+    All,
+    /// Density weighted by formal charge
+    Charge,
+    /// Density weighted by electronegativity
+    Electronegativity,
+}
+
+impl Channel {
+    pub fn parse(s: &str) -> std::result::Result<Channel, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "other" => Ok(Channel::Other),
+            "all" => Ok(Channel::All),
+            "charge" => Ok(Channel::Charge),
+            "electronegativity" => Ok(Channel::Electronegativity),
+            _ => Element::from_symbol(s)
+                .map(Channel::Element)
+                .ok_or_else(|| format!("unknown voxel channel {}, expected an element symbol, other, all, charge or electronegativity", s)),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Channel::Element(e) => e.symbol().to_string(),
+            Channel::Other => "other".to_string(),
+            Channel::All => "all".to_string(),
+            Channel::Charge => "charge".to_string(),
+            Channel::Electronegativity => "electronegativity".to_string(),
+        }
+    }
+}
+
+
+/// Settings of the voxel export.
+pub struct VoxelOptions {
+    /// Edge length of a voxel in Å
+    pub size: f64,
+    /// Fixed number of voxels per axis, centered on the structure. The grid
+    /// spans the bounding box plus `padding` if unset.
+    pub dim: Option<usize>,
+    pub padding: f64,
+    pub channels: Vec<Channel>,
+}
+
+
+/// Density grid of a structure, `[channel, x, y, z]` in C order.
+struct Grid {
+    values: Vec<f32>,
+    /// Cartesian position of the corner of the first voxel
+    origin: [f64; 3],
+    dims: [usize; 3],
+}
+
+
+/// Rasterizes the atoms of `pdb` into a `[channel, x, y, z]` grid of
+/// Gaussian densities with a width of half the van der Waals radius from
+/// `radii`, and writes it to an `.npz` archive with the grid origin, voxel
+/// size and channel names.
+pub fn write_voxels(pdb: &PDB, opts: &VoxelOptions, radii: &mut Radii, save_fname: &Path) -> Result<()> {
+    let Grid { values, origin, dims } = voxelize(pdb, opts, radii)?;
+    let mut npz = NpzWriter::create(save_fname)?;
+    npz.add("grid", &values, &[opts.channels.len(), dims[0], dims[1], dims[2]])?;
+    npz.add("origin", &origin, &[3])?;
+    npz.add("voxel_size", &[opts.size], &[])?;
+    npz.add_strings("channels", &opts.channels.iter().map(Channel::name).collect::<Vec<_>>())?;
+    npz.finish()
+}
+
+
+fn voxelize(pdb: &PDB, opts: &VoxelOptions, radii: &mut Radii) -> Result<Grid> {
+    let atoms: Vec<&Atom> = pdb.atoms().filter(|a| a.element().is_some()).collect();
+    let (mut min, mut max) = ([f64::INFINITY; 3], [f64::NEG_INFINITY; 3]);
+    for a in &atoms {
+        let p = a.pos();
+        for (axis, v) in [p.0, p.1, p.2].into_iter().enumerate() {
+            min[axis] = min[axis].min(v);
+            max[axis] = max[axis].max(v);
+        }
+    }
+    if atoms.is_empty() {
+        (min, max) = ([0.0; 3], [0.0; 3]);
+    }
+    let (origin, dims) = match opts.dim {
+        Some(n) => {
+            let half = n as f64 * opts.size / 2.0;
+            ([0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0 - half), [n; 3])
+        }
+        None => (
+            [0, 1, 2].map(|axis| min[axis] - opts.padding),
+            [0, 1, 2].map(|axis| ((max[axis] - min[axis] + 2.0 * opts.padding) / opts.size).ceil() as usize + 1),
+        ),
+    };
+
+    let cells = dims[0] * dims[1] * dims[2];
+    let mut grid = vec![0f32; opts.channels.len() * cells];
+    let listed: Vec<&Element> = opts.channels.iter()
+        .filter_map(|c| if let Channel::Element(e) = c { Some(e) } else { None })
+        .collect();
+    for atom in &atoms {
+        let ele = atom.element().unwrap();
+        let weights: Vec<f64> = opts.channels.iter().map(|c| match c {
+            Channel::Element(e) => (e == ele) as u8 as f64,
+            Channel::Other => (!listed.contains(&ele)) as u8 as f64,
+            Channel::All => 1.0,
+            Channel::Charge => atom.charge() as f64,
+            Channel::Electronegativity => electronegativity(ele).unwrap_or(0.0),
+        }).collect();
+        if weights.iter().all(|w| *w == 0.0) {
+            continue;
+        }
+        let sigma = radii.radius(ele, atom.serial_number())? / 2.0;
+        let reach = 3.0 * sigma;
+        let p = atom.pos();
+        let p = [p.0, p.1, p.2];
+        let range = |axis: usize| {
+            let lo = ((p[axis] - reach - origin[axis]) / opts.size).floor().max(0.0) as usize;
+            let hi = (((p[axis] + reach - origin[axis]) / opts.size).ceil().max(0.0) as usize).min(dims[axis]);
+            lo..hi
+        };
+        for x in range(0) {
+            for y in range(1) {
+                for z in range(2) {
+                    let center = [x, y, z].map(|i| i as f64 + 0.5);
+                    let d2: f64 = (0..3).map(|axis| (origin[axis] + center[axis] * opts.size - p[axis]).powi(2)).sum();
+                    let density = (-d2 / (2.0 * sigma * sigma)).exp();
+                    let cell = (x * dims[1] + y) * dims[2] + z;
+                    for (c, w) in weights.iter().enumerate() {
+                        grid[c * cells + cell] += (w * density) as f32;
+                    }
+                }
+            }
+        }
+    }
+    Ok(Grid { values: grid, origin, dims })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::radius::UnknownRadius;
+
+    #[test]
+    fn places_atom_densities() {
+        let text = "ATOM      1  O   HOH A   1       1.250   0.250   0.250  1.00  0.00           O\nEND\n";
+        let pdb = pdbtbx::open_pdb_raw(std::io::BufReader::new(text.as_bytes()), pdbtbx::Context::None, pdbtbx::StrictnessLevel::Loose).unwrap().0;
+        let opts = VoxelOptions { size: 0.5, dim: None, padding: 1.0, channels: vec![Channel::Element(Element::C), Channel::Other] };
+        let grid = voxelize(&pdb, &opts, &mut Radii::new(UnknownRadius::default())).unwrap();
+
+        // A single atom spans twice the padding, plus a voxel
+        assert_eq!(grid.dims, [5; 3]);
+        assert_eq!(grid.origin, [0.25, -0.75, -0.75]);
+        let cells = 5 * 5 * 5;
+        assert!(grid.values[..cells].iter().all(|v| *v == 0.0));
+        // The atom lies on the corner shared by the central voxels
+        let other = &grid.values[cells..];
+        let peak = other.iter().cloned().fold(0.0, f32::max);
+        let at = |x: usize, y: usize, z: usize| other[(x * 5 + y) * 5 + z];
+        assert_eq!(at(1, 1, 1), peak);
+        assert_eq!(at(2, 2, 2), peak);
+        assert!(at(0, 0, 0) < peak && at(4, 4, 4) < at(0, 0, 0));
+    }
+}